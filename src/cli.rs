@@ -7,6 +7,7 @@ pub struct Cli {
     pub dir_name: Option<String>,
     pub db_filename: Option<String>,
     pub replicaof: Option<String>,
+    pub databases: Option<usize>,
 }
 
 // impl Display for Cli {
@@ -26,6 +27,7 @@ impl Cli {
         let mut listening_port = Some(6379u16);
         let bind_address = Some(String::from("127.0.0.1"));
         let mut replicaof = None;
+        let mut databases = None;
         while let Some(param) = args.next() {
             match param.to_ascii_lowercase().as_str() {
                 "--dir" => {
@@ -53,6 +55,13 @@ impl Cli {
                         replicaof = Some(s);
                     }
                 }
+
+                "--databases" => {
+                    if let Some(s) = args.next() {
+                        let count = s.parse::<usize>().ok().filter(|count| *count > 0);
+                        databases = Some(count.expect("--databases takes a positive number"));
+                    }
+                }
                 _ => {}
             }
         }
@@ -63,6 +72,7 @@ impl Cli {
             dir_name,
             db_filename,
             replicaof,
+            databases,
         }
     }
 }
@@ -0,0 +1,100 @@
+use bytes::Bytes;
+
+/// Whether a range counts bytes or bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitUnit {
+    Byte,
+    Bit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setbit {
+    pub key: Bytes,
+    pub offset: usize,
+    pub value: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Getbit {
+    pub key: Bytes,
+    pub offset: usize,
+}
+
+/// BITCOUNT. The range is inclusive, with negative offsets counting from
+/// the end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitcount {
+    pub key: Bytes,
+    pub range: Option<(i64, i64, BitUnit)>,
+}
+
+/// BITPOS. Without an `end` the search runs to the end of the string, past
+/// which a clear bit is always found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitpos {
+    pub key: Bytes,
+    pub bit: bool,
+    pub start: i64,
+    pub end: Option<i64>,
+    pub unit: BitUnit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// BITOP. `Not` comes with exactly one key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitop {
+    pub op: BitOperation,
+    pub destination: Bytes,
+    pub keys: Vec<Bytes>,
+}
+
+/// An `i1` to `i64` or `u1` to `u63` integer type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitfieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+/// What a BITFIELD write does with a result that does not fit its type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitfieldOverflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+/// One BITFIELD subcommand. Offsets are in bits, `#N` ones already
+/// multiplied out by the type's width.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BitfieldOp {
+    Get {
+        ty: BitfieldType,
+        offset: usize,
+    },
+    Set {
+        ty: BitfieldType,
+        offset: usize,
+        value: i64,
+    },
+    Incrby {
+        ty: BitfieldType,
+        offset: usize,
+        increment: i64,
+    },
+    /// Applies to the writes after it.
+    Overflow(BitfieldOverflow),
+}
+
+/// BITFIELD, and BITFIELD_RO, which only parses GETs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitfield {
+    pub key: Bytes,
+    pub ops: Vec<BitfieldOp>,
+}
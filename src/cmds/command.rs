@@ -1,30 +1,64 @@
-use std::time::Duration;
+use bytes::Bytes;
+
+use crate::glob::glob_match;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Get {
-    pub key: String,
+    pub key: Bytes,
 }
 
+/// SET, and SETEX/PSETEX which are SET with an expiry. The parser enforces
+/// the exclusions: at most one of `nx`/`xx`, and `keep_ttl` never together
+/// with an `expiry`.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Set {
-    pub key: String,
-    pub value: String,
-    pub expiry: Option<Duration>,
+    pub key: Bytes,
+    pub value: Bytes,
+    /// Only set the key when it does not exist.
+    pub nx: bool,
+    /// Only set the key when it exists.
+    pub xx: bool,
+    /// Reply with the value the key had.
+    pub get: bool,
+    pub expiry: Option<Expiry>,
+    /// Keep the expiry the key has rather than clearing it.
+    pub keep_ttl: bool,
 }
 
+/// INCR, DECR, INCRBY and DECRBY, all as an increment that may be negative.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Incr {
-    pub key: String,
+    pub key: Bytes,
+    pub by: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Incrbyfloat {
+    pub key: Bytes,
+    pub increment: f64,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Ping {
-    pub value: Option<String>,
+    pub value: Option<Bytes>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Echo {
-    pub value: Option<String>,
+    pub value: Option<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hello {
+    pub protover: Option<i64>,
+    pub auth: Option<(String, String)>,
+    pub setname: Option<String>,
+}
+
+/// SELECT, which picks the database the connection's commands run on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub index: i64,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -32,7 +66,7 @@ pub struct Save;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Keys {
-    pub arg: String,
+    pub pattern: Bytes,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -52,19 +86,19 @@ pub struct Wait {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Type {
-    pub key: String,
+    pub key: Bytes,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Xadd {
-    pub key: String,
+    pub key: Bytes,
     pub entry_id: String,
-    pub args: Vec<(String, String)>,
+    pub args: Vec<(Bytes, Bytes)>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Xrange {
-    pub key: String,
+    pub key: Bytes,
     pub start: String,
     pub end: String,
 }
@@ -72,7 +106,7 @@ pub struct Xrange {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Xread {
     pub block: Option<u64>,
-    pub keys: Vec<String>,
+    pub keys: Vec<Bytes>,
     pub entry_ids: Vec<String>,
 }
 
@@ -84,3 +118,73 @@ pub struct Exec;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Discard;
+
+/// An expiry time as the EXPIRE family of commands gives it, in milliseconds:
+/// either from now on or as a unix time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry {
+    In(i64),
+    At(i64),
+}
+
+impl Expiry {
+    /// The expiry as a unix time in milliseconds. `None` when it does not
+    /// fit.
+    pub fn resolve(self, now: u64) -> Option<i64> {
+        match self {
+            Self::In(ms) => (now as i64).checked_add(ms),
+            Self::At(ms) => Some(ms),
+        }
+    }
+}
+
+/// NX, XX, GT and LT of the EXPIRE family.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpireCondition {
+    Always,
+    /// Only when there is no expiry yet.
+    Nx,
+    /// Only when there is one already.
+    Xx,
+    /// Only when later than the current one; no expiry counts as infinite.
+    Gt,
+    /// Only when earlier than the current one.
+    Lt,
+}
+
+impl ExpireCondition {
+    pub fn allows(self, current: Option<u64>, new: i64) -> bool {
+        match (self, current) {
+            (Self::Always, _) | (Self::Nx, None) | (Self::Lt, None) => true,
+            (Self::Nx, Some(_)) | (Self::Xx, None) | (Self::Gt, None) => false,
+            (Self::Xx, Some(_)) => true,
+            (Self::Gt, Some(t)) => new > t as i64,
+            (Self::Lt, Some(t)) => new < t as i64,
+        }
+    }
+}
+
+/// The cursor and options of SCAN, SSCAN, HSCAN and ZSCAN. MATCH and TYPE
+/// filter a page after it is taken, so it may come back with fewer than
+/// `count` elements, or none, before the scan is done.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanOptions {
+    pub cursor: u64,
+    /// `None` for a pattern that matches everything.
+    pub pattern: Option<Bytes>,
+    pub count: usize,
+    /// TYPE, which only SCAN takes.
+    pub type_name: Option<String>,
+    /// NOVALUES, which only HSCAN takes.
+    pub no_values: bool,
+}
+
+impl ScanOptions {
+    /// Whether `element` matches MATCH, case sensitively.
+    pub fn matches(&self, element: &[u8]) -> bool {
+        match &self.pattern {
+            Some(pattern) => glob_match(pattern, element, false),
+            None => true,
+        }
+    }
+}
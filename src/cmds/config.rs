@@ -1,6 +1,7 @@
 #[derive(Debug, Clone, PartialEq)]
 pub enum SubCommand {
-    Get(String),
+    /// The glob-style patterns of the parameters to get.
+    Get(Vec<String>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -0,0 +1,82 @@
+use bytes::Bytes;
+
+use crate::database::{GeoPoint, GeoShape};
+
+/// The unit distances are given and replied in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoUnit {
+    M,
+    Km,
+    Ft,
+    Mi,
+}
+
+impl GeoUnit {
+    pub fn meters(self) -> f64 {
+        match self {
+            GeoUnit::M => 1.0,
+            GeoUnit::Km => 1000.0,
+            GeoUnit::Ft => 0.3048,
+            GeoUnit::Mi => 1609.34,
+        }
+    }
+}
+
+/// GEOADD. At most one of `nx` and `xx` is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geoadd {
+    pub key: Bytes,
+    pub nx: bool,
+    pub xx: bool,
+    pub ch: bool,
+    pub members: Vec<(GeoPoint, Bytes)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geodist {
+    pub key: Bytes,
+    pub member1: Bytes,
+    pub member2: Bytes,
+    pub unit: GeoUnit,
+}
+
+/// GEOPOS and GEOHASH.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geopos {
+    pub key: Bytes,
+    pub members: Vec<Bytes>,
+    pub geohash: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoCenter {
+    Member(Bytes),
+    Point(GeoPoint),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoSort {
+    Asc,
+    Desc,
+}
+
+/// GEOSEARCH, and GEOSEARCHSTORE when `destination` is given. The shape is
+/// in meters, whatever `unit` it came in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geosearch {
+    pub key: Bytes,
+    pub destination: Option<Bytes>,
+    pub center: GeoCenter,
+    pub shape: GeoShape,
+    pub unit: GeoUnit,
+    /// `None` leaves the matches in the order they were found.
+    pub sort: Option<GeoSort>,
+    pub count: Option<usize>,
+    /// Stop at the first `count` matches rather than return the nearest.
+    pub any: bool,
+    pub with_coord: bool,
+    pub with_dist: bool,
+    pub with_hash: bool,
+    /// Store the distances as the scores, rather than the positions.
+    pub store_dist: bool,
+}
@@ -0,0 +1,108 @@
+use bytes::Bytes;
+
+use super::{ExpireCondition, Expiry, ScanOptions};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hset {
+    pub key: Bytes,
+    pub pairs: Vec<(Bytes, Bytes)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hget {
+    pub key: Bytes,
+    pub field: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hmget {
+    pub key: Bytes,
+    pub fields: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hgetall {
+    pub key: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hdel {
+    pub key: Bytes,
+    pub fields: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hexists {
+    pub key: Bytes,
+    pub field: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hincrby {
+    pub key: Bytes,
+    pub field: Bytes,
+    pub increment: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hincrbyfloat {
+    pub key: Bytes,
+    pub field: Bytes,
+    pub increment: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hkeys {
+    pub key: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hvals {
+    pub key: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hlen {
+    pub key: Bytes,
+}
+
+/// HSCAN. The cursor and COUNT are checked but not needed: the whole hash is
+/// returned at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hscan {
+    pub key: Bytes,
+    pub options: ScanOptions,
+}
+
+/// HRANDFIELD. A negative count may return the same field more than once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hrandfield {
+    pub key: Bytes,
+    pub count: Option<i64>,
+    pub with_values: bool,
+}
+
+/// HEXPIRE, HPEXPIRE, HEXPIREAT and HPEXPIREAT.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hexpire {
+    pub key: Bytes,
+    pub expiry: Expiry,
+    pub condition: ExpireCondition,
+    pub fields: Vec<Bytes>,
+}
+
+/// HTTL, HPTTL, HEXPIRETIME and HPEXPIRETIME: the time left, or the expiry
+/// as a unix time when `absolute`, in seconds or milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Httl {
+    pub key: Bytes,
+    pub fields: Vec<Bytes>,
+    pub absolute: bool,
+    pub millis: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hpersist {
+    pub key: Bytes,
+    pub fields: Vec<Bytes>,
+}
@@ -0,0 +1,20 @@
+use bytes::Bytes;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pfadd {
+    pub key: Bytes,
+    pub elements: Vec<Bytes>,
+}
+
+/// PFCOUNT. With several keys it counts their union.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pfcount {
+    pub keys: Vec<Bytes>,
+}
+
+/// PFMERGE. The destination's own registers are part of the union.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pfmerge {
+    pub destination: Bytes,
+    pub sources: Vec<Bytes>,
+}
@@ -0,0 +1,89 @@
+use bytes::Bytes;
+
+use super::{ExpireCondition, Expiry, ScanOptions};
+
+/// DEL, and UNLINK, which frees the values no differently here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Del {
+    pub keys: Vec<Bytes>,
+}
+
+/// EXISTS, and TOUCH, which has no access times to update here. A key given
+/// twice counts twice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exists {
+    pub keys: Vec<Bytes>,
+}
+
+/// RENAME, and RENAMENX when `only_if_new`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rename {
+    pub key: Bytes,
+    pub new_key: Bytes,
+    pub only_if_new: bool,
+}
+
+/// COPY, into database `db` when given rather than the selected one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyKey {
+    pub source: Bytes,
+    pub destination: Bytes,
+    pub db: Option<i64>,
+    pub replace: bool,
+}
+
+/// MOVE, which takes a key to database `db` unless one is there already.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Move {
+    pub key: Bytes,
+    pub db: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Swapdb {
+    pub db1: i64,
+    pub db2: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Randomkey;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dbsize;
+
+/// FLUSHDB, and FLUSHALL when `all`. ASYNC and SYNC are accepted, but the
+/// keys are always dropped right away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flush {
+    pub all: bool,
+}
+
+/// EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT. The expiry is set only when
+/// every one of `conditions` allows it; XX goes together with GT or LT.
+/// `command` names the variant in errors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expire {
+    pub command: &'static str,
+    pub key: Bytes,
+    pub expiry: Expiry,
+    pub conditions: Vec<ExpireCondition>,
+}
+
+/// TTL, PTTL, EXPIRETIME and PEXPIRETIME: the time left, or the expiry as a
+/// unix time when `absolute`, in seconds or milliseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ttl {
+    pub key: Bytes,
+    pub absolute: bool,
+    pub millis: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Persist {
+    pub key: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scan {
+    pub options: ScanOptions,
+}
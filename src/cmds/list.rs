@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+/// The side of a list an element is pushed to or popped from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum End {
+    Left,
+    Right,
+}
+
+/// LPUSH, RPUSH, LPUSHX and RPUSHX.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Push {
+    pub key: Bytes,
+    pub end: End,
+    pub elements: Vec<Bytes>,
+    pub only_if_exists: bool,
+}
+
+/// LPOP and RPOP. With a count the reply is an array, even for one element.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pop {
+    pub key: Bytes,
+    pub end: End,
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Llen {
+    pub key: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lrange {
+    pub key: Bytes,
+    pub start: i64,
+    pub stop: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lindex {
+    pub key: Bytes,
+    pub index: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lset {
+    pub key: Bytes,
+    pub index: i64,
+    pub element: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lrem {
+    pub key: Bytes,
+    pub count: i64,
+    pub element: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ltrim {
+    pub key: Bytes,
+    pub start: i64,
+    pub stop: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Linsert {
+    pub key: Bytes,
+    pub before: bool,
+    pub pivot: Bytes,
+    pub element: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lpos {
+    pub key: Bytes,
+    pub element: Bytes,
+    pub rank: i64,
+    pub count: Option<usize>,
+    pub maxlen: usize,
+}
+
+/// LMOVE, and RPOPLPUSH which is LMOVE from the right to the left.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lmove {
+    pub source: Bytes,
+    pub destination: Bytes,
+    pub from: End,
+    pub to: End,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lmpop {
+    pub keys: Vec<Bytes>,
+    pub end: End,
+    pub count: usize,
+}
+
+/// BLPOP and BRPOP. A `None` timeout waits forever.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bpop {
+    pub keys: Vec<Bytes>,
+    pub end: End,
+    pub timeout: Option<Duration>,
+}
+
+/// BLMOVE, and BRPOPLPUSH which is BLMOVE from the right to the left.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blmove {
+    pub lmove: Lmove,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blmpop {
+    pub lmpop: Lmpop,
+    pub timeout: Option<Duration>,
+}
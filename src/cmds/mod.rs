@@ -1,13 +1,49 @@
+pub use bitmap::{
+    BitOperation, BitUnit, Bitcount, Bitfield, BitfieldOp, BitfieldOverflow, BitfieldType, Bitop,
+    Bitpos, Getbit, Setbit,
+};
 pub use command::{
-    Discard, Echo, Exec, Get, Incr, Keys, Multi, Ping, Psync, Replconf, Save, Set, Type, Wait,
-    Xadd, Xrange, Xread,
+    Discard, Echo, Exec, ExpireCondition, Expiry, Get, Hello, Incr, Incrbyfloat, Keys, Multi, Ping,
+    Psync, Replconf, Save, ScanOptions, Select, Set, Type, Wait, Xadd, Xrange, Xread,
 };
 pub use config::{Config, SubCommand};
+pub use geo::{GeoCenter, GeoSort, GeoUnit, Geoadd, Geodist, Geopos, Geosearch};
+pub use hash::{
+    Hdel, Hexists, Hexpire, Hget, Hgetall, Hincrby, Hincrbyfloat, Hkeys, Hlen, Hmget, Hpersist,
+    Hrandfield, Hscan, Hset, Httl, Hvals,
+};
+pub use hyperloglog::{Pfadd, Pfcount, Pfmerge};
 pub use info::{Info, InfoSubCommand};
+pub use keyspace::{
+    CopyKey, Dbsize, Del, Exists, Expire, Flush, Move, Persist, Randomkey, Rename, Scan, Swapdb,
+    Ttl,
+};
+pub use list::{
+    Blmove, Blmpop, Bpop, End, Lindex, Linsert, Llen, Lmove, Lmpop, Lpos, Lrange, Lrem, Lset,
+    Ltrim, Pop, Push,
+};
+pub use set::{
+    Sadd, Scard, SetOp, Setop, Sintercard, Sismember, Smembers, Smismember, Spop, Srandmember,
+    Srem, Sscan,
+};
+pub use string::{Append, Getdel, Getex, Getrange, Getset, Lcs, Mget, Mset, Setrange, Strlen};
+pub use zset::{
+    Aggregate, Bzmpop, Bzpop, Zadd, Zcard, Zcount, Zincrby, Zmpop, Zpop, Zrange, ZrangeBy, Zrank,
+    Zrem, Zscan, Zscore, Zsetop,
+};
 
+mod bitmap;
 mod command;
 mod config;
+mod geo;
+mod hash;
+mod hyperloglog;
 mod info;
+mod keyspace;
+mod list;
+mod set;
+mod string;
+mod zset;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command {
@@ -15,8 +51,11 @@ pub enum Command {
     Get(Get),
     Set(Set),
     Incr(Incr),
+    Incrbyfloat(Incrbyfloat),
     Ping(Ping),
     Echo(Echo),
+    Hello(Hello),
+    Select(Select),
     Multi(Multi),
     Config(Config),
     Exec(Exec),
@@ -30,6 +69,98 @@ pub enum Command {
     Xadd(Xadd),
     Xrange(Xrange),
     Xread(Xread),
+    Push(Push),
+    Pop(Pop),
+    Llen(Llen),
+    Lrange(Lrange),
+    Lindex(Lindex),
+    Lset(Lset),
+    Lrem(Lrem),
+    Ltrim(Ltrim),
+    Linsert(Linsert),
+    Lpos(Lpos),
+    Lmove(Lmove),
+    Lmpop(Lmpop),
+    Bpop(Bpop),
+    Blmove(Blmove),
+    Blmpop(Blmpop),
+    Hset(Hset),
+    Hget(Hget),
+    Hmget(Hmget),
+    Hgetall(Hgetall),
+    Hdel(Hdel),
+    Hexists(Hexists),
+    Hincrby(Hincrby),
+    Hincrbyfloat(Hincrbyfloat),
+    Hkeys(Hkeys),
+    Hvals(Hvals),
+    Hlen(Hlen),
+    Hscan(Hscan),
+    Hrandfield(Hrandfield),
+    Hexpire(Hexpire),
+    Httl(Httl),
+    Hpersist(Hpersist),
+    Sadd(Sadd),
+    Srem(Srem),
+    Smembers(Smembers),
+    Sismember(Sismember),
+    Smismember(Smismember),
+    Scard(Scard),
+    Spop(Spop),
+    Srandmember(Srandmember),
+    Setop(Setop),
+    Sintercard(Sintercard),
+    Zadd(Zadd),
+    Zincrby(Zincrby),
+    Zrange(Zrange),
+    Zrank(Zrank),
+    Zscore(Zscore),
+    Zcard(Zcard),
+    Zrem(Zrem),
+    Zcount(Zcount),
+    Zpop(Zpop),
+    Zmpop(Zmpop),
+    Bzpop(Bzpop),
+    Bzmpop(Bzmpop),
+    Zsetop(Zsetop),
+    Append(Append),
+    Strlen(Strlen),
+    Getrange(Getrange),
+    Setrange(Setrange),
+    Mget(Mget),
+    Mset(Mset),
+    Getdel(Getdel),
+    Getex(Getex),
+    Getset(Getset),
+    Lcs(Lcs),
+    Setbit(Setbit),
+    Getbit(Getbit),
+    Bitcount(Bitcount),
+    Bitpos(Bitpos),
+    Bitop(Bitop),
+    Bitfield(Bitfield),
+    Pfadd(Pfadd),
+    Pfcount(Pfcount),
+    Pfmerge(Pfmerge),
+    Geoadd(Geoadd),
+    Geodist(Geodist),
+    Geopos(Geopos),
+    Geosearch(Geosearch),
+    Del(Del),
+    Exists(Exists),
+    Rename(Rename),
+    CopyKey(CopyKey),
+    Move(Move),
+    Swapdb(Swapdb),
+    Randomkey(Randomkey),
+    Dbsize(Dbsize),
+    Flush(Flush),
+    Expire(Expire),
+    Ttl(Ttl),
+    Persist(Persist),
+    Scan(Scan),
+    Sscan(Sscan),
+    Zscan(Zscan),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -37,22 +168,30 @@ pub enum CommandError {
     SyntaxError(String),
     WrongNumberOfArguments(String),
     NotSupported,
-    NotValidType(String),
     UnknownSubCommand(String),
+    NotAnInteger,
+    InvalidProtocolVersion,
+    NotPositive,
+    NotAFloat,
+    InvalidArgument(String),
 }
 
 impl CommandError {
     pub fn message(&self) -> String {
         match self {
-            Self::SyntaxError(x) => format!("ERR syntax error"),
+            Self::SyntaxError(_) => "ERR syntax error".to_string(),
             Self::WrongNumberOfArguments(x) => {
                 format!("ERR wrong number of arguments for '{}' command", x)
             }
-            Self::NotSupported => format!("ERR Command Not Supported"),
-            Self::NotValidType(x) => {
-                format!("ERR Not a valid type for the command '{}'", x)
-            }
+            Self::NotSupported => "ERR Command Not Supported".to_string(),
             Self::UnknownSubCommand(x) => format!("ERR Unknown subcommand '{}'", x),
+            Self::NotAnInteger => "ERR value is not an integer or out of range".to_string(),
+            Self::InvalidProtocolVersion => {
+                "ERR Protocol version is not an integer or out of range".to_string()
+            }
+            Self::NotPositive => "ERR value is out of range, must be positive".to_string(),
+            Self::NotAFloat => "ERR value is not a valid float".to_string(),
+            Self::InvalidArgument(x) => x.clone(),
         }
     }
 }
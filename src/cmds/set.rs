@@ -0,0 +1,82 @@
+use bytes::Bytes;
+
+use super::ScanOptions;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sadd {
+    pub key: Bytes,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Srem {
+    pub key: Bytes,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Smembers {
+    pub key: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sismember {
+    pub key: Bytes,
+    pub member: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Smismember {
+    pub key: Bytes,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scard {
+    pub key: Bytes,
+}
+
+/// SPOP. With a count the reply is a set, even for one member.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spop {
+    pub key: Bytes,
+    pub count: Option<usize>,
+}
+
+/// SRANDMEMBER. A negative count may return the same member more than once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Srandmember {
+    pub key: Bytes,
+    pub count: Option<i64>,
+}
+
+/// How the sets given to a multi-key set command are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    /// The members of the first set that are in none of the others.
+    Diff,
+}
+
+/// SINTER, SUNION and SDIFF, and their STORE forms when `destination` is
+/// given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setop {
+    pub op: SetOp,
+    pub keys: Vec<Bytes>,
+    pub destination: Option<Bytes>,
+}
+
+/// SINTERCARD. A `limit` of zero means no limit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sintercard {
+    pub keys: Vec<Bytes>,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sscan {
+    pub key: Bytes,
+    pub options: ScanOptions,
+}
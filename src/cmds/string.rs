@@ -0,0 +1,73 @@
+use bytes::Bytes;
+
+use super::Expiry;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Append {
+    pub key: Bytes,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strlen {
+    pub key: Bytes,
+}
+
+/// GETRANGE, with inclusive offsets where negative ones count from the end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Getrange {
+    pub key: Bytes,
+    pub start: i64,
+    pub end: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setrange {
+    pub key: Bytes,
+    pub offset: usize,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mget {
+    pub keys: Vec<Bytes>,
+}
+
+/// MSET, and MSETNX when `only_if_none_exist`, which SETNX is the single
+/// key case of.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mset {
+    pub pairs: Vec<(Bytes, Bytes)>,
+    pub only_if_none_exist: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Getdel {
+    pub key: Bytes,
+}
+
+/// GETEX. At most one of `expiry` and `persist` is given; with neither it
+/// is a plain GET.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Getex {
+    pub key: Bytes,
+    pub expiry: Option<Expiry>,
+    pub persist: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Getset {
+    pub key: Bytes,
+    pub value: Bytes,
+}
+
+/// LCS. `min_match_len` and `with_match_len` only matter with `idx`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lcs {
+    pub key1: Bytes,
+    pub key2: Bytes,
+    pub len: bool,
+    pub idx: bool,
+    pub min_match_len: usize,
+    pub with_match_len: bool,
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::{ScanOptions, SetOp};
+use crate::database::{LexBound, ScoreBound};
+
+/// ZADD. The options are checked for conflicts while parsing, so at most one
+/// of `nx`/`xx` and of `gt`/`lt` is set, and `nx` never goes with either of
+/// the latter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zadd {
+    pub key: Bytes,
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
+    /// Reply with the number of members added or changed, not just added.
+    pub ch: bool,
+    /// Add the score to the member's current one and reply with the result,
+    /// like ZINCRBY. Comes with exactly one pair.
+    pub incr: bool,
+    pub pairs: Vec<(f64, Bytes)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zincrby {
+    pub key: Bytes,
+    pub increment: f64,
+    pub member: Bytes,
+}
+
+/// What ZRANGE selects by. Score and lex ranges always go from `min` to
+/// `max`, whichever order the command gave them in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZrangeBy {
+    Rank { start: i64, stop: i64 },
+    Score { min: ScoreBound, max: ScoreBound },
+    Lex { min: LexBound, max: LexBound },
+}
+
+/// ZRANGE, in its unified form, and ZRANGESTORE when `destination` is
+/// given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zrange {
+    pub key: Bytes,
+    pub destination: Option<Bytes>,
+    pub by: ZrangeBy,
+    pub rev: bool,
+    /// `LIMIT offset count`. A negative offset selects nothing, a negative
+    /// count everything from the offset on.
+    pub limit: Option<(i64, i64)>,
+    pub with_scores: bool,
+}
+
+/// ZRANK and ZREVRANK.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zrank {
+    pub key: Bytes,
+    pub member: Bytes,
+    pub rev: bool,
+    pub with_score: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zscore {
+    pub key: Bytes,
+    pub member: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zcard {
+    pub key: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zrem {
+    pub key: Bytes,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zcount {
+    pub key: Bytes,
+    pub min: ScoreBound,
+    pub max: ScoreBound,
+}
+
+/// ZPOPMIN and ZPOPMAX.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zpop {
+    pub key: Bytes,
+    pub max: bool,
+    pub count: Option<usize>,
+}
+
+/// ZMPOP: pops from the first of `keys` holding a sorted set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zmpop {
+    pub keys: Vec<Bytes>,
+    pub max: bool,
+    pub count: usize,
+}
+
+/// BZPOPMIN and BZPOPMAX. A `None` timeout waits forever.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bzpop {
+    pub keys: Vec<Bytes>,
+    pub max: bool,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bzmpop {
+    pub zmpop: Zmpop,
+    pub timeout: Option<Duration>,
+}
+
+/// How the scores a member has in the combined sorted sets make its score
+/// in the result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+/// ZUNION, ZINTER and ZDIFF, and their STORE forms when `destination` is
+/// given. Plain sets count as sorted sets with all scores 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zsetop {
+    pub op: SetOp,
+    pub keys: Vec<Bytes>,
+    /// Multiplies the scores of each key's members, one weight per key.
+    pub weights: Vec<f64>,
+    pub aggregate: Aggregate,
+    pub destination: Option<Bytes>,
+    pub with_scores: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zscan {
+    pub key: Bytes,
+    pub options: ScanOptions,
+}
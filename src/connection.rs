@@ -1,13 +1,17 @@
 use crate::{
-    cmds::{Command, CommandError, InfoSubCommand, SubCommand},
-    database::{self, Peer, SharedState, StreamEntry},
+    cmds::{Command, CommandError, Config, Echo, Hello, Info, InfoSubCommand, Ping, SubCommand},
+    database::{self, KeyValueStore, Peer, SharedState},
+    handlers,
     parse::parse_command,
-    resp::RespError,
+    resp::{Protocol, RespError},
 };
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use std::collections::VecDeque;
 use std::net::SocketAddr;
-use std::sync::{atomic::AtomicUsize, atomic::Ordering::Relaxed, Arc};
+use std::sync::{
+    atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed},
+    Arc,
+};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
@@ -15,11 +19,52 @@ use tokio::{
     time::{self, Duration},
 };
 
+use crate::glob::glob_match;
 use crate::global::STATE;
 use crate::resp::RespData;
 
 const CHUNK_SIZE: usize = 16 * 1024;
-const CRLF: &str = "\r\n";
+
+// Snapshot sent to a replica after `+FULLRESYNC`: an empty RDB file.
+const EMPTY_RDB: [u8; 88] = [
+    82, 69, 68, 73, 83, 48, 48, 49, 49, 250, 9, 114, 101, 100, 105, 115, 45, 118, 101, 114, 5, 55,
+    46, 50, 46, 48, 250, 10, 114, 101, 100, 105, 115, 45, 98, 105, 116, 115, 192, 64, 250, 5, 99,
+    116, 105, 109, 101, 194, 5, 28, 228, 102, 250, 8, 117, 115, 101, 100, 45, 109, 101, 109, 194,
+    184, 75, 14, 0, 250, 8, 97, 111, 102, 45, 98, 97, 115, 101, 192, 0, 255, 187, 243, 46, 0, 102,
+    82, 8, 22,
+];
+
+// Parameters CONFIG GET reports, each with the `STATE` entry it is kept in.
+const CONFIG_PARAMETERS: [(&str, &str); 5] = [
+    ("bind", "bind_address"),
+    ("databases", "databases"),
+    ("dbfilename", "dbfilename"),
+    ("dir", "dir"),
+    ("port", "listening_port"),
+];
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Settings a client negotiates for its own connection.
+#[derive(Debug)]
+pub struct Session {
+    pub id: u64,
+    pub protocol: Protocol,
+    pub name: Option<String>,
+    /// The database commands run on.
+    pub db: usize,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Relaxed),
+            protocol: Protocol::default(),
+            name: None,
+            db: 0,
+        }
+    }
+}
 
 pub struct Connection {
     state: Arc<SharedState>,
@@ -28,6 +73,7 @@ pub struct Connection {
     // reader: Arc<Mutex<BufReader<ReadHalf<'a>>>>,
     // writer: Arc<Mutex<BufWriter<WriteHalf<'a>>>>,
     buffer: BytesMut,
+    session: Session,
 }
 
 impl Connection {
@@ -37,6 +83,7 @@ impl Connection {
             socket_addr,
             stream,
             buffer: BytesMut::with_capacity(CHUNK_SIZE),
+            session: Session::new(),
         }
     }
 
@@ -55,771 +102,507 @@ impl Connection {
             tokio::select! {
                 //while let Ok(num_bytes) = self.stream.read_buf(&mut self.buffer).await {
                 Some(msg) = rx.recv() => {
-                    self.write(vec![msg]).await;
+                    self.write(&msg).await;
                 }
                 network_read_result = self.stream.read_buf(&mut self.buffer) => {
-                    if let Ok(num_bytes_read) = network_read_result {
-                        if num_bytes_read == 0 {
-                            if self.buffer.is_empty() {
-                                return Ok(());
-                            } else {
-                                return Err(RespError::Invalid);
-                            }
+                    // A failed read, such as a reset, ends the connection like a hang-up.
+                    let Ok(num_bytes_read) = network_read_result else {
+                        return Ok(());
+                    };
+                    if num_bytes_read == 0 {
+                        if self.buffer.is_empty() {
+                            return Ok(());
+                        } else {
+                            return Err(RespError::Invalid);
                         }
-                        let str_from_network = self.buffer[..num_bytes_read].to_vec();
+                    }
+                    // A single read may carry several pipelined commands, or only
+                    // part of one. Run every complete frame and keep the rest
+                    // buffered for the next read.
+                    let mut output = BytesMut::new();
+                    loop {
+                        let (frame, frame_len) = match RespData::decode_request(&self.buffer) {
+                            Ok(Some(decoded)) => decoded,
+                            Ok(None) => break,
+                            Err(e) => {
+                                RespData::ErrorStr(e.message()).encode(&mut output, self.session.protocol);
+                                self.write(&output).await;
+                                return Err(e);
+                            }
+                        };
+                        let raw_frame = self.buffer.split_to(frame_len);
                         let responses = process_socket_read(
-                            &str_from_network, self.state.clone(), self.socket_addr, tx.clone(), &mut identify_replica).await?;
-                        self.write(responses).await;
-                        self.buffer.clear();
+                            frame, &raw_frame, self.state.clone(), self.socket_addr, tx.clone(), &mut identify_replica, &mut self.session, &self.stream).await?;
+                        for response in responses {
+                            response.encode(&mut output, self.session.protocol);
+                        }
                     }
+                    self.write(&output).await;
                 }
             }
         }
     }
 
-    pub async fn write(&mut self, message: Vec<Vec<u8>>) {
-        for content in message {
-            if let Err(e) = self.stream.write_all(&content).await {
-                log::error!("Writing to TCP stream failed! {}", e);
-            }
-            if let Err(e) = self.stream.flush().await {
-                log::error!("Writing to TCP stream failed! {}", e);
-            }
+    pub async fn write(&mut self, content: &[u8]) {
+        if content.is_empty() {
+            return;
+        }
+        if let Err(e) = self.stream.write_all(content).await {
+            log::error!("Writing to TCP stream failed! {}", e);
+        }
+        if let Err(e) = self.stream.flush().await {
+            log::error!("Writing to TCP stream failed! {}", e);
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process_socket_read(
+    frame: RespData,
     str_from_network: &[u8],
     state: Arc<SharedState>,
     socket_addr: SocketAddr,
     tx: UnboundedSender<Vec<u8>>,
     identify_replica: &mut Vec<(SocketAddr, String)>,
-) -> anyhow::Result<Vec<Vec<u8>>, RespError> {
-    let mut responses: Vec<Vec<u8>> = Vec::new();
-
-    //let mut client_lock = state.clients.write().await;
-    //let client_handle = client_lock.get_mut(&socket_addr).unwrap();
-    //if client_handle.multi_lock.load(Relaxed) {
-    //    client_handle
-    //        .multi_queue
-    //        .lock()
-    //        .await
-    //        .push_back(str_from_network.to_vec());
-    //    responses.push(format!("+QUEUED{}", CRLF).as_bytes().to_vec());
-    //    return Ok(responses);
-    //}
+    session: &mut Session,
+    client: &TcpStream,
+) -> anyhow::Result<Vec<RespData>, RespError> {
+    let mut responses: Vec<RespData> = Vec::new();
 
     let s = String::from_utf8_lossy(str_from_network).to_string();
-    let resp_parsed = if let Ok(resp_parsed) = RespData::parse(&s) {
-        resp_parsed
-    } else {
+
+    let RespData::Array(v) = frame else {
         return Err(RespError::Invalid);
     };
 
-    let mut resp_parsed_iter = resp_parsed.iter();
+    let parsed = parse_command(v);
+    if let Some(reply) = queue_in_multi(&state, socket_addr, &parsed, str_from_network).await {
+        responses.push(reply);
+        return Ok(responses);
+    }
 
-    while let Some(parsed) = resp_parsed_iter.next() {
-        if let RespData::Array(v) = parsed {
-            match parse_command(v.to_vec()) {
-                Ok(res) => match res {
-                    Command::Ping(o) => {
-                        if o.value.is_some() {
-                            responses
-                                .push(format!("+{}{}", o.value.unwrap(), CRLF).as_bytes().to_vec());
-                        } else {
-                            responses.push(format!("+PONG{}", CRLF).as_bytes().to_vec());
-                        }
-                        if identify_replica.is_empty() {
-                            identify_replica.push((socket_addr, s.clone()));
-                        }
-                    }
-                    Command::Echo(o) => {
-                        if o.value.is_some() {
-                            responses
-                                .push(format!("+{}{}", o.value.unwrap(), CRLF).as_bytes().to_vec());
-                        } else {
-                            responses.push(
-                                format!(
-                                    "-Error ERR wrong number of arguments for 'echo' command{}",
-                                    CRLF
-                                )
-                                .as_bytes()
-                                .to_vec(),
-                            );
-                        }
-                    }
-                    Command::Multi(_o) => {
-                        let mut client_lock = state.clients.write().await;
-                        if let Some(client) = client_lock.get_mut(&socket_addr) {
-                            client
-                                .multi_lock
-                                .store(true, std::sync::atomic::Ordering::Relaxed);
-                        }
-                        responses.push(format!("+OK{}", CRLF).as_bytes().to_vec());
-                    }
-                    Command::Discard(o) => {
-                        let mut client_lock = state.clients.write().await;
-                        if let Some(client_handle) = client_lock.get_mut(&socket_addr) {
-                            if !client_handle.multi_lock.load(Relaxed) {
-                                responses.push(
-                                    format!("-ERR DISCARD without MULTI{}", CRLF)
-                                        .as_bytes()
-                                        .to_vec(),
-                                );
-                            } else {
-                                let mut queue_lock = client_handle.multi_queue.lock().await;
-                                // if queue_lock.len() > 0 {
-                                queue_lock.clear();
-                                responses.push("+OK\r\n".to_string().as_bytes().to_vec());
-                                //} else {
-                                //    responses.push("*0\r\n".to_string().as_bytes().to_vec());
-                                //}
-                                drop(queue_lock);
-                            }
-                            client_handle.multi_lock.store(false, Relaxed);
-                        }
-                        drop(client_lock);
+    match parsed {
+        Ok(res) => match res {
+            Command::Ping(o) => {
+                responses.push(ping(o));
+                if identify_replica.is_empty() {
+                    identify_replica.push((socket_addr, s.clone()));
+                }
+            }
+            Command::Echo(o) => responses.push(echo(o)),
+            Command::Hello(o) => responses.push(hello(o, session)),
+            Command::Select(o) => {
+                let count = state.kv_store.lock().await.dbs.len();
+                responses.push(handlers::select(o, &mut session.db, count));
+            }
+            Command::Multi(_o) => {
+                let client_lock = state.clients.read().await;
+                match client_lock.get(&socket_addr) {
+                    Some(client) if client.multi_lock.load(Relaxed) => responses.push(
+                        RespData::ErrorStr("ERR MULTI calls can not be nested".into()),
+                    ),
+                    Some(client) => {
+                        client.multi_lock.store(true, Relaxed);
+                        responses.push(RespData::ok());
                     }
-                    Command::Exec(o) => {
-                        let mut client_lock = state.clients.write().await;
-                        if let Some(client_handle) = client_lock.get_mut(&socket_addr) {
-                            if !client_handle.multi_lock.load(Relaxed) {
-                                responses.push(
-                                    format!("-ERR EXEC without MULTI{}", CRLF)
-                                        .as_bytes()
-                                        .to_vec(),
-                                );
-                            } else {
-                                let mut queue_lock = client_handle.multi_queue.lock().await;
-                                if queue_lock.len() > 0 {
-                                    responses.push(
-                                        format!("*{}{}", queue_lock.len(), CRLF)
-                                            .as_bytes()
-                                            .to_vec(),
-                                    );
-                                    while let Some(cmd) = queue_lock.pop_front() {
-                                        match cmd {
-                                            Command::Get(o) => {
-                                                let key = o.key.clone();
-                                                if let Some(value) = state.kv_store_get(&key).await
-                                                {
-                                                    responses.push(
-                                                        format!(
-                                                            "${}{}{}{}",
-                                                            &value.len().to_string(),
-                                                            CRLF,
-                                                            &value,
-                                                            CRLF
-                                                        )
-                                                        .as_bytes()
-                                                        .to_vec(),
-                                                    );
-                                                } else {
-                                                    responses.push(
-                                                        format!("$-1{}", CRLF).as_bytes().to_vec(),
-                                                    );
-                                                }
-                                            }
-                                            Command::Set(o) => {
-                                                let key = o.key;
-                                                let value = o.value;
-                                                let expiry = o.expiry;
-                                                state
-                                                    .kv_store_insert(
-                                                        key.clone(),
-                                                        value.clone(),
-                                                        expiry,
-                                                    )
-                                                    .await;
-                                                responses.push(
-                                                    format!("+OK{}", CRLF).as_bytes().to_vec(),
-                                                );
-                                                // replicate data to peers
-                                                state
-                                                    .broadcast_peers(str_from_network.to_vec())
-                                                    .await;
-                                            }
-                                            Command::Incr(o) => {
-                                                let mut invalid: bool = false;
-                                                let key = o.key;
-                                                let new_value = if let Some(value) =
-                                                    state.kv_store_get(&key).await
-                                                {
-                                                    let mut new_value = 0i64;
-                                                    if let Ok(value) = value.parse::<i64>() {
-                                                        new_value = value + 1;
-                                                    } else {
-                                                        responses.push(
-                                                            format!("-ERR value is not an integer or out of range{}", CRLF)
-                                                            .as_bytes()
-                                                            .to_vec(),
-                                                        );
-                                                        invalid = true;
-                                                    }
-                                                    new_value
-                                                } else {
-                                                    1i64
-                                                };
-
-                                                if !invalid {
-                                                    state
-                                                        .kv_store
-                                                        .insert(
-                                                            key.clone(),
-                                                            new_value.to_string(),
-                                                            None,
-                                                        )
-                                                        .await;
-                                                    responses.push(
-                                                        format!(":{}{}", new_value, CRLF)
-                                                            .as_bytes()
-                                                            .to_vec(),
-                                                    );
-                                                }
-                                                // replicate data to peers
-                                                state
-                                                    .broadcast_peers(str_from_network.to_vec())
-                                                    .await;
-                                            }
-                                            _ => {}
-                                        }
-                                    }
-                                } else {
-                                    responses.push("*0\r\n".to_string().as_bytes().to_vec());
-                                }
-                                drop(queue_lock);
+                    None => responses.push(RespData::ok()),
+                }
+            }
+            Command::Discard(_o) => match take_transaction(&state, socket_addr).await {
+                Some(_) => responses.push(RespData::ok()),
+                None => responses.push(RespData::ErrorStr("ERR DISCARD without MULTI".into())),
+            },
+            Command::Exec(_o) => match take_transaction(&state, socket_addr).await {
+                None => responses.push(RespData::ErrorStr("ERR EXEC without MULTI".into())),
+                Some((_, true)) => responses.push(RespData::ErrorStr(
+                    "EXECABORT Transaction discarded because of previous errors.".into(),
+                )),
+                Some((queue, false)) => {
+                    let mut transaction = handlers::Transaction::begin(&state).await;
+                    let mut results: Vec<RespData> = Vec::new();
+                    for (cmd, raw_frame) in queue {
+                        results.push(match cmd {
+                            Command::Ping(o) => ping(o),
+                            Command::Echo(o) => echo(o),
+                            Command::Hello(o) => hello(o, session),
+                            Command::Select(o) => {
+                                let count = transaction.databases().len();
+                                handlers::select(o, &mut session.db, count)
                             }
-                            client_handle.multi_lock.store(false, Relaxed);
-                        }
-                        drop(client_lock);
+                            Command::Config(o) => config(o),
+                            Command::Info(o) => info(o, session.protocol),
+                            Command::Save(_o) => save(transaction.databases()),
+                            cmd => transaction.run(cmd, &raw_frame, session.db, session.protocol),
+                        });
                     }
-                    Command::Get(o) => {
-                        let mut is_multi = false;
-                        let key = o.key.clone();
-                        // first check if a 'multi' execution is going on from the same client
-                        let mut client_lock = state.clients.write().await;
-                        if let Some(client_handle) = client_lock.get_mut(&socket_addr) {
-                            if client_handle.multi_lock.load(Relaxed) {
-                                client_handle
-                                    .multi_queue
-                                    .lock()
-                                    .await
-                                    .push_back(Command::Get(o));
-                                responses.push(format!("+QUEUED{}", CRLF).as_bytes().to_vec());
-                                is_multi = true;
-                            }
-                        }
+                    transaction.commit().await;
+                    responses.push(RespData::Array(results));
+                }
+            },
+            cmd @ (Command::Bpop(_)
+            | Command::Blmove(_)
+            | Command::Blmpop(_)
+            | Command::Bzpop(_)
+            | Command::Bzmpop(_)
+            | Command::Xread(_)) => {
+                let hang_up = hang_up(client);
+                responses.push(
+                    handlers::run_blocking(
+                        cmd,
+                        str_from_network,
+                        &state,
+                        session.db,
+                        session.protocol,
+                        hang_up,
+                    )
+                    .await,
+                );
+            }
+            Command::Config(o) => responses.push(config(o)),
+            Command::Save(_o) => {
+                let keyspace = state.kv_store.lock().await;
+                responses.push(save(&keyspace.dbs));
+            }
+            Command::Info(o) => responses.push(info(o, session.protocol)),
+            Command::Replconf(o) => {
+                let args = o.args;
+                let mut args_iter = args.iter();
+                let first = args_iter.next().expect("First cannot be empty");
 
-                        if !is_multi {
-                            if let Some(value) = state.kv_store_get(&key).await {
-                                responses.push(
-                                    format!(
-                                        "${}{}{}{}",
-                                        &value.len().to_string(),
-                                        CRLF,
-                                        &value,
-                                        CRLF
-                                    )
-                                    .as_bytes()
-                                    .to_vec(),
-                                );
-                            } else {
-                                responses.push(format!("$-1{}", CRLF).as_bytes().to_vec());
-                            }
+                match first.to_ascii_lowercase().as_str() {
+                    "capa" => {
+                        if args_iter.next() == Some(&"psync2".to_string()) {
+                            responses.push(RespData::ok())
                         }
-                        drop(client_lock);
-                    }
-                    Command::Set(o) => {
-                        let key = o.key.clone();
-                        let value = o.value.clone();
-                        let expiry = o.expiry;
-                        let mut client_lock = state.clients.write().await;
-                        let client_handle = client_lock.get_mut(&socket_addr).unwrap();
-                        if !client_handle.multi_lock.load(Relaxed) {
-                            state
-                                .kv_store_insert(key.clone(), value.clone(), expiry)
-                                .await;
-                            responses.push(format!("+OK{}", CRLF).as_bytes().to_vec());
-                            // replicate data to peers
-                            state.broadcast_peers(str_from_network.to_vec()).await;
-                        } else {
-                            client_handle
-                                .multi_queue
-                                .lock()
-                                .await
-                                .push_back(Command::Set(o));
-                            responses.push(format!("+QUEUED{}", CRLF).as_bytes().to_vec());
-                        }
-                        drop(client_lock);
-                    }
-                    Command::Incr(o) => {
-                        let mut invalid: bool = false;
-                        let key = o.key.clone();
-                        let mut client_lock = state.clients.write().await;
-                        let client_handle = client_lock.get_mut(&socket_addr).unwrap();
-                        if !client_handle.multi_lock.load(Relaxed) {
-                            let new_value = if let Some(value) = state.kv_store_get(&key).await {
-                                let mut new_value = 0i64;
-                                if let Ok(value) = value.parse::<i64>() {
-                                    new_value = value + 1;
-                                } else {
-                                    responses.push(
-                                        format!(
-                                            "-ERR value is not an integer or out of range{}",
-                                            CRLF
-                                        )
-                                        .as_bytes()
-                                        .to_vec(),
-                                    );
-                                    invalid = true;
+                        if identify_replica.len() == 2 {
+                            if let Some(t) = identify_replica.last() {
+                                if t.0 == socket_addr
+                                    && t.1.to_ascii_lowercase().contains("replconf")
+                                {
+                                    identify_replica.push((socket_addr, s.clone()));
                                 }
-                                new_value
-                            } else {
-                                1i64
-                            };
-
-                            if !invalid {
-                                state
-                                    .kv_store
-                                    .insert(key.clone(), new_value.to_string(), None)
-                                    .await;
-                                responses
-                                    .push(format!(":{}{}", new_value, CRLF).as_bytes().to_vec());
                             }
-                            // replicate data to peers
-                            state.broadcast_peers(str_from_network.to_vec()).await;
-                        } else {
-                            client_handle
-                                .multi_queue
-                                .lock()
-                                .await
-                                .push_back(Command::Incr(o));
-                            responses.push(format!("+QUEUED{}", CRLF).as_bytes().to_vec());
                         }
                     }
-                    Command::Config(o) => {
-                        match o.sub_command {
-                            SubCommand::Get(pattern) => {
-                                if let Some(res) = STATE.get_val(&pattern) {
-                                    // *2\r\n$3\r\ndir\r\n$16\r\n/tmp/redis-files\r\n
-                                    responses.push(
-                                        format!(
-                                            "*2{}${}{}{}{}${}{}{}{}",
-                                            CRLF,
-                                            pattern.len(),
-                                            CRLF,
-                                            pattern,
-                                            CRLF,
-                                            res.len(),
-                                            CRLF,
-                                            res,
-                                            CRLF
-                                        )
-                                        .as_bytes()
-                                        .to_vec(),
-                                    );
+                    "listening-port" => {
+                        let port = args_iter.next().expect("Expect a valid port number");
+                        if let Ok(_port) = port.parse::<u16>() {
+                            responses.push(RespData::ok());
+                        }
+                        if identify_replica.len() == 1 {
+                            if let Some(t) = identify_replica.last() {
+                                if t.0 == socket_addr && t.1.to_ascii_lowercase().contains("ping") {
+                                    identify_replica.push((socket_addr, s.clone()));
                                 }
                             }
                         }
                     }
-                    Command::Save(_o) => {
-                        responses.push(format!("+OK{}", CRLF).as_bytes().to_vec());
-                        database::write_to_disk(state.kv_store.clone())
-                            .await
-                            .expect("Write failed")
-                    }
-                    Command::Keys(o) => {
-                        let _arg = o.arg;
-                        // *1\r\n$3\r\nfoo\r\n
-                        let mut response =
-                            format!("*{}{}", state.kv_store.get_ht_size().await, CRLF);
-                        for (key, _) in state.kv_store.iter().await {
-                            response.push_str(&format!("${}{}{}{}", key.len(), CRLF, key, CRLF));
-                        }
-                        responses.push(response.as_bytes().to_vec());
+                    "ack" => {
+                        let bytes_written = args_iter.next().expect("Expect a valid entry");
+                        let bytes_written = bytes_written
+                            .parse::<usize>()
+                            .expect("expect a valid number as bytes");
+                        state
+                            .update_peers_bytes_written(socket_addr, bytes_written)
+                            .await;
                     }
-                    Command::Info(o) => match o.sub_command {
-                        Some(InfoSubCommand::Replication) => {
-                            if let Some(_leader_addr) = STATE.get_val(&"LEADER".to_string()) {
-                                responses.push(
-                                    format!(
-                                        "${}{}{}{}",
-                                        "role:slave".len(),
-                                        CRLF,
-                                        "role:slave",
-                                        CRLF,
-                                    )
-                                    .as_bytes()
-                                    .to_vec(),
-                                );
-                            } else {
-                                let master_replid = if let Some(master_replid) =
-                                    STATE.get_val(&"master_replid".into())
-                                {
-                                    master_replid.to_owned()
-                                } else {
-                                    "".to_string()
-                                };
+                    _ => {}
+                }
+            }
+            Command::Psync(o) => {
+                let args = o.args;
+                let mut args_iter = args.iter();
+                if args_iter.next() == Some(&"?".to_string())
+                    && args_iter.next() == Some(&"-1".to_string())
+                {
+                    let repl_id = STATE
+                        .get_val(&"master_replid".to_string())
+                        .expect("Expect a valid replication id");
 
-                                let master_repl_offset = if let Some(master_repl_offset) =
-                                    STATE.get_val(&"master_repl_offset".into())
-                                {
-                                    master_repl_offset.to_owned()
-                                } else {
-                                    "".to_string()
-                                };
+                    responses.push(RespData::String(format!("FULLRESYNC {} 0", repl_id)));
 
-                                let data = format!("role:master{CRLF}master_replid:{master_replid}{CRLF}master_repl_offset:{master_repl_offset}");
+                    // The snapshot is framed like a bulk string without the trailing
+                    // CRLF. It goes through the replica's outgoing channel, which is
+                    // drained only after the `+FULLRESYNC` reply has been written.
+                    let mut rdb_payload = format!("${}\r\n", EMPTY_RDB.len()).into_bytes();
+                    rdb_payload.extend(EMPTY_RDB);
+                    let _ = tx.send(rdb_payload);
 
-                                responses.push(
-                                    format!("${}{}{}{}", data.len(), CRLF, data, CRLF,)
-                                        .as_bytes()
-                                        .to_vec(),
-                                );
-                            }
-                        }
-                        None => {}
-                    },
-                    Command::Replconf(o) => {
-                        let args = o.args;
-                        let mut args_iter = args.iter();
-                        let first = args_iter.next().expect("First cannot be empty");
-
-                        match first.to_ascii_lowercase().as_str() {
-                            "capa" => {
-                                if args_iter.next() == Some(&"psync2".to_string()) {
-                                    responses.push(format!("+OK{}", CRLF).as_bytes().to_vec())
-                                }
-                                if identify_replica.len() == 2 {
-                                    if let Some(t) = identify_replica.last() {
-                                        if t.0 == socket_addr
-                                            && t.1.to_ascii_lowercase().contains("replconf")
-                                        {
-                                            identify_replica.push((socket_addr, s.clone()));
-                                        }
-                                    }
-                                }
-                            }
-                            "listening-port" => {
-                                let port = args_iter.next().expect("Expect a valid port number");
-                                if let Ok(_port) = port.parse::<u16>() {
-                                    responses.push(format!("+OK{}", CRLF).as_bytes().to_vec());
-                                }
-                                if identify_replica.len() == 1 {
-                                    if let Some(t) = identify_replica.last() {
-                                        if t.0 == socket_addr
-                                            && t.1.to_ascii_lowercase().contains("ping")
-                                        {
-                                            identify_replica.push((socket_addr, s.clone()));
-                                        }
-                                    }
-                                }
-                            }
-                            "ack" => {
-                                let bytes_written = args_iter.next().expect("Expect a valid entry");
-                                let bytes_written = bytes_written
-                                    .parse::<usize>()
-                                    .expect("expect a valid number as bytes");
-                                state
-                                    .update_peers_bytes_written(socket_addr, bytes_written)
-                                    .await;
-                            }
-                            _ => {}
-                        }
-                    }
-                    Command::Psync(o) => {
-                        let args = o.args;
-                        let mut args_iter = args.iter();
-                        if args_iter.next() == Some(&"?".to_string())
-                            && args_iter.next() == Some(&"-1".to_string())
-                        {
-                            let repl_id = STATE
-                                .get_val(&"master_replid".to_string())
-                                .expect("Expect a valid replication id");
-
-                            responses.push(
-                                format!("+FULLRESYNC {} 0{}", repl_id, CRLF)
-                                    .as_bytes()
-                                    .to_vec(),
-                            );
-                            let rdb_contents = [
-                                82, 69, 68, 73, 83, 48, 48, 49, 49, 250, 9, 114, 101, 100, 105,
-                                115, 45, 118, 101, 114, 5, 55, 46, 50, 46, 48, 250, 10, 114, 101,
-                                100, 105, 115, 45, 98, 105, 116, 115, 192, 64, 250, 5, 99, 116,
-                                105, 109, 101, 194, 5, 28, 228, 102, 250, 8, 117, 115, 101, 100,
-                                45, 109, 101, 109, 194, 184, 75, 14, 0, 250, 8, 97, 111, 102, 45,
-                                98, 97, 115, 101, 192, 0, 255, 187, 243, 46, 0, 102, 82, 8, 22,
-                            ];
-                            let mut res = format!("${}{}", rdb_contents.len(), CRLF)
-                                .as_bytes()
-                                .to_vec();
-                            res.extend(rdb_contents);
-                            responses.push(res);
-                            let tx = tx.clone();
-
-                            if identify_replica.len() == 3 {
-                                if let Some(t) = identify_replica.last() {
-                                    if t.0 == socket_addr
-                                        && t.1.to_ascii_lowercase().contains("replconf")
-                                    // means the connected client is a replica instance.
-                                    {
-                                        identify_replica.push((socket_addr, s.clone()));
-                                        let peer = Peer {
-                                            sender: tx,
-                                            bytes_sent: AtomicUsize::new(0),
-                                            bytes_written: AtomicUsize::new(0),
-                                            commands_processed: VecDeque::with_capacity(5),
-                                        };
-                                        state.insert_peer(socket_addr, peer).await;
-                                    }
-                                }
+                    if identify_replica.len() == 3 {
+                        if let Some(t) = identify_replica.last() {
+                            if t.0 == socket_addr && t.1.to_ascii_lowercase().contains("replconf")
+                            // means the connected client is a replica instance.
+                            {
+                                identify_replica.push((socket_addr, s.clone()));
+                                let peer = Peer {
+                                    sender: tx.clone(),
+                                    bytes_sent: AtomicUsize::new(0),
+                                    bytes_written: AtomicUsize::new(0),
+                                    commands_processed: VecDeque::with_capacity(5),
+                                };
+                                // The replica starts out in database 0, so
+                                // the next write elsewhere is preceded by a
+                                // SELECT. Holding the keyspace keeps writes
+                                // from going out in between.
+                                let mut keyspace = state.kv_store.lock().await;
+                                keyspace.replicated_db = 0;
+                                state.insert_peer(socket_addr, peer).await;
+                                drop(keyspace);
                             }
                         }
                     }
-                    Command::Type(o) => {
-                        let key = o.key;
-                        if let Some(_value) = state.kv_store_get(&key).await {
-                            responses.push(format!("+string{}", CRLF,).as_bytes().to_vec());
-                        } else if state.stream_store.check_key(&key).await.is_some() {
-                            responses.push(format!("+stream{}", CRLF,).as_bytes().to_vec());
-                        } else {
-                            responses.push(format!("+none{}", CRLF).as_bytes().to_vec());
-                        }
-                    }
+                }
+            }
+            Command::Wait(o) => {
+                let args = o.args;
+                let mut args_iter = args.iter();
+                let numreplicas = args_iter
+                    .next()
+                    .expect("`numreplicas` cannot be empty")
+                    .parse::<usize>()
+                    .expect("`numreplicas` should be number");
+                let timeout = args_iter.next().expect("`timeout` cannot be empty");
+                let timeout = timeout
+                    .parse::<u64>()
+                    .expect("`timeout` should be a number");
 
-                    Command::Wait(o) => {
-                        let args = o.args;
-                        let mut args_iter = args.iter();
-                        let numreplicas = args_iter
-                            .next()
-                            .expect("`numreplicas` cannot be empty")
-                            .parse::<usize>()
-                            .expect("`numreplicas` should be number");
-                        let timeout = args_iter.next().expect("`timeout` cannot be empty");
-                        let timeout = timeout
-                            .parse::<u64>()
-                            .expect("`timeout` should be a number");
-
-                        let n = if numreplicas == 0 {
-                            state.peers.read().await.len()
-                        } else {
-                            let msg = "*3\r\n$8\r\nREPLCONF\r\n$6\r\nGETACK\r\n$1\r\n*\r\n"
-                                .as_bytes()
-                                .to_vec();
-                            let offset_len = msg.len();
-                            //let mut state = state.lock().await;
-                            state.broadcast_peers(msg).await;
-                            time::sleep(Duration::from_millis(timeout)).await;
-                            let n = if state.count_peers_commands_processed().await == 0 {
-                                state.peers.read().await.len()
-                            } else {
-                                state.verify_peers_propagation(offset_len).await
-                            };
-                            n
-                        };
-                        let res = format!(":{}{}", n, CRLF);
-                        responses.push(res.as_bytes().to_vec());
-                    }
-                    Command::Xadd(o) => {
-                        let mut res = String::new();
-                        let key = o.key;
-                        let entry_id = o.entry_id;
-                        let args = o.args;
-                        match state
-                            .stream_store_insert(key.as_str(), entry_id.as_str(), args)
-                            .await
-                        {
-                            Ok(entry_id) => {
-                                res.push_str(&format!(
-                                    "${}{}{}{}",
-                                    entry_id.len(),
-                                    CRLF,
-                                    entry_id,
-                                    CRLF
-                                ));
-                            }
-                            Err(e) => {
-                                let error_msg =
-                                    format!("-{}{}", e.chain().collect::<Vec<_>>()[0], CRLF);
-                                res.push_str(&error_msg);
-                            }
-                        }
-                        responses.push(res.as_bytes().to_vec());
-                    }
-                    Command::Xrange(o) => {
-                        let key = o.key.as_str();
-                        let start = o.start.as_str();
-                        let end = o.end.as_str();
-                        if let Ok(items_in_range) = state.stream_store.xrange(key, start, end).await
-                        {
-                            responses.extend(format_xrange_output(&items_in_range, "".to_string()));
-                        } else {
-                            responses.push("$-1\r\n".to_string().as_bytes().to_vec());
-                        }
+                let n = if numreplicas == 0 {
+                    state.peers.read().await.len()
+                } else {
+                    let msg = RespData::command(&["REPLCONF", "GETACK", "*"]).to_vec();
+                    let offset_len = msg.len();
+                    //let mut state = state.lock().await;
+                    state.broadcast_peers(msg).await;
+                    time::sleep(Duration::from_millis(timeout)).await;
+                    if state.count_peers_commands_processed().await == 0 {
+                        state.peers.read().await.len()
+                    } else {
+                        state.verify_peers_propagation(offset_len).await
                     }
-                    Command::Xread(o) => {
-                        let block = o.block;
-                        let keys = o.keys.clone();
-                        let entry_ids = o.entry_ids.clone();
-
-                        match block {
-                            Some(timeout) => {
-                                let resp_init_str = format!("*{}{}", keys.len(), CRLF);
-                                responses.push(resp_init_str.as_bytes().to_vec());
-                                for (key, entry_id) in keys.iter().zip(entry_ids.iter()) {
-                                    if let Ok(items_in_range) =
-                                        state.stream_store.xrange(key, entry_id, "++").await
-                                    {
-                                        let response_init_str = format!(
-                                            "*2{}${}{}{}{}",
-                                            CRLF,
-                                            key.len(),
-                                            CRLF,
-                                            key,
-                                            CRLF
-                                        );
-
-                                        responses.push(response_init_str.as_bytes().to_vec());
-                                        let t =
-                                            format_xrange_output(&items_in_range, "".to_string());
-                                        responses.extend(t);
-                                    } else {
-                                        //dbg!(&o);
-                                        match state
-                                            .stream_store
-                                            .check_availability(timeout, entry_id.as_str())
-                                            .await
-                                        {
-                                            Some((last_entry_id, _new_entry_id)) => {
-                                                let entry_id = if entry_id == "$" {
-                                                    last_entry_id.as_str()
-                                                } else {
-                                                    entry_id
-                                                };
-                                                if let Ok(items_in_range) = state
-                                                    .stream_store
-                                                    .xrange(key, entry_id, "++")
-                                                    .await
-                                                {
-                                                    let response_init_str = format!(
-                                                        "*2{}${}{}{}{}",
-                                                        CRLF,
-                                                        key.len(),
-                                                        CRLF,
-                                                        key,
-                                                        CRLF
-                                                    );
-
-                                                    dbg!(&response_init_str);
-
-                                                    responses.push(
-                                                        response_init_str.as_bytes().to_vec(),
-                                                    );
-                                                    let t = format_xrange_output(
-                                                        &items_in_range,
-                                                        "".to_string(),
-                                                    );
-                                                    responses.extend(t);
-                                                }
-                                            }
-                                            None => {
-                                                dbg!("None");
-                                                responses.clear();
-                                                responses
-                                                    .push("$-1\r\n".to_string().as_bytes().to_vec())
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            None => {
-                                let response_init_str = format!("*{}{}", keys.len(), CRLF);
-                                responses.push(response_init_str.as_bytes().to_vec());
-                                for (key, entry_id) in keys.iter().zip(entry_ids.iter()) {
-                                    let response_init_str =
-                                        format!("*2{}${}{}{}{}", CRLF, key.len(), CRLF, key, CRLF);
-                                    responses.push(response_init_str.as_bytes().to_vec());
-                                    if let Ok(items_in_range) =
-                                        state.stream_store.xrange(key, entry_id, "++").await
-                                    {
-                                        responses.extend(format_xrange_output(
-                                            &items_in_range,
-                                            "".to_string(),
-                                        ))
-                                    } else {
-                                        responses.clear();
-                                        responses.push("$-1\r\n".to_string().as_bytes().to_vec())
-                                    }
-                                }
-                            }
-                        }
-                    }
-                },
-                Err(e) => match e.clone() {
-                    CommandError::SyntaxError(_n) => {
-                        responses.push(format!("-{}{}", &e.message(), CRLF).as_bytes().to_vec());
-                    }
-                    CommandError::WrongNumberOfArguments(_n) => {
-                        responses.push(format!("-{}{}", &e.message(), CRLF).as_bytes().to_vec());
-                    }
-                    CommandError::NotSupported => {
-                        responses.push(format!("-{}{}", &e.message(), CRLF).as_bytes().to_vec());
-                    }
-                    CommandError::NotValidType(_x) => {
-                        responses.push(format!("-{}{}", &e.message(), CRLF).as_bytes().to_vec());
-                    }
-                    CommandError::UnknownSubCommand(_x) => {
-                        responses.push(format!("-{}{}", &e.message(), CRLF).as_bytes().to_vec());
-                    }
-                },
-            };
-        } else {
-            return Err(RespError::Invalid);
+                };
+                responses.push(RespData::Integer(n as i64));
+            }
+            cmd => {
+                responses.push(
+                    handlers::run(cmd, str_from_network, &state, session.db, session.protocol)
+                        .await,
+                );
+            }
+        },
+        Err(e) => {
+            responses.push(RespData::ErrorStr(e.message()));
         }
-    }
+    };
     Ok(responses)
 }
 
-fn format_xrange_output(items_in_range: &Vec<StreamEntry>, resp_init_str: String) -> Vec<Vec<u8>> {
-    let mut responses: Vec<Vec<u8>> = Vec::new();
-    if !items_in_range.is_empty() {
-        let mut response = if resp_init_str.is_empty() {
-            format!("*{}{}", items_in_range.len(), CRLF)
-        } else {
-            format!("{}*{}{}", resp_init_str, items_in_range.len(), CRLF)
-        };
-
-        for entry in items_in_range {
-            let data_len = entry.data.len() * 2;
-            response.push_str(&format!(
-                "*2{}${}{}{}{}*{}{}",
-                CRLF,
-                &entry.entry_id.len(),
-                CRLF,
-                &entry.entry_id,
-                CRLF,
-                &data_len,
-                CRLF,
+/// Queues a command for EXEC when the client is inside MULTI, returning the
+/// reply to it then. One that failed to parse, or that cannot run inside a
+/// transaction, is not queued and makes EXEC discard the transaction.
+/// MULTI, EXEC and DISCARD themselves are left to the caller.
+async fn queue_in_multi(
+    state: &SharedState,
+    socket_addr: SocketAddr,
+    parsed: &Result<Command, CommandError>,
+    raw_frame: &[u8],
+) -> Option<RespData> {
+    let client_lock = state.clients.read().await;
+    let client_handle = client_lock
+        .get(&socket_addr)
+        .filter(|client_handle| client_handle.multi_lock.load(Relaxed))?;
+    let cmd = match parsed {
+        Ok(Command::Multi(_) | Command::Exec(_) | Command::Discard(_)) => return None,
+        Ok(Command::Replconf(_) | Command::Psync(_) | Command::Wait(_)) => {
+            client_handle.multi_aborted.store(true, Relaxed);
+            return Some(RespData::ErrorStr(
+                "ERR Command not allowed inside a transaction".into(),
             ));
-            for (k, v) in entry.data.iter() {
-                response.push_str(&format!(
-                    "${}{}{}{}${}{}{}{}",
-                    k.len(),
-                    CRLF,
-                    k,
-                    CRLF,
-                    v.len(),
-                    CRLF,
-                    v,
-                    CRLF
-                ));
+        }
+        Ok(cmd) => cmd,
+        Err(e) => {
+            client_handle.multi_aborted.store(true, Relaxed);
+            return Some(RespData::ErrorStr(e.message()));
+        }
+    };
+    client_handle
+        .multi_queue
+        .lock()
+        .await
+        .push_back((cmd.clone(), Bytes::copy_from_slice(raw_frame)));
+    Some(RespData::String("QUEUED".into()))
+}
+
+/// Ends the client's MULTI, returning the commands it queued and whether
+/// one failed to. `None` when it was not in one.
+async fn take_transaction(
+    state: &SharedState,
+    socket_addr: SocketAddr,
+) -> Option<(VecDeque<(Command, Bytes)>, bool)> {
+    let client_lock = state.clients.read().await;
+    let client_handle = client_lock.get(&socket_addr)?;
+    if !client_handle.multi_lock.swap(false, Relaxed) {
+        return None;
+    }
+    let queue = std::mem::take(&mut *client_handle.multi_queue.lock().await);
+    Some((queue, client_handle.multi_aborted.swap(false, Relaxed)))
+}
+
+/// Resolves once the client closes the connection while a command of theirs
+/// is blocked. Input sent in the meantime stays unread, and once there is
+/// some a hang-up can no longer be told apart, so the wait goes on.
+async fn hang_up(client: &TcpStream) {
+    let mut buf = [0; 1];
+    match client.peek(&mut buf).await {
+        Ok(0) | Err(_) => {}
+        Ok(_) => std::future::pending().await,
+    }
+}
+
+/// PONG, or the argument as a bulk string, which unlike a simple string can
+/// hold any bytes.
+fn ping(o: Ping) -> RespData {
+    match o.value {
+        Some(value) => RespData::BulkStr(value),
+        None => RespData::String("PONG".into()),
+    }
+}
+
+fn echo(o: Echo) -> RespData {
+    match o.value {
+        Some(value) => RespData::BulkStr(value),
+        None => RespData::ErrorStr(CommandError::WrongNumberOfArguments("echo".into()).message()),
+    }
+}
+
+fn config(o: Config) -> RespData {
+    match o.sub_command {
+        SubCommand::Get(patterns) => {
+            // *2\r\n$3\r\ndir\r\n$16\r\n/tmp/redis-files\r\n
+            let mut pairs: Vec<(RespData, RespData)> = Vec::new();
+            for (name, state_key) in CONFIG_PARAMETERS {
+                // Parameter names match case insensitively.
+                if !patterns
+                    .iter()
+                    .any(|pattern| glob_match(pattern.as_bytes(), name.as_bytes(), true))
+                {
+                    continue;
+                }
+                if let Some(value) = STATE.get_val(&state_key.to_string()) {
+                    pairs.push((RespData::bulk(name), RespData::bulk(value)));
+                }
             }
+            RespData::Map(pairs)
+        }
+    }
+}
+
+/// INFO as `field:value` lines for RESP2 clients, and for RESP3 ones as a
+/// map of sections, each a map of its fields.
+fn info(o: Info, protocol: Protocol) -> RespData {
+    let sections = match o.sub_command {
+        Some(InfoSubCommand::Replication) | None => vec![("replication", replication_info())],
+    };
+    match protocol {
+        Protocol::Resp2 => {
+            let lines: Vec<String> = sections
+                .iter()
+                .flat_map(|(_, fields)| fields)
+                .map(|(field, value)| format!("{}:{}", field, value))
+                .collect();
+            RespData::VerbatimStr(lines.join("\r\n").into())
         }
-        responses.push(response.as_bytes().to_vec());
+        Protocol::Resp3 => RespData::Map(
+            sections
+                .into_iter()
+                .map(|(section, fields)| {
+                    let fields = fields
+                        .into_iter()
+                        .map(|(field, value)| (RespData::bulk(field), RespData::bulk(value)))
+                        .collect();
+                    (RespData::bulk(section), RespData::Map(fields))
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn save(dbs: &[KeyValueStore]) -> RespData {
+    match database::write_to_disk(dbs) {
+        Ok(()) => RespData::ok(),
+        Err(e) => RespData::ErrorStr(format!("ERR {}", e)),
+    }
+}
+
+fn hello(o: Hello, session: &mut Session) -> RespData {
+    if let Some((username, _password)) = o.auth {
+        // No ACLs are configured, so only the passwordless default user exists.
+        if username != "default" {
+            return RespData::ErrorStr(
+                "WRONGPASS invalid username-password pair or user is disabled.".into(),
+            );
+        }
+    }
+
+    // Every argument is checked before any is applied, so a rejected HELLO
+    // leaves the session as it was.
+    let protocol = match o.protover {
+        Some(2) => Some(Protocol::Resp2),
+        Some(3) => Some(Protocol::Resp3),
+        Some(_) => return RespData::ErrorStr("NOPROTO unsupported protocol version".into()),
+        None => None,
+    };
+    if let Some(name) = &o.setname {
+        if name.chars().any(|c| !c.is_ascii_graphic()) {
+            return RespData::ErrorStr(
+                "ERR Client names cannot contain spaces, newlines or special characters.".into(),
+            );
+        }
+    }
+
+    if let Some(protocol) = protocol {
+        session.protocol = protocol;
+    }
+    if let Some(name) = o.setname {
+        session.name = Some(name);
+    }
+
+    let proto = match session.protocol {
+        Protocol::Resp2 => 2,
+        Protocol::Resp3 => 3,
+    };
+    let role = if STATE.get_val(&"LEADER".to_string()).is_some() {
+        "replica"
     } else {
-        responses.push("$-1\r\n".to_string().as_bytes().to_vec());
+        "master"
+    };
+    RespData::Map(vec![
+        (RespData::bulk("server"), RespData::bulk("redis")),
+        (RespData::bulk("version"), RespData::bulk("7.2.0")),
+        (RespData::bulk("proto"), RespData::Integer(proto)),
+        (RespData::bulk("id"), RespData::Integer(session.id as i64)),
+        (RespData::bulk("mode"), RespData::bulk("standalone")),
+        (RespData::bulk("role"), RespData::bulk(role)),
+        (RespData::bulk("modules"), RespData::Array(Vec::new())),
+    ])
+}
+
+fn replication_info() -> Vec<(&'static str, String)> {
+    if STATE.get_val(&"LEADER".to_string()).is_some() {
+        return vec![("role", "slave".to_string())];
     }
 
-    responses
+    let master_replid = STATE
+        .get_val(&"master_replid".into())
+        .cloned()
+        .unwrap_or_default();
+    let master_repl_offset = STATE
+        .get_val(&"master_repl_offset".into())
+        .cloned()
+        .unwrap_or_default();
+
+    vec![
+        ("role", "master".to_string()),
+        ("master_replid", master_replid),
+        ("master_repl_offset", master_repl_offset),
+    ]
 }
@@ -0,0 +1,100 @@
+use std::collections::{HashMap, VecDeque};
+
+use bytes::Bytes;
+use tokio::sync::oneshot;
+
+use crate::{
+    cmds::Command,
+    resp::{Protocol, RespData},
+};
+
+/// A client parked on one or more keys until a write lets its command
+/// through.
+pub struct Waiter {
+    /// The database `keys` are in, the one the client had selected.
+    pub db: usize,
+    pub keys: Vec<Bytes>,
+    /// Retried, as a single non-blocking attempt, whenever one of `keys`
+    /// becomes ready.
+    pub command: Command,
+    pub protocol: Protocol,
+    pub reply: oneshot::Sender<RespData>,
+}
+
+/// Blocked clients by the keys they wait on, each key together with its
+/// database. Each key keeps its waiters in the order they blocked, so the
+/// longest waiting client is served first.
+#[derive(Default)]
+pub struct BlockedClients {
+    next_id: u64,
+    waiters: HashMap<u64, Waiter>,
+    by_key: HashMap<(usize, Bytes), VecDeque<u64>>,
+}
+
+impl BlockedClients {
+    pub fn block(
+        &mut self,
+        db: usize,
+        keys: Vec<Bytes>,
+        command: Command,
+        protocol: Protocol,
+    ) -> (u64, oneshot::Receiver<RespData>) {
+        let id = self.next_id;
+        self.next_id += 1;
+        for key in &keys {
+            self.by_key
+                .entry((db, key.clone()))
+                .or_default()
+                .push_back(id);
+        }
+        let (reply, receiver) = oneshot::channel();
+        self.waiters.insert(
+            id,
+            Waiter {
+                db,
+                keys,
+                command,
+                protocol,
+                reply,
+            },
+        );
+        (id, receiver)
+    }
+
+    /// Removes the waiter from every key it was registered on. `None` when
+    /// it was already served.
+    pub fn unblock(&mut self, id: u64) -> Option<Waiter> {
+        let waiter = self.waiters.remove(&id)?;
+        for key in &waiter.keys {
+            let key = (waiter.db, key.clone());
+            if let Some(ids) = self.by_key.get_mut(&key) {
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    self.by_key.remove(&key);
+                }
+            }
+        }
+        Some(waiter)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Waiter> {
+        self.waiters.get(&id)
+    }
+
+    /// Waiters on `key` of database `db`, oldest first.
+    pub fn waiting_on(&self, db: usize, key: &Bytes) -> Vec<u64> {
+        self.by_key
+            .get(&(db, key.clone()))
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// The keys of database `db` someone waits on.
+    pub fn keys_in(&self, db: usize) -> Vec<Bytes> {
+        self.by_key
+            .keys()
+            .filter(|(key_db, _)| *key_db == db)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use tokio::time::{self, Duration, Instant, MissedTickBehavior};
+
+use super::SharedState;
+use crate::handlers;
+
+/// How often the cycle runs, every 100ms like Redis at its default `hz`.
+const PERIOD: Duration = Duration::from_millis(100);
+/// How long a cycle may go on for, a quarter of the period.
+const TIME_BUDGET: Duration = Duration::from_millis(25);
+/// Percentage of expired keys in a round above which another round follows.
+const ACCEPTABLE_STALE: usize = 10;
+
+/// Reclaims expired keys and hash fields nobody looks up, which otherwise
+/// stay in memory for good. Each cycle runs expiry rounds until one finds
+/// few enough expired keys that the rest can wait, or the time budget is
+/// spent. A round samples every database, and replicas are told what it
+/// deleted. The keyspace is unlocked between rounds so clients get their
+/// turn.
+///
+/// Only leaders run it. Replicas delete keys when their leader does, so
+/// the two never disagree on which ones are there.
+pub async fn active_expire_cycle(state: Arc<SharedState>) {
+    let mut interval = time::interval(PERIOD);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let start = Instant::now();
+        loop {
+            let mut keyspace = state.kv_store.lock().await;
+            let (sampled, expired) = keyspace
+                .dbs
+                .iter_mut()
+                .map(|db| db.expire_round())
+                .fold((0, 0), |(sampled, expired), (s, e)| {
+                    (sampled + s, expired + e)
+                });
+            handlers::replicate_expired(&mut keyspace, &state).await;
+            drop(keyspace);
+            if expired * 100 <= sampled * ACCEPTABLE_STALE || start.elapsed() >= TIME_BUDGET {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+}
@@ -0,0 +1,448 @@
+/// Bits per coordinate in the scores geo members are kept under, which
+/// interleave to 52 and so fit a double exactly.
+const STEP: u32 = 26;
+const LON_MIN: f64 = -180.0;
+const LON_MAX: f64 = 180.0;
+/// Latitudes the Web Mercator projection covers.
+const LAT_MIN: f64 = -85.05112878;
+const LAT_MAX: f64 = 85.05112878;
+const EARTH_RADIUS: f64 = 6372797.560856;
+/// Half the circumference of the projected globe, in meters.
+const MERCATOR_MAX: f64 = 20037726.37;
+
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// A point on the globe, in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lon: f64,
+    pub lat: f64,
+}
+
+impl GeoPoint {
+    /// `None` for coordinates outside the area scores can encode.
+    pub fn new(lon: f64, lat: f64) -> Option<Self> {
+        ((LON_MIN..=LON_MAX).contains(&lon) && (LAT_MIN..=LAT_MAX).contains(&lat))
+            .then_some(Self { lon, lat })
+    }
+
+    /// The center of the cell a sorted set score stands for.
+    pub fn from_score(score: f64) -> Self {
+        Cell::new(score as u64, STEP).center()
+    }
+
+    pub fn score(self) -> f64 {
+        Cell::containing(self, STEP).bits as f64
+    }
+
+    /// The 11 character geohash of the standard geohash grid, which unlike
+    /// the scores spans latitudes up to the poles.
+    pub fn geohash(self) -> String {
+        let lat = (self.lat + 90.0) / 180.0;
+        let lon = (self.lon - LON_MIN) / (LON_MAX - LON_MIN);
+        let bits = interleave(
+            (lat * (1u64 << STEP) as f64) as u32,
+            (lon * (1u64 << STEP) as f64) as u32,
+        );
+        // 55 bits make 11 characters; the last three are zeros.
+        (0..11)
+            .map(|i| {
+                let index = match i {
+                    10 => 0,
+                    _ => (bits >> (52 - (i + 1) * 5)) & 0x1f,
+                };
+                BASE32[index as usize] as char
+            })
+            .collect()
+    }
+
+    /// Great-circle distance in meters, by the haversine formula.
+    pub fn distance(self, other: GeoPoint) -> f64 {
+        let v = ((other.lon.to_radians() - self.lon.to_radians()) / 2.0).sin();
+        if v == 0.0 {
+            return lat_distance(self.lat, other.lat);
+        }
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let u = ((lat2 - lat1) / 2.0).sin();
+        let a = u * u + lat1.cos() * lat2.cos() * v * v;
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+}
+
+/// The area a geo search covers around its center, in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+impl GeoShape {
+    /// How far `point` is from `center`, or `None` if it lies outside the
+    /// shape.
+    pub fn distance_within(self, center: GeoPoint, point: GeoPoint) -> Option<f64> {
+        match self {
+            GeoShape::Radius(radius) => {
+                Some(center.distance(point)).filter(|distance| *distance <= radius)
+            }
+            GeoShape::Box { width, height } => {
+                if lat_distance(point.lat, center.lat) > height / 2.0 {
+                    return None;
+                }
+                let along = GeoPoint {
+                    lon: center.lon,
+                    lat: point.lat,
+                };
+                if point.distance(along) > width / 2.0 {
+                    return None;
+                }
+                Some(center.distance(point))
+            }
+        }
+    }
+
+    /// Score ranges, each with an inclusive start and an exclusive end,
+    /// that hold every point of the shape and few others: the cell around
+    /// `center` and its neighbours, at a size just large enough.
+    pub fn score_ranges(self, center: GeoPoint) -> Vec<(f64, f64)> {
+        let (half_width, half_height, radius) = match self {
+            GeoShape::Radius(radius) => (radius, radius, radius),
+            GeoShape::Box { width, height } => {
+                (width / 2.0, height / 2.0, (width / 2.0).hypot(height / 2.0))
+            }
+        };
+        let lat_delta = (half_height / EARTH_RADIUS).to_degrees();
+        // Longitude degrees shrink away from the equator, so the box is
+        // widest on the side nearer the pole.
+        let lon_delta =
+            |lat: f64| (half_width / EARTH_RADIUS / lat.to_radians().cos()).to_degrees();
+        let lon_delta = if center.lat < 0.0 {
+            lon_delta(center.lat - lat_delta)
+        } else {
+            lon_delta(center.lat + lat_delta)
+        };
+        let (min_lon, max_lon) = (center.lon - lon_delta, center.lon + lon_delta);
+        let (min_lat, max_lat) = (center.lat - lat_delta, center.lat + lat_delta);
+
+        let mut step = estimate_step(radius, center.lat);
+        let mut cell = Cell::containing(center, step);
+        // A cell smaller than the box around the shape leaves parts of it
+        // beyond the neighbours.
+        let too_small = cell.moved(0, 1).area().max_lat < max_lat
+            || cell.moved(0, -1).area().min_lat > min_lat
+            || cell.moved(1, 0).area().max_lon < max_lon
+            || cell.moved(-1, 0).area().min_lon > min_lon;
+        if step > 1 && too_small {
+            step -= 1;
+            cell = Cell::containing(center, step);
+        }
+
+        let area = cell.area();
+        let mut cells = vec![cell];
+        for (east, north) in [
+            (0, 1),
+            (0, -1),
+            (1, 0),
+            (-1, 0),
+            (1, 1),
+            (-1, 1),
+            (1, -1),
+            (-1, -1),
+        ] {
+            // Neighbours on a side the box does not reach past the center
+            // cell are left out.
+            let useless = step >= 2
+                && ((north < 0 && area.min_lat < min_lat)
+                    || (north > 0 && area.max_lat > max_lat)
+                    || (east < 0 && area.min_lon < min_lon)
+                    || (east > 0 && area.max_lon > max_lon));
+            let neighbour = cell.moved(east, north);
+            // With cells this large, neighbours can wrap around to the same
+            // cell.
+            if !useless && !cells.contains(&neighbour) {
+                cells.push(neighbour);
+            }
+        }
+        cells
+            .into_iter()
+            .map(|cell| {
+                let shift = 2 * (STEP - cell.step);
+                (
+                    (cell.bits << shift) as f64,
+                    ((cell.bits + 1) << shift) as f64,
+                )
+            })
+            .collect()
+    }
+}
+
+/// A cell of the geohash grid: longitude and latitude halved `step` times
+/// each, the bits of the two interleaved with latitude's in the even places.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cell {
+    bits: u64,
+    step: u32,
+}
+
+impl Cell {
+    fn new(bits: u64, step: u32) -> Self {
+        Self { bits, step }
+    }
+
+    fn containing(point: GeoPoint, step: u32) -> Self {
+        let lat = (point.lat - LAT_MIN) / (LAT_MAX - LAT_MIN);
+        let lon = (point.lon - LON_MIN) / (LON_MAX - LON_MIN);
+        let cells = (1u64 << step) as f64;
+        Self::new(interleave((lat * cells) as u32, (lon * cells) as u32), step)
+    }
+
+    fn area(self) -> Area {
+        let (lat, lon) = deinterleave(self.bits);
+        let cells = (1u64 << self.step) as f64;
+        let edge = |index: u32, min: f64, max: f64| min + index as f64 / cells * (max - min);
+        Area {
+            min_lon: edge(lon, LON_MIN, LON_MAX),
+            max_lon: edge(lon + 1, LON_MIN, LON_MAX),
+            min_lat: edge(lat, LAT_MIN, LAT_MAX),
+            max_lat: edge(lat + 1, LAT_MIN, LAT_MAX),
+        }
+    }
+
+    fn center(self) -> GeoPoint {
+        let area = self.area();
+        GeoPoint {
+            lon: ((area.min_lon + area.max_lon) / 2.0).clamp(LON_MIN, LON_MAX),
+            lat: ((area.min_lat + area.max_lat) / 2.0).clamp(LAT_MIN, LAT_MAX),
+        }
+    }
+
+    /// The cell `east` cells to the east and `north` to the north, wrapping
+    /// around the edges of the grid.
+    fn moved(self, east: i8, north: i8) -> Self {
+        let width = 2 * self.step;
+        let mut bits = self.bits;
+        for (delta, places) in [
+            (east, 0xaaaa_aaaa_aaaa_aaaau64),
+            (north, 0x5555_5555_5555_5555),
+        ] {
+            if delta == 0 {
+                continue;
+            }
+            // The other coordinate's places, filled with ones, carry the
+            // step from one place of this coordinate to the next.
+            let others = !places >> (64 - width);
+            let coordinate = bits & places;
+            let coordinate = if delta > 0 {
+                coordinate.wrapping_add(others + 1)
+            } else {
+                (coordinate | others).wrapping_sub(others + 1)
+            };
+            bits = (bits & !places) | (coordinate & (places >> (64 - width)));
+        }
+        Self::new(bits, self.step)
+    }
+}
+
+struct Area {
+    min_lon: f64,
+    max_lon: f64,
+    min_lat: f64,
+    max_lat: f64,
+}
+
+/// The coarsest step whose cells, with their neighbours, still cover
+/// `radius` meters around a point at latitude `lat`.
+fn estimate_step(radius: f64, lat: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP;
+    }
+    let mut step: i32 = 1;
+    let mut range = radius;
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    step -= 2;
+    // Cells narrow towards the poles.
+    if lat.abs() > 66.0 {
+        step -= 1;
+        if lat.abs() > 80.0 {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP as i32) as u32
+}
+
+fn lat_distance(lat1: f64, lat2: f64) -> f64 {
+    EARTH_RADIUS * (lat2.to_radians() - lat1.to_radians()).abs()
+}
+
+/// Spreads `even` over the even bits and `odd` over the odd ones.
+fn interleave(even: u32, odd: u32) -> u64 {
+    spread(even) | spread(odd) << 1
+}
+
+fn deinterleave(bits: u64) -> (u32, u32) {
+    (squash(bits), squash(bits >> 1))
+}
+
+fn spread(value: u32) -> u64 {
+    let mut x = value as u64;
+    x = (x | x << 16) & 0x0000_ffff_0000_ffff;
+    x = (x | x << 8) & 0x00ff_00ff_00ff_00ff;
+    x = (x | x << 4) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | x << 2) & 0x3333_3333_3333_3333;
+    (x | x << 1) & 0x5555_5555_5555_5555
+}
+
+fn squash(bits: u64) -> u32 {
+    let mut x = bits & 0x5555_5555_5555_5555;
+    x = (x | x >> 1) & 0x3333_3333_3333_3333;
+    x = (x | x >> 2) & 0x0f0f_0f0f_0f0f_0f0f;
+    x = (x | x >> 4) & 0x00ff_00ff_00ff_00ff;
+    x = (x | x >> 8) & 0x0000_ffff_0000_ffff;
+    (x | x >> 16) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example members of the Redis GEO documentation, with the scores,
+    // positions, geohashes and distances Redis reports for them.
+    const PALERMO: (f64, f64) = (13.361389, 38.115556);
+    const CATANIA: (f64, f64) = (15.087269, 37.502669);
+
+    /// The point as stored: the center of the cell its score stands for.
+    fn stored(coordinates: (f64, f64)) -> GeoPoint {
+        let point = GeoPoint::new(coordinates.0, coordinates.1).unwrap();
+        GeoPoint::from_score(point.score())
+    }
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    fn in_ranges(ranges: &[(f64, f64)], score: f64) -> bool {
+        ranges
+            .iter()
+            .any(|(start, end)| (*start..*end).contains(&score))
+    }
+
+    #[test]
+    fn scores_and_positions_match_redis() {
+        let palermo = GeoPoint::new(PALERMO.0, PALERMO.1).unwrap();
+        let catania = GeoPoint::new(CATANIA.0, CATANIA.1).unwrap();
+        assert_eq!(palermo.score(), 3479099956230698.0);
+        assert_eq!(catania.score(), 3479447370796909.0);
+
+        let palermo = stored(PALERMO);
+        assert_near(palermo.lon, 13.361389338970184, 1e-12);
+        assert_near(palermo.lat, 38.1155563954963, 1e-12);
+        let catania = stored(CATANIA);
+        assert_near(catania.lon, 15.087267458438873, 1e-12);
+        assert_near(catania.lat, 37.50266842333162, 1e-12);
+        // Decoding and encoding again lands in the same cell.
+        assert_eq!(palermo.score(), 3479099956230698.0);
+    }
+
+    #[test]
+    fn coordinates_outside_the_projection_are_rejected() {
+        assert!(GeoPoint::new(180.0, LAT_MAX).is_some());
+        assert!(GeoPoint::new(-180.0, LAT_MIN).is_some());
+        assert!(GeoPoint::new(180.000001, 0.0).is_none());
+        assert!(GeoPoint::new(0.0, 85.06).is_none());
+        assert!(GeoPoint::new(0.0, -85.06).is_none());
+    }
+
+    #[test]
+    fn geohashes_match_redis() {
+        assert_eq!(stored(PALERMO).geohash(), "sqc8b49rny0");
+        assert_eq!(stored(CATANIA).geohash(), "sqdtr74hyu0");
+    }
+
+    #[test]
+    fn distances_match_redis() {
+        let (palermo, catania) = (stored(PALERMO), stored(CATANIA));
+        assert_eq!(format!("{:.4}", palermo.distance(catania)), "166274.1516");
+        assert_eq!(format!("{:.4}", catania.distance(palermo)), "166274.1516");
+        assert_eq!(palermo.distance(palermo), 0.0);
+
+        let center = GeoPoint::new(15.0, 37.0).unwrap();
+        assert_eq!(
+            format!("{:.4}", center.distance(palermo) / 1000.0),
+            "190.4424"
+        );
+        assert_eq!(
+            format!("{:.4}", center.distance(catania) / 1000.0),
+            "56.4413"
+        );
+        // Along a meridian, where the haversine formula is skipped.
+        let north = GeoPoint::new(15.0, 38.0).unwrap();
+        assert_near(center.distance(north), 111226.3, 0.1);
+    }
+
+    #[test]
+    fn radius_includes_its_boundary() {
+        let (palermo, catania) = (stored(PALERMO), stored(CATANIA));
+        let center = GeoPoint::new(15.0, 37.0).unwrap();
+        let to_palermo = center.distance(palermo);
+
+        assert_eq!(
+            GeoShape::Radius(to_palermo).distance_within(center, palermo),
+            Some(to_palermo)
+        );
+        assert_eq!(
+            GeoShape::Radius(to_palermo - 0.001).distance_within(center, palermo),
+            None
+        );
+        assert!(GeoShape::Radius(100_000.0)
+            .distance_within(center, catania)
+            .is_some());
+        assert!(GeoShape::Radius(100_000.0)
+            .distance_within(center, palermo)
+            .is_none());
+
+        // The cells searched hold every member in range.
+        let ranges = GeoShape::Radius(200_000.0).score_ranges(center);
+        assert!(in_ranges(&ranges, palermo.score()));
+        assert!(in_ranges(&ranges, catania.score()));
+        let ranges = GeoShape::Radius(to_palermo).score_ranges(center);
+        assert!(in_ranges(&ranges, palermo.score()));
+        let ranges = GeoShape::Radius(0.0).score_ranges(palermo);
+        assert!(in_ranges(&ranges, palermo.score()));
+    }
+
+    #[test]
+    fn box_is_measured_from_its_center() {
+        let (palermo, catania) = (stored(PALERMO), stored(CATANIA));
+        let center = GeoPoint::new(15.0, 37.0).unwrap();
+        let box_of = |width: f64, height: f64| GeoShape::Box { width, height };
+        let shape = box_of(400_000.0, 400_000.0);
+        assert_eq!(
+            shape.distance_within(center, palermo),
+            Some(center.distance(palermo))
+        );
+        assert!(shape.distance_within(center, catania).is_some());
+        let ranges = shape.score_ranges(center);
+        assert!(in_ranges(&ranges, palermo.score()));
+        assert!(in_ranges(&ranges, catania.score()));
+
+        // Palermo is 124.08 km north and, along its parallel, 143.39 km
+        // west of the center.
+        assert!(box_of(286_800.0, 400_000.0)
+            .distance_within(center, palermo)
+            .is_some());
+        assert!(box_of(286_700.0, 400_000.0)
+            .distance_within(center, palermo)
+            .is_none());
+        assert!(box_of(400_000.0, 248_200.0)
+            .distance_within(center, palermo)
+            .is_some());
+        let low = box_of(400_000.0, 248_100.0);
+        assert!(low.distance_within(center, palermo).is_none());
+        assert!(low.distance_within(center, catania).is_some());
+    }
+}
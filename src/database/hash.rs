@@ -0,0 +1,133 @@
+use std::collections::{hash_map, BTreeSet, HashMap};
+
+use bytes::Bytes;
+
+use super::ScanIndex;
+
+/// Latest unix time in milliseconds a hash field can be set to expire at.
+pub const MAX_FIELD_EXPIRY: i64 = (1 << 48) - 1;
+
+/// Hash: fields with values. Fields can be given their own expiry time; the
+/// keyspace drops the expired ones through `expire_fields` whenever it looks
+/// the hash up, so commands only ever see live fields.
+#[derive(Clone, Debug, Default)]
+pub struct Hash {
+    fields: HashMap<Bytes, Bytes>,
+    /// Absolute unix time in milliseconds, for the fields that have one.
+    expires: HashMap<Bytes, u64>,
+    /// `expires` ordered by time, so finding the expired fields does not
+    /// mean looking at all of them.
+    expiry_index: BTreeSet<(u64, Bytes)>,
+    /// The fields, in the order HSCAN goes through them.
+    scan_index: ScanIndex,
+}
+
+impl Hash {
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        self.fields.get(field)
+    }
+
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.fields.contains_key(field)
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, Bytes, Bytes> {
+        self.fields.iter()
+    }
+
+    pub fn keys(&self) -> hash_map::Keys<'_, Bytes, Bytes> {
+        self.fields.keys()
+    }
+
+    pub fn values(&self) -> hash_map::Values<'_, Bytes, Bytes> {
+        self.fields.values()
+    }
+
+    /// Sets a field, which like HSET clears any expiry it had.
+    pub fn insert(&mut self, field: Bytes, value: Bytes) -> Option<Bytes> {
+        self.persist(&field);
+        self.update(field, value)
+    }
+
+    /// Sets a field, keeping its expiry. For commands that change a value in
+    /// place, like HINCRBY.
+    pub fn update(&mut self, field: Bytes, value: Bytes) -> Option<Bytes> {
+        let old = self.fields.insert(field.clone(), value);
+        if old.is_none() {
+            self.scan_index.insert(field);
+        }
+        old
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Bytes> {
+        self.persist(field);
+        let (field, value) = self.fields.remove_entry(field)?;
+        self.scan_index.remove(field);
+        Some(value)
+    }
+
+    pub fn scan_index(&self) -> &ScanIndex {
+        &self.scan_index
+    }
+
+    pub fn expires_at(&self, field: &[u8]) -> Option<u64> {
+        self.expires.get(field).copied()
+    }
+
+    /// Gives an existing field an expiry time, replacing the one it had.
+    pub fn set_expires_at(&mut self, field: &[u8], expires_at: u64) {
+        let Some((field, _)) = self.fields.get_key_value(field) else {
+            return;
+        };
+        let field = field.clone();
+        self.persist(&field);
+        self.expiry_index.insert((expires_at, field.clone()));
+        self.expires.insert(field, expires_at);
+    }
+
+    /// Removes the field's expiry time. Returns whether it had one.
+    pub fn persist(&mut self, field: &[u8]) -> bool {
+        match self.expires.remove_entry(field) {
+            Some((field, expires_at)) => {
+                self.expiry_index.remove(&(expires_at, field));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Deletes the fields whose expiry time is `now` or earlier. Returns
+    /// them.
+    pub fn expire_fields(&mut self, now: u64) -> Vec<Bytes> {
+        let mut expired = Vec::new();
+        while let Some((expires_at, field)) = self.expiry_index.first().cloned() {
+            if expires_at > now {
+                break;
+            }
+            self.remove(&field);
+            expired.push(field);
+        }
+        expired
+    }
+
+    /// How many fields have an expiry time, checking that both records of
+    /// them agree.
+    #[cfg(test)]
+    pub fn expiring_len(&self) -> usize {
+        assert_eq!(self.expires.len(), self.expiry_index.len());
+        self.expires.len()
+    }
+
+    /// The earliest expiry time among the fields.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.expiry_index.first().map(|(expires_at, _)| *expires_at)
+    }
+}
@@ -0,0 +1,281 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use super::DbError;
+
+/// Bits of the hash that pick the register.
+const P: u32 = 14;
+const REGISTERS: usize = 1 << P;
+/// Bits of the hash left to count leading zeros in.
+const Q: u32 = 64 - P;
+
+const MAGIC: &[u8] = b"HYLL";
+const HEADER_LEN: usize = 16;
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+/// Six bits per register.
+const DENSE_LEN: usize = HEADER_LEN + REGISTERS * 6 / 8;
+/// Redis's default `hll-sparse-max-bytes`, past which sparse values are
+/// written dense.
+const SPARSE_MAX_LEN: usize = 3000;
+/// Largest register the sparse encoding can hold.
+const SPARSE_MAX_VALUE: u8 = 32;
+
+const HASH_SEED: u32 = 0xadc83b19;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+/// HyperLogLog cardinality estimator. It lives in a string value, laid out
+/// the way Redis lays it out so the two can exchange them: a 16 byte header
+/// with the encoding and the last count, then the registers, either packed
+/// at six bits each (dense) or run-length encoded (sparse). Decoded into one
+/// byte per register while a command works on it.
+#[derive(Clone, Debug)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+    dense: bool,
+    /// The last count, unless a register changed since.
+    cached: Option<u64>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self {
+            registers: vec![0; REGISTERS],
+            dense: false,
+            cached: Some(0),
+        }
+    }
+}
+
+impl HyperLogLog {
+    pub fn decode(value: &[u8]) -> Result<Self, DbError> {
+        if value.len() < HEADER_LEN || !value.starts_with(MAGIC) {
+            return Err(DbError::NotAHyperLogLog);
+        }
+        let card = u64::from_le_bytes(value[8..HEADER_LEN].try_into().unwrap());
+        // The top bit of the count marks it stale.
+        let cached = (card >> 63 == 0).then_some(card);
+        let body = &value[HEADER_LEN..];
+        let (registers, dense) = match value[4] {
+            DENSE if value.len() == DENSE_LEN => {
+                ((0..REGISTERS).map(|i| dense_get(body, i)).collect(), true)
+            }
+            SPARSE => (
+                sparse_decode(body).ok_or(DbError::CorruptHyperLogLog)?,
+                false,
+            ),
+            _ => return Err(DbError::NotAHyperLogLog),
+        };
+        Ok(Self {
+            registers,
+            dense,
+            cached,
+        })
+    }
+
+    /// Sparse while that stays small and can hold every register, dense
+    /// from then on.
+    pub fn encode(&self) -> Bytes {
+        let sparse = (!self.dense)
+            .then(|| sparse_encode(&self.registers))
+            .flatten()
+            .filter(|body| HEADER_LEN + body.len() <= SPARSE_MAX_LEN);
+        let mut value = BytesMut::with_capacity(DENSE_LEN);
+        value.put_slice(MAGIC);
+        value.put_u8(if sparse.is_some() { SPARSE } else { DENSE });
+        value.put_bytes(0, 3);
+        value.put_u64_le(self.cached.unwrap_or(1 << 63));
+        match sparse {
+            Some(body) => value.put_slice(&body),
+            None => {
+                let mut body = vec![0u8; DENSE_LEN - HEADER_LEN];
+                for (i, &register) in self.registers.iter().enumerate() {
+                    dense_set(&mut body, i, register);
+                }
+                value.put_slice(&body);
+            }
+        }
+        value.freeze()
+    }
+
+    /// Counts `element` in, telling whether that changed any register.
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let hash = murmur_hash_64a(element, HASH_SEED);
+        let index = hash as usize & (REGISTERS - 1);
+        // The extra bit caps the run of zeros at `Q`.
+        let zeros = ((hash >> P) | (1 << Q)).trailing_zeros() as u8 + 1;
+        if zeros <= self.registers[index] {
+            return false;
+        }
+        self.registers[index] = zeros;
+        self.cached = None;
+        true
+    }
+
+    /// Makes this count everything `other` counts too.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (register, &theirs) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(theirs);
+        }
+        self.dense |= other.dense;
+        self.cached = None;
+    }
+
+    pub fn cached_count(&self) -> Option<u64> {
+        self.cached
+    }
+
+    /// The estimated number of distinct elements added, which is then kept
+    /// until the next change.
+    pub fn count(&mut self) -> u64 {
+        if let Some(count) = self.cached {
+            return count;
+        }
+        let count = estimate(&self.registers);
+        self.cached = Some(count);
+        count
+    }
+}
+
+fn dense_get(body: &[u8], i: usize) -> u8 {
+    let (byte, shift) = (i * 6 / 8, i * 6 % 8);
+    let low = body[byte] as u16;
+    // The last register ends within its first byte.
+    let high = body.get(byte + 1).copied().unwrap_or(0) as u16;
+    (((low | high << 8) >> shift) & 63) as u8
+}
+
+fn dense_set(body: &mut [u8], i: usize, register: u8) {
+    let (byte, shift) = (i * 6 / 8, i * 6 % 8);
+    let bits = (register as u16 & 63) << shift;
+    body[byte] = body[byte] & !(63 << shift) as u8 | bits as u8;
+    if let Some(next) = body.get_mut(byte + 1) {
+        *next = *next & !(63u16 << shift >> 8) as u8 | (bits >> 8) as u8;
+    }
+}
+
+/// Runs of registers, one opcode each:
+/// `00xxxxxx`: x + 1 zeros, up to 64.
+/// `01xxxxxx yyyyyyyy`: xy + 1 zeros, up to 16384.
+/// `1vvvvvxx`: x + 1 registers, up to 4, of value v + 1, up to 32.
+fn sparse_decode(body: &[u8]) -> Option<Vec<u8>> {
+    let mut registers = Vec::with_capacity(REGISTERS);
+    let mut ops = body.iter();
+    while let Some(&op) = ops.next() {
+        let (value, run) = match op >> 6 {
+            0b00 => (0, (op & 0x3f) as usize + 1),
+            0b01 => (0, (((op & 0x3f) as usize) << 8 | *ops.next()? as usize) + 1),
+            _ => ((op >> 2 & 0x1f) + 1, (op & 0x03) as usize + 1),
+        };
+        if registers.len() + run > REGISTERS {
+            return None;
+        }
+        registers.resize(registers.len() + run, value);
+    }
+    (registers.len() == REGISTERS).then_some(registers)
+}
+
+/// `None` when a register is too large for the sparse encoding.
+fn sparse_encode(registers: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut i = 0;
+    while i < registers.len() {
+        let value = registers[i];
+        let run = registers[i..].iter().take_while(|&&r| r == value).count();
+        i += run;
+        if value > SPARSE_MAX_VALUE {
+            return None;
+        }
+        let max_run = if value == 0 { REGISTERS } else { 4 };
+        let mut left = run;
+        while left > 0 {
+            let len = left.min(max_run);
+            left -= len;
+            let len = len - 1;
+            match value {
+                0 if len < 64 => body.push(len as u8),
+                0 => body.extend_from_slice(&[0x40 | (len >> 8) as u8, len as u8]),
+                _ => body.push(0x80 | (value - 1) << 2 | len as u8),
+            }
+        }
+    }
+    Some(body)
+}
+
+/// The estimator from Otmar Ertl's "New cardinality estimation algorithms
+/// for HyperLogLog sketches", as Redis uses it, from how many registers hold
+/// each value.
+fn estimate(registers: &[u8]) -> u64 {
+    let mut histogram = [0u32; Q as usize + 2];
+    for &register in registers {
+        histogram[register as usize] += 1;
+    }
+    let m = REGISTERS as f64;
+    let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+    for &count in histogram[1..=Q as usize].iter().rev() {
+        z += count as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+/// MurmurHash64A, the hash Redis picks registers with.
+fn murmur_hash_64a(key: &[u8], seed: u32) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+    let mut h = seed as u64 ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &byte) in tail.iter().enumerate() {
+            h ^= (byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
@@ -1,131 +1,726 @@
-use std::collections::HashMap;
-use std::fmt::{Debug, Display};
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
-
-pub struct KeyValueStoreIterator<K, V> {
-    iter: std::collections::hash_map::IntoIter<K, (V, Option<(Instant, Duration)>)>,
+use std::collections::{hash_map, BTreeSet, HashMap};
+use std::hash::{Hash as _, Hasher};
+use std::ops::Bound;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use super::{RedisValue, StreamError, ValueType};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DbError {
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+
+    #[error("ERR value is not an integer or out of range")]
+    NotAnInteger,
+
+    #[error("ERR value is not a valid float")]
+    NotAFloat,
+
+    #[error("ERR increment or decrement would overflow")]
+    Overflow,
+
+    #[error("ERR hash value is not an integer")]
+    HashValueNotAnInteger,
+
+    #[error("ERR hash value is not a float")]
+    HashValueNotAFloat,
+
+    #[error("ERR increment would produce NaN or Infinity")]
+    NanOrInfinity,
+
+    #[error("ERR string exceeds maximum allowed size (proto-max-bulk-len)")]
+    StringTooLong,
+
+    #[error("ERR Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len")]
+    LcsTooLong,
+
+    #[error("ERR invalid expire time in '{0}' command")]
+    InvalidExpireTime(&'static str),
+
+    #[error("ERR resulting score is not a number (NaN)")]
+    ScoreIsNan,
+
+    #[error(
+        "ERR invalid expire time, must be >= 0 and <= {}",
+        super::MAX_FIELD_EXPIRY
+    )]
+    InvalidFieldExpireTime,
+
+    #[error("WRONGTYPE Key is not a valid HyperLogLog string value.")]
+    NotAHyperLogLog,
+
+    #[error("INVALIDOBJ Corrupted HLL object detected")]
+    CorruptHyperLogLog,
+
+    #[error("ERR could not decode requested zset member")]
+    UnknownGeoMember,
+
+    #[error("ERR source and destination objects are the same")]
+    SameObject,
+
+    #[error("ERR DB index is out of range")]
+    DbIndexOutOfRange,
+
+    #[error("ERR no such key")]
+    NoSuchKey,
+
+    #[error("ERR index out of range")]
+    IndexOutOfRange,
+
+    #[error("ERR value is out of range")]
+    ValueOutOfRange,
+
+    #[error(transparent)]
+    Stream(#[from] StreamError),
 }
 
-impl<K, V> Iterator for KeyValueStoreIterator<K, V> {
-    type Item = (K, (V, Option<(Instant, Duration)>));
+/// Longest a string can grow to, Redis's default `proto-max-bulk-len`.
+pub const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next()
-    }
+/// Current unix time in milliseconds, the unit expiry times are kept in.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64
 }
 
 #[derive(Clone, Debug)]
-pub struct KeyValueStore<K, V> {
-    size: Arc<Mutex<usize>>,
-    expire_size: Arc<Mutex<usize>>,
-    hash_map: Arc<Mutex<HashMap<K, (V, Option<(Instant, Duration)>)>>>,
+pub struct Entry {
+    pub value: RedisValue,
+    /// Absolute unix time in milliseconds after which the key is gone.
+    pub expires_at: Option<u64>,
+}
+
+impl Entry {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|t| t <= now)
+    }
+}
+
+/// Keys an active expiry round looks at.
+const EXPIRE_SAMPLE: usize = 20;
+
+/// What looking up a key or hash field past its expiry time does.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LazyExpiry {
+    /// Deletes it, noting the deletion for replicas.
+    #[default]
+    Delete,
+    /// Reads it as gone but keeps it. Replicas wait for the leader to delete
+    /// it, so they never drop a key the leader still has.
+    Hide,
+    /// Reads it as there. Replicas apply the leader's writes this way, as
+    /// the leader ran them before the key expired on its side.
+    Ignore,
 }
 
-impl<K, V> KeyValueStore<K, V>
-where
-    K: Display + Debug + Clone + Eq + std::hash::Hash,
-    V: Display + Debug + Clone,
-{
+/// A deletion made by expiry rather than by a command, which replicas are
+/// sent as a DEL or an HDEL.
+#[derive(Debug, PartialEq)]
+pub enum Expired {
+    Key(Bytes),
+    Fields(Bytes, Vec<Bytes>),
+}
+
+/// The keyspace. Keys are binary safe. A key past its expiry time is removed
+/// the moment a command looks it up, and so are the expired fields of a
+/// hash; the ones nobody looks up are left to `expire_round`. Replicas
+/// change that through `lazy_expiry`.
+#[derive(Debug, Default)]
+pub struct KeyValueStore {
+    hash_map: HashMap<Bytes, Entry>,
+    /// Every key, in the order `scan` goes through them.
+    scan_index: ScanIndex,
+    /// The keys with an expiry time.
+    volatile: BTreeSet<Bytes>,
+    /// Hashes that may have fields with an expiry time. Every hash handed
+    /// out for writing is added, and `expire_round` drops the ones that turn
+    /// out to have none.
+    volatile_hashes: BTreeSet<Bytes>,
+    /// Where the last `expire_round` stopped in each of the two.
+    expire_cursor: Option<Bytes>,
+    hash_expire_cursor: Option<Bytes>,
+    lazy_expiry: LazyExpiry,
+    /// Deletions made by expiry since `take_expired` last collected them.
+    expired: Vec<Expired>,
+    /// Copies of hashes without their expired fields, which reads get
+    /// instead of the hash itself when `lazy_expiry` is `Hide`.
+    masked: HashMap<Bytes, Entry>,
+}
+
+impl KeyValueStore {
     pub fn new() -> Self {
-        Self {
-            size: Arc::new(Mutex::new(0)),
-            expire_size: Arc::new(Mutex::new(0)),
-            hash_map: Arc::new(Mutex::new(HashMap::new())),
-        }
+        Self::default()
     }
 
-    pub async fn get_ht_size(&self) -> usize {
-        *self.size.lock().await
+    pub fn get_ht_size(&self) -> usize {
+        self.hash_map.len()
     }
 
-    pub async fn get_ht_expire_size(&self) -> usize {
-        *self.expire_size.lock().await
+    pub fn get_ht_expire_size(&self) -> usize {
+        self.volatile.len()
     }
 
-    pub async fn iter(&self) -> KeyValueStoreIterator<K, V> {
-        let map = self.hash_map.lock().await;
-        let iter = map.clone().into_iter();
-        KeyValueStoreIterator { iter }
+    /// Every key that has not expired yet.
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &Entry)> {
+        let now = now_ms();
+        self.hash_map
+            .iter()
+            .filter(move |(_, entry)| !entry.is_expired(now))
     }
 
-    pub async fn insert(&self, k: K, v: V, expiry: Option<Duration>) -> Option<V> {
-        let mut guard = self.hash_map.lock().await;
-        let val = if expiry.is_some() {
-            *self.expire_size.lock().await += 1;
-            guard
-                .insert(k, (v, Some((Instant::now(), expiry.unwrap()))))
-                .map(|v| v.0)
-        } else {
-            guard.insert(k, (v, None)).map(|v| v.0)
+    pub fn set_lazy_expiry(&mut self, lazy_expiry: LazyExpiry) {
+        self.lazy_expiry = lazy_expiry;
+    }
+
+    /// The deletions made by expiry since the last call.
+    pub fn take_expired(&mut self) -> Vec<Expired> {
+        std::mem::take(&mut self.expired)
+    }
+
+    fn expire_if_needed(&mut self, key: &[u8]) {
+        let now = now_ms();
+        let Some(entry) = self.hash_map.get_mut(key) else {
+            return;
         };
-        *self.size.lock().await += 1;
-        drop(guard);
-        val
-    }
-
-    pub async fn get(&self, k: &K) -> Option<V> {
-        let now = Instant::now();
-        let mut guard = self.hash_map.lock().await;
-        let val = if guard.contains_key(&k) {
-            let expired = guard.get(&k).and_then(|(_, t)| {
-                if t.is_some() {
-                    if (now - t.unwrap().0) > t.unwrap().1 {
-                        Some(true)
-                    } else {
-                        Some(false)
+        if entry.is_expired(now) {
+            self.remove(key);
+            self.expired.push(Expired::Key(Bytes::copy_from_slice(key)));
+            return;
+        }
+        if let RedisValue::Hash(hash) = &mut entry.value {
+            let fields = hash.expire_fields(now);
+            if fields.is_empty() {
+                return;
+            }
+            let key = Bytes::copy_from_slice(key);
+            if hash.is_empty() {
+                self.remove(&key);
+                self.expired.push(Expired::Key(key));
+            } else {
+                self.expired.push(Expired::Fields(key, fields));
+            }
+        }
+    }
+
+    /// Lazy expiry ahead of a write, which sees expired keys as gone unless
+    /// they are to be ignored.
+    fn expire_for_write(&mut self, key: &[u8]) {
+        if self.lazy_expiry != LazyExpiry::Ignore {
+            self.expire_if_needed(key);
+        }
+    }
+
+    /// Lazy expiry ahead of a read. Returns whether there is something to
+    /// read, found through `read_entry`.
+    fn expire_for_read(&mut self, key: &[u8]) -> bool {
+        match self.lazy_expiry {
+            LazyExpiry::Delete => {
+                self.expire_if_needed(key);
+                self.hash_map.contains_key(key)
+            }
+            LazyExpiry::Ignore => self.hash_map.contains_key(key),
+            LazyExpiry::Hide => {
+                let now = now_ms();
+                let Some(entry) = self.hash_map.get(key) else {
+                    return false;
+                };
+                if entry.is_expired(now) {
+                    return false;
+                }
+                if let RedisValue::Hash(hash) = &entry.value {
+                    if hash.next_expiry().is_some_and(|t| t <= now) {
+                        let mut hash = hash.clone();
+                        hash.expire_fields(now);
+                        if hash.is_empty() {
+                            return false;
+                        }
+                        let masked = Entry {
+                            value: RedisValue::Hash(hash),
+                            expires_at: entry.expires_at,
+                        };
+                        self.masked.insert(Bytes::copy_from_slice(key), masked);
                     }
-                } else {
-                    Some(false)
                 }
-            });
-            if expired.is_some_and(|x| x == true) {
-                *self.size.lock().await -= 1;
-                *self.expire_size.lock().await -= 1;
-                guard.remove(&k);
-                None
-            } else {
-                guard.get(k).and_then(|(val, _)| Some(val)).cloned()
+                true
+            }
+        }
+    }
+
+    fn read_entry(&self, key: &[u8]) -> Option<&Entry> {
+        self.masked.get(key).or_else(|| self.hash_map.get(key))
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Option<&RedisValue> {
+        self.masked.clear();
+        if !self.expire_for_read(key) {
+            return None;
+        }
+        self.read_entry(key).map(|entry| &entry.value)
+    }
+
+    /// Looks up a value that must be of type `T`.
+    pub fn get_as<T: ValueType>(&mut self, key: &[u8]) -> Result<Option<&T>, DbError> {
+        match self.get(key) {
+            Some(value) => T::from_value(value).map(Some).ok_or(DbError::WrongType),
+            None => Ok(None),
+        }
+    }
+
+    /// Like `get_as` for several keys at once, for commands that combine
+    /// values.
+    pub fn get_many_as<T: ValueType>(
+        &mut self,
+        keys: &[Bytes],
+    ) -> Result<Vec<Option<&T>>, DbError> {
+        self.masked.clear();
+        let present: Vec<bool> = keys.iter().map(|key| self.expire_for_read(key)).collect();
+        keys.iter()
+            .zip(present)
+            .map(
+                |(key, present)| match self.read_entry(key).filter(|_| present) {
+                    Some(entry) => T::from_value(&entry.value)
+                        .map(Some)
+                        .ok_or(DbError::WrongType),
+                    None => Ok(None),
+                },
+            )
+            .collect()
+    }
+
+    /// Like `get_as`, for commands that modify the value in place.
+    pub fn get_mut_as<T: ValueType>(&mut self, key: &[u8]) -> Result<Option<&mut T>, DbError> {
+        self.expire_for_write(key);
+        let Some(entry) = self.hash_map.get_mut(key) else {
+            return Ok(None);
+        };
+        if matches!(entry.value, RedisValue::Hash(_)) && !self.volatile_hashes.contains(key) {
+            self.volatile_hashes.insert(Bytes::copy_from_slice(key));
+        }
+        T::from_value_mut(&mut entry.value)
+            .map(Some)
+            .ok_or(DbError::WrongType)
+    }
+
+    /// Like `get_mut_as`, but creates an empty value when the key is missing.
+    pub fn entry_as<T: ValueType>(&mut self, key: Bytes) -> Result<&mut T, DbError> {
+        self.expire_for_write(&key);
+        let entry = match self.hash_map.entry(key.clone()) {
+            hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            hash_map::Entry::Vacant(entry) => {
+                self.scan_index.insert(entry.key().clone());
+                entry.insert(Entry {
+                    value: T::default().into_value(),
+                    expires_at: None,
+                })
             }
+        };
+        if matches!(entry.value, RedisValue::Hash(_)) {
+            self.volatile_hashes.insert(key);
+        }
+        T::from_value_mut(&mut entry.value).ok_or(DbError::WrongType)
+    }
+
+    /// Deletes `key` once the aggregate value it holds has no elements left.
+    pub fn remove_if_empty(&mut self, key: &[u8]) {
+        if self
+            .hash_map
+            .get(key)
+            .is_some_and(|entry| entry.value.is_empty_collection())
+        {
+            self.remove(key);
+        }
+    }
+
+    /// Stores `value` under `key`, replacing whatever was there along with
+    /// its expiry.
+    pub fn insert(&mut self, key: Bytes, value: RedisValue, expires_at: Option<u64>) {
+        if expires_at.is_some() {
+            self.volatile.insert(key.clone());
         } else {
-            None
+            self.volatile.remove(&key);
+        }
+        if matches!(&value, RedisValue::Hash(hash) if hash.next_expiry().is_some()) {
+            self.volatile_hashes.insert(key.clone());
+        }
+        if !self.hash_map.contains_key(&key) {
+            self.scan_index.insert(key.clone());
+        }
+        self.hash_map.insert(key, Entry { value, expires_at });
+    }
+
+    /// The key's expiry time. `None` when it has none or does not exist.
+    pub fn expires_at(&mut self, key: &[u8]) -> Option<u64> {
+        if !self.expire_for_read(key) {
+            return None;
+        }
+        self.hash_map.get(key)?.expires_at
+    }
+
+    /// Gives an existing key an expiry time, or with `None` removes the one
+    /// it has. Returns whether the key exists.
+    pub fn set_expires_at(&mut self, key: &[u8], expires_at: Option<u64>) -> bool {
+        self.expire_for_write(key);
+        let Some(entry) = self.hash_map.get_mut(key) else {
+            return false;
         };
-        drop(guard);
-        val
+        if expires_at.is_none() {
+            self.volatile.remove(key);
+        } else if entry.expires_at.is_none() {
+            self.volatile.insert(Bytes::copy_from_slice(key));
+        }
+        entry.expires_at = expires_at;
+        true
     }
 
-    pub async fn contains_key(&self, k: &K) -> bool {
-        let guard = self.hash_map.lock().await;
-        let val = guard.contains_key(k);
-        drop(guard);
-        val
+    /// Drops every key.
+    pub fn clear(&mut self) {
+        self.hash_map.clear();
+        self.scan_index.clear();
+        self.volatile.clear();
+        self.volatile_hashes.clear();
+        self.masked.clear();
     }
 
-    pub async fn prune(&self) {
-        loop {
-            let now = Instant::now();
-            // let Self { hash_map, duration } = self;
-            let mut guard = self.hash_map.lock().await;
-            let keys = guard.keys().cloned().collect::<Vec<K>>();
-            for k in keys {
-                let expired = guard.get(&k).and_then(|(_, t)| {
-                    if t.is_some() {
-                        if (now - t.unwrap().0) > t.unwrap().1 {
-                            Some(true)
-                        } else {
-                            Some(false)
-                        }
-                    } else {
-                        Some(false)
-                    }
-                });
+    pub fn remove(&mut self, key: &[u8]) -> Option<Entry> {
+        let (key, entry) = self.hash_map.remove_entry(key)?;
+        self.scan_index.remove(key.clone());
+        self.volatile.remove(&key);
+        self.volatile_hashes.remove(&key);
+        Some(entry)
+    }
+
+    /// A page of a SCAN, as `ScanIndex::page` picks it, and the cursor to
+    /// continue from. Expired keys are dropped on the way.
+    pub fn scan(&mut self, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        let (next, mut keys) = self.scan_index.page(cursor, count);
+        keys.retain(|key| self.get(key).is_some());
+        (next, keys)
+    }
+
+    /// One round of active expiry: looks at the next `EXPIRE_SAMPLE` keys
+    /// with an expiry time and the next `EXPIRE_SAMPLE` hashes that may have
+    /// fields with one, from where the last round stopped, and deletes what
+    /// expired. Returns how many keys it looked at and how many of those
+    /// were expired or had expired fields.
+    pub fn expire_round(&mut self) -> (usize, usize) {
+        let now = now_ms();
+        let keys = next_sample(&self.volatile, &mut self.expire_cursor);
+        let hashes = next_sample(&self.volatile_hashes, &mut self.hash_expire_cursor);
+        let sampled = keys.len() + hashes.len();
 
-                if expired.is_some_and(|x| x == true) {
-                    guard.remove(&k);
+        let mut expired = 0;
+        for key in keys {
+            if self
+                .hash_map
+                .get(&key)
+                .is_some_and(|entry| entry.is_expired(now))
+            {
+                self.remove(&key);
+                self.expired.push(Expired::Key(key));
+                expired += 1;
+            }
+        }
+        for key in hashes {
+            let Some(Entry {
+                value: RedisValue::Hash(hash),
+                ..
+            }) = self.hash_map.get_mut(&key)
+            else {
+                self.volatile_hashes.remove(&key);
+                continue;
+            };
+            let fields = hash.expire_fields(now);
+            if !fields.is_empty() {
+                expired += 1;
+            }
+            if hash.is_empty() {
+                self.remove(&key);
+                self.expired.push(Expired::Key(key));
+            } else {
+                if hash.next_expiry().is_none() {
+                    self.volatile_hashes.remove(&key);
+                }
+                if !fields.is_empty() {
+                    self.expired.push(Expired::Fields(key, fields));
                 }
             }
-            drop(guard);
         }
+        (sampled, expired)
+    }
+}
+
+/// Where a key or a collection element falls in the order scans go through
+/// them.
+pub fn scan_hash(key: &[u8]) -> u64 {
+    let mut hasher = hash_map::DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Keys or collection elements ordered by `scan_hash`, which is the order
+/// SCAN and its SSCAN, HSCAN and ZSCAN siblings page through them in. A page
+/// only costs as much as what it returns.
+#[derive(Clone, Debug, Default)]
+pub struct ScanIndex(BTreeSet<(u64, Bytes)>);
+
+impl ScanIndex {
+    pub fn insert(&mut self, element: Bytes) {
+        self.0.insert((scan_hash(&element), element));
+    }
+
+    pub fn remove(&mut self, element: Bytes) {
+        self.0.remove(&(scan_hash(&element), element));
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// At least `count` elements, unless there are no more, from `cursor`
+    /// on, and the cursor to continue from, 0 once the end was reached. The
+    /// order stays the same however the elements change, so one present
+    /// from the first page to the last is returned exactly once.
+    pub fn page(&self, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        let mut entries = self.0.range((cursor, Bytes::new())..).peekable();
+        let mut elements = Vec::new();
+        let mut last = None;
+        while let Some((hash, element)) = entries.next_if(|(hash, _)| {
+            // Elements sharing a hash go on the same page, as the next one
+            // starts past it.
+            elements.len() < count || Some(*hash) == last
+        }) {
+            elements.push(element.clone());
+            last = Some(*hash);
+        }
+        let next = match (entries.peek(), last) {
+            (Some(_), Some(hash)) => hash + 1,
+            _ => 0,
+        };
+        (next, elements)
+    }
+}
+
+/// The next `EXPIRE_SAMPLE` keys of `set` after `cursor`, moving it along.
+/// It starts over from the beginning once it reaches the end.
+fn next_sample(set: &BTreeSet<Bytes>, cursor: &mut Option<Bytes>) -> Vec<Bytes> {
+    let start = match cursor.as_ref() {
+        Some(key) => Bound::Excluded(key),
+        None => Bound::Unbounded,
+    };
+    let sample: Vec<Bytes> = set
+        .range::<Bytes, _>((start, Bound::Unbounded))
+        .take(EXPIRE_SAMPLE)
+        .cloned()
+        .collect();
+    *cursor = if sample.len() < EXPIRE_SAMPLE {
+        None
+    } else {
+        sample.last().cloned()
+    };
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Hash;
+
+    fn string(value: &'static str) -> RedisValue {
+        RedisValue::String(Bytes::from(value))
+    }
+
+    /// A hash with fields `a`, which expired, and `b`, which did not.
+    fn half_expired_hash() -> RedisValue {
+        let mut hash = Hash::default();
+        hash.insert(Bytes::from("a"), Bytes::from("1"));
+        hash.insert(Bytes::from("b"), Bytes::from("2"));
+        hash.set_expires_at(b"a", now_ms() - 1);
+        RedisValue::Hash(hash)
+    }
+
+    fn hash_len(db: &mut KeyValueStore, key: &[u8]) -> Option<usize> {
+        db.get_as::<Hash>(key).unwrap().map(Hash::len)
+    }
+
+    #[test]
+    fn leaders_delete_expired_keys_and_fields_on_lookup() {
+        let mut db = KeyValueStore::new();
+        db.insert(Bytes::from("gone"), string("v"), Some(now_ms() - 1));
+        db.insert(Bytes::from("h"), half_expired_hash(), None);
+
+        assert!(db.get(b"gone").is_none());
+        assert_eq!(hash_len(&mut db, b"h"), Some(1));
+        assert_eq!(db.get_ht_size(), 1);
+        assert_eq!(
+            db.take_expired(),
+            [
+                Expired::Key(Bytes::from("gone")),
+                Expired::Fields(Bytes::from("h"), vec![Bytes::from("a")]),
+            ]
+        );
+        assert!(db.take_expired().is_empty());
+    }
+
+    #[test]
+    fn replicas_hide_expired_keys_and_fields_without_deleting_them() {
+        let mut db = KeyValueStore::new();
+        db.set_lazy_expiry(LazyExpiry::Hide);
+        db.insert(Bytes::from("gone"), string("v"), Some(now_ms() - 1));
+        db.insert(Bytes::from("h"), half_expired_hash(), None);
+
+        assert!(db.get(b"gone").is_none());
+        assert_eq!(db.expires_at(b"gone"), None);
+        assert_eq!(hash_len(&mut db, b"h"), Some(1));
+        assert_eq!(db.get_ht_size(), 2);
+        assert!(db.take_expired().is_empty());
+
+        // The leader's writes still find them.
+        db.set_lazy_expiry(LazyExpiry::Ignore);
+        assert!(db.get(b"gone").is_some());
+        assert_eq!(hash_len(&mut db, b"h"), Some(2));
+        assert_eq!(db.get_ht_size(), 2);
+        assert!(db.take_expired().is_empty());
+    }
+
+    fn key(i: usize) -> Bytes {
+        Bytes::from(format!("key:{:02}", i))
+    }
+
+    #[test]
+    fn expire_round_counts_sampled_and_expired_keys() {
+        let mut db = KeyValueStore::new();
+        for i in 0..10 {
+            let expires_at = if i < 4 {
+                now_ms() - 1
+            } else {
+                now_ms() + 60_000
+            };
+            db.insert(key(i), string("v"), Some(expires_at));
+        }
+        for i in 10..13 {
+            db.insert(key(i), string("v"), None);
+        }
+
+        assert_eq!(db.expire_round(), (10, 4));
+        assert_eq!(db.get_ht_size(), 9);
+        assert_eq!(db.get_ht_expire_size(), 6);
+        assert_eq!(
+            db.take_expired(),
+            (0..4).map(|i| Expired::Key(key(i))).collect::<Vec<_>>()
+        );
+        assert_eq!(db.expire_round(), (6, 0));
+    }
+
+    #[test]
+    fn expire_round_cursor_wraps_around() {
+        let mut db = KeyValueStore::new();
+        for i in 0..45 {
+            db.insert(key(i), string("v"), Some(now_ms() + 60_000));
+        }
+        // Last in order, so only the third round gets to it.
+        db.set_expires_at(&key(44), Some(now_ms() - 1));
+
+        assert_eq!(db.expire_round(), (20, 0));
+        assert_eq!(db.expire_cursor, Some(key(19)));
+        assert_eq!(db.expire_round(), (20, 0));
+        assert_eq!(db.expire_cursor, Some(key(39)));
+        assert_eq!(db.expire_round(), (5, 1));
+        assert_eq!(db.expire_cursor, None);
+        assert!(db.get(&key(44)).is_none());
+
+        // Back at the start.
+        assert_eq!(db.expire_round(), (20, 0));
+        assert_eq!(db.expire_cursor, Some(key(19)));
+    }
+
+    #[test]
+    fn scan_index_pages_through_elements_as_they_change() {
+        let mut index = ScanIndex::default();
+        for i in 0..100 {
+            index.insert(key(i));
+        }
+        let mut seen = Vec::new();
+        let mut removed = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (next, page) = index.page(cursor, 10);
+            assert!(page.len() >= 10 || next == 0);
+            seen.extend(page);
+            // Changes between pages leave the untouched elements in place.
+            removed.push(key(seen.len() % 100));
+            index.remove(key(seen.len() % 100));
+            index.insert(Bytes::from(format!("new:{}", seen.len())));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        for key in (0..100).map(key).filter(|key| !removed.contains(key)) {
+            assert_eq!(seen.iter().filter(|seen| **seen == key).count(), 1);
+        }
+    }
+
+    #[test]
+    fn hash_scan_index_follows_its_fields() {
+        let mut hash = Hash::default();
+        hash.insert(Bytes::from("a"), Bytes::from("1"));
+        hash.update(Bytes::from("b"), Bytes::from("2"));
+        hash.insert(Bytes::from("a"), Bytes::from("3"));
+        hash.set_expires_at(b"b", now_ms() - 1);
+        hash.expire_fields(now_ms());
+        assert_eq!(hash.scan_index().page(0, 10), (0, vec![Bytes::from("a")]));
+        hash.remove(b"a");
+        assert_eq!(hash.scan_index().page(0, 10), (0, vec![]));
+    }
+
+    #[test]
+    fn expire_round_prunes_hashes_without_expiring_fields() {
+        let mut db = KeyValueStore::new();
+        // Handed out for writing, but never given an expiring field.
+        db.entry_as::<Hash>(Bytes::from("plain"))
+            .unwrap()
+            .insert(Bytes::from("f"), Bytes::from("v"));
+        // Replaced by a string since.
+        db.entry_as::<Hash>(Bytes::from("replaced")).unwrap();
+        db.insert(Bytes::from("replaced"), string("v"), None);
+        // One field expired, one still to.
+        let mut mixed = Hash::default();
+        mixed.insert(Bytes::from("a"), Bytes::from("1"));
+        mixed.insert(Bytes::from("b"), Bytes::from("2"));
+        mixed.set_expires_at(b"a", now_ms() - 1);
+        mixed.set_expires_at(b"b", now_ms() + 60_000);
+        db.insert(Bytes::from("mixed"), RedisValue::Hash(mixed), None);
+        // The expired field was the only one with an expiry.
+        db.insert(Bytes::from("settled"), half_expired_hash(), None);
+        // Every field expired.
+        let mut gone = Hash::default();
+        gone.insert(Bytes::from("a"), Bytes::from("1"));
+        gone.set_expires_at(b"a", now_ms() - 1);
+        db.insert(Bytes::from("gone"), RedisValue::Hash(gone), None);
+        assert_eq!(db.volatile_hashes.len(), 5);
+
+        assert_eq!(db.expire_round(), (5, 3));
+        assert_eq!(
+            db.volatile_hashes.iter().collect::<Vec<_>>(),
+            [&Bytes::from("mixed")]
+        );
+        assert!(db.get(b"gone").is_none());
+        assert_eq!(db.get_ht_size(), 4);
+
+        let expiring = |db: &mut KeyValueStore, key: &[u8]| {
+            let hash = db.get_as::<Hash>(key).unwrap().unwrap();
+            (hash.len(), hash.expiring_len())
+        };
+        assert_eq!(expiring(&mut db, b"mixed"), (1, 1));
+        assert_eq!(expiring(&mut db, b"settled"), (1, 0));
+        assert_eq!(expiring(&mut db, b"plain"), (1, 0));
+
+        assert_eq!(db.expire_round(), (1, 0));
+        assert_eq!(expiring(&mut db, b"mixed"), (1, 1));
     }
 }
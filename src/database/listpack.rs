@@ -0,0 +1,208 @@
+use bytes::Bytes;
+
+const HEADER_LEN: usize = 6;
+const EOF: u8 = 0xFF;
+
+/// An element of a listpack, which keeps integers apart from strings.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListpackEntry {
+    Int(i64),
+    Str(Bytes),
+}
+
+impl ListpackEntry {
+    /// The integer, whichever way it was kept.
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            ListpackEntry::Int(value) => Some(*value),
+            ListpackEntry::Str(s) => std::str::from_utf8(s).ok()?.parse().ok(),
+        }
+    }
+
+    /// The string, with integers written out in decimal.
+    pub fn into_bytes(self) -> Bytes {
+        match self {
+            ListpackEntry::Int(value) => Bytes::from(value.to_string()),
+            ListpackEntry::Str(s) => s,
+        }
+    }
+}
+
+/// Builds a listpack, the compact sequence of strings and integers Redis
+/// keeps small collections and stream nodes in: a header with the total
+/// size and element count, then each element as its encoding, its data and
+/// its own length again so the list can be walked backwards.
+#[derive(Default)]
+pub struct ListpackWriter {
+    body: Vec<u8>,
+    len: usize,
+}
+
+impl ListpackWriter {
+    pub fn push_int(&mut self, value: i64) {
+        let start = self.body.len();
+        match value {
+            0..=127 => self.body.push(value as u8),
+            -4096..=4095 => {
+                let value = value as u16 & 0x1fff;
+                self.body.extend([0xC0 | (value >> 8) as u8, value as u8]);
+            }
+            -32768..=32767 => {
+                self.body.push(0xF1);
+                self.body.extend((value as i16).to_le_bytes());
+            }
+            -8388608..=8388607 => {
+                self.body.push(0xF2);
+                self.body.extend(&(value as i32).to_le_bytes()[..3]);
+            }
+            -2147483648..=2147483647 => {
+                self.body.push(0xF3);
+                self.body.extend((value as i32).to_le_bytes());
+            }
+            _ => {
+                self.body.push(0xF4);
+                self.body.extend(value.to_le_bytes());
+            }
+        }
+        self.end_element(start);
+    }
+
+    pub fn push_str(&mut self, s: &[u8]) {
+        let start = self.body.len();
+        let len = s.len();
+        if len < 64 {
+            self.body.push(0x80 | len as u8);
+        } else if len < 4096 {
+            self.body.extend([0xE0 | (len >> 8) as u8, len as u8]);
+        } else {
+            self.body.push(0xF0);
+            self.body.extend((len as u32).to_le_bytes());
+        }
+        self.body.extend_from_slice(s);
+        self.end_element(start);
+    }
+
+    /// Appends the length of the element that began at `start`, seven bits
+    /// per byte, most significant first, with the top bit set on every byte
+    /// but the first so a reader going backwards knows where it ends.
+    fn end_element(&mut self, start: usize) {
+        let len = self.body.len() - start;
+        let size = backlen_size(len);
+        for i in 0..size {
+            let shift = 7 * (size - 1 - i);
+            let byte = (len >> shift) as u8 & 127;
+            self.body.push(if i == 0 { byte } else { byte | 128 });
+        }
+        self.len += 1;
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let total = HEADER_LEN + self.body.len() + 1;
+        let mut listpack = Vec::with_capacity(total);
+        listpack.extend((total as u32).to_le_bytes());
+        // Counts past the field's range are left for readers to find out.
+        listpack.extend((self.len.min(u16::MAX as usize) as u16).to_le_bytes());
+        listpack.extend(self.body);
+        listpack.push(EOF);
+        listpack
+    }
+}
+
+/// The elements of a listpack, `None` if it is malformed.
+pub fn decode(listpack: &[u8]) -> Option<Vec<ListpackEntry>> {
+    let body = listpack.get(HEADER_LEN..)?;
+    let int = |bytes: Option<&[u8]>| -> Option<i64> {
+        let bytes = bytes?;
+        let mut buf = [0; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        // Sign extend from the top bit of the last byte.
+        let shift = 64 - 8 * bytes.len() as u32;
+        Some(i64::from_le_bytes(buf) << shift >> shift)
+    };
+    let mut entries = Vec::new();
+    let mut i = 0;
+    loop {
+        let encoding = *body.get(i)?;
+        let (entry, len) = match encoding {
+            EOF => return Some(entries),
+            0x00..=0x7F => (ListpackEntry::Int(encoding as i64), 1),
+            0x80..=0xBF => {
+                let n = (encoding & 0x3F) as usize;
+                (string(body, i + 1, n)?, 1 + n)
+            }
+            0xC0..=0xDF => {
+                let value = ((encoding & 0x1F) as i64) << 8 | *body.get(i + 1)? as i64;
+                (ListpackEntry::Int(value << 51 >> 51), 2)
+            }
+            0xE0..=0xEF => {
+                let n = ((encoding & 0x0F) as usize) << 8 | *body.get(i + 1)? as usize;
+                (string(body, i + 2, n)?, 2 + n)
+            }
+            0xF0 => {
+                let n = int(body.get(i + 1..i + 5))? as u32 as usize;
+                (string(body, i + 5, n)?, 5 + n)
+            }
+            0xF1 => (ListpackEntry::Int(int(body.get(i + 1..i + 3))?), 3),
+            0xF2 => (ListpackEntry::Int(int(body.get(i + 1..i + 4))?), 4),
+            0xF3 => (ListpackEntry::Int(int(body.get(i + 1..i + 5))?), 5),
+            0xF4 => (ListpackEntry::Int(int(body.get(i + 1..i + 9))?), 9),
+            _ => return None,
+        };
+        entries.push(entry);
+        i += len + backlen_size(len);
+    }
+}
+
+fn string(body: &[u8], start: usize, len: usize) -> Option<ListpackEntry> {
+    let s = body.get(start..start + len)?;
+    Some(ListpackEntry::Str(Bytes::copy_from_slice(s)))
+}
+
+/// Bytes the length of an element of `len` bytes takes after it.
+fn backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listpack_integers_round_trip() {
+        let values = [
+            0,
+            127,
+            128,
+            -1,
+            4095,
+            -4096,
+            32767,
+            -32768,
+            8388607,
+            -8388608,
+            i32::MAX as i64,
+            i32::MIN as i64,
+            i64::MAX,
+            i64::MIN,
+        ];
+        let mut writer = ListpackWriter::default();
+        for value in values {
+            writer.push_int(value);
+        }
+        let long = "x".repeat(5000);
+        writer.push_str(long.as_bytes());
+        let decoded = decode(&writer.finish()).unwrap();
+        let ints: Vec<_> = decoded[..values.len()]
+            .iter()
+            .map(|entry| entry.as_int().unwrap())
+            .collect();
+        assert_eq!(ints, values);
+        assert_eq!(decoded[values.len()], ListpackEntry::Str(Bytes::from(long)));
+    }
+}
@@ -6,22 +6,39 @@ use std::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
-    time::Duration,
 };
 use tokio::sync::Mutex;
 
-use anyhow::Result;
-
-pub use kv::KeyValueStore;
+pub use blocking::BlockedClients;
+use bytes::Bytes;
+pub use expire::active_expire_cycle;
+pub use geo::{GeoPoint, GeoShape};
+pub use hash::{Hash, MAX_FIELD_EXPIRY};
+pub use hyperloglog::HyperLogLog;
+pub use kv::{now_ms, DbError, Expired, KeyValueStore, LazyExpiry, ScanIndex, MAX_STRING_LEN};
 pub use rdb::{load_from_rdb, write_to_disk};
-pub use stream::{RadixTreeStore, StreamEntry};
+pub use set::Set;
+pub use stream::{EntryID, Stream, StreamEntry, StreamError};
 use tokio::sync::{mpsc, RwLock};
+pub use value::{RedisValue, ValueType};
+pub use zset::{LexBound, ScoreBound, ZSet};
 
 use crate::cmds::Command;
+use crate::global::STATE;
 
+mod blocking;
+mod expire;
+mod geo;
+mod hash;
+mod hyperloglog;
 mod kv;
+mod listpack;
 mod rdb;
+mod set;
 mod stream;
+mod value;
+mod ziplist;
+mod zset;
 
 type Tx = mpsc::UnboundedSender<Vec<u8>>;
 type _Rx = mpsc::UnboundedReceiver<Vec<u8>>;
@@ -37,15 +54,46 @@ pub struct Peer {
 #[derive(Debug, Default)]
 pub struct Client {
     pub multi_lock: AtomicBool,
-    pub multi_queue: Arc<Mutex<VecDeque<Command>>>,
+    // Queued commands together with the frames they arrived in, which are
+    // what gets replicated once EXEC runs them.
+    pub multi_queue: Arc<Mutex<VecDeque<(Command, Bytes)>>>,
+    // Set when a command failed to queue, which makes EXEC discard the rest.
+    pub multi_aborted: AtomicBool,
+}
+
+/// Number of databases unless `--databases` says otherwise.
+pub const DEFAULT_DATABASES: usize = 16;
+
+/// The logical databases clients `SELECT` between, by index.
+pub struct Databases {
+    pub dbs: Vec<KeyValueStore>,
+    /// The database replicas apply writes to, which changes only when they
+    /// are sent a SELECT. A new replica starts out in database 0.
+    pub replicated_db: usize,
+}
+
+impl Databases {
+    pub fn new(count: usize) -> Self {
+        Self {
+            dbs: (0..count).map(|_| KeyValueStore::new()).collect(),
+            replicated_db: 0,
+        }
+    }
+
+    pub fn set_lazy_expiry(&mut self, lazy_expiry: LazyExpiry) {
+        for db in &mut self.dbs {
+            db.set_lazy_expiry(lazy_expiry);
+        }
+    }
 }
 
 pub struct SharedState {
     // a connection may be either a client or a replica (follower)
     pub peers: Arc<RwLock<HashMap<SocketAddr, Peer>>>,
     pub clients: Arc<RwLock<HashMap<SocketAddr, Client>>>,
-    pub stream_store: RadixTreeStore,
-    pub kv_store: KeyValueStore<String, String>,
+    pub kv_store: Mutex<Databases>,
+    // Only locked while holding `kv_store`.
+    pub blocked_clients: Mutex<BlockedClients>,
 }
 
 impl SharedState {
@@ -53,41 +101,24 @@ impl SharedState {
         SharedState {
             peers: Arc::new(RwLock::new(HashMap::new())),
             clients: Arc::new(RwLock::new(HashMap::new())),
-            stream_store: RadixTreeStore::new(),
-            kv_store: KeyValueStore::new(),
+            kv_store: Mutex::new(Databases::new(database_count())),
+            blocked_clients: Mutex::new(BlockedClients::default()),
         }
     }
 
-    /// insert into the stream store
-    pub async fn stream_store_insert(
-        &self,
-        key: &str,
-        entry_id: &str,
-        data: Vec<(String, String)>,
-    ) -> Result<String> {
-        self.stream_store.insert(key, entry_id, data).await
-    }
-
-    /// Insert into the kv_store
-    pub async fn kv_store_insert(&self, k: String, v: String, expiry: Option<Duration>) {
-        self.kv_store.insert(k, v, expiry).await;
-    }
-
-    pub async fn kv_store_get(&self, k: &String) -> Option<String> {
-        self.kv_store.get(k).await
-    }
-
     /// Insert a new peer
     pub async fn insert_peer(&self, socket_addr: SocketAddr, peer: Peer) {
         self.peers.write().await.entry(socket_addr).or_insert(peer);
     }
 
+    /// Registers a new connection. A client that had the same address
+    /// before is gone, so nothing of its state carries over.
     pub async fn insert_client(&self, socket_addr: SocketAddr, client: Client) {
-        self.clients
-            .write()
-            .await
-            .entry(socket_addr)
-            .or_insert(client);
+        self.clients.write().await.insert(socket_addr, client);
+    }
+
+    pub async fn remove_client(&self, socket_addr: SocketAddr) {
+        self.clients.write().await.remove(&socket_addr);
     }
 
     pub async fn broadcast_peers(&self, message: Vec<u8>) {
@@ -145,3 +176,11 @@ impl SharedState {
         count
     }
 }
+
+/// The number of databases the server was started with.
+pub fn database_count() -> usize {
+    STATE
+        .get_val(&"databases".into())
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(DEFAULT_DATABASES)
+}
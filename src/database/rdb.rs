@@ -1,328 +1,1171 @@
-use super::KeyValueStore;
+use super::listpack::{self, ListpackEntry, ListpackWriter};
+use super::ziplist;
+use super::{now_ms, EntryID, Hash, KeyValueStore, RedisValue, Set, Stream, ZSet};
 use crate::global::STATE;
-use anyhow::Error;
-use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
-use std::any::Any;
-use std::fs::OpenOptions;
-use std::io::{self, BufReader, Read};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use std::{
-    fs::{self, File},
-    io::Write,
-    path::{Path, PathBuf},
-};
-
-// Magic string + RDB version number (ASCII): "REDIS0011".
-const MAGIC_STRING: [u8; 9] = *b"REDIS0011";
-
-async fn init_db(file_mode: String) -> anyhow::Result<File, Error> {
-    let rdb_dir = STATE
-        .get_val(&"dir".into())
-        .expect("directory name is empty");
+use anyhow::{anyhow, bail, Error};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use bytes::Bytes;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// Magic string + RDB version number (ASCII): "REDIS0012". Version 12 is
+// the first with TYPE_HASH_METADATA.
+const MAGIC_STRING: [u8; 9] = *b"REDIS0012";
+
+// Op codes
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
+
+// Value Type
+// ==========
+// A one byte flag indicates encoding used to save the Value. Only the plain
+// encodings are written, apart from streams, which Redis only ever keeps in
+// listpacks. The compact encodings Redis saves small collections in are read
+// too, so files it wrote load here.
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+// Sorted set with scores as strings, only read for older files.
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+// Sorted set with scores as little endian doubles.
+const TYPE_ZSET_2: u8 = 5;
+// Streams as listpack nodes; each version adds metadata to the last.
+const TYPE_STREAM_LISTPACKS: u8 = 15;
+const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+// A hash with per-field expiry times.
+const TYPE_HASH_METADATA: u8 = 24;
+// Compact encodings, only read.
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+// Lists as nodes of ziplists, and then of listpacks or single elements.
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_SET_LISTPACK: u8 = 20;
+// A listpack hash with per-field expiry times.
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+// How a node of a `TYPE_LIST_QUICKLIST_2` list holds its elements.
+const QUICKLIST_NODE_PLAIN: usize = 1;
+const QUICKLIST_NODE_PACKED: usize = 2;
+
+// Entries per stream node, Redis's default `stream-node-max-entries`.
+const STREAM_NODE_MAX_ENTRIES: usize = 100;
+// Flags of an entry in a stream node.
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+// Special string encodings, flagged by a length byte starting with `11`.
+const ENC_INT8: u8 = 0;
+const ENC_INT16: u8 = 1;
+const ENC_INT32: u8 = 2;
+const ENC_LZF: u8 = 3;
 
-    let rdb_file = STATE
+// Most elements or bytes reserved up front from a length read from the file,
+// so a corrupt length fails on the missing data instead of the allocation.
+const MAX_PREALLOC: usize = 1024;
+
+fn rdb_path() -> PathBuf {
+    let dir = STATE
+        .get_val(&"dir".into())
+        .cloned()
+        .unwrap_or_else(|| ".".into());
+    let dbfilename = STATE
         .get_val(&"dbfilename".into())
-        .expect("filename is empty");
+        .cloned()
+        .unwrap_or_else(|| "dump.rdb".into());
+    PathBuf::from(dir).join(dbfilename)
+}
 
-    // rdb_file_p.push(&p);
-    let p = Path::new(&rdb_dir);
-    if !Path::new(&p).exists() {
-        fs::create_dir(p)?; // .expect("Directory creation failed");
+pub fn write_to_disk(dbs: &[KeyValueStore]) -> anyhow::Result<()> {
+    write_rdb(&rdb_path(), dbs)
+}
+
+fn write_rdb(rdb_path: &Path, dbs: &[KeyValueStore]) -> anyhow::Result<()> {
+    if let Some(dir) = rdb_path.parent() {
+        fs::create_dir_all(dir)?;
     }
 
-    let rdb_file_p = PathBuf::from(&rdb_dir).join(rdb_file);
-    if Path::try_exists(&rdb_file_p)? {
-        if file_mode == "write".to_owned() {
-            return Ok(OpenOptions::new().append(true).open(&rdb_file_p)?);
-        } else if file_mode == "read".to_owned() {
-            return Ok(OpenOptions::new().read(true).open(&rdb_file_p)?);
+    // Write a temporary file and move it in place, so a failed save never
+    // leaves a truncated snapshot behind.
+    let tmp_path = rdb_path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    writer.write_all(&MAGIC_STRING)?;
+
+    // Meta Data
+    writer.write_u8(OPCODE_AUX)?;
+    write_string(&mut writer, b"redis-ver")?;
+    write_string(&mut writer, b"7.2.0")?;
+
+    // A subsection for each database with keys in it.
+    for (index, db) in dbs.iter().enumerate() {
+        let entries: Vec<_> = db.iter().collect();
+        if entries.is_empty() {
+            continue;
+        }
+        writer.write_u8(OPCODE_SELECTDB)?;
+        write_length(&mut writer, index)?;
+
+        let expire_size = entries
+            .iter()
+            .filter(|(_, entry)| entry.expires_at.is_some())
+            .count();
+        writer.write_u8(OPCODE_RESIZEDB)?;
+        write_length(&mut writer, entries.len())?;
+        write_length(&mut writer, expire_size)?;
+
+        for (key, entry) in entries {
+            if let Some(expires_at) = entry.expires_at {
+                writer.write_u8(OPCODE_EXPIRETIME_MS)?;
+                writer.write_u64::<LittleEndian>(expires_at)?;
+            }
+            write_value(&mut writer, key, &entry.value)?;
         }
     }
-    let mut rdb_file = File::create(rdb_file_p.clone())?; // .expect("RDB File creation failed");
 
-    // MAGIC_STRING
-    rdb_file.write_all(&MAGIC_STRING)?;
+    // Checksums are disabled, which readers recognise by eight zero bytes.
+    writer.write_u8(OPCODE_EOF)?;
+    writer.write_u64::<LittleEndian>(0)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    fs::rename(tmp_path, rdb_path)?;
+    Ok(())
+}
 
-    // Meta Data
-    let op_code: u8 = 0xFA;
-    let metadata_inputs: Vec<String> = vec!["redis-ver".to_string(), "6.0.16".to_string()];
-    let mut metadata = op_code.to_le_bytes().to_vec();
-    for data in metadata_inputs {
-        let mut d = (data.len() as u8).to_le_bytes().to_vec();
-        d.extend(data.as_bytes().to_vec());
-        metadata.extend(d);
-        // len_encoded_str.extend(data.as_bytes().to_vec());
-    }
-
-    rdb_file.write_all(&metadata)?;
-    if file_mode == "read".to_owned() {
-        drop(rdb_file);
-        return Ok(OpenOptions::new().read(true).open(&rdb_file_p)?);
-    } else {
-        return Ok(rdb_file);
+fn value_type(value: &RedisValue) -> u8 {
+    match value {
+        RedisValue::String(_) => TYPE_STRING,
+        RedisValue::List(_) => TYPE_LIST,
+        RedisValue::Set(_) => TYPE_SET,
+        RedisValue::Hash(hash) if hash.next_expiry().is_some() => TYPE_HASH_METADATA,
+        RedisValue::Hash(_) => TYPE_HASH,
+        RedisValue::ZSet(_) => TYPE_ZSET_2,
+        RedisValue::Stream(_) => TYPE_STREAM_LISTPACKS_3,
     }
 }
 
-pub async fn write_to_disk(mut db: KeyValueStore<String, String>) -> anyhow::Result<()> {
-    let mut rdb_file = init_db("write".to_string()).await?;
-
-    // Database Subsection
-    let op_code: u8 = 0xFE;
-    let db_index: u8 = 0;
-    rdb_file
-        .write_all(&[op_code])
-        .expect("Writing db subsection failed - op_code");
-    rdb_file
-        .write_all(&[db_index])
-        .expect("Writing db subsection failed - db_index");
-
-    let op_code: u8 = 0xFB;
-    let ht_size = db.get_ht_size().await;
-    let ht_expire_size = db.get_ht_expire_size().await;
-    rdb_file
-        .write_all(&[op_code])
-        .expect("Writing db subsection failed - ht_size - op_code");
-    rdb_file
-        .write_all(&length_encoded_int(ht_size))
-        .expect("Writing db subsection failed - ht_size");
-    rdb_file
-        .write_all(&length_encoded_int(ht_expire_size))
-        .expect("Writing db subsection failed - ht_expire_size");
-    for (k, item) in db.iter().await {
-        let mut d = Vec::from([0u8]); // 1-byte flag - string encoding
-        d.extend((k.len() as u8).to_le_bytes());
-        d.extend(k.as_bytes());
-        d.extend((item.0.len() as u8).to_le_bytes());
-        d.extend(item.0.as_bytes());
-        rdb_file
-            .write_all(&d)
-            .expect("Writing db subsection failed - data");
+fn write_value(writer: &mut impl Write, key: &[u8], value: &RedisValue) -> io::Result<()> {
+    writer.write_u8(value_type(value))?;
+    write_string(writer, key)?;
+    match value {
+        RedisValue::String(s) => write_string(writer, s)?,
+        RedisValue::List(list) => {
+            write_length(writer, list.len())?;
+            for item in list {
+                write_string(writer, item)?;
+            }
+        }
+        RedisValue::Set(set) => {
+            write_length(writer, set.len())?;
+            for member in set {
+                write_string(writer, member)?;
+            }
+        }
+        RedisValue::Hash(hash) => match hash.next_expiry() {
+            None => {
+                write_length(writer, hash.len())?;
+                for (field, value) in hash.iter() {
+                    write_string(writer, field)?;
+                    write_string(writer, value)?;
+                }
+            }
+            // Field expiry times are stored relative to the earliest one,
+            // plus one so that zero can stand for none.
+            Some(min_expiry) => {
+                writer.write_u64::<LittleEndian>(min_expiry)?;
+                write_length(writer, hash.len())?;
+                for (field, value) in hash.iter() {
+                    let ttl = hash.expires_at(field).map_or(0, |expires_at| {
+                        // `min_expiry` is the earliest of these times.
+                        debug_assert!(expires_at >= min_expiry);
+                        expires_at - min_expiry + 1
+                    });
+                    write_length(writer, ttl as usize)?;
+                    write_string(writer, field)?;
+                    write_string(writer, value)?;
+                }
+            }
+        },
+        RedisValue::ZSet(zset) => {
+            write_length(writer, zset.len())?;
+            for (member, score) in zset.iter() {
+                write_string(writer, member)?;
+                writer.write_f64::<LittleEndian>(score)?;
+            }
+        }
+        RedisValue::Stream(stream) => write_stream(writer, stream)?,
     }
     Ok(())
 }
 
-fn length_encoded_int(n: usize) -> Vec<u8> {
-    println!("n = {}", n);
-    let mut encoded_int: Vec<u8> = Vec::new();
+// Entries go in listpack nodes of up to `STREAM_NODE_MAX_ENTRIES`, keyed by
+// the ID of their first entry, which the IDs in the node are relative to.
+// A node opens with its master entry: the entry counts and the fields of the
+// first entry, which entries with the same fields then leave out. Every
+// entry ends with the number of elements it took, for walking backwards.
+fn write_stream(writer: &mut impl Write, stream: &Stream) -> io::Result<()> {
+    let entries: Vec<_> = stream.iter().collect();
+    write_length(writer, entries.chunks(STREAM_NODE_MAX_ENTRIES).len())?;
+    for node in entries.chunks(STREAM_NODE_MAX_ENTRIES) {
+        let (master_id, master_fields) = node[0];
+        let mut listpack = ListpackWriter::default();
+        listpack.push_int(node.len() as i64);
+        listpack.push_int(0);
+        listpack.push_int(master_fields.len() as i64);
+        for (field, _) in master_fields {
+            listpack.push_str(field);
+        }
+        listpack.push_int(0);
+
+        for &(id, fields) in node {
+            let same_fields = fields.len() == master_fields.len()
+                && fields
+                    .iter()
+                    .zip(master_fields)
+                    .all(|((field, _), (master_field, _))| field == master_field);
+            listpack.push_int(if same_fields {
+                STREAM_ITEM_FLAG_SAMEFIELDS
+            } else {
+                0
+            });
+            listpack.push_int(
+                id.milliseconds_time
+                    .wrapping_sub(master_id.milliseconds_time) as i64,
+            );
+            listpack.push_int(id.sequence_number.wrapping_sub(master_id.sequence_number) as i64);
+            if same_fields {
+                for (_, value) in fields {
+                    listpack.push_str(value);
+                }
+            } else {
+                listpack.push_int(fields.len() as i64);
+                for (field, value) in fields {
+                    listpack.push_str(field);
+                    listpack.push_str(value);
+                }
+            }
+            let elements = if same_fields {
+                fields.len() + 3
+            } else {
+                2 * fields.len() + 4
+            };
+            listpack.push_int(elements as i64);
+        }
 
-    if n <= 63usize {
-        encoded_int.extend((n as u8).to_le_bytes());
-    } else if n >= 64usize && n <= 16383usize {
-        encoded_int.extend((n as u16).to_le_bytes());
-    } else if n >= 16384usize && n <= 4_294_967_295usize {
-        encoded_int.extend((n as u32).to_le_bytes());
+        let mut node_key = Vec::with_capacity(16);
+        node_key.write_u64::<BigEndian>(master_id.milliseconds_time)?;
+        node_key.write_u64::<BigEndian>(master_id.sequence_number)?;
+        write_string(writer, &node_key)?;
+        write_string(writer, &listpack.finish())?;
     }
-    encoded_int
+
+    let last_id = stream.last_entry_id();
+    let first_id = entries.first().map_or(EntryID::MIN, |(id, _)| **id);
+    write_length(writer, entries.len())?;
+    write_length(writer, last_id.milliseconds_time as usize)?;
+    write_length(writer, last_id.sequence_number as usize)?;
+    write_length(writer, first_id.milliseconds_time as usize)?;
+    write_length(writer, first_id.sequence_number as usize)?;
+    // Nothing is ever deleted from a stream here, so no maximum deleted ID
+    // and every entry ever added is still there.
+    write_length(writer, 0)?;
+    write_length(writer, 0)?;
+    write_length(writer, entries.len())?;
+    // Consumer groups, of which there are none.
+    write_length(writer, 0)
 }
 
-pub async fn load_from_rdb(mut db: KeyValueStore<String, String>) -> anyhow::Result<(), Error> {
-    let file = init_db("read".to_string()).await.expect("File init failed");
-    let mut reader = BufReader::new(file);
+// -> 00	The next 6 bits represent the length
+// -> 01	Read one additional byte. The combined 14 bits represent the length
+// -> 10	Discard the remaining 6 bits. The next 4 (0x80) or 8 (0x81) bytes
+//          from the stream represent the length
+// -> 11	The next object is encoded in a special format. The remaining 6 bits
+//          indicate the format, see the `ENC_*` constants.
+fn write_length(writer: &mut impl Write, n: usize) -> io::Result<()> {
+    if n < 1 << 6 {
+        writer.write_u8(n as u8)
+    } else if n < 1 << 14 {
+        writer.write_u16::<BigEndian>(0x4000 | n as u16)
+    } else if n <= u32::MAX as usize {
+        writer.write_u8(0x80)?;
+        writer.write_u32::<BigEndian>(n as u32)
+    } else {
+        writer.write_u8(0x81)?;
+        writer.write_u64::<BigEndian>(n as u64)
+    }
+}
 
-    let mut header = [0; 9]; // The first 9 bytes contain the header.
-    reader.read_exact(&mut header)?;
+fn write_string(writer: &mut impl Write, s: &[u8]) -> io::Result<()> {
+    write_length(writer, s.len())?;
+    writer.write_all(s)
+}
+
+enum Length {
+    Plain(usize),
+    Encoded(u8),
+}
+
+fn read_length_or_encoding(reader: &mut impl Read) -> io::Result<Length> {
+    let byte = reader.read_u8()?;
+    let length = match byte >> 6 {
+        0b00 => (byte & 0x3F) as usize,
+        0b01 => ((byte & 0x3F) as usize) << 8 | reader.read_u8()? as usize,
+        0b10 => match byte {
+            0x80 => reader.read_u32::<BigEndian>()? as usize,
+            0x81 => reader.read_u64::<BigEndian>()? as usize,
+            _ => return Err(invalid_data("Invalid length encoding")),
+        },
+        _ => return Ok(Length::Encoded(byte & 0x3F)),
+    };
+    Ok(Length::Plain(length))
+}
+
+fn read_length(reader: &mut impl Read) -> io::Result<usize> {
+    match read_length_or_encoding(reader)? {
+        Length::Plain(length) => Ok(length),
+        Length::Encoded(_) => Err(invalid_data("Expected a length")),
+    }
+}
 
-    println!("MAGIC STRING: {:?}", std::str::from_utf8(&header));
-    // Validate the RDB version (example for RDB version 9).
-    let version = &header[5..9];
-    // if version != b"0009" {
-    //     return Err(io::Error::new(io::ErrorKind::InvalidData, "Unsupported RDB version").into());
-    // }
-
-    println!("RDB Version: {:?}", std::str::from_utf8(version));
-
-    while let Ok(byte) = reader.read_u8() {
-        if faster_hex::hex_string(&[byte]) == "fb" {
-            break;
-        }
-    }
-
-    // -> 00	The next 6 bits represent the length
-    // -> 01	Read one additional byte. The combined 14 bits represent the length
-    // -> 10	Discard the remaining 6 bits. The next 4 bytes from the stream represent the length
-    // -> 11	The next object is encoded in a special format. The remaining 6 bits indicate the format.
-    //          May be used to store numbers or Strings, see String Encoding:
-    //              If the value of those 6 bits is:
-    //                  0 indicates that an 8 bit integer follows
-    //                  1 indicates that a 16 bit integer follows
-    //                  2 indicates that a 32 bit integer follows
-    let byte = match reader.read_u8() {
-        Ok(byte) => byte,
-        Err(_) => return Ok(()),
+fn read_bytes(reader: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::with_capacity(len.min(MAX_PREALLOC));
+    reader.take(len as u64).read_to_end(&mut buffer)?;
+    if buffer.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "RDB file ends in the middle of a string",
+        ));
+    }
+    Ok(buffer)
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<Bytes> {
+    let s = match read_length_or_encoding(reader)? {
+        Length::Plain(len) => read_bytes(reader, len)?,
+        Length::Encoded(ENC_INT8) => reader.read_i8()?.to_string().into_bytes(),
+        Length::Encoded(ENC_INT16) => reader.read_i16::<LittleEndian>()?.to_string().into_bytes(),
+        Length::Encoded(ENC_INT32) => reader.read_i32::<LittleEndian>()?.to_string().into_bytes(),
+        Length::Encoded(ENC_LZF) => {
+            let compressed_len = read_length(reader)?;
+            let len = read_length(reader)?;
+            lzf_decompress(&read_bytes(reader, compressed_len)?, len)?
+        }
+        Length::Encoded(_) => return Err(invalid_data("Unknown string encoding")),
     };
-    let mut ht_size: Vec<Box<dyn Any>> = Vec::new();
-    match ((byte & 1 << 7) > 0, (byte & 1 << 6) > 0) {
-        (false, false) => ht_size.push(Box::new(byte)),
-        (false, true) => {
-            let number = ((byte as u16) << 8) | reader.read_u8().unwrap() as u16;
-            ht_size.push(Box::new(number));
-        }
-        (true, false) => ht_size.push(Box::new(reader.read_u32::<BigEndian>().unwrap())),
-        (true, true) => {
-            match (
-                (byte & 1 << 5) > 0,
-                (byte & 1 << 4) > 0,
-                (byte & 1 << 3) > 0,
-                (byte & 1 << 2) > 0,
-                (byte & 1 << 1) > 0,
-                (byte & 1 << 0) > 0,
-            ) {
-                (false, false, false, false, false, false) => {
-                    ht_size.push(Box::new(reader.read_u8().unwrap()))
+    Ok(Bytes::from(s))
+}
+
+fn lzf_decompress(input: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    let corrupt = || invalid_data("Corrupt LZF string");
+    let mut output = Vec::with_capacity(len.min(MAX_PREALLOC));
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 32 {
+            // Literal run of `ctrl + 1` bytes
+            let run = input.get(i..i + ctrl + 1).ok_or_else(corrupt)?;
+            output.extend_from_slice(run);
+            i += ctrl + 1;
+        } else {
+            // Back reference into the output produced so far
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(i).ok_or_else(corrupt)? as usize;
+                i += 1;
+            }
+            let offset = ((ctrl & 0x1F) << 8) + *input.get(i).ok_or_else(corrupt)? as usize + 1;
+            i += 1;
+            let start = output.len().checked_sub(offset).ok_or_else(corrupt)?;
+            for k in 0..run + 2 {
+                output.push(output[start + k]);
+            }
+        }
+    }
+    if output.len() != len {
+        return Err(corrupt());
+    }
+    Ok(output)
+}
+
+fn read_value(reader: &mut impl Read, value_type: u8, now: u64) -> anyhow::Result<RedisValue> {
+    let value = match value_type {
+        TYPE_STRING => RedisValue::String(read_string(reader)?),
+        TYPE_LIST => {
+            let len = read_length(reader)?;
+            let mut list = VecDeque::with_capacity(len.min(MAX_PREALLOC));
+            for _ in 0..len {
+                list.push_back(read_string(reader)?);
+            }
+            RedisValue::List(list)
+        }
+        TYPE_SET => {
+            let len = read_length(reader)?;
+            let mut set = Set::new();
+            for _ in 0..len {
+                set.insert(read_string(reader)?);
+            }
+            RedisValue::Set(set)
+        }
+        TYPE_ZSET | TYPE_ZSET_2 => {
+            let len = read_length(reader)?;
+            let mut zset = ZSet::default();
+            for _ in 0..len {
+                let member = read_string(reader)?;
+                let score = if value_type == TYPE_ZSET {
+                    read_string_score(reader)?
+                } else {
+                    reader.read_f64::<LittleEndian>()?
+                };
+                zset.insert(member, score);
+            }
+            RedisValue::ZSet(zset)
+        }
+        TYPE_HASH => {
+            let len = read_length(reader)?;
+            let mut hash = Hash::default();
+            for _ in 0..len {
+                hash.insert(read_string(reader)?, read_string(reader)?);
+            }
+            RedisValue::Hash(hash)
+        }
+        TYPE_HASH_METADATA => {
+            let min_expiry = reader.read_u64::<LittleEndian>()?;
+            let len = read_length(reader)?;
+            let mut hash = Hash::default();
+            for _ in 0..len {
+                let ttl = read_length(reader)? as u64;
+                let field = read_string(reader)?;
+                let value = read_string(reader)?;
+                let expires_at = match ttl {
+                    0 => None,
+                    ttl => Some(
+                        min_expiry
+                            .checked_add(ttl - 1)
+                            .ok_or_else(|| invalid_data("Hash field expiry out of range"))?,
+                    ),
+                };
+                if expires_at.is_some_and(|t| t <= now) {
+                    continue;
                 }
-                (false, false, false, false, false, true) => {
-                    ht_size.push(Box::new(reader.read_u16::<BigEndian>().unwrap()))
+                hash.insert(field.clone(), value);
+                if let Some(expires_at) = expires_at {
+                    hash.set_expires_at(&field, expires_at);
                 }
-                (false, false, false, false, true, false) => {
-                    ht_size.push(Box::new(reader.read_u32::<BigEndian>().unwrap()))
+            }
+            RedisValue::Hash(hash)
+        }
+        TYPE_STREAM_LISTPACKS | TYPE_STREAM_LISTPACKS_2 | TYPE_STREAM_LISTPACKS_3 => {
+            RedisValue::Stream(read_stream(reader, value_type)?)
+        }
+        TYPE_LIST_ZIPLIST => RedisValue::List(
+            read_compact(reader, ziplist::decode)?
+                .into_iter()
+                .map(ListpackEntry::into_bytes)
+                .collect(),
+        ),
+        TYPE_LIST_QUICKLIST | TYPE_LIST_QUICKLIST_2 => {
+            let mut list = VecDeque::new();
+            for _ in 0..read_length(reader)? {
+                let container = if value_type == TYPE_LIST_QUICKLIST {
+                    QUICKLIST_NODE_PACKED
+                } else {
+                    read_length(reader)?
+                };
+                let elements = match container {
+                    QUICKLIST_NODE_PLAIN => {
+                        list.push_back(read_string(reader)?);
+                        continue;
+                    }
+                    QUICKLIST_NODE_PACKED if value_type == TYPE_LIST_QUICKLIST => {
+                        read_compact(reader, ziplist::decode)?
+                    }
+                    QUICKLIST_NODE_PACKED => read_compact(reader, listpack::decode)?,
+                    _ => bail!("Unknown quicklist node container {}", container),
+                };
+                list.extend(elements.into_iter().map(ListpackEntry::into_bytes));
+            }
+            RedisValue::List(list)
+        }
+        TYPE_SET_INTSET => {
+            let members =
+                decode_intset(&read_string(reader)?).ok_or_else(|| anyhow!("Corrupt intset"))?;
+            RedisValue::Set(
+                members
+                    .into_iter()
+                    .map(|member| Bytes::from(member.to_string()))
+                    .collect(),
+            )
+        }
+        TYPE_SET_LISTPACK => RedisValue::Set(
+            read_compact(reader, listpack::decode)?
+                .into_iter()
+                .map(ListpackEntry::into_bytes)
+                .collect(),
+        ),
+        TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+            let decode = if value_type == TYPE_ZSET_ZIPLIST {
+                ziplist::decode
+            } else {
+                listpack::decode
+            };
+            let mut zset = ZSet::default();
+            for (member, score) in into_pairs(read_compact(reader, decode)?)? {
+                zset.insert(member, compact_score(score)?);
+            }
+            RedisValue::ZSet(zset)
+        }
+        TYPE_HASH_ZIPMAP => {
+            let pairs =
+                decode_zipmap(&read_string(reader)?).ok_or_else(|| anyhow!("Corrupt zipmap"))?;
+            let mut hash = Hash::default();
+            for (field, value) in pairs {
+                hash.insert(field, value);
+            }
+            RedisValue::Hash(hash)
+        }
+        TYPE_HASH_ZIPLIST | TYPE_HASH_LISTPACK => {
+            let decode = if value_type == TYPE_HASH_ZIPLIST {
+                ziplist::decode
+            } else {
+                listpack::decode
+            };
+            let mut hash = Hash::default();
+            for (field, value) in into_pairs(read_compact(reader, decode)?)? {
+                hash.insert(field, value.into_bytes());
+            }
+            RedisValue::Hash(hash)
+        }
+        // The earliest expiry time, then field, value and absolute expiry
+        // time triples, with 0 for fields without one.
+        TYPE_HASH_LISTPACK_EX => {
+            reader.read_u64::<LittleEndian>()?;
+            let mut elements = read_compact(reader, listpack::decode)?.into_iter();
+            let mut hash = Hash::default();
+            while let Some(field) = elements.next() {
+                let (Some(value), Some(expires_at)) = (elements.next(), elements.next()) else {
+                    bail!("Corrupt hash listpack");
+                };
+                let expires_at = match expires_at.as_int() {
+                    Some(0) => None,
+                    Some(t) => Some(t as u64),
+                    None => bail!("Corrupt hash listpack"),
+                };
+                if expires_at.is_some_and(|t| t <= now) {
+                    continue;
+                }
+                let field = field.into_bytes();
+                hash.insert(field.clone(), value.into_bytes());
+                if let Some(expires_at) = expires_at {
+                    hash.set_expires_at(&field, expires_at);
                 }
-                (_, _, _, _, _, _) => {}
             }
+            RedisValue::Hash(hash)
         }
+        _ => bail!("Unsupported RDB value type {}", value_type),
     };
+    Ok(value)
+}
 
-    let ht_size = ht_size.pop().unwrap();
-    let ht_size = if let Some(value) = ht_size.downcast_ref::<u8>() {
-        value.clone() as usize
-    } else if let Some(value) = ht_size.downcast_ref::<u16>() {
-        value.clone() as usize
-    } else if let Some(value) = ht_size.downcast_ref::<u32>() {
-        value.clone() as usize
-    } else {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid Hash Table Size").into());
+fn read_stream(reader: &mut impl Read, value_type: u8) -> anyhow::Result<Stream> {
+    let mut entries = BTreeMap::new();
+    for _ in 0..read_length(reader)? {
+        let node_key = read_string(reader)?;
+        if node_key.len() != 16 {
+            bail!("Invalid stream node key");
+        }
+        let master_id = EntryID {
+            milliseconds_time: u64::from_be_bytes(node_key[..8].try_into()?),
+            sequence_number: u64::from_be_bytes(node_key[8..].try_into()?),
+        };
+        let elements = listpack::decode(&read_string(reader)?)
+            .and_then(|elements| read_stream_node(master_id, elements, &mut entries));
+        if elements.is_none() {
+            bail!("Corrupt stream node");
+        }
+    }
+
+    read_length(reader)?;
+    let last_entry_id = EntryID {
+        milliseconds_time: read_length(reader)? as u64,
+        sequence_number: read_length(reader)? as u64,
     };
+    if value_type >= TYPE_STREAM_LISTPACKS_2 {
+        // The first ID, the largest deleted one and the count of entries
+        // ever added, which are not kept here.
+        for _ in 0..5 {
+            read_length(reader)?;
+        }
+    }
 
-    // ht_expire_size
-    let byte = reader.read_u8().unwrap();
-    let mut ht_expire_size: Vec<Box<dyn Any>> = Vec::new();
-    match ((byte & 1 << 7) > 0, (byte & 1 << 6) > 0) {
-        (false, false) => ht_expire_size.push(Box::new(byte)),
-        (false, true) => {
-            let number = ((byte as u16) << 8) | reader.read_u8().unwrap() as u16;
-            ht_expire_size.push(Box::new(number));
-        }
-        (true, false) => ht_expire_size.push(Box::new(reader.read_u32::<BigEndian>().unwrap())),
-        (true, true) => {
-            match (
-                (byte & 1 << 5) > 0,
-                (byte & 1 << 4) > 0,
-                (byte & 1 << 3) > 0,
-                (byte & 1 << 2) > 0,
-                (byte & 1 << 1) > 0,
-                (byte & 1 << 0) > 0,
-            ) {
-                (false, false, false, false, false, false) => {
-                    ht_expire_size.push(Box::new(reader.read_u8().unwrap()))
-                }
-                (false, false, false, false, false, true) => {
-                    ht_expire_size.push(Box::new(reader.read_u16::<BigEndian>().unwrap()))
-                }
-                (false, false, false, false, true, false) => {
-                    ht_expire_size.push(Box::new(reader.read_u32::<BigEndian>().unwrap()))
-                }
-                (_, _, _, _, _, _) => {}
+    // Consumer groups are not supported, so files Redis saved with some
+    // load without them.
+    let groups = read_length(reader)?;
+    if groups > 0 {
+        log::warn!("Skipping {} stream consumer groups", groups);
+    }
+    for _ in 0..groups {
+        read_string(reader)?;
+        read_length(reader)?;
+        read_length(reader)?;
+        if value_type >= TYPE_STREAM_LISTPACKS_2 {
+            read_length(reader)?;
+        }
+        // The pending entries: ID, delivery time and delivery count.
+        for _ in 0..read_length(reader)? {
+            read_bytes(reader, 16)?;
+            reader.read_u64::<LittleEndian>()?;
+            read_length(reader)?;
+        }
+        // The consumers: name, seen time, active time and pending IDs.
+        for _ in 0..read_length(reader)? {
+            read_string(reader)?;
+            reader.read_u64::<LittleEndian>()?;
+            if value_type >= TYPE_STREAM_LISTPACKS_3 {
+                reader.read_u64::<LittleEndian>()?;
+            }
+            for _ in 0..read_length(reader)? {
+                read_bytes(reader, 16)?;
+            }
+        }
+    }
+    Ok(Stream::from_entries(entries, last_entry_id))
+}
+
+// Reads the entries of a stream node, laid out as `write_stream` describes,
+// leaving out the ones flagged deleted. `None` if the node is malformed.
+fn read_stream_node(
+    master_id: EntryID,
+    elements: Vec<ListpackEntry>,
+    entries: &mut BTreeMap<EntryID, Vec<(Bytes, Bytes)>>,
+) -> Option<()> {
+    let mut elements = elements.into_iter();
+    let int = |elements: &mut std::vec::IntoIter<ListpackEntry>| elements.next()?.as_int();
+    let count = int(&mut elements)? + int(&mut elements)?;
+    let master_fields: Vec<Bytes> = (0..int(&mut elements)?)
+        .map(|_| elements.next().map(ListpackEntry::into_bytes))
+        .collect::<Option<_>>()?;
+    int(&mut elements)?;
+
+    for _ in 0..count {
+        let flags = int(&mut elements)?;
+        let id = EntryID {
+            milliseconds_time: master_id
+                .milliseconds_time
+                .wrapping_add(int(&mut elements)? as u64),
+            sequence_number: master_id
+                .sequence_number
+                .wrapping_add(int(&mut elements)? as u64),
+        };
+        let fields: Vec<(Bytes, Bytes)> = if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+            master_fields
+                .iter()
+                .map(|field| Some((field.clone(), elements.next()?.into_bytes())))
+                .collect::<Option<_>>()?
+        } else {
+            (0..int(&mut elements)?)
+                .map(|_| {
+                    let field = elements.next()?.into_bytes();
+                    Some((field, elements.next()?.into_bytes()))
+                })
+                .collect::<Option<_>>()?
+        };
+        int(&mut elements)?;
+        if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+            entries.insert(id, fields);
+        }
+    }
+    Some(())
+}
+
+// Reads a string holding a ziplist or a listpack and decodes it.
+fn read_compact(
+    reader: &mut impl Read,
+    decode: fn(&[u8]) -> Option<Vec<ListpackEntry>>,
+) -> anyhow::Result<Vec<ListpackEntry>> {
+    decode(&read_string(reader)?).ok_or_else(|| anyhow!("Corrupt ziplist or listpack"))
+}
+
+// The elements of a compact hash or sorted set, which alternate between
+// fields or members and their values or scores.
+fn into_pairs(elements: Vec<ListpackEntry>) -> anyhow::Result<Vec<(Bytes, ListpackEntry)>> {
+    let mut elements = elements.into_iter();
+    let mut pairs = Vec::new();
+    while let Some(key) = elements.next() {
+        let Some(value) = elements.next() else {
+            bail!("Odd number of elements in a compact hash or sorted set");
+        };
+        pairs.push((key.into_bytes(), value));
+    }
+    Ok(pairs)
+}
+
+// A score in a compact sorted set: an integer, or text such as `1.5` or
+// `inf`.
+fn compact_score(score: ListpackEntry) -> anyhow::Result<f64> {
+    match score {
+        ListpackEntry::Int(score) => Ok(score as f64),
+        ListpackEntry::Str(score) => std::str::from_utf8(&score)
+            .ok()
+            .and_then(|score| score.parse().ok())
+            .ok_or_else(|| anyhow!("invalid sorted set score")),
+    }
+}
+
+// An intset: the width of its integers, 2, 4 or 8 bytes, and their count,
+// then the integers themselves in ascending order, all little endian.
+fn decode_intset(intset: &[u8]) -> Option<Vec<i64>> {
+    let width = u32::from_le_bytes(intset.get(..4)?.try_into().ok()?) as usize;
+    let len = u32::from_le_bytes(intset.get(4..8)?.try_into().ok()?) as usize;
+    if ![2, 4, 8].contains(&width) {
+        return None;
+    }
+    let contents = intset.get(8..8 + len.checked_mul(width)?)?;
+    let shift = 64 - 8 * width as u32;
+    Some(
+        contents
+            .chunks_exact(width)
+            .map(|int| {
+                let mut buf = [0; 8];
+                buf[..width].copy_from_slice(int);
+                i64::from_le_bytes(buf) << shift >> shift
+            })
+            .collect(),
+    )
+}
+
+// A zipmap, the hash encoding before ziplists: a count byte, then each
+// field and value as their length, the value also with a count of unused
+// bytes after it, and an end marker. Lengths take a byte, or 254 and four
+// more.
+fn decode_zipmap(zipmap: &[u8]) -> Option<Vec<(Bytes, Bytes)>> {
+    let length = |i: &mut usize| -> Option<usize> {
+        match *zipmap.get(*i)? {
+            254 => {
+                let len = u32::from_le_bytes(zipmap.get(*i + 1..*i + 5)?.try_into().ok()?);
+                *i += 5;
+                Some(len as usize)
+            }
+            255 => None,
+            len => {
+                *i += 1;
+                Some(len as usize)
             }
         }
     };
+    let mut pairs = Vec::new();
+    let mut i = 1;
+    while *zipmap.get(i)? != 255 {
+        let field_len = length(&mut i)?;
+        let field = Bytes::copy_from_slice(zipmap.get(i..i + field_len)?);
+        i += field_len;
+        let value_len = length(&mut i)?;
+        let free = *zipmap.get(i)? as usize;
+        let value = Bytes::copy_from_slice(zipmap.get(i + 1..i + 1 + value_len)?);
+        i += 1 + value_len + free;
+        pairs.push((field, value));
+    }
+    Some(pairs)
+}
 
-    let ht_expire_size = ht_expire_size.pop().unwrap();
-    let ht_expire_size = if let Some(value) = ht_expire_size.downcast_ref::<u8>() {
-        value.clone() as usize
-    } else if let Some(value) = ht_expire_size.downcast_ref::<u16>() {
-        value.clone() as usize
-    } else if let Some(value) = ht_expire_size.downcast_ref::<u32>() {
-        value.clone() as usize
-    } else {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid Hash Table Size").into());
+// A length byte, with 253, 254 and 255 standing for NaN, +inf and -inf, then
+// the score as text.
+fn read_string_score(reader: &mut impl Read) -> io::Result<f64> {
+    match reader.read_u8()? {
+        253 => Ok(f64::NAN),
+        254 => Ok(f64::INFINITY),
+        255 => Ok(f64::NEG_INFINITY),
+        len => std::str::from_utf8(&read_bytes(reader, len as usize)?)
+            .ok()
+            .and_then(|score| score.parse().ok())
+            .ok_or_else(|| invalid_data("invalid sorted set score")),
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn load_from_rdb(dbs: &mut [KeyValueStore]) -> anyhow::Result<(), Error> {
+    load_rdb(&rdb_path(), dbs)
+}
+
+fn load_rdb(rdb_path: &Path, dbs: &mut [KeyValueStore]) -> anyhow::Result<(), Error> {
+    let file = match File::open(rdb_path) {
+        Ok(file) => file,
+        // Nothing saved yet: start with an empty keyspace.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
     };
+    let mut reader = BufReader::new(file);
 
-    println!("{} {}", ht_size, ht_expire_size);
-
-    // Value Type
-    // ==========
-    // A one byte flag indicates encoding used to save the Value.
-    // 0 = String Encoding
-    // 1 = List Encoding
-    // 2 = Set Encoding
-    // 3 = Sorted Set Encoding
-    // 4 = Hash Encoding
-    // 9 = Zipmap Encoding
-    // 10 = Ziplist Encoding
-    // 11 = Intset Encoding
-    // 12 = Sorted Set in Ziplist Encoding
-    // 13 = Hashmap in Ziplist Encoding (Introduced in RDB version 4)
-    // 14 = List in Quicklist encoding (Introduced in RDB version 7)
-    // Key
-    // ===
-    // The key is simply encoded as a Redis string. See the section String Encoding to learn how the key is encoded.
-    //
-    // Value
-    // =====
-    // The value is parsed according to the previously read Value Type
-    let mut key: Option<String> = None;
-    let mut value: Option<String> = None;
-    let mut expiry: Option<Duration> = None;
-    let mut i = 0usize;
-    while i < ht_size {
-        let byte = match reader.read_u8() {
-            Ok(byte) => byte,
-            Err(e) => return Err(e.into()),
-        };
-        let byte_hex = faster_hex::hex_string(&[byte]);
-        match byte_hex.as_str() {
-            "00" => {
-                // key - value pair
-                let key_len = reader.read_u8().unwrap() as usize;
-                let mut buffer: Vec<u8> = vec![0; key_len]; // Vec::with_capacity(key_len);
-                reader.read_exact(&mut buffer).unwrap();
-                key = Some(String::from_utf8(buffer).unwrap());
-
-                // result.push(key);
-                let value_len = reader.read_u8().unwrap() as usize;
-                let mut buffer: Vec<u8> = vec![0; value_len]; // Vec::with_capacity(value_len);
-                reader.read_exact(&mut buffer).unwrap();
-                value = Some(String::from_utf8(buffer).unwrap());
-                i += 1;
+    let mut header = [0; 9]; // The first 9 bytes contain the header.
+    reader.read_exact(&mut header)?;
+    if &header[..5] != b"REDIS" {
+        bail!("Not an RDB file");
+    }
+
+    let now = now_ms();
+    // Keys before any SELECTDB go to database 0.
+    let mut db = 0;
+    let mut expires_at: Option<u64> = None;
+    loop {
+        match reader.read_u8()? {
+            OPCODE_EOF => break,
+            OPCODE_AUX => {
+                read_string(&mut reader)?;
+                read_string(&mut reader)?;
             }
-            "fc" | "fd" => {
-                // let mut buffer: Vec<u8> = vec![0; 8]; // Vec::with_capacity(key_len);
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Time went backwards");
-                let duration = if &byte_hex == "fc" {
-                    let expiry_unixtime = reader.read_u64::<LittleEndian>().unwrap();
-                    let now = now.as_millis() as u64;
-                    if expiry_unixtime > now {
-                        let time_left = expiry_unixtime - now;
-
-                        Duration::from_millis(time_left)
-                    } else {
-                        Duration::from_millis(0)
-                    }
-                } else {
-                    let expiry_unixtime = reader.read_u32::<LittleEndian>().unwrap() as u64;
-                    let now = now.as_secs();
-                    if expiry_unixtime > now {
-                        let time_left = expiry_unixtime - now;
-                        Duration::from_secs(time_left)
-                    } else {
-                        Duration::from_secs(0)
-                    }
-                };
-                expiry = Some(Duration::new(duration.as_secs(), duration.subsec_nanos()));
+            OPCODE_SELECTDB => {
+                db = read_length(&mut reader)?;
+                if db >= dbs.len() {
+                    bail!(
+                        "RDB file has database {}, but the server only has {}",
+                        db,
+                        dbs.len()
+                    );
+                }
             }
-            "ff" => {
-                println!("EOF = ff");
-                break;
+            OPCODE_RESIZEDB => {
+                read_length(&mut reader)?;
+                read_length(&mut reader)?;
+            }
+            OPCODE_EXPIRETIME_MS => expires_at = Some(reader.read_u64::<LittleEndian>()?),
+            OPCODE_EXPIRETIME => {
+                expires_at = Some(reader.read_u32::<LittleEndian>()? as u64 * 1000);
+            }
+            value_type => {
+                let key = read_string(&mut reader)?;
+                let value = read_value(&mut reader, value_type, now)?;
+                // Keys that expired while the server was down are dropped,
+                // and so are hashes whose fields all did.
+                match expires_at.take() {
+                    Some(t) if t <= now => {}
+                    _ if value.is_empty_collection() => {}
+                    expires_at => dbs[db].insert(key, value, expires_at),
+                }
             }
-            _ => {}
-        }
-        // println!("{:?} {:?} {:?}", key.take(), value.take(), expiry.take());
-        if key.is_some() && value.is_some() {
-            db.insert(key.take().unwrap(), value.take().unwrap(), expiry.take())
-                .await;
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry_id(milliseconds_time: u64, sequence_number: u64) -> EntryID {
+        EntryID {
+            milliseconds_time,
+            sequence_number,
+        }
+    }
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(Bytes, Bytes)> {
+        pairs
+            .iter()
+            .map(|(field, value)| {
+                (
+                    Bytes::from(field.to_string()),
+                    Bytes::from(value.to_string()),
+                )
+            })
+            .collect()
+    }
+
+    fn round_trip(dbs: &[KeyValueStore]) -> Vec<KeyValueStore> {
+        let path = std::env::temp_dir().join(format!(
+            "rdb-test-{}-{:?}.rdb",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        write_rdb(&path, dbs).unwrap();
+        let mut loaded: Vec<_> = dbs.iter().map(|_| KeyValueStore::new()).collect();
+        let result = load_rdb(&path, &mut loaded);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+        loaded
+    }
+
+    fn stream_entries(db: &mut KeyValueStore, key: &[u8]) -> Vec<(EntryID, Vec<(Bytes, Bytes)>)> {
+        match db.get(key) {
+            Some(RedisValue::Stream(stream)) => stream
+                .iter()
+                .map(|(id, fields)| (*id, fields.clone()))
+                .collect(),
+            other => panic!("expected a stream, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn streams_round_trip() {
+        // More entries than fit in one node, with IDs far and near, some
+        // with the fields of their node's first entry and some without.
+        let mut entries = BTreeMap::new();
+        for i in 0..250u64 {
+            let pairs = if i % 3 == 0 {
+                fields(&[
+                    ("temperature", "20"),
+                    ("humidity", "long value ".repeat(10).trim()),
+                ])
+            } else {
+                fields(&[("sensor", &i.to_string())])
+            };
+            entries.insert(entry_id(1_700_000_000_000 + i * 1000, i % 2), pairs);
+        }
+        entries.insert(entry_id(u64::MAX, 7), fields(&[("last", "")]));
+        let stream = Stream::from_entries(entries.clone(), entry_id(u64::MAX, 7));
+
+        let mut dbs = vec![KeyValueStore::new()];
+        dbs[0].insert(Bytes::from("s"), RedisValue::Stream(stream), None);
+        dbs[0].insert(
+            Bytes::from("empty"),
+            RedisValue::Stream(Stream::from_entries(BTreeMap::new(), entry_id(5, 1))),
+            None,
+        );
+
+        let mut loaded = round_trip(&dbs);
+        assert_eq!(
+            stream_entries(&mut loaded[0], b"s"),
+            entries.into_iter().collect::<Vec<_>>()
+        );
+        assert!(stream_entries(&mut loaded[0], b"empty").is_empty());
+        match loaded[0].get(b"empty") {
+            Some(RedisValue::Stream(stream)) => assert_eq!(stream.last_entry_id(), entry_id(5, 1)),
+            other => panic!("expected a stream, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn databases_round_trip() {
+        let mut dbs: Vec<_> = (0..4).map(|_| KeyValueStore::new()).collect();
+        dbs[0].insert(
+            Bytes::from("k"),
+            RedisValue::String(Bytes::from("db0")),
+            None,
+        );
+        dbs[2].insert(
+            Bytes::from("k"),
+            RedisValue::String(Bytes::from("db2")),
+            Some(now_ms() + 60_000),
+        );
+        let mut entries = BTreeMap::new();
+        entries.insert(entry_id(1, 0), fields(&[("a", "1")]));
+        entries.insert(entry_id(1, 1), fields(&[("a", "2"), ("b", "3")]));
+        dbs[3].insert(
+            Bytes::from("events"),
+            RedisValue::Stream(Stream::from_entries(entries.clone(), entry_id(1, 1))),
+            None,
+        );
+
+        let mut loaded = round_trip(&dbs);
+        assert_eq!(loaded[0].get_ht_size(), 1);
+        assert!(matches!(loaded[0].get(b"k"), Some(RedisValue::String(s)) if s == "db0"));
+        assert_eq!(loaded[1].get_ht_size(), 0);
+        assert!(matches!(loaded[2].get(b"k"), Some(RedisValue::String(s)) if s == "db2"));
+        assert!(loaded[2].expires_at(b"k").is_some());
+        assert!(loaded[3].get(b"k").is_none());
+        assert_eq!(
+            stream_entries(&mut loaded[3], b"events"),
+            entries.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    fn listpack(elements: &[&str]) -> Vec<u8> {
+        let mut listpack = ListpackWriter::default();
+        for element in elements {
+            match element.parse() {
+                Ok(int) => listpack.push_int(int),
+                Err(_) => listpack.push_str(element.as_bytes()),
+            }
+        }
+        listpack.finish()
+    }
+
+    fn members(value: Option<&RedisValue>) -> Vec<Bytes> {
+        let mut members: Vec<Bytes> = match value {
+            Some(RedisValue::Set(set)) => set.iter().cloned().collect(),
+            Some(RedisValue::List(list)) => return list.iter().cloned().collect(),
+            Some(RedisValue::Hash(hash)) => hash
+                .iter()
+                .map(|(field, value)| Bytes::from([&field[..], b"=", value].concat()))
+                .collect(),
+            Some(RedisValue::ZSet(zset)) => {
+                return zset
+                    .iter()
+                    .map(|(member, score)| {
+                        Bytes::from(format!("{}={}", String::from_utf8_lossy(member), score))
+                    })
+                    .collect()
+            }
+            other => panic!("expected a collection, got {:?}", other),
+        };
+        members.sort();
+        members
+    }
+
+    /// Loads an RDB file made of `file` into a single database.
+    fn load_file(file: &[u8]) -> anyhow::Result<Vec<KeyValueStore>> {
+        let path = std::env::temp_dir().join(format!(
+            "rdb-file-test-{}-{:?}.rdb",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        fs::write(&path, file).unwrap();
+        let mut dbs = vec![KeyValueStore::new()];
+        let result = load_rdb(&path, &mut dbs);
+        fs::remove_file(&path).unwrap();
+        result.map(|_| dbs)
+    }
+
+    #[test]
+    fn corrupt_lengths_fail_to_load() {
+        // A list claiming u64::MAX elements.
+        let mut file = MAGIC_STRING.to_vec();
+        file.push(TYPE_LIST);
+        write_string(&mut file, b"l").unwrap();
+        file.push(0x81);
+        file.extend(u64::MAX.to_be_bytes());
+        assert!(load_file(&file).is_err());
+
+        // A string claiming 2^36 bytes, of which only a few follow.
+        let mut file = MAGIC_STRING.to_vec();
+        file.push(TYPE_STRING);
+        write_string(&mut file, b"s").unwrap();
+        file.push(0x81);
+        file.extend((1u64 << 36).to_be_bytes());
+        file.extend(b"short");
+        assert!(load_file(&file).is_err());
+    }
+
+    #[test]
+    fn compact_encodings_load() {
+        let mut file = MAGIC_STRING.to_vec();
+        let mut value = |value_type: u8, key: &str, payload: &[u8]| {
+            file.push(value_type);
+            write_string(&mut file, key.as_bytes()).unwrap();
+            file.extend(payload);
+        };
+        let mut payload = Vec::new();
+        let mut intset = vec![4, 0, 0, 0, 2, 0, 0, 0];
+        intset.extend((-70000i32).to_le_bytes());
+        intset.extend(3i32.to_le_bytes());
+        write_string(&mut payload, &intset).unwrap();
+        value(TYPE_SET_INTSET, "intset", &payload);
+
+        let mut payload = Vec::new();
+        write_string(&mut payload, &listpack(&["a", "7"])).unwrap();
+        value(TYPE_SET_LISTPACK, "set", &payload);
+
+        let mut payload = Vec::new();
+        write_string(&mut payload, &listpack(&["f", "v", "n", "1"])).unwrap();
+        value(TYPE_HASH_LISTPACK, "hash", &payload);
+
+        let mut payload = Vec::new();
+        write_string(
+            &mut payload,
+            &listpack(&["m", "2", "x", "1.5", "y", "-inf"]),
+        )
+        .unwrap();
+        value(TYPE_ZSET_LISTPACK, "zset", &payload);
+
+        // One packed node and one plain one.
+        let mut payload = Vec::new();
+        write_length(&mut payload, 2).unwrap();
+        write_length(&mut payload, QUICKLIST_NODE_PACKED).unwrap();
+        write_string(&mut payload, &listpack(&["a", "-5"])).unwrap();
+        write_length(&mut payload, QUICKLIST_NODE_PLAIN).unwrap();
+        write_string(&mut payload, b"big").unwrap();
+        value(TYPE_LIST_QUICKLIST_2, "list", &payload);
+
+        let expires_at = now_ms() + 60_000;
+        let mut payload = expires_at.to_le_bytes().to_vec();
+        let gone = (now_ms() - 1).to_string();
+        let expiring = expires_at.to_string();
+        let triples = ["a", "1", "0", "b", "2", &expiring, "c", "3", &gone];
+        write_string(&mut payload, &listpack(&triples)).unwrap();
+        value(TYPE_HASH_LISTPACK_EX, "hash-ex", &payload);
+
+        let mut payload = Vec::new();
+        let zipmap = [2, 1, b'k', 2, 1, b'v', b'1', 0, 1, b'z', 0, 0, 255];
+        write_string(&mut payload, &zipmap).unwrap();
+        value(TYPE_HASH_ZIPMAP, "zipmap", &payload);
+
+        file.push(OPCODE_EOF);
+        file.extend([0; 8]);
+        let mut dbs = load_file(&file).unwrap();
+
+        let db = &mut dbs[0];
+        assert_eq!(members(db.get(b"intset")), ["-70000", "3"]);
+        assert_eq!(members(db.get(b"set")), ["7", "a"]);
+        assert_eq!(members(db.get(b"hash")), ["f=v", "n=1"]);
+        assert_eq!(members(db.get(b"zset")), ["y=-inf", "x=1.5", "m=2"]);
+        assert_eq!(members(db.get(b"list")), ["a", "-5", "big"]);
+        assert_eq!(members(db.get(b"hash-ex")), ["a=1", "b=2"]);
+        let Some(RedisValue::Hash(hash)) = db.get(b"hash-ex") else {
+            panic!("expected a hash");
+        };
+        assert_eq!(hash.expires_at(b"a"), None);
+        assert_eq!(hash.expires_at(b"b"), Some(expires_at));
+        assert_eq!(members(db.get(b"zipmap")), ["k=v1", "z="]);
+    }
+
+    #[test]
+    fn hash_field_expiry_out_of_range_fails_to_load() {
+        let mut file = MAGIC_STRING.to_vec();
+        file.push(TYPE_HASH_METADATA);
+        write_string(&mut file, b"h").unwrap();
+        file.extend(u64::MAX.to_le_bytes());
+        write_length(&mut file, 1).unwrap();
+        write_length(&mut file, 2).unwrap();
+        write_string(&mut file, b"f").unwrap();
+        write_string(&mut file, b"v").unwrap();
+        file.push(OPCODE_EOF);
+        file.extend([0; 8]);
+        assert!(load_file(&file).is_err());
+    }
+
+    #[test]
+    fn hash_field_expiry_round_trips_as_version_12() {
+        let expires_at = now_ms() + 60_000;
+        let mut hash = Hash::default();
+        hash.insert(Bytes::from("a"), Bytes::from("1"));
+        hash.insert(Bytes::from("b"), Bytes::from("2"));
+        hash.set_expires_at(b"b", expires_at);
+        let mut dbs = vec![KeyValueStore::new()];
+        dbs[0].insert(Bytes::from("h"), RedisValue::Hash(hash), None);
+
+        let path = std::env::temp_dir().join(format!(
+            "rdb-version-test-{}-{:?}.rdb",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        write_rdb(&path, &dbs).unwrap();
+        let header = fs::read(&path).unwrap()[..9].to_vec();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(header, b"REDIS0012");
+
+        let mut loaded = round_trip(&dbs);
+        let Some(RedisValue::Hash(hash)) = loaded[0].get(b"h") else {
+            panic!("expected a hash");
+        };
+        assert_eq!(hash.len(), 2);
+        assert_eq!(hash.expires_at(b"a"), None);
+        assert_eq!(hash.expires_at(b"b"), Some(expires_at));
+    }
+}
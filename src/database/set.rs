@@ -0,0 +1,78 @@
+use std::collections::{hash_set, HashSet};
+
+use bytes::Bytes;
+
+use super::ScanIndex;
+
+/// Set: unique members, kept in a hash set for lookups and in a `ScanIndex`
+/// for SSCAN.
+#[derive(Clone, Debug, Default)]
+pub struct Set {
+    members: HashSet<Bytes>,
+    scan_index: ScanIndex,
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.members.contains(member)
+    }
+
+    pub fn iter(&self) -> hash_set::Iter<'_, Bytes> {
+        self.members.iter()
+    }
+
+    /// Adds `member`. Returns whether it was new.
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        let added = self.members.insert(member.clone());
+        if added {
+            self.scan_index.insert(member);
+        }
+        added
+    }
+
+    /// Removes `member`. Returns whether it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.members.take(member) {
+            Some(member) => {
+                self.scan_index.remove(member);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn scan_index(&self) -> &ScanIndex {
+        &self.scan_index
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> Self {
+        let mut set = Set::new();
+        for member in iter {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+impl<'a> IntoIterator for &'a Set {
+    type Item = &'a Bytes;
+    type IntoIter = hash_set::Iter<'a, Bytes>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.members.iter()
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+
+use super::{Hash, Set, Stream, ZSet};
+
+/// A value stored under a key. Every key holds exactly one kind of value;
+/// commands that find a different kind than they work on fail with WRONGTYPE.
+#[derive(Clone, Debug)]
+pub enum RedisValue {
+    String(Bytes),
+    List(VecDeque<Bytes>),
+    Hash(Hash),
+    Set(Set),
+    ZSet(ZSet),
+    Stream(Stream),
+}
+
+impl RedisValue {
+    /// Name reported by `TYPE`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "string",
+            Self::List(_) => "list",
+            Self::Hash(_) => "hash",
+            Self::Set(_) => "set",
+            Self::ZSet(_) => "zset",
+            Self::Stream(_) => "stream",
+        }
+    }
+
+    /// Aggregate values are removed together with their last element.
+    /// Strings and streams stay around when empty.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Self::List(list) => list.is_empty(),
+            Self::Hash(hash) => hash.is_empty(),
+            Self::Set(set) => set.is_empty(),
+            Self::ZSet(zset) => zset.is_empty(),
+            Self::String(_) | Self::Stream(_) => false,
+        }
+    }
+}
+
+/// The concrete types a `RedisValue` can hold, so the keyspace can hand out
+/// typed references and report WRONGTYPE in one place.
+pub trait ValueType: Default + Sized {
+    fn from_value(value: &RedisValue) -> Option<&Self>;
+    fn from_value_mut(value: &mut RedisValue) -> Option<&mut Self>;
+    fn into_value(self) -> RedisValue;
+}
+
+macro_rules! value_type {
+    ($ty:ty, $variant:ident) => {
+        impl ValueType for $ty {
+            fn from_value(value: &RedisValue) -> Option<&Self> {
+                match value {
+                    RedisValue::$variant(inner) => Some(inner),
+                    _ => None,
+                }
+            }
+
+            fn from_value_mut(value: &mut RedisValue) -> Option<&mut Self> {
+                match value {
+                    RedisValue::$variant(inner) => Some(inner),
+                    _ => None,
+                }
+            }
+
+            fn into_value(self) -> RedisValue {
+                RedisValue::$variant(self)
+            }
+        }
+    };
+}
+
+value_type!(Bytes, String);
+value_type!(VecDeque<Bytes>, List);
+value_type!(Hash, Hash);
+value_type!(Set, Set);
+value_type!(ZSet, ZSet);
+value_type!(Stream, Stream);
@@ -0,0 +1,98 @@
+use bytes::Bytes;
+
+use super::listpack::ListpackEntry;
+
+const HEADER_LEN: usize = 10;
+const END: u8 = 0xFF;
+
+/// The elements of a ziplist, `None` if it is malformed. Ziplists came
+/// before listpacks and are only found in RDB files older Redis versions
+/// saved: a header with the total size, the offset of the last element and
+/// the element count, then each element as the length of the one before it,
+/// its encoding and its data.
+pub fn decode(ziplist: &[u8]) -> Option<Vec<ListpackEntry>> {
+    let body = ziplist.get(HEADER_LEN..)?;
+    let int = |bytes: Option<&[u8]>| -> Option<i64> {
+        let bytes = bytes?;
+        let mut buf = [0; 8];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        let shift = 64 - 8 * bytes.len() as u32;
+        Some(i64::from_le_bytes(buf) << shift >> shift)
+    };
+    let mut entries = Vec::new();
+    let mut i = 0;
+    loop {
+        // The previous element's length, in one byte or 0xFE and four more.
+        i += match *body.get(i)? {
+            END => return Some(entries),
+            0xFE => 5,
+            _ => 1,
+        };
+        let encoding = *body.get(i)?;
+        let (entry, len) = match encoding >> 6 {
+            0b00 => {
+                let n = (encoding & 0x3F) as usize;
+                (string(body, i + 1, n)?, 1 + n)
+            }
+            0b01 => {
+                let n = ((encoding & 0x3F) as usize) << 8 | *body.get(i + 1)? as usize;
+                (string(body, i + 2, n)?, 2 + n)
+            }
+            0b10 => {
+                let n = u32::from_be_bytes(body.get(i + 1..i + 5)?.try_into().ok()?) as usize;
+                (string(body, i + 5, n)?, 5 + n)
+            }
+            _ => match encoding {
+                0xC0 => (ListpackEntry::Int(int(body.get(i + 1..i + 3))?), 3),
+                0xD0 => (ListpackEntry::Int(int(body.get(i + 1..i + 5))?), 5),
+                0xE0 => (ListpackEntry::Int(int(body.get(i + 1..i + 9))?), 9),
+                0xF0 => (ListpackEntry::Int(int(body.get(i + 1..i + 4))?), 4),
+                0xFE => (ListpackEntry::Int(int(body.get(i + 1..i + 2))?), 2),
+                // Small values kept in the encoding itself, 1 to 13 for 0 to 12.
+                0xF1..=0xFD => (ListpackEntry::Int((encoding & 0x0F) as i64 - 1), 1),
+                _ => return None,
+            },
+        };
+        entries.push(entry);
+        i += len;
+    }
+}
+
+fn string(body: &[u8], start: usize, len: usize) -> Option<ListpackEntry> {
+    let s = body.get(start..start + len)?;
+    Some(ListpackEntry::Str(Bytes::copy_from_slice(s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ziplist_elements_decode() {
+        // As Redis 6 saves ["a", 12, -2, 300, 70000, "x" * 70].
+        let long = "x".repeat(70);
+        let mut body = vec![0x00, 0x01, b'a'];
+        body.extend([0x03, 0xFD]);
+        body.extend([0x02, 0xFE, 0xFE]);
+        body.extend([0x02, 0xC0, 0x2C, 0x01]);
+        body.extend([0x04, 0xF0, 0x70, 0x11, 0x01]);
+        body.extend([0x05, 0x40, 70]);
+        body.extend(long.as_bytes());
+        body.push(END);
+        let mut ziplist = vec![0; HEADER_LEN];
+        ziplist.extend(body);
+
+        assert_eq!(
+            decode(&ziplist).unwrap(),
+            [
+                ListpackEntry::Str(Bytes::from("a")),
+                ListpackEntry::Int(12),
+                ListpackEntry::Int(-2),
+                ListpackEntry::Int(300),
+                ListpackEntry::Int(70000),
+                ListpackEntry::Str(Bytes::from(long)),
+            ]
+        );
+        assert_eq!(decode(&ziplist[..ziplist.len() - 1]), None);
+    }
+}
@@ -0,0 +1,799 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use super::ScanIndex;
+
+/// One end of a score range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+/// One end of a lexicographical range. Only meaningful when all members
+/// share the same score.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    /// `-`, before every member.
+    Min,
+    /// `+`, after every member.
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+/// Sorted set: unique members, each with a score, ordered by score and then
+/// by member. The scores are kept twice, in a map for lookups by member and
+/// in a skiplist for everything that needs the order.
+#[derive(Clone, Debug, Default)]
+pub struct ZSet {
+    scores: HashMap<Bytes, f64>,
+    list: SkipList,
+    /// The members, in the order ZSCAN goes through them.
+    scan_index: ScanIndex,
+}
+
+impl ZSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Adds `member` or changes its score. Returns the score it had.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        let old = self.scores.insert(member.clone(), score);
+        match old {
+            Some(old) if old == score => {}
+            Some(old) => {
+                self.list.delete(old, &member);
+                self.list.insert(score, member);
+            }
+            None => {
+                self.scan_index.insert(member.clone());
+                self.list.insert(score, member);
+            }
+        }
+        old
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let (member, score) = self.scores.remove_entry(member)?;
+        self.list.delete(score, &member);
+        self.scan_index.remove(member);
+        Some(score)
+    }
+
+    /// Position of `member` counting from the lowest score, or from the
+    /// highest when `rev`.
+    pub fn rank(&self, member: &[u8], rev: bool) -> Option<usize> {
+        let score = self.score(member)?;
+        let rank = self.list.rank(score, member)?;
+        Some(if rev { self.len() - 1 - rank } else { rank })
+    }
+
+    pub fn scan_index(&self) -> &ScanIndex {
+        &self.scan_index
+    }
+
+    /// Members and scores from lowest to highest.
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> + '_ {
+        self.list.iter_from(self.list.first(), false)
+    }
+
+    /// The members at positions `start..=stop`, counted from the highest
+    /// score when `rev`.
+    pub fn range_by_rank(&self, start: usize, stop: usize, rev: bool) -> Vec<(Bytes, f64)> {
+        if start > stop || start >= self.len() {
+            return Vec::new();
+        }
+        let first = if rev { self.len() - 1 - start } else { start };
+        self.list
+            .iter_from(self.list.by_rank(first), rev)
+            .take(stop.min(self.len() - 1) - start + 1)
+            .map(|(member, score)| (member.clone(), score))
+            .collect()
+    }
+
+    pub fn range_by_score(
+        &self,
+        min: ScoreBound,
+        max: ScoreBound,
+        rev: bool,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Vec<(Bytes, f64)> {
+        self.range_where(
+            |node| below_min_score(node.score, min),
+            |node| within_max_score(node.score, max),
+            rev,
+            offset,
+            limit,
+        )
+    }
+
+    pub fn range_by_lex(
+        &self,
+        min: &LexBound,
+        max: &LexBound,
+        rev: bool,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Vec<(Bytes, f64)> {
+        self.range_where(
+            |node| below_min_lex(&node.member, min),
+            |node| within_max_lex(&node.member, max),
+            rev,
+            offset,
+            limit,
+        )
+    }
+
+    /// How many members have a score within the range, found from the ranks
+    /// of both ends rather than by walking the range.
+    pub fn count_in_score_range(&self, min: ScoreBound, max: ScoreBound) -> usize {
+        let first = self
+            .list
+            .first_where(|node| below_min_score(node.score, min));
+        let last = self
+            .list
+            .last_where(|node| within_max_score(node.score, max));
+        match (first, last) {
+            (Some((_, first)), Some((_, last))) if first <= last => last - first + 1,
+            _ => 0,
+        }
+    }
+
+    /// Removes and returns up to `count` members with the lowest scores, or
+    /// the highest when `max`.
+    pub fn pop(&mut self, count: usize, max: bool) -> Vec<(Bytes, f64)> {
+        let start = if max {
+            self.list.last()
+        } else {
+            self.list.first()
+        };
+        let popped: Vec<(Bytes, f64)> = self
+            .list
+            .iter_from(start, max)
+            .take(count)
+            .map(|(member, score)| (member.clone(), score))
+            .collect();
+        for (member, _) in &popped {
+            self.remove(member);
+        }
+        popped
+    }
+
+    /// Members between the first one not `below_min` and the last one
+    /// `within_max`, both of which must hold for a prefix of the order and
+    /// then never again.
+    fn range_where(
+        &self,
+        below_min: impl Fn(&Node) -> bool,
+        within_max: impl Fn(&Node) -> bool,
+        rev: bool,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Vec<(Bytes, f64)> {
+        let start = if rev {
+            self.list
+                .last_where(&within_max)
+                .filter(|(node, _)| !below_min(self.list.node(*node)))
+                .and_then(|(_, rank)| rank.checked_sub(offset))
+        } else {
+            self.list
+                .first_where(&below_min)
+                .filter(|(node, _)| within_max(self.list.node(*node)))
+                .map(|(_, rank)| rank + offset)
+        };
+        let Some(start) = start else {
+            return Vec::new();
+        };
+        self.list
+            .nodes_from(self.list.by_rank(start), rev)
+            .map(|node| self.list.node(node))
+            .take_while(|node| {
+                if rev {
+                    !below_min(node)
+                } else {
+                    within_max(node)
+                }
+            })
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|node| (node.member.clone(), node.score))
+            .collect()
+    }
+}
+
+fn below_min_score(score: f64, min: ScoreBound) -> bool {
+    match min {
+        ScoreBound::Inclusive(min) => score < min,
+        ScoreBound::Exclusive(min) => score <= min,
+    }
+}
+
+fn within_max_score(score: f64, max: ScoreBound) -> bool {
+    match max {
+        ScoreBound::Inclusive(max) => score <= max,
+        ScoreBound::Exclusive(max) => score < max,
+    }
+}
+
+fn below_min_lex(member: &[u8], min: &LexBound) -> bool {
+    match min {
+        LexBound::Min => false,
+        LexBound::Max => true,
+        LexBound::Inclusive(min) => member < min.as_ref(),
+        LexBound::Exclusive(min) => member <= min.as_ref(),
+    }
+}
+
+fn within_max_lex(member: &[u8], max: &LexBound) -> bool {
+    match max {
+        LexBound::Min => false,
+        LexBound::Max => true,
+        LexBound::Inclusive(max) => member <= max.as_ref(),
+        LexBound::Exclusive(max) => member < max.as_ref(),
+    }
+}
+
+const MAX_LEVEL: usize = 32;
+// Chance of a node reaching the next level up.
+const LEVEL_P: f64 = 0.25;
+// The head node, which holds no member and sits before all others.
+const HEAD: usize = 0;
+
+#[derive(Clone, Debug)]
+struct Level {
+    forward: Option<usize>,
+    /// Nodes skipped by following `forward`, counting the one it lands on.
+    /// With no `forward`, the nodes left until the end.
+    span: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+impl Node {
+    fn new(member: Bytes, score: f64, level: usize) -> Self {
+        Self {
+            member,
+            score,
+            backward: None,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0,
+                };
+                level
+            ],
+        }
+    }
+
+    /// Whether the node sorts before `score`/`member`.
+    fn is_before(&self, score: f64, member: &[u8]) -> bool {
+        self.score < score || (self.score == score && self.member.as_ref() < member)
+    }
+}
+
+/// Skiplist with spans, as in Redis, so that ranks are found in O(log n)
+/// too. Nodes live in an arena and link to each other by index; freed slots
+/// are reused.
+#[derive(Clone, Debug)]
+struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    len: usize,
+    level: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node::new(Bytes::new(), 0.0, MAX_LEVEL)],
+            free: Vec::new(),
+            tail: None,
+            len: 0,
+            level: 1,
+        }
+    }
+}
+
+impl SkipList {
+    fn node(&self, index: usize) -> &Node {
+        &self.nodes[index]
+    }
+
+    fn forward(&self, index: usize, level: usize) -> Option<usize> {
+        self.nodes[index].levels[level].forward
+    }
+
+    fn span(&self, index: usize, level: usize) -> usize {
+        self.nodes[index].levels[level].span
+    }
+
+    fn first(&self) -> Option<usize> {
+        self.forward(HEAD, 0)
+    }
+
+    fn last(&self) -> Option<usize> {
+        self.tail
+    }
+
+    fn random_level() -> usize {
+        let mut level = 1;
+        while level < MAX_LEVEL && rand::random::<f64>() < LEVEL_P {
+            level += 1;
+        }
+        level
+    }
+
+    /// Inserts a member that is not in the list yet.
+    fn insert(&mut self, score: f64, member: Bytes) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.forward(x, i) {
+                if !self.nodes[next].is_before(score, &member) {
+                    break;
+                }
+                rank[i] += self.span(x, i);
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = Self::random_level();
+        if level > self.level {
+            for i in self.level..level {
+                update[i] = HEAD;
+                rank[i] = 0;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let new = self.alloc(Node::new(member, score, level));
+        for i in 0..level {
+            let prev = update[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[new].levels[i] = Level {
+                forward: self.forward(prev, i),
+                span: self.span(prev, i) - skipped,
+            };
+            self.nodes[prev].levels[i] = Level {
+                forward: Some(new),
+                span: skipped + 1,
+            };
+        }
+        for (i, prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[*prev].levels[i].span += 1;
+        }
+
+        self.nodes[new].backward = (update[0] != HEAD).then_some(update[0]);
+        match self.forward(new, 0) {
+            Some(next) => self.nodes[next].backward = Some(new),
+            None => self.tail = Some(new),
+        }
+        self.len += 1;
+    }
+
+    fn delete(&mut self, score: f64, member: &[u8]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if !self.nodes[next].is_before(score, member) {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+        let Some(target) = self.forward(x, 0) else {
+            return;
+        };
+        if self.nodes[target].score != score || self.nodes[target].member != member {
+            return;
+        }
+
+        for (i, prev) in update.iter().enumerate().take(self.level) {
+            let removed = self.nodes[target].levels.get(i).cloned();
+            let prev = &mut self.nodes[*prev].levels[i];
+            if prev.forward == Some(target) {
+                let removed = removed.expect("linked at this level");
+                *prev = Level {
+                    forward: removed.forward,
+                    span: prev.span + removed.span - 1,
+                };
+            } else {
+                prev.span -= 1;
+            }
+        }
+        let backward = self.nodes[target].backward;
+        match self.forward(target, 0) {
+            Some(next) => self.nodes[next].backward = backward,
+            None => self.tail = backward,
+        }
+        while self.level > 1 && self.forward(HEAD, self.level - 1).is_none() {
+            self.level -= 1;
+        }
+        self.len -= 1;
+
+        self.nodes[target] = Node::new(Bytes::new(), 0.0, 0);
+        self.free.push(target);
+    }
+
+    fn alloc(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    /// Zero based rank of the member with this score.
+    fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                let node = &self.nodes[next];
+                if !(node.is_before(score, member) || node.member == member) {
+                    break;
+                }
+                rank += self.span(x, i);
+                x = next;
+            }
+            if x != HEAD && self.nodes[x].member == member {
+                return Some(rank - 1);
+            }
+        }
+        None
+    }
+
+    /// The node at zero based `rank`.
+    fn by_rank(&self, rank: usize) -> Option<usize> {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if traversed + self.span(x, i) > target {
+                    break;
+                }
+                traversed += self.span(x, i);
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    /// The first node for which `before` does not hold, with its rank.
+    /// `before` must hold for a prefix of the list only.
+    fn first_where(&self, before: impl Fn(&Node) -> bool) -> Option<(usize, usize)> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if !before(&self.nodes[next]) {
+                    break;
+                }
+                rank += self.span(x, i);
+                x = next;
+            }
+        }
+        self.forward(x, 0).map(|node| (node, rank))
+    }
+
+    /// The last node for which `within` holds, with its rank. `within` must
+    /// hold for a prefix of the list only.
+    fn last_where(&self, within: impl Fn(&Node) -> bool) -> Option<(usize, usize)> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if !within(&self.nodes[next]) {
+                    break;
+                }
+                rank += self.span(x, i);
+                x = next;
+            }
+        }
+        (x != HEAD).then(|| (x, rank - 1))
+    }
+
+    /// Node indexes from `start` on, towards the head when `rev`.
+    fn nodes_from(&self, start: Option<usize>, rev: bool) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(start, move |&node| {
+            if rev {
+                self.nodes[node].backward
+            } else {
+                self.forward(node, 0)
+            }
+        })
+    }
+
+    fn iter_from(
+        &self,
+        start: Option<usize>,
+        rev: bool,
+    ) -> impl Iterator<Item = (&Bytes, f64)> + '_ {
+        self.nodes_from(start, rev).map(|node| {
+            let node = &self.nodes[node];
+            (&node.member, node.score)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zset(entries: &[(&str, f64)]) -> ZSet {
+        let mut zset = ZSet::default();
+        for (member, score) in entries {
+            zset.insert(Bytes::from(member.to_string()), *score);
+        }
+        zset
+    }
+
+    fn members(entries: Vec<(Bytes, f64)>) -> Vec<String> {
+        entries
+            .into_iter()
+            .map(|(member, _)| String::from_utf8(member.to_vec()).unwrap())
+            .collect()
+    }
+
+    fn lex(member: &str) -> LexBound {
+        LexBound::Inclusive(Bytes::from(member.to_string()))
+    }
+
+    fn lex_exclusive(member: &str) -> LexBound {
+        LexBound::Exclusive(Bytes::from(member.to_string()))
+    }
+
+    /// Checks the order, the backward links, every span and every rank
+    /// against a plain walk of the list.
+    fn assert_consistent(zset: &ZSet) {
+        let list = &zset.list;
+        let entries: Vec<(Bytes, f64)> = zset
+            .iter()
+            .map(|(member, score)| (member.clone(), score))
+            .collect();
+        assert_eq!(entries.len(), zset.len());
+        assert_eq!(list.len, zset.len());
+        assert!(
+            entries
+                .windows(2)
+                .all(|pair| pair[0].1 < pair[1].1
+                    || (pair[0].1 == pair[1].1 && pair[0].0 < pair[1].0))
+        );
+
+        let backward: Vec<&Bytes> = list
+            .iter_from(list.last(), true)
+            .map(|(member, _)| member)
+            .collect();
+        assert!(backward
+            .into_iter()
+            .eq(entries.iter().rev().map(|(m, _)| m)));
+
+        for level in 0..list.level {
+            let (mut x, mut position) = (HEAD, 0);
+            loop {
+                position += list.span(x, level);
+                match list.forward(x, level) {
+                    Some(next) => {
+                        assert_eq!(list.by_rank(position - 1), Some(next));
+                        x = next;
+                    }
+                    None => {
+                        assert_eq!(position, list.len);
+                        break;
+                    }
+                }
+            }
+        }
+
+        for (rank, (member, score)) in entries.iter().enumerate() {
+            assert_eq!(zset.score(member), Some(*score));
+            assert_eq!(zset.rank(member, false), Some(rank));
+            assert_eq!(zset.rank(member, true), Some(entries.len() - 1 - rank));
+        }
+    }
+
+    #[test]
+    fn ranks_follow_score_then_member() {
+        let mut zset = zset(&[("d", 3.0), ("c", 2.0), ("a", 1.0), ("e", 5.0), ("b", 2.0)]);
+        assert_consistent(&zset);
+        assert_eq!(zset.rank(b"b", false), Some(1));
+        assert_eq!(zset.rank(b"c", false), Some(2));
+        assert_eq!(zset.rank(b"e", true), Some(0));
+        assert_eq!(zset.rank(b"x", false), None);
+
+        assert_eq!(members(zset.range_by_rank(1, 3, false)), ["b", "c", "d"]);
+        assert_eq!(members(zset.range_by_rank(1, 3, true)), ["d", "c", "b"]);
+        assert_eq!(members(zset.range_by_rank(3, 100, false)), ["d", "e"]);
+        assert!(zset.range_by_rank(5, 6, false).is_empty());
+        assert!(zset.range_by_rank(2, 1, false).is_empty());
+
+        // Moving a member moves its rank, and the ones it passes.
+        assert_eq!(zset.insert(Bytes::from("a"), 4.0), Some(1.0));
+        assert_consistent(&zset);
+        assert_eq!(zset.rank(b"a", false), Some(3));
+        assert_eq!(zset.rank(b"b", false), Some(0));
+        assert_eq!(zset.remove(b"c"), Some(2.0));
+        assert_eq!(zset.remove(b"c"), None);
+        assert_consistent(&zset);
+        assert_eq!(
+            members(zset.range_by_rank(0, 10, false)),
+            ["b", "d", "a", "e"]
+        );
+    }
+
+    #[test]
+    fn spans_survive_inserts_removes_and_updates() {
+        let mut zset = ZSet::default();
+        let mut model: HashMap<Bytes, f64> = HashMap::new();
+        // Few members and scores, so that ties and score updates are common.
+        let mut seed: u64 = 42;
+        for _ in 0..2000 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let member = Bytes::from(format!("m{}", (seed >> 33) % 40));
+            // One operation in three is a removal.
+            match (seed >> 20) % 3 {
+                0 => assert_eq!(zset.remove(&member), model.remove(&member)),
+                _ => {
+                    let score = ((seed >> 40) % 8) as f64;
+                    assert_eq!(
+                        zset.insert(member.clone(), score),
+                        model.insert(member, score)
+                    );
+                }
+            }
+            assert_consistent(&zset);
+        }
+        let mut expected: Vec<(Bytes, f64)> = model.into_iter().collect();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        assert_eq!(zset.range_by_rank(0, usize::MAX - 1, false), expected);
+    }
+
+    #[test]
+    fn range_by_score_honours_bounds_offset_and_limit() {
+        use ScoreBound::{Exclusive, Inclusive};
+        let mut zset = zset(&[("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0), ("e", 5.0)]);
+        let all = (Inclusive(f64::NEG_INFINITY), Inclusive(f64::INFINITY));
+        let range = |zset: &ZSet, min, max, rev, offset, limit| {
+            members(zset.range_by_score(min, max, rev, offset, limit))
+        };
+
+        assert_eq!(
+            range(&zset, Inclusive(2.0), Inclusive(3.0), false, 0, None),
+            ["b", "c", "d"]
+        );
+        assert_eq!(
+            range(&zset, Exclusive(2.0), Inclusive(5.0), false, 0, None),
+            ["d", "e"]
+        );
+        assert_eq!(range(&zset, all.0, Exclusive(2.0), false, 0, None), ["a"]);
+        assert_eq!(
+            range(&zset, Inclusive(1.0), Inclusive(3.0), true, 0, None),
+            ["d", "c", "b", "a"]
+        );
+        assert_eq!(range(&zset, all.0, all.1, false, 1, Some(2)), ["b", "c"]);
+        assert_eq!(range(&zset, all.0, all.1, true, 1, Some(1)), ["d"]);
+        assert!(range(&zset, all.0, all.1, false, 5, None).is_empty());
+        assert!(range(&zset, all.0, all.1, true, 5, None).is_empty());
+        // Empty ranges, between members and past either end.
+        assert!(range(&zset, Inclusive(4.0), Inclusive(3.0), false, 0, None).is_empty());
+        assert!(range(&zset, Exclusive(2.0), Exclusive(3.0), false, 0, None).is_empty());
+        assert!(range(&zset, Inclusive(3.5), Inclusive(4.5), true, 0, None).is_empty());
+        assert!(range(&zset, Inclusive(6.0), all.1, false, 0, None).is_empty());
+        assert!(range(&zset, all.0, Exclusive(1.0), true, 0, None).is_empty());
+        assert_eq!(zset.count_in_score_range(Inclusive(2.0), Inclusive(3.0)), 3);
+        assert_eq!(zset.count_in_score_range(Exclusive(2.0), Exclusive(5.0)), 1);
+        assert_eq!(zset.count_in_score_range(Inclusive(4.0), Inclusive(3.0)), 0);
+
+        zset.insert(Bytes::from("c"), 4.0);
+        zset.remove(b"a");
+        assert_eq!(
+            range(&zset, Inclusive(2.0), Inclusive(3.0), false, 0, None),
+            ["b", "d"]
+        );
+        assert_eq!(
+            range(&zset, all.0, Inclusive(4.0), true, 0, None),
+            ["c", "d", "b"]
+        );
+        assert_eq!(zset.count_in_score_range(all.0, all.1), 4);
+    }
+
+    #[test]
+    fn range_by_lex_honours_bounds_offset_and_limit() {
+        let mut zset = zset(&[("a", 0.0), ("b", 0.0), ("c", 0.0), ("d", 0.0), ("e", 0.0)]);
+        let range = |zset: &ZSet, min: &LexBound, max: &LexBound, rev, offset, limit| {
+            members(zset.range_by_lex(min, max, rev, offset, limit))
+        };
+
+        assert_eq!(
+            range(&zset, &LexBound::Min, &LexBound::Max, false, 0, None),
+            ["a", "b", "c", "d", "e"]
+        );
+        assert_eq!(
+            range(&zset, &lex("b"), &lex_exclusive("d"), false, 0, None),
+            ["b", "c"]
+        );
+        assert_eq!(
+            range(&zset, &lex_exclusive("b"), &LexBound::Max, false, 0, None),
+            ["c", "d", "e"]
+        );
+        assert_eq!(
+            range(&zset, &LexBound::Min, &LexBound::Max, true, 0, None),
+            ["e", "d", "c", "b", "a"]
+        );
+        assert_eq!(
+            range(&zset, &lex("b"), &LexBound::Max, true, 1, Some(2)),
+            ["d", "c"]
+        );
+        assert_eq!(range(&zset, &lex("bb"), &lex("cc"), false, 0, None), ["c"]);
+        assert!(range(&zset, &lex("c"), &lex("b"), false, 0, None).is_empty());
+        assert!(range(
+            &zset,
+            &lex_exclusive("c"),
+            &lex_exclusive("c"),
+            true,
+            0,
+            None
+        )
+        .is_empty());
+        assert!(range(&zset, &LexBound::Min, &LexBound::Max, false, 5, None).is_empty());
+        assert!(range(&zset, &lex_exclusive("e"), &LexBound::Max, false, 0, None).is_empty());
+
+        zset.remove(b"c");
+        zset.insert(Bytes::from("bb"), 0.0);
+        assert_consistent(&zset);
+        assert_eq!(
+            range(&zset, &lex("b"), &lex("c"), false, 0, None),
+            ["b", "bb"]
+        );
+        assert_eq!(
+            range(&zset, &LexBound::Min, &lex("c"), true, 0, None),
+            ["bb", "b", "a"]
+        );
+    }
+
+    #[test]
+    fn pop_takes_from_either_end() {
+        let mut zset = zset(&[("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0), ("e", 5.0)]);
+        assert_eq!(members(zset.pop(2, false)), ["a", "b"]);
+        assert_eq!(members(zset.pop(1, true)), ["e"]);
+        assert_consistent(&zset);
+        assert_eq!(members(zset.pop(10, true)), ["d", "c"]);
+        assert!(zset.is_empty());
+        assert_consistent(&zset);
+    }
+}
@@ -0,0 +1,142 @@
+/// Whether `string` matches the glob-style `pattern`, the way Redis matches
+/// them: `*` for any run of bytes, `?` for any one byte, `[...]` for one of
+/// a set of bytes and ranges, `[^...]` for one not in it, and `\` to take
+/// the next byte literally. With `nocase`, ASCII letters match either case.
+///
+/// SCAN, SSCAN, HSCAN and ZSCAN use it for MATCH, KEYS for its pattern and
+/// CONFIG GET for parameter names. There is no pub/sub, so no PSUBSCRIBE.
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Where to go back to on a mismatch: the pattern after the last `*`, and
+    // how far into the string that `*` reaches so far.
+    let mut star: Option<(usize, usize)> = None;
+    while s < string.len() {
+        if p < pattern.len() {
+            if pattern[p] == b'*' {
+                p += 1;
+                star = Some((p, s));
+                continue;
+            }
+            if let Some(len) = match_one(&pattern[p..], string[s], nocase) {
+                p += len;
+                s += 1;
+                continue;
+            }
+        }
+        // The last `*` takes one more byte and matching starts over after
+        // it; without one, there is nothing left to try.
+        let Some((after_star, reach)) = star else {
+            return false;
+        };
+        p = after_star;
+        s = reach + 1;
+        star = Some((after_star, s));
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Matches `c` against the token at the start of `pattern`, which is not
+/// `*`. Returns the length of the token if it matches.
+fn match_one(pattern: &[u8], c: u8, nocase: bool) -> Option<usize> {
+    let eq = |a: u8, b: u8| a == b || (nocase && a.eq_ignore_ascii_case(&b));
+    match pattern[0] {
+        b'?' => Some(1),
+        b'\\' if pattern.len() >= 2 => eq(pattern[1], c).then_some(2),
+        b'[' => {
+            let negate = pattern.get(1) == Some(&b'^');
+            let mut i = if negate { 2 } else { 1 };
+            let mut matched = false;
+            // An unterminated set runs to the end of the pattern.
+            while i < pattern.len() {
+                match pattern[i] {
+                    b']' => {
+                        i += 1;
+                        break;
+                    }
+                    b'\\' if i + 1 < pattern.len() => {
+                        matched |= eq(pattern[i + 1], c);
+                        i += 2;
+                    }
+                    start if pattern.get(i + 1) == Some(&b'-') && i + 2 < pattern.len() => {
+                        let lower = |b: u8| if nocase { b.to_ascii_lowercase() } else { b };
+                        let (start, end) = (lower(start), lower(pattern[i + 2]));
+                        // A range may be given backwards.
+                        matched |= (start.min(end)..=start.max(end)).contains(&lower(c));
+                        i += 3;
+                    }
+                    literal => {
+                        matched |= eq(literal, c);
+                        i += 1;
+                    }
+                }
+            }
+            (matched != negate).then_some(i)
+        }
+        literal => eq(literal, c).then_some(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    fn matches(pattern: &str, string: &str) -> bool {
+        glob_match(pattern.as_bytes(), string.as_bytes(), false)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("h*o", "hello"));
+        assert!(matches("h*l*o", "hello"));
+        assert!(matches("*llo", "hello"));
+        assert!(!matches("h*x", "hello"));
+        assert!(matches("h?llo", "hello"));
+        assert!(matches("h?llo", "hallo"));
+        assert!(!matches("h?llo", "hllo"));
+        assert!(!matches("?", ""));
+    }
+
+    #[test]
+    fn sets_and_ranges() {
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("key[a-c]", "keyb"));
+        assert!(!matches("key[a-c]", "keyd"));
+        // Backwards ranges are the same as forwards ones.
+        assert!(matches("key[c-a]", "keya"));
+        assert!(!matches("key[c-a]", "keyd"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(!matches("[^a-c]", "b"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches("a\\*b", "a*b"));
+        assert!(!matches("a\\*b", "axb"));
+        assert!(matches("\\?", "?"));
+        assert!(!matches("\\?", "x"));
+        assert!(matches("[\\]]", "]"));
+        // A trailing backslash stands for itself.
+        assert!(matches("a\\", "a\\"));
+        assert!(!matches("a\\", "a"));
+    }
+
+    #[test]
+    fn unclosed_set_runs_to_the_end() {
+        assert!(matches("[abc", "b"));
+        assert!(!matches("[abc", "d"));
+        assert!(!matches("x[", "x"));
+    }
+
+    #[test]
+    fn nocase() {
+        assert!(glob_match(b"HeLLo", b"hello", true));
+        assert!(!glob_match(b"HeLLo", b"hello", false));
+        assert!(glob_match(b"[A-C]x", b"bX", true));
+        assert!(glob_match(b"h[^E]llo", b"hallo", true));
+        assert!(!glob_match(b"h[^E]llo", b"hello", true));
+    }
+}
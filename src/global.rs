@@ -1,4 +1,3 @@
-use std::borrow::BorrowMut;
 use std::sync::OnceLock;
 
 pub struct GlobalConfig<T> {
@@ -18,21 +17,6 @@ impl<T> GlobalConfig<T> {
             next.push(value)
         };
     }
-    fn contains(&self, key: &T) -> bool
-    where
-        T: PartialEq,
-    {
-        self.data
-            .get()
-            .map(|item| item.0 == *key)
-            .filter(|v| *v)
-            .unwrap_or_else(|| {
-                self.next
-                    .get()
-                    .map(|next| next.contains(&key))
-                    .unwrap_or(false)
-            })
-    }
 
     pub fn get_val(&self, key: &T) -> Option<&T>
     where
@@ -40,21 +24,13 @@ impl<T> GlobalConfig<T> {
     {
         let mut data = self.data.get();
         let mut next = self.next.get();
-        loop {
-            if let Some((k, v)) = data {
-                if *k == *key {
-                    return Some(v);
-                }
-                if next.is_some() {
-                    let next_item = next.as_mut().unwrap().borrow_mut();
-                    data = next_item.data.get();
-                    next = next_item.next.get();
-                } else {
-                    break;
-                }
-            } else {
-                break;
+        while let Some((k, v)) = data {
+            if *k == *key {
+                return Some(v);
             }
+            let next_item = next?;
+            data = next_item.data.get();
+            next = next_item.next.get();
         }
         None
     }
@@ -0,0 +1,289 @@
+use std::ops::RangeInclusive;
+
+use bytes::Bytes;
+
+use super::Context;
+use crate::{
+    cmds::{
+        BitOperation, BitUnit, Bitcount, Bitfield, BitfieldOp, BitfieldOverflow, BitfieldType,
+        Bitop, Bitpos, Getbit, Setbit,
+    },
+    database::{DbError, RedisValue},
+    resp::RespData,
+};
+
+pub fn setbit(o: Setbit, ctx: &mut Context) -> Result<RespData, DbError> {
+    let value = ctx.db.entry_as::<Bytes>(o.key)?;
+    // Taking the value out lets its buffer be reused, not copied, when no
+    // reply still shares it.
+    let mut bits = Vec::from(std::mem::take(value));
+    let byte = o.offset / 8;
+    if bits.len() <= byte {
+        bits.resize(byte + 1, 0);
+    }
+    let mask = 0x80 >> (o.offset % 8);
+    let old = bits[byte] & mask != 0;
+    if o.value {
+        bits[byte] |= mask;
+    } else {
+        bits[byte] &= !mask;
+    }
+    *value = Bytes::from(bits);
+    ctx.mark_dirty();
+    Ok(RespData::Integer(old as i64))
+}
+
+pub fn getbit(o: Getbit, ctx: &mut Context) -> Result<RespData, DbError> {
+    let bit = match ctx.db.get_as::<Bytes>(&o.key)? {
+        Some(value) => bit_at(value, o.offset),
+        None => false,
+    };
+    Ok(RespData::Integer(bit as i64))
+}
+
+pub fn bitcount(o: Bitcount, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(value) = ctx.db.get_as::<Bytes>(&o.key)? else {
+        return Ok(RespData::Integer(0));
+    };
+    let (start, end, unit) = o.range.unwrap_or((0, -1, BitUnit::Byte));
+    let count = match bit_range(start, end, unit, value.len()) {
+        Some(range) => count_ones(value, range),
+        None => 0,
+    };
+    Ok(RespData::Integer(count as i64))
+}
+
+pub fn bitpos(o: Bitpos, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(value) = ctx.db.get_as::<Bytes>(&o.key)? else {
+        // A missing key is an empty string, clear bits all the way.
+        return Ok(RespData::Integer(if o.bit { -1 } else { 0 }));
+    };
+    let Some(range) = bit_range(o.start, o.end.unwrap_or(-1), o.unit, value.len()) else {
+        return Ok(RespData::Integer(-1));
+    };
+    let (mut pos, last) = range.into_inner();
+    // Whole bytes without the bit being looked for are skipped at once.
+    let skip = if o.bit { 0x00 } else { 0xff };
+    while pos <= last {
+        if pos % 8 == 0 && pos + 7 <= last && value[pos / 8] == skip {
+            pos += 8;
+        } else if bit_at(value, pos) == o.bit {
+            return Ok(RespData::Integer(pos as i64));
+        } else {
+            pos += 1;
+        }
+    }
+    // Past the end of the string every bit is clear, but only an open range
+    // reaches there.
+    let pos = if !o.bit && o.end.is_none() {
+        last as i64 + 1
+    } else {
+        -1
+    };
+    Ok(RespData::Integer(pos))
+}
+
+pub fn bitop(o: Bitop, ctx: &mut Context) -> Result<RespData, DbError> {
+    let mut sources = Vec::new();
+    for key in &o.keys {
+        sources.push(ctx.db.get_as::<Bytes>(key)?.cloned().unwrap_or_default());
+    }
+    // Shorter strings, and missing keys, count as padded with zero bytes.
+    let len = sources.iter().map(Bytes::len).max().unwrap_or(0);
+    let result: Vec<u8> = (0..len)
+        .map(|i| {
+            let mut bytes = sources
+                .iter()
+                .map(|source| source.get(i).copied().unwrap_or(0));
+            let first = bytes.next().unwrap_or(0);
+            match o.op {
+                BitOperation::And => bytes.fold(first, |acc, byte| acc & byte),
+                BitOperation::Or => bytes.fold(first, |acc, byte| acc | byte),
+                BitOperation::Xor => bytes.fold(first, |acc, byte| acc ^ byte),
+                BitOperation::Not => !first,
+            }
+        })
+        .collect();
+
+    if result.is_empty() {
+        ctx.db.remove(&o.destination);
+    } else {
+        ctx.db
+            .insert(o.destination, RedisValue::String(Bytes::from(result)), None);
+    }
+    ctx.mark_dirty();
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn bitfield(o: Bitfield, ctx: &mut Context) -> Result<RespData, DbError> {
+    let write_end = o
+        .ops
+        .iter()
+        .filter_map(|op| match op {
+            BitfieldOp::Set { ty, offset, .. } | BitfieldOp::Incrby { ty, offset, .. } => {
+                Some(offset + ty.bits as usize)
+            }
+            _ => None,
+        })
+        .max();
+    let Some(write_end) = write_end else {
+        let bits = match ctx.db.get_as::<Bytes>(&o.key)? {
+            Some(value) => &value[..],
+            None => &[],
+        };
+        let replies = o
+            .ops
+            .iter()
+            .filter_map(|op| match *op {
+                BitfieldOp::Get { ty, offset } => {
+                    Some(RespData::Integer(read_field(bits, offset, ty)))
+                }
+                _ => None,
+            })
+            .collect();
+        return Ok(RespData::Array(replies));
+    };
+
+    let value = ctx.db.entry_as::<Bytes>(o.key)?;
+    let mut bits = Vec::from(std::mem::take(value));
+    // The string grows to fit every write up front, even writes that then
+    // fail on overflow.
+    let len = write_end.div_ceil(8);
+    if bits.len() < len {
+        bits.resize(len, 0);
+    }
+    let mut overflow = BitfieldOverflow::Wrap;
+    let mut replies = Vec::new();
+    for op in o.ops {
+        match op {
+            BitfieldOp::Overflow(mode) => overflow = mode,
+            BitfieldOp::Get { ty, offset } => {
+                replies.push(RespData::Integer(read_field(&bits, offset, ty)));
+            }
+            BitfieldOp::Set { ty, offset, value } => {
+                let old = read_field(&bits, offset, ty);
+                // A negative value overflows an unsigned field: WRAP keeps
+                // its low bits and SAT brings it up to 0.
+                replies.push(match fit(value as i128, ty, overflow) {
+                    Some(value) => {
+                        write_field(&mut bits, offset, ty, value);
+                        RespData::Integer(old)
+                    }
+                    None => RespData::Null,
+                });
+            }
+            BitfieldOp::Incrby {
+                ty,
+                offset,
+                increment,
+            } => {
+                let sum = read_field(&bits, offset, ty) as i128 + increment as i128;
+                replies.push(match fit(sum, ty, overflow) {
+                    Some(value) => {
+                        write_field(&mut bits, offset, ty, value);
+                        RespData::Integer(value)
+                    }
+                    None => RespData::Null,
+                });
+            }
+        }
+    }
+    *value = Bytes::from(bits);
+    ctx.mark_dirty();
+    Ok(RespData::Array(replies))
+}
+
+/// The bits an inclusive `start..=end` range in `unit`s selects from a
+/// string of `len` bytes. Negative offsets count from the end and the range
+/// is clamped to the string, as GETRANGE does; `None` if it selects nothing.
+fn bit_range(start: i64, end: i64, unit: BitUnit, len: usize) -> Option<RangeInclusive<usize>> {
+    let total = match unit {
+        BitUnit::Byte => len,
+        BitUnit::Bit => len * 8,
+    } as i64;
+    if start < 0 && end < 0 && start > end {
+        return None;
+    }
+    let start = if start < 0 { start + total } else { start }.max(0);
+    let end = if end < 0 { end + total } else { end }
+        .max(0)
+        .min(total - 1);
+    if total == 0 || start > end {
+        return None;
+    }
+    let (start, end) = (start as usize, end as usize);
+    Some(match unit {
+        BitUnit::Byte => start * 8..=end * 8 + 7,
+        BitUnit::Bit => start..=end,
+    })
+}
+
+/// Bits count from the most significant one of the first byte.
+fn bit_at(value: &[u8], pos: usize) -> bool {
+    value
+        .get(pos / 8)
+        .is_some_and(|byte| byte & (0x80 >> (pos % 8)) != 0)
+}
+
+fn count_ones(value: &[u8], range: RangeInclusive<usize>) -> usize {
+    let (first, last) = range.into_inner();
+    let (first_byte, last_byte) = (first / 8, last / 8);
+    let mut count: usize = value[first_byte..=last_byte]
+        .iter()
+        .map(|byte| byte.count_ones() as usize)
+        .sum();
+    // Take back what the edge bytes have outside the range.
+    count -= (value[first_byte] & !(0xff >> (first % 8))).count_ones() as usize;
+    count -= (value[last_byte] & (0xff >> (last % 8) >> 1)).count_ones() as usize;
+    count
+}
+
+/// Reads a field, with the bits past the end of the string as zeros.
+fn read_field(bits: &[u8], offset: usize, ty: BitfieldType) -> i64 {
+    let mut value = 0u64;
+    for pos in offset..offset + ty.bits as usize {
+        value = (value << 1) | bit_at(bits, pos) as u64;
+    }
+    if ty.signed && ty.bits < 64 && (value >> (ty.bits - 1)) & 1 == 1 {
+        value |= u64::MAX << ty.bits;
+    }
+    value as i64
+}
+
+fn write_field(bits: &mut [u8], offset: usize, ty: BitfieldType, value: i64) {
+    for i in 0..ty.bits {
+        let pos = offset + i as usize;
+        let mask = 0x80 >> (pos % 8);
+        if (value as u64 >> (ty.bits - 1 - i)) & 1 == 1 {
+            bits[pos / 8] |= mask;
+        } else {
+            bits[pos / 8] &= !mask;
+        }
+    }
+}
+
+/// Brings `value` into the range of `ty` the way `overflow` says, or `None`
+/// if it is out of range and `overflow` is FAIL.
+fn fit(value: i128, ty: BitfieldType, overflow: BitfieldOverflow) -> Option<i64> {
+    let size = 1i128 << ty.bits;
+    let (min, max) = if ty.signed {
+        (-size / 2, size / 2 - 1)
+    } else {
+        (0, size - 1)
+    };
+    if (min..=max).contains(&value) {
+        return Some(value as i64);
+    }
+    match overflow {
+        BitfieldOverflow::Fail => None,
+        BitfieldOverflow::Sat => Some(value.clamp(min, max) as i64),
+        BitfieldOverflow::Wrap => {
+            let wrapped = value.rem_euclid(size);
+            Some(if wrapped > max {
+                wrapped - size
+            } else {
+                wrapped
+            } as i64)
+        }
+    }
+}
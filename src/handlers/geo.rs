@@ -0,0 +1,166 @@
+use bytes::Bytes;
+
+use super::{zset, Context};
+use crate::{
+    cmds::{GeoCenter, GeoSort, Geoadd, Geodist, Geopos, Geosearch, Zadd},
+    database::{DbError, GeoPoint, ScoreBound, ZSet},
+    resp::RespData,
+};
+
+/// A member a search found.
+struct Match {
+    member: Bytes,
+    score: f64,
+    point: GeoPoint,
+    distance: f64,
+}
+
+/// Distances are replied as strings with four decimals, in either protocol.
+fn distance_reply(meters: f64, unit: f64) -> RespData {
+    RespData::bulk(format!("{:.4}", meters / unit))
+}
+
+fn point_reply(point: GeoPoint) -> RespData {
+    RespData::Array(vec![
+        RespData::Double(point.lon),
+        RespData::Double(point.lat),
+    ])
+}
+
+/// Adds the members to the sorted set, scored by their positions.
+pub fn geoadd(o: Geoadd, ctx: &mut Context) -> Result<RespData, DbError> {
+    let pairs = o
+        .members
+        .into_iter()
+        .map(|(point, member)| (point.score(), member))
+        .collect();
+    zset::zadd(
+        Zadd {
+            key: o.key,
+            nx: o.nx,
+            xx: o.xx,
+            gt: false,
+            lt: false,
+            ch: o.ch,
+            incr: false,
+            pairs,
+        },
+        ctx,
+    )
+}
+
+pub fn geodist(o: Geodist, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(zset) = ctx.db.get_as::<ZSet>(&o.key)? else {
+        return Ok(RespData::Null);
+    };
+    Ok(match (zset.score(&o.member1), zset.score(&o.member2)) {
+        (Some(score1), Some(score2)) => {
+            let meters = GeoPoint::from_score(score1).distance(GeoPoint::from_score(score2));
+            distance_reply(meters, o.unit.meters())
+        }
+        _ => RespData::Null,
+    })
+}
+
+pub fn geopos(o: Geopos, ctx: &mut Context) -> Result<RespData, DbError> {
+    let zset = ctx.db.get_as::<ZSet>(&o.key)?;
+    Ok(RespData::Array(
+        o.members
+            .iter()
+            .map(|member| match zset.and_then(|zset| zset.score(member)) {
+                Some(score) if o.geohash => RespData::bulk(GeoPoint::from_score(score).geohash()),
+                Some(score) => point_reply(GeoPoint::from_score(score)),
+                None if o.geohash => RespData::Null,
+                None => RespData::NullArray,
+            })
+            .collect(),
+    ))
+}
+
+pub fn geosearch(o: Geosearch, ctx: &mut Context) -> Result<RespData, DbError> {
+    let mut found = Vec::new();
+    if let Some(zset) = ctx.db.get_as::<ZSet>(&o.key)? {
+        let center = match o.center {
+            GeoCenter::Point(point) => point,
+            GeoCenter::Member(member) => zset
+                .score(&member)
+                .map(GeoPoint::from_score)
+                .ok_or(DbError::UnknownGeoMember)?,
+        };
+        // With ANY, any matches do, so the search stops at the first ones.
+        let limit = o.count.filter(|_| o.any).unwrap_or(usize::MAX);
+        'ranges: for (min, max) in o.shape.score_ranges(center) {
+            let candidates = zset.range_by_score(
+                ScoreBound::Inclusive(min),
+                ScoreBound::Exclusive(max),
+                false,
+                0,
+                None,
+            );
+            for (member, score) in candidates {
+                let point = GeoPoint::from_score(score);
+                let Some(distance) = o.shape.distance_within(center, point) else {
+                    continue;
+                };
+                found.push(Match {
+                    member,
+                    score,
+                    point,
+                    distance,
+                });
+                if found.len() >= limit {
+                    break 'ranges;
+                }
+            }
+        }
+    }
+
+    // The nearest `count` matches need them sorted.
+    let sort = match o.sort {
+        None if o.count.is_some() && !o.any => Some(GeoSort::Asc),
+        sort => sort,
+    };
+    match sort {
+        Some(GeoSort::Asc) => found.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+        Some(GeoSort::Desc) => found.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+        None => {}
+    }
+    if let Some(count) = o.count {
+        found.truncate(count);
+    }
+
+    let unit = o.unit.meters();
+    if let Some(destination) = o.destination {
+        let members = found.into_iter().map(|found| {
+            let score = if o.store_dist {
+                found.distance / unit
+            } else {
+                found.score
+            };
+            (found.member, score)
+        });
+        return zset::store(destination, members, ctx);
+    }
+    let detailed = o.with_dist || o.with_hash || o.with_coord;
+    Ok(RespData::Array(
+        found
+            .into_iter()
+            .map(|found| {
+                if !detailed {
+                    return RespData::BulkStr(found.member);
+                }
+                let mut item = vec![RespData::BulkStr(found.member)];
+                if o.with_dist {
+                    item.push(distance_reply(found.distance, unit));
+                }
+                if o.with_hash {
+                    item.push(RespData::Integer(found.score as i64));
+                }
+                if o.with_coord {
+                    item.push(point_reply(found.point));
+                }
+                RespData::Array(item)
+            })
+            .collect(),
+    ))
+}
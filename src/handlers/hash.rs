@@ -0,0 +1,333 @@
+use bytes::Bytes;
+use rand::{seq::IteratorRandom, seq::SliceRandom, Rng};
+
+use super::{format_float_sum, keyspace, parse_integer, Context};
+use crate::{
+    cmds::{
+        Hdel, Hexists, Hexpire, Hget, Hgetall, Hincrby, Hincrbyfloat, Hkeys, Hlen, Hmget, Hpersist,
+        Hrandfield, Hscan, Hset, Httl, Hvals,
+    },
+    database::{now_ms, DbError, Hash, MAX_FIELD_EXPIRY},
+    resp::{Protocol, RespData},
+};
+
+/// Field/value pairs as RESP3 clients expect them with WITHVALUES: one array
+/// per pair. RESP2 clients get them flattened.
+fn pairs_reply(pairs: Vec<(Bytes, Bytes)>, protocol: Protocol) -> RespData {
+    let pairs = pairs
+        .into_iter()
+        .map(|(field, value)| (RespData::BulkStr(field), RespData::BulkStr(value)));
+    RespData::Array(match protocol {
+        Protocol::Resp3 => pairs
+            .map(|(field, value)| RespData::Array(vec![field, value]))
+            .collect(),
+        Protocol::Resp2 => pairs.flat_map(|(field, value)| [field, value]).collect(),
+    })
+}
+
+pub fn hset(o: Hset, ctx: &mut Context) -> Result<RespData, DbError> {
+    let hash = ctx.db.entry_as::<Hash>(o.key)?;
+    let added = o
+        .pairs
+        .into_iter()
+        .filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none())
+        .count();
+    ctx.mark_dirty();
+    Ok(RespData::Integer(added as i64))
+}
+
+pub fn hget(o: Hget, ctx: &mut Context) -> Result<RespData, DbError> {
+    let value = ctx.db.get_as::<Hash>(&o.key)?.and_then(|h| h.get(&o.field));
+    Ok(value.map_or(RespData::Null, RespData::bulk))
+}
+
+pub fn hmget(o: Hmget, ctx: &mut Context) -> Result<RespData, DbError> {
+    let hash = ctx.db.get_as::<Hash>(&o.key)?;
+    Ok(RespData::Array(
+        o.fields
+            .iter()
+            .map(|field| {
+                hash.and_then(|h| h.get(field))
+                    .map_or(RespData::Null, RespData::bulk)
+            })
+            .collect(),
+    ))
+}
+
+pub fn hgetall(o: Hgetall, ctx: &mut Context) -> Result<RespData, DbError> {
+    let pairs = ctx
+        .db
+        .get_as::<Hash>(&o.key)?
+        .map_or_else(Vec::new, |hash| {
+            hash.iter()
+                .map(|(field, value)| (RespData::bulk(field), RespData::bulk(value)))
+                .collect()
+        });
+    Ok(RespData::Map(pairs))
+}
+
+pub fn hdel(o: Hdel, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(hash) = ctx.db.get_mut_as::<Hash>(&o.key)? else {
+        return Ok(RespData::Integer(0));
+    };
+    let removed = o
+        .fields
+        .iter()
+        .filter(|field| hash.remove(field).is_some())
+        .count();
+    ctx.db.remove_if_empty(&o.key);
+    if removed > 0 {
+        ctx.mark_dirty();
+    }
+    Ok(RespData::Integer(removed as i64))
+}
+
+pub fn hexists(o: Hexists, ctx: &mut Context) -> Result<RespData, DbError> {
+    let exists = ctx
+        .db
+        .get_as::<Hash>(&o.key)?
+        .is_some_and(|hash| hash.contains_key(&o.field));
+    Ok(RespData::Integer(exists as i64))
+}
+
+pub fn hincrby(o: Hincrby, ctx: &mut Context) -> Result<RespData, DbError> {
+    let hash = ctx.db.entry_as::<Hash>(o.key)?;
+    let value = match hash.get(&o.field) {
+        Some(value) => parse_integer(value).ok_or(DbError::HashValueNotAnInteger)?,
+        None => 0,
+    };
+    let new_value = value.checked_add(o.increment).ok_or(DbError::Overflow)?;
+    hash.update(o.field, Bytes::from(new_value.to_string()));
+    ctx.mark_dirty();
+    Ok(RespData::Integer(new_value))
+}
+
+pub fn hincrbyfloat(o: Hincrbyfloat, ctx: &mut Context) -> Result<RespData, DbError> {
+    let hash = ctx.db.entry_as::<Hash>(o.key.clone())?;
+    let value = match hash.get(&o.field) {
+        Some(value) => std::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|value| value.is_finite())
+            .ok_or(DbError::HashValueNotAFloat)?,
+        None => 0.0,
+    };
+    let new_value = value + o.increment;
+    if !new_value.is_finite() {
+        return Err(DbError::NanOrInfinity);
+    }
+    let encoded = Bytes::from(format_float_sum(value, o.increment));
+    hash.update(o.field.clone(), encoded.clone());
+    let expires_at = hash.expires_at(&o.field);
+    // Float addition may round differently elsewhere, so replicas get the
+    // result rather than the increment. HSET clears the field's expiry,
+    // which then has to be restored.
+    ctx.propagate(&[b"HSET", &o.key, &o.field, &encoded]);
+    if let Some(expires_at) = expires_at {
+        let expires_at = expires_at.to_string();
+        ctx.propagate(&[
+            b"HPEXPIREAT",
+            &o.key,
+            expires_at.as_bytes(),
+            b"FIELDS",
+            b"1",
+            &o.field,
+        ]);
+    }
+    Ok(RespData::BulkStr(encoded))
+}
+
+pub fn hkeys(o: Hkeys, ctx: &mut Context) -> Result<RespData, DbError> {
+    let fields = ctx
+        .db
+        .get_as::<Hash>(&o.key)?
+        .map_or_else(Vec::new, |hash| hash.keys().map(RespData::bulk).collect());
+    Ok(RespData::Array(fields))
+}
+
+pub fn hvals(o: Hvals, ctx: &mut Context) -> Result<RespData, DbError> {
+    let values = ctx
+        .db
+        .get_as::<Hash>(&o.key)?
+        .map_or_else(Vec::new, |hash| hash.values().map(RespData::bulk).collect());
+    Ok(RespData::Array(values))
+}
+
+pub fn hlen(o: Hlen, ctx: &mut Context) -> Result<RespData, DbError> {
+    let len = ctx.db.get_as::<Hash>(&o.key)?.map_or(0, Hash::len);
+    Ok(RespData::Integer(len as i64))
+}
+
+/// Pages through the fields with `keyspace::scan_page`, each as a field and
+/// value pair unless NOVALUES is given.
+pub fn hscan(o: Hscan, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(hash) = ctx.db.get_as::<Hash>(&o.key)? else {
+        return Ok(keyspace::scan_reply(0, Vec::new()));
+    };
+    let (cursor, page) = keyspace::scan_page(hash.scan_index(), &o.options);
+    let mut fields = Vec::new();
+    for field in page {
+        if !o.options.no_values {
+            let value = hash.get(&field).expect("Indexed fields exist");
+            fields.push(RespData::bulk(&field));
+            fields.push(RespData::bulk(value));
+        } else {
+            fields.push(RespData::BulkStr(field));
+        }
+    }
+    Ok(keyspace::scan_reply(cursor, fields))
+}
+
+pub fn hrandfield(o: Hrandfield, ctx: &mut Context) -> Result<RespData, DbError> {
+    // As in Redis, so the reply's length can be doubled WITHVALUES.
+    if o.count.is_some_and(|count| count < -(i64::MAX / 2)) {
+        return Err(DbError::ValueOutOfRange);
+    }
+    let hash = ctx.db.get_as::<Hash>(&o.key)?;
+    let mut rng = rand::thread_rng();
+    let Some(count) = o.count else {
+        let field = hash.and_then(|hash| hash.keys().choose(&mut rng));
+        return Ok(field.map_or(RespData::Null, RespData::bulk));
+    };
+    let Some(hash) = hash else {
+        return Ok(RespData::Array(Vec::new()));
+    };
+
+    let pairs = hash
+        .iter()
+        .map(|(field, value)| (field.clone(), value.clone()));
+    let picked: Vec<(Bytes, Bytes)> = if count >= 0 {
+        // Distinct fields, as many as there are up to `count`.
+        let mut picked = pairs.choose_multiple(&mut rng, (count as usize).min(hash.len()));
+        picked.shuffle(&mut rng);
+        picked
+    } else {
+        // Grown one pick at a time rather than sized up front from a count
+        // the client chose.
+        let pairs: Vec<_> = pairs.collect();
+        let mut picked = Vec::new();
+        for _ in 0..count.unsigned_abs() {
+            picked.push(pairs[rng.gen_range(0..pairs.len())].clone());
+        }
+        picked
+    };
+    Ok(if o.with_values {
+        pairs_reply(picked, ctx.protocol)
+    } else {
+        RespData::Array(
+            picked
+                .into_iter()
+                .map(|(field, _)| RespData::BulkStr(field))
+                .collect(),
+        )
+    })
+}
+
+pub fn hexpire(o: Hexpire, ctx: &mut Context) -> Result<RespData, DbError> {
+    let now = now_ms();
+    let expires_at = o
+        .expiry
+        .resolve(now)
+        .filter(|t| *t <= MAX_FIELD_EXPIRY)
+        .ok_or(DbError::InvalidFieldExpireTime)?;
+    let Some(hash) = ctx.db.get_mut_as::<Hash>(&o.key)? else {
+        return Ok(RespData::Array(vec![RespData::Integer(-2); o.fields.len()]));
+    };
+
+    let mut updated = Vec::new();
+    let mut deleted = Vec::new();
+    let mut replies = Vec::with_capacity(o.fields.len());
+    for field in &o.fields {
+        let reply = if !hash.contains_key(field) {
+            -2
+        } else if !o.condition.allows(hash.expires_at(field), expires_at) {
+            0
+        } else if expires_at <= now as i64 {
+            hash.remove(field);
+            deleted.push(field.as_ref());
+            2
+        } else {
+            hash.set_expires_at(field, expires_at as u64);
+            updated.push(field.as_ref());
+            1
+        };
+        replies.push(RespData::Integer(reply));
+    }
+    ctx.db.remove_if_empty(&o.key);
+
+    // Replicas get the absolute time, and deletions as such, so they do not
+    // depend on when the command reaches them.
+    if !updated.is_empty() {
+        let expires_at = expires_at.to_string();
+        let numfields = updated.len().to_string();
+        let mut args: Vec<&[u8]> = vec![
+            b"HPEXPIREAT",
+            &o.key,
+            expires_at.as_bytes(),
+            b"FIELDS",
+            numfields.as_bytes(),
+        ];
+        args.extend(updated);
+        ctx.propagate(&args);
+    }
+    if !deleted.is_empty() {
+        let mut args: Vec<&[u8]> = vec![b"HDEL", &o.key];
+        args.extend(deleted);
+        ctx.propagate(&args);
+    }
+    Ok(RespData::Array(replies))
+}
+
+pub fn httl(o: Httl, ctx: &mut Context) -> Result<RespData, DbError> {
+    let now = now_ms();
+    let hash = ctx.db.get_as::<Hash>(&o.key)?;
+    let replies = o
+        .fields
+        .iter()
+        .map(|field| {
+            let Some(hash) = hash.filter(|hash| hash.contains_key(field)) else {
+                return RespData::Integer(-2);
+            };
+            let Some(expires_at) = hash.expires_at(field) else {
+                return RespData::Integer(-1);
+            };
+            let ms = if o.absolute {
+                expires_at
+            } else {
+                expires_at.saturating_sub(now)
+            };
+            let time = match (o.millis, o.absolute) {
+                (true, _) => ms,
+                (false, true) => ms / 1000,
+                (false, false) => (ms + 500) / 1000,
+            };
+            RespData::Integer(time as i64)
+        })
+        .collect();
+    Ok(RespData::Array(replies))
+}
+
+pub fn hpersist(o: Hpersist, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(hash) = ctx.db.get_mut_as::<Hash>(&o.key)? else {
+        return Ok(RespData::Array(vec![RespData::Integer(-2); o.fields.len()]));
+    };
+    let replies: Vec<_> = o
+        .fields
+        .iter()
+        .map(|field| {
+            if !hash.contains_key(field) {
+                -2
+            } else if hash.persist(field) {
+                1
+            } else {
+                -1
+            }
+        })
+        .collect();
+    if replies.contains(&1) {
+        ctx.mark_dirty();
+    }
+    Ok(RespData::Array(
+        replies.into_iter().map(RespData::Integer).collect(),
+    ))
+}
@@ -0,0 +1,67 @@
+use bytes::Bytes;
+
+use super::Context;
+use crate::{
+    cmds::{Pfadd, Pfcount, Pfmerge},
+    database::{DbError, HyperLogLog},
+    resp::RespData,
+};
+
+pub fn pfadd(o: Pfadd, ctx: &mut Context) -> Result<RespData, DbError> {
+    let (mut hll, mut changed) = match ctx.db.get_as::<Bytes>(&o.key)? {
+        Some(value) => (HyperLogLog::decode(value)?, false),
+        // Creating the key is a change even with no elements.
+        None => (HyperLogLog::default(), true),
+    };
+    for element in &o.elements {
+        changed |= hll.add(element);
+    }
+    if changed {
+        store(o.key, &hll, ctx)?;
+    }
+    Ok(RespData::Integer(changed as i64))
+}
+
+pub fn pfcount(o: Pfcount, ctx: &mut Context) -> Result<RespData, DbError> {
+    if let [key] = &o.keys[..] {
+        let Some(value) = ctx.db.get_as::<Bytes>(key)? else {
+            return Ok(RespData::Integer(0));
+        };
+        let mut hll = HyperLogLog::decode(value)?;
+        if let Some(count) = hll.cached_count() {
+            return Ok(RespData::Integer(count as i64));
+        }
+        let count = hll.count();
+        // The count is kept in the value, so working it out is a write.
+        store(key.clone(), &hll, ctx)?;
+        return Ok(RespData::Integer(count as i64));
+    }
+    let mut union = HyperLogLog::default();
+    for key in &o.keys {
+        if let Some(value) = ctx.db.get_as::<Bytes>(key)? {
+            union.merge(&HyperLogLog::decode(value)?);
+        }
+    }
+    Ok(RespData::Integer(union.count() as i64))
+}
+
+pub fn pfmerge(o: Pfmerge, ctx: &mut Context) -> Result<RespData, DbError> {
+    let mut merged = match ctx.db.get_as::<Bytes>(&o.destination)? {
+        Some(value) => HyperLogLog::decode(value)?,
+        None => HyperLogLog::default(),
+    };
+    for source in &o.sources {
+        if let Some(value) = ctx.db.get_as::<Bytes>(source)? {
+            merged.merge(&HyperLogLog::decode(value)?);
+        }
+    }
+    store(o.destination, &merged, ctx)?;
+    Ok(RespData::ok())
+}
+
+/// Writes `hll` to `key`, keeping the key's expiry.
+fn store(key: Bytes, hll: &HyperLogLog, ctx: &mut Context) -> Result<(), DbError> {
+    *ctx.db.entry_as::<Bytes>(key)? = hll.encode();
+    ctx.mark_dirty();
+    Ok(())
+}
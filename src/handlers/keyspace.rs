@@ -0,0 +1,251 @@
+use bytes::Bytes;
+use rand::seq::IteratorRandom;
+
+use super::Context;
+use crate::{
+    cmds::{
+        CopyKey, Dbsize, Del, Exists, Expire, Flush, Keys, Move, Persist, Randomkey, Rename, Scan,
+        ScanOptions, Swapdb, Ttl, Type,
+    },
+    database::{now_ms, DbError, RedisValue, ScanIndex},
+    glob::glob_match,
+    resp::RespData,
+};
+
+pub fn key_type(o: Type, ctx: &mut Context) -> Result<RespData, DbError> {
+    let type_name = ctx.db.get(&o.key).map_or("none", RedisValue::type_name);
+    Ok(RespData::String(type_name.into()))
+}
+
+pub fn keys(o: Keys, ctx: &mut Context) -> Result<RespData, DbError> {
+    let keys = ctx
+        .db
+        .iter()
+        .filter(|(key, _)| glob_match(&o.pattern, key, false))
+        .map(|(key, _)| RespData::bulk(key))
+        .collect();
+    Ok(RespData::Array(keys))
+}
+
+pub fn del(o: Del, ctx: &mut Context) -> Result<RespData, DbError> {
+    let mut removed = 0;
+    for key in &o.keys {
+        // Looking the key up first leaves out ones that just expired.
+        if ctx.db.get(key).is_some() {
+            ctx.db.remove(key);
+            removed += 1;
+        }
+    }
+    if removed > 0 {
+        ctx.mark_dirty();
+    }
+    Ok(RespData::Integer(removed))
+}
+
+pub fn exists(o: Exists, ctx: &mut Context) -> Result<RespData, DbError> {
+    let count = o
+        .keys
+        .iter()
+        .filter(|key| ctx.db.get(key).is_some())
+        .count();
+    Ok(RespData::Integer(count as i64))
+}
+
+pub fn rename(o: Rename, ctx: &mut Context) -> Result<RespData, DbError> {
+    if ctx.db.get(&o.key).is_none() {
+        return Err(DbError::NoSuchKey);
+    }
+    let renamed = if o.only_if_new {
+        RespData::Integer(1)
+    } else {
+        RespData::ok()
+    };
+    if o.only_if_new && ctx.db.get(&o.new_key).is_some() {
+        return Ok(RespData::Integer(0));
+    }
+    if o.key == o.new_key {
+        return Ok(renamed);
+    }
+    // The expiry goes along with the value.
+    if let Some(entry) = ctx.db.remove(&o.key) {
+        ctx.db
+            .insert(o.new_key.clone(), entry.value, entry.expires_at);
+    }
+    ctx.signal_key_as_ready(&o.new_key);
+    ctx.mark_dirty();
+    Ok(renamed)
+}
+
+pub fn copy(o: CopyKey, ctx: &mut Context) -> Result<RespData, DbError> {
+    let db = match o.db {
+        Some(db) => db_index(db, ctx)?,
+        None => ctx.index(),
+    };
+    if o.source == o.destination && db == ctx.index() {
+        return Err(DbError::SameObject);
+    }
+    let Some(value) = ctx.db.get(&o.source).cloned() else {
+        return Ok(RespData::Integer(0));
+    };
+    let expires_at = ctx.db.expires_at(&o.source);
+    let destination = ctx.database(db);
+    if !o.replace && destination.get(&o.destination).is_some() {
+        return Ok(RespData::Integer(0));
+    }
+    destination.insert(o.destination.clone(), value, expires_at);
+    ctx.signal_key_in_db_as_ready(db, &o.destination);
+    ctx.mark_dirty();
+    Ok(RespData::Integer(1))
+}
+
+pub fn move_key(o: Move, ctx: &mut Context) -> Result<RespData, DbError> {
+    let db = db_index(o.db, ctx)?;
+    if db == ctx.index() {
+        return Err(DbError::SameObject);
+    }
+    if ctx.db.get(&o.key).is_none() || ctx.database(db).get(&o.key).is_some() {
+        return Ok(RespData::Integer(0));
+    }
+    let entry = ctx.db.remove(&o.key).expect("Key was just looked up");
+    ctx.database(db)
+        .insert(o.key.clone(), entry.value, entry.expires_at);
+    ctx.signal_key_in_db_as_ready(db, &o.key);
+    ctx.mark_dirty();
+    Ok(RespData::Integer(1))
+}
+
+pub fn swapdb(o: Swapdb, ctx: &mut Context) -> Result<RespData, DbError> {
+    let (db1, db2) = (db_index(o.db1, ctx)?, db_index(o.db2, ctx)?);
+    ctx.swap_databases(db1, db2);
+    // Clients blocked in either database may find what they wait for in
+    // the keys that came over.
+    ctx.signal_db_as_ready(db1);
+    ctx.signal_db_as_ready(db2);
+    ctx.mark_dirty();
+    Ok(RespData::ok())
+}
+
+pub fn randomkey(_: Randomkey, ctx: &mut Context) -> Result<RespData, DbError> {
+    let key = ctx
+        .db
+        .iter()
+        .map(|(key, _)| key)
+        .choose(&mut rand::thread_rng());
+    Ok(key.map_or(RespData::Null, RespData::bulk))
+}
+
+pub fn dbsize(_: Dbsize, ctx: &mut Context) -> Result<RespData, DbError> {
+    Ok(RespData::Integer(ctx.db.get_ht_size() as i64))
+}
+
+pub fn flush(o: Flush, ctx: &mut Context) -> Result<RespData, DbError> {
+    if o.all {
+        for index in 0..ctx.database_count() {
+            ctx.database(index).clear();
+        }
+    } else {
+        ctx.db.clear();
+    }
+    ctx.mark_dirty();
+    Ok(RespData::ok())
+}
+
+pub fn expire(o: Expire, ctx: &mut Context) -> Result<RespData, DbError> {
+    let now = now_ms();
+    let expires_at = o
+        .expiry
+        .resolve(now)
+        .ok_or(DbError::InvalidExpireTime(o.command))?;
+    if ctx.db.get(&o.key).is_none() {
+        return Ok(RespData::Integer(0));
+    }
+    let current = ctx.db.expires_at(&o.key);
+    if !o
+        .conditions
+        .iter()
+        .all(|condition| condition.allows(current, expires_at))
+    {
+        return Ok(RespData::Integer(0));
+    }
+
+    // Replicas get the absolute time, and a time already past as the
+    // deletion it is, so they do not depend on when the command reaches
+    // them.
+    if expires_at <= now as i64 {
+        ctx.db.remove(&o.key);
+        ctx.propagate(&[b"DEL", &o.key]);
+    } else {
+        ctx.db.set_expires_at(&o.key, Some(expires_at as u64));
+        ctx.propagate(&[b"PEXPIREAT", &o.key, expires_at.to_string().as_bytes()]);
+    }
+    Ok(RespData::Integer(1))
+}
+
+pub fn ttl(o: Ttl, ctx: &mut Context) -> Result<RespData, DbError> {
+    if ctx.db.get(&o.key).is_none() {
+        return Ok(RespData::Integer(-2));
+    }
+    let Some(expires_at) = ctx.db.expires_at(&o.key) else {
+        return Ok(RespData::Integer(-1));
+    };
+    let ms = if o.absolute {
+        expires_at
+    } else {
+        expires_at.saturating_sub(now_ms())
+    };
+    let time = if o.millis { ms } else { (ms + 500) / 1000 };
+    Ok(RespData::Integer(time as i64))
+}
+
+pub fn persist(o: Persist, ctx: &mut Context) -> Result<RespData, DbError> {
+    if ctx.db.get(&o.key).is_none() || ctx.db.expires_at(&o.key).is_none() {
+        return Ok(RespData::Integer(0));
+    }
+    ctx.db.set_expires_at(&o.key, None);
+    ctx.mark_dirty();
+    Ok(RespData::Integer(1))
+}
+
+pub fn scan(o: Scan, ctx: &mut Context) -> Result<RespData, DbError> {
+    let options = o.options;
+    let (cursor, keys) = ctx.db.scan(options.cursor, options.count);
+    let keys = keys
+        .into_iter()
+        .filter(|key| {
+            let type_matches = match &options.type_name {
+                Some(type_name) => ctx
+                    .db
+                    .get(key)
+                    .is_some_and(|value| value.type_name() == type_name),
+                None => true,
+            };
+            options.matches(key) && type_matches
+        })
+        .map(RespData::BulkStr)
+        .collect();
+    Ok(scan_reply(cursor, keys))
+}
+
+/// A page of an SSCAN, HSCAN or ZSCAN over the elements of a collection,
+/// taken from its scan index the way `KeyValueStore::scan` takes keys, and
+/// the cursor to continue from.
+pub(super) fn scan_page(index: &ScanIndex, options: &ScanOptions) -> (u64, Vec<Bytes>) {
+    let (cursor, mut page) = index.page(options.cursor, options.count);
+    page.retain(|element| options.matches(element));
+    (cursor, page)
+}
+
+pub(super) fn scan_reply(cursor: u64, elements: Vec<RespData>) -> RespData {
+    RespData::Array(vec![
+        RespData::bulk(cursor.to_string()),
+        RespData::Array(elements),
+    ])
+}
+
+/// `index` as the index of one of the databases.
+fn db_index(index: i64, ctx: &Context) -> Result<usize, DbError> {
+    usize::try_from(index)
+        .ok()
+        .filter(|index| *index < ctx.database_count())
+        .ok_or(DbError::DbIndexOutOfRange)
+}
@@ -0,0 +1,327 @@
+use std::collections::VecDeque;
+
+use bytes::Bytes;
+use itertools::Either;
+
+use super::{index_range, Context};
+use crate::{
+    cmds::{
+        Blmove, Blmpop, Bpop, End, Lindex, Linsert, Llen, Lmove, Lmpop, Lpos, Lrange, Lrem, Lset,
+        Ltrim, Pop, Push,
+    },
+    database::DbError,
+    resp::RespData,
+};
+
+type List = VecDeque<Bytes>;
+
+fn push_element(list: &mut List, end: End, element: Bytes) {
+    match end {
+        End::Left => list.push_front(element),
+        End::Right => list.push_back(element),
+    }
+}
+
+fn pop_elements(list: &mut List, end: End, count: usize) -> Vec<Bytes> {
+    let count = count.min(list.len());
+    match end {
+        End::Left => list.drain(..count).collect(),
+        End::Right => (0..count).filter_map(|_| list.pop_back()).collect(),
+    }
+}
+
+/// Resolves a possibly negative index into a position within `len` elements.
+fn list_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+fn end_name(end: End) -> &'static [u8] {
+    match end {
+        End::Left => b"LEFT",
+        End::Right => b"RIGHT",
+    }
+}
+
+fn pop_command(end: End) -> &'static [u8] {
+    match end {
+        End::Left => b"LPOP",
+        End::Right => b"RPOP",
+    }
+}
+
+fn bulk_array(elements: Vec<Bytes>) -> RespData {
+    RespData::Array(elements.into_iter().map(RespData::BulkStr).collect())
+}
+
+pub fn push(o: Push, ctx: &mut Context) -> Result<RespData, DbError> {
+    let list = if o.only_if_exists {
+        match ctx.db.get_mut_as::<List>(&o.key)? {
+            Some(list) => list,
+            None => return Ok(RespData::Integer(0)),
+        }
+    } else {
+        ctx.db.entry_as::<List>(o.key.clone())?
+    };
+    for element in o.elements {
+        push_element(list, o.end, element);
+    }
+    let len = list.len();
+    ctx.mark_dirty();
+    ctx.signal_key_as_ready(&o.key);
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn pop(o: Pop, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(list) = ctx.db.get_mut_as::<List>(&o.key)? else {
+        return Ok(match o.count {
+            Some(_) => RespData::NullArray,
+            None => RespData::Null,
+        });
+    };
+    let mut popped = pop_elements(list, o.end, o.count.unwrap_or(1));
+    ctx.db.remove_if_empty(&o.key);
+    if !popped.is_empty() {
+        ctx.mark_dirty();
+    }
+    Ok(match o.count {
+        Some(_) => bulk_array(popped),
+        None => popped.pop().map_or(RespData::Null, RespData::BulkStr),
+    })
+}
+
+pub fn llen(o: Llen, ctx: &mut Context) -> Result<RespData, DbError> {
+    let len = ctx.db.get_as::<List>(&o.key)?.map_or(0, List::len);
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn lrange(o: Lrange, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(list) = ctx.db.get_as::<List>(&o.key)? else {
+        return Ok(RespData::Array(Vec::new()));
+    };
+    let elements = match index_range(o.start, o.stop, list.len()) {
+        Some(range) => list.range(range).cloned().collect(),
+        None => Vec::new(),
+    };
+    Ok(bulk_array(elements))
+}
+
+pub fn lindex(o: Lindex, ctx: &mut Context) -> Result<RespData, DbError> {
+    let element = ctx
+        .db
+        .get_as::<List>(&o.key)?
+        .and_then(|list| list_index(o.index, list.len()).map(|index| list[index].clone()));
+    Ok(element.map_or(RespData::Null, RespData::BulkStr))
+}
+
+pub fn lset(o: Lset, ctx: &mut Context) -> Result<RespData, DbError> {
+    let list = ctx
+        .db
+        .get_mut_as::<List>(&o.key)?
+        .ok_or(DbError::NoSuchKey)?;
+    let index = list_index(o.index, list.len()).ok_or(DbError::IndexOutOfRange)?;
+    list[index] = o.element;
+    ctx.mark_dirty();
+    Ok(RespData::ok())
+}
+
+pub fn lrem(o: Lrem, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(list) = ctx.db.get_mut_as::<List>(&o.key)? else {
+        return Ok(RespData::Integer(0));
+    };
+    let len = list.len();
+    let matches = list.iter().filter(|e| **e == o.element).count();
+    let remove = match o.count {
+        0 => matches,
+        count => (count.unsigned_abs() as usize).min(matches),
+    };
+    // Removing from the tail is removing from the head after skipping the
+    // matches that stay.
+    let mut skip = if o.count < 0 { matches - remove } else { 0 };
+    let mut remaining = remove;
+    list.retain(|e| {
+        if remaining == 0 || *e != o.element {
+            return true;
+        }
+        if skip > 0 {
+            skip -= 1;
+            return true;
+        }
+        remaining -= 1;
+        false
+    });
+    let removed = len - list.len();
+    ctx.db.remove_if_empty(&o.key);
+    if removed > 0 {
+        ctx.mark_dirty();
+    }
+    Ok(RespData::Integer(removed as i64))
+}
+
+pub fn ltrim(o: Ltrim, ctx: &mut Context) -> Result<RespData, DbError> {
+    if let Some(list) = ctx.db.get_mut_as::<List>(&o.key)? {
+        match index_range(o.start, o.stop, list.len()) {
+            Some(range) => {
+                list.truncate(range.end() + 1);
+                list.drain(..range.start());
+            }
+            None => list.clear(),
+        }
+        ctx.db.remove_if_empty(&o.key);
+        ctx.mark_dirty();
+    }
+    Ok(RespData::ok())
+}
+
+pub fn linsert(o: Linsert, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(list) = ctx.db.get_mut_as::<List>(&o.key)? else {
+        return Ok(RespData::Integer(0));
+    };
+    let Some(pivot) = list.iter().position(|e| *e == o.pivot) else {
+        return Ok(RespData::Integer(-1));
+    };
+    let index = if o.before { pivot } else { pivot + 1 };
+    list.insert(index, o.element);
+    let len = list.len();
+    ctx.mark_dirty();
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn lpos(o: Lpos, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(list) = ctx.db.get_as::<List>(&o.key)? else {
+        return Ok(match o.count {
+            Some(_) => RespData::Array(Vec::new()),
+            None => RespData::Null,
+        });
+    };
+
+    let len = list.len();
+    let maxlen = if o.maxlen == 0 {
+        len
+    } else {
+        o.maxlen.min(len)
+    };
+    // A negative rank searches from the tail.
+    let indexes = if o.rank > 0 {
+        Either::Left(0..maxlen)
+    } else {
+        Either::Right((len - maxlen..len).rev())
+    };
+    let limit = match o.count {
+        Some(0) => usize::MAX,
+        Some(count) => count,
+        None => 1,
+    };
+
+    let mut skip = o.rank.unsigned_abs() - 1;
+    let mut positions = Vec::new();
+    for index in indexes {
+        if list[index] != o.element {
+            continue;
+        }
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        positions.push(RespData::Integer(index as i64));
+        if positions.len() == limit {
+            break;
+        }
+    }
+
+    Ok(match o.count {
+        Some(_) => RespData::Array(positions),
+        None => positions.pop().unwrap_or(RespData::Null),
+    })
+}
+
+pub fn lmove(o: Lmove, ctx: &mut Context) -> Result<RespData, DbError> {
+    // The destination must hold a list before anything is taken from the
+    // source.
+    ctx.db.get_as::<List>(&o.destination)?;
+    let Some(source) = ctx.db.get_mut_as::<List>(&o.source)? else {
+        return Ok(RespData::Null);
+    };
+    let Some(element) = pop_elements(source, o.from, 1).pop() else {
+        return Ok(RespData::Null);
+    };
+    let destination = ctx.db.entry_as::<List>(o.destination.clone())?;
+    push_element(destination, o.to, element.clone());
+    // Only after the push, so a one-element list moved onto itself keeps
+    // its key and expiry.
+    ctx.db.remove_if_empty(&o.source);
+    ctx.mark_dirty();
+    ctx.signal_key_as_ready(&o.destination);
+    Ok(RespData::BulkStr(element))
+}
+
+/// Pops up to `count` elements from the first of `keys` holding a list.
+fn pop_first(
+    keys: Vec<Bytes>,
+    end: End,
+    count: usize,
+    ctx: &mut Context,
+) -> Result<Option<(Bytes, Vec<Bytes>)>, DbError> {
+    for key in keys {
+        let Some(list) = ctx.db.get_mut_as::<List>(&key)? else {
+            continue;
+        };
+        let popped = pop_elements(list, end, count);
+        ctx.db.remove_if_empty(&key);
+        ctx.mark_dirty();
+        return Ok(Some((key, popped)));
+    }
+    Ok(None)
+}
+
+pub fn lmpop(o: Lmpop, ctx: &mut Context) -> Result<RespData, DbError> {
+    Ok(match pop_first(o.keys, o.end, o.count, ctx)? {
+        Some((key, popped)) => RespData::Array(vec![RespData::BulkStr(key), bulk_array(popped)]),
+        None => RespData::NullArray,
+    })
+}
+
+// The blocking variants below only make the single attempt; waiting is up to
+// the caller. Whether they run right away or once a push served them, replicas
+// get the equivalent non-blocking command.
+
+pub fn bpop(o: Bpop, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some((key, mut popped)) = pop_first(o.keys, o.end, 1, ctx)? else {
+        return Ok(RespData::NullArray);
+    };
+    ctx.propagate(&[pop_command(o.end), &key]);
+    let element = popped.pop().map_or(RespData::Null, RespData::BulkStr);
+    Ok(RespData::Array(vec![RespData::BulkStr(key), element]))
+}
+
+pub fn blmove(o: Blmove, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Lmove {
+        source,
+        destination,
+        from,
+        to,
+    } = o.lmove.clone();
+    let reply = lmove(o.lmove, ctx)?;
+    if !matches!(reply, RespData::Null) {
+        ctx.propagate(&[
+            b"LMOVE",
+            &source,
+            &destination,
+            end_name(from),
+            end_name(to),
+        ]);
+    }
+    Ok(reply)
+}
+
+pub fn blmpop(o: Blmpop, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Lmpop { keys, end, count } = o.lmpop;
+    let Some((key, popped)) = pop_first(keys, end, count, ctx)? else {
+        return Ok(RespData::NullArray);
+    };
+    ctx.propagate(&[pop_command(end), &key, popped.len().to_string().as_bytes()]);
+    Ok(RespData::Array(vec![
+        RespData::BulkStr(key),
+        bulk_array(popped),
+    ]))
+}
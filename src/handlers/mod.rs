@@ -0,0 +1,665 @@
+use std::{cmp::Ordering, collections::VecDeque, future::Future, ops::RangeInclusive};
+
+use bytes::Bytes;
+use tokio::{
+    sync::MutexGuard,
+    time::{self, Duration},
+};
+
+use crate::{
+    cmds::{Command, CommandError, Select},
+    database::{
+        BlockedClients, Databases, DbError, Expired, KeyValueStore, LazyExpiry, SharedState,
+    },
+    resp::{Protocol, RespData},
+};
+
+mod bitmap;
+mod geo;
+mod hash;
+mod hyperloglog;
+mod keyspace;
+mod list;
+mod set;
+mod stream;
+mod string;
+mod zset;
+
+/// What a keyspace command runs against, and what it leaves behind for
+/// replication and for clients blocked on its keys.
+pub struct Context<'a> {
+    /// The database the client has selected.
+    pub db: &'a mut KeyValueStore,
+    pub protocol: Protocol,
+    index: usize,
+    // The other databases, on either side of `db`.
+    before: &'a mut [KeyValueStore],
+    after: &'a mut [KeyValueStore],
+    dirty: bool,
+    rewritten: Vec<RespData>,
+    ready_keys: Vec<(usize, Bytes)>,
+    ready_dbs: Vec<usize>,
+}
+
+/// What commands left behind once their context is done with the
+/// databases: frames for replicas, each with the database it applies to,
+/// and what was made ready for blocked clients.
+#[derive(Default)]
+struct Effects {
+    frames: Vec<(usize, Vec<u8>)>,
+    ready_keys: Vec<(usize, Bytes)>,
+    ready_dbs: Vec<usize>,
+}
+
+impl Effects {
+    fn extend(&mut self, other: Effects) {
+        self.frames.extend(other.frames);
+        self.ready_keys.extend(other.ready_keys);
+        self.ready_dbs.extend(other.ready_dbs);
+    }
+}
+
+impl<'a> Context<'a> {
+    /// A context with database `index` of `dbs` selected.
+    pub fn new(dbs: &'a mut [KeyValueStore], index: usize, protocol: Protocol) -> Self {
+        let (before, rest) = dbs.split_at_mut(index);
+        let (db, after) = rest
+            .split_first_mut()
+            .expect("Selected database out of range");
+        Self {
+            db,
+            protocol,
+            index,
+            before,
+            after,
+            dirty: false,
+            rewritten: Vec::new(),
+            ready_keys: Vec::new(),
+            ready_dbs: Vec::new(),
+        }
+    }
+
+    /// The index of the selected database.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn database_count(&self) -> usize {
+        self.before.len() + 1 + self.after.len()
+    }
+
+    /// Database `index`, which may be the selected one too.
+    pub fn database(&mut self, index: usize) -> &mut KeyValueStore {
+        match index.cmp(&self.index) {
+            Ordering::Less => &mut self.before[index],
+            Ordering::Equal => self.db,
+            Ordering::Greater => &mut self.after[index - self.index - 1],
+        }
+    }
+
+    /// Swaps the contents of two databases. Clients keep the index they
+    /// selected, and so see the other one's keys from now on.
+    pub fn swap_databases(&mut self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+        let first = std::mem::take(self.database(a));
+        let second = std::mem::replace(self.database(b), first);
+        *self.database(a) = second;
+    }
+
+    /// The command changed the keyspace; replicas get it as it was received.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// The command changed the keyspace in a way that replaying it would not
+    /// reproduce, so replicas get `args` instead.
+    pub fn propagate(&mut self, args: &[&[u8]]) {
+        self.rewritten
+            .push(RespData::Array(args.iter().map(RespData::bulk).collect()));
+    }
+
+    /// Clients blocked on `key` get to check it again once the command is done.
+    pub fn signal_key_as_ready(&mut self, key: &Bytes) {
+        self.ready_keys.push((self.index, key.clone()));
+    }
+
+    /// Like `signal_key_as_ready`, for a key of database `db`.
+    pub fn signal_key_in_db_as_ready(&mut self, db: usize, key: &Bytes) {
+        self.ready_keys.push((db, key.clone()));
+    }
+
+    /// Clients blocked on any key of database `db` get to check it again.
+    pub fn signal_db_as_ready(&mut self, db: usize) {
+        self.ready_dbs.push(db);
+    }
+
+    /// Frames to send to replicas for the command that ran, together with
+    /// what it made ready. Keys and fields that expired on the way are
+    /// deleted on replicas first, as they would be gone by the time the
+    /// command got to them.
+    fn finish(mut self, raw_frame: &[u8]) -> Effects {
+        let mut expired = Vec::new();
+        for index in 0..self.database_count() {
+            for deletion in self.database(index).take_expired() {
+                let args: Vec<&[u8]> = match &deletion {
+                    Expired::Key(key) => vec![b"DEL", key],
+                    Expired::Fields(key, fields) => [&b"HDEL"[..], key]
+                        .into_iter()
+                        .chain(fields.iter().map(|field| &field[..]))
+                        .collect(),
+                };
+                let frame = RespData::Array(args.iter().map(RespData::bulk).collect());
+                expired.push((index, frame.to_vec()));
+            }
+        }
+        let frames = if !self.rewritten.is_empty() {
+            self.rewritten.iter().map(RespData::to_vec).collect()
+        } else if self.dirty {
+            vec![raw_frame.to_vec()]
+        } else {
+            Vec::new()
+        };
+        let index = self.index;
+        expired.extend(frames.into_iter().map(|frame| (index, frame)));
+        Effects {
+            frames: expired,
+            ready_keys: self.ready_keys,
+            ready_dbs: self.ready_dbs,
+        }
+    }
+}
+
+/// Resolves inclusive `start`/`stop` indexes, where negative ones count from
+/// the end, to positions within `len` elements. `None` when nothing is
+/// selected.
+fn index_range(start: i64, stop: i64, len: usize) -> Option<RangeInclusive<usize>> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        stop + len
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some(start as usize..=stop as usize)
+}
+
+/// Parses an integer the way Redis's `string2ll` does: no sign but `-`, no
+/// leading zeros and nothing around the digits, so only the canonical form of
+/// each number is accepted.
+fn parse_integer(value: &[u8]) -> Option<i64> {
+    let parsed = std::str::from_utf8(value).ok()?.parse::<i64>().ok()?;
+    (parsed.to_string().as_bytes() == value).then_some(parsed)
+}
+
+/// The sum INCRBYFLOAT and HINCRBYFLOAT store: 17 significant digits, no
+/// trailing zeros and no exponent. Redis adds in long doubles, so `0.1 + 0.2`
+/// is `0.3` rather than `0.30000000000000004`; adding the shortest decimals
+/// that round-trip to the operands, exactly, gives the same results.
+fn format_float_sum(value: f64, increment: f64) -> String {
+    let (a, a_exponent) = shortest_decimal(value);
+    let (b, b_exponent) = shortest_decimal(increment);
+    let exponent = a_exponent.min(b_exponent);
+    let scale = |mantissa: i128, from: i32| {
+        10i128
+            .checked_pow((from - exponent) as u32)
+            .and_then(|power| mantissa.checked_mul(power))
+    };
+    // Operands too far apart to line up only differ from their float sum
+    // past the digits that are kept.
+    let (mantissa, exponent) = match (scale(a, a_exponent), scale(b, b_exponent)) {
+        (Some(a), Some(b)) => a.checked_add(b).map_or_else(
+            || shortest_decimal(value + increment),
+            |sum| (sum, exponent),
+        ),
+        _ => shortest_decimal(value + increment),
+    };
+
+    let mut digits = mantissa.unsigned_abs().to_string();
+    let mut exponent = exponent;
+    if digits.len() > 17 {
+        let mut kept: u128 = digits[..17].parse().unwrap_or_default();
+        if digits.as_bytes()[17] >= b'5' {
+            kept += 1;
+        }
+        exponent += digits.len() as i32 - 17;
+        digits = kept.to_string();
+    }
+    let trimmed = digits.trim_end_matches('0');
+    if trimmed.is_empty() {
+        return "0".to_string();
+    }
+    exponent += (digits.len() - trimmed.len()) as i32;
+
+    let sign = if mantissa < 0 { "-" } else { "" };
+    let point = trimmed.len() as i32 + exponent;
+    if exponent >= 0 {
+        format!("{}{}{}", sign, trimmed, "0".repeat(exponent as usize))
+    } else if point > 0 {
+        let (whole, fraction) = trimmed.split_at(point as usize);
+        format!("{}{}.{}", sign, whole, fraction)
+    } else {
+        format!("{}0.{}{}", sign, "0".repeat(-point as usize), trimmed)
+    }
+}
+
+/// The shortest decimal that reads back as `d`, as digits and a power of ten.
+fn shortest_decimal(d: f64) -> (i128, i32) {
+    // `{:e}` prints those digits with one before the point, e.g. `-3.2e-1`.
+    let formatted = format!("{:e}", d);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let fraction_len = mantissa.split_once('.').map_or(0, |(_, f)| f.len());
+    let digits = mantissa.replace('.', "").parse().unwrap_or_default();
+    let exponent: i32 = exponent.parse().unwrap_or_default();
+    (digits, exponent - fraction_len as i32)
+}
+
+/// Runs a keyspace command. Connection level commands (PING, MULTI,
+/// REPLCONF, ...) are answered by the connection itself.
+pub fn execute(cmd: Command, ctx: &mut Context) -> RespData {
+    let reply = match cmd {
+        Command::Get(o) => string::get(o, ctx),
+        Command::Set(o) => string::set(o, ctx),
+        Command::Incr(o) => string::incr(o, ctx),
+        Command::Incrbyfloat(o) => string::incrbyfloat(o, ctx),
+        Command::Append(o) => string::append(o, ctx),
+        Command::Strlen(o) => string::strlen(o, ctx),
+        Command::Getrange(o) => string::getrange(o, ctx),
+        Command::Setrange(o) => string::setrange(o, ctx),
+        Command::Mget(o) => string::mget(o, ctx),
+        Command::Mset(o) => string::mset(o, ctx),
+        Command::Getdel(o) => string::getdel(o, ctx),
+        Command::Getex(o) => string::getex(o, ctx),
+        Command::Getset(o) => string::getset(o, ctx),
+        Command::Lcs(o) => string::lcs(o, ctx),
+        Command::Setbit(o) => bitmap::setbit(o, ctx),
+        Command::Getbit(o) => bitmap::getbit(o, ctx),
+        Command::Bitcount(o) => bitmap::bitcount(o, ctx),
+        Command::Bitpos(o) => bitmap::bitpos(o, ctx),
+        Command::Bitop(o) => bitmap::bitop(o, ctx),
+        Command::Bitfield(o) => bitmap::bitfield(o, ctx),
+        Command::Pfadd(o) => hyperloglog::pfadd(o, ctx),
+        Command::Pfcount(o) => hyperloglog::pfcount(o, ctx),
+        Command::Pfmerge(o) => hyperloglog::pfmerge(o, ctx),
+        Command::Geoadd(o) => geo::geoadd(o, ctx),
+        Command::Geodist(o) => geo::geodist(o, ctx),
+        Command::Geopos(o) => geo::geopos(o, ctx),
+        Command::Geosearch(o) => geo::geosearch(o, ctx),
+        Command::Del(o) => keyspace::del(o, ctx),
+        Command::Exists(o) => keyspace::exists(o, ctx),
+        Command::Rename(o) => keyspace::rename(o, ctx),
+        Command::CopyKey(o) => keyspace::copy(o, ctx),
+        Command::Move(o) => keyspace::move_key(o, ctx),
+        Command::Swapdb(o) => keyspace::swapdb(o, ctx),
+        Command::Randomkey(o) => keyspace::randomkey(o, ctx),
+        Command::Dbsize(o) => keyspace::dbsize(o, ctx),
+        Command::Flush(o) => keyspace::flush(o, ctx),
+        Command::Expire(o) => keyspace::expire(o, ctx),
+        Command::Ttl(o) => keyspace::ttl(o, ctx),
+        Command::Persist(o) => keyspace::persist(o, ctx),
+        Command::Scan(o) => keyspace::scan(o, ctx),
+        Command::Type(o) => keyspace::key_type(o, ctx),
+        Command::Keys(o) => keyspace::keys(o, ctx),
+        Command::Xadd(o) => stream::xadd(o, ctx),
+        Command::Xrange(o) => stream::xrange(o, ctx),
+        Command::Push(o) => list::push(o, ctx),
+        Command::Pop(o) => list::pop(o, ctx),
+        Command::Llen(o) => list::llen(o, ctx),
+        Command::Lrange(o) => list::lrange(o, ctx),
+        Command::Lindex(o) => list::lindex(o, ctx),
+        Command::Lset(o) => list::lset(o, ctx),
+        Command::Lrem(o) => list::lrem(o, ctx),
+        Command::Ltrim(o) => list::ltrim(o, ctx),
+        Command::Linsert(o) => list::linsert(o, ctx),
+        Command::Lpos(o) => list::lpos(o, ctx),
+        Command::Lmove(o) => list::lmove(o, ctx),
+        Command::Lmpop(o) => list::lmpop(o, ctx),
+        Command::Bpop(o) => list::bpop(o, ctx),
+        Command::Blmove(o) => list::blmove(o, ctx),
+        Command::Blmpop(o) => list::blmpop(o, ctx),
+        Command::Xread(o) => stream::xread(o, ctx),
+        Command::Hset(o) => hash::hset(o, ctx),
+        Command::Hget(o) => hash::hget(o, ctx),
+        Command::Hmget(o) => hash::hmget(o, ctx),
+        Command::Hgetall(o) => hash::hgetall(o, ctx),
+        Command::Hdel(o) => hash::hdel(o, ctx),
+        Command::Hexists(o) => hash::hexists(o, ctx),
+        Command::Hincrby(o) => hash::hincrby(o, ctx),
+        Command::Hincrbyfloat(o) => hash::hincrbyfloat(o, ctx),
+        Command::Hkeys(o) => hash::hkeys(o, ctx),
+        Command::Hvals(o) => hash::hvals(o, ctx),
+        Command::Hlen(o) => hash::hlen(o, ctx),
+        Command::Hscan(o) => hash::hscan(o, ctx),
+        Command::Sscan(o) => set::sscan(o, ctx),
+        Command::Zscan(o) => zset::zscan(o, ctx),
+        Command::Hrandfield(o) => hash::hrandfield(o, ctx),
+        Command::Hexpire(o) => hash::hexpire(o, ctx),
+        Command::Httl(o) => hash::httl(o, ctx),
+        Command::Hpersist(o) => hash::hpersist(o, ctx),
+        Command::Sadd(o) => set::sadd(o, ctx),
+        Command::Srem(o) => set::srem(o, ctx),
+        Command::Smembers(o) => set::smembers(o, ctx),
+        Command::Sismember(o) => set::sismember(o, ctx),
+        Command::Smismember(o) => set::smismember(o, ctx),
+        Command::Scard(o) => set::scard(o, ctx),
+        Command::Spop(o) => set::spop(o, ctx),
+        Command::Srandmember(o) => set::srandmember(o, ctx),
+        Command::Setop(o) => set::setop(o, ctx),
+        Command::Sintercard(o) => set::sintercard(o, ctx),
+        Command::Zadd(o) => zset::zadd(o, ctx),
+        Command::Zincrby(o) => zset::zincrby(o, ctx),
+        Command::Zrange(o) => zset::zrange(o, ctx),
+        Command::Zrank(o) => zset::zrank(o, ctx),
+        Command::Zscore(o) => zset::zscore(o, ctx),
+        Command::Zcard(o) => zset::zcard(o, ctx),
+        Command::Zrem(o) => zset::zrem(o, ctx),
+        Command::Zcount(o) => zset::zcount(o, ctx),
+        Command::Zpop(o) => zset::zpop(o, ctx),
+        Command::Zmpop(o) => zset::zmpop(o, ctx),
+        Command::Bzpop(o) => zset::bzpop(o, ctx),
+        Command::Bzmpop(o) => zset::bzmpop(o, ctx),
+        Command::Zsetop(o) => zset::zsetop(o, ctx),
+        _ => return RespData::ErrorStr(CommandError::NotSupported.message()),
+    };
+    reply.unwrap_or_else(|e: DbError| RespData::ErrorStr(e.to_string()))
+}
+
+/// Runs `cmd` on database `db` under the keyspace lock, then replicates it
+/// and serves the clients blocked on the keys it made ready.
+pub async fn run(
+    cmd: Command,
+    raw_frame: &[u8],
+    state: &SharedState,
+    db: usize,
+    protocol: Protocol,
+) -> RespData {
+    let mut keyspace = state.kv_store.lock().await;
+    let mut ctx = Context::new(&mut keyspace.dbs, db, protocol);
+    let reply = execute(cmd, &mut ctx);
+    let effects = ctx.finish(raw_frame);
+    complete(&mut keyspace, effects, state).await;
+    reply
+}
+
+/// Switches `db` to the database SELECT names, one of `count`.
+pub fn select(o: Select, db: &mut usize, count: usize) -> RespData {
+    match usize::try_from(o.index).ok().filter(|index| *index < count) {
+        Some(index) => {
+            *db = index;
+            RespData::ok()
+        }
+        None => RespData::ErrorStr(DbError::DbIndexOutOfRange.to_string()),
+    }
+}
+
+/// The commands of a MULTI block, run one after the other under a single
+/// keyspace lock so no other client sees the keyspace halfway through them.
+pub struct Transaction<'a> {
+    state: &'a SharedState,
+    keyspace: MutexGuard<'a, Databases>,
+    effects: Effects,
+    from_leader: bool,
+}
+
+impl<'a> Transaction<'a> {
+    pub async fn begin(state: &'a SharedState) -> Transaction<'a> {
+        Self {
+            state,
+            keyspace: state.kv_store.lock().await,
+            effects: Effects::default(),
+            from_leader: false,
+        }
+    }
+
+    /// A transaction applying writes a replica got from its leader, which
+    /// see keys and fields past their expiry time as still there.
+    pub async fn from_leader(state: &'a SharedState) -> Transaction<'a> {
+        Self {
+            from_leader: true,
+            ..Self::begin(state).await
+        }
+    }
+
+    pub fn databases(&self) -> &[KeyValueStore] {
+        &self.keyspace.dbs
+    }
+
+    /// Runs a keyspace command on database `db`. Blocking commands make a
+    /// single attempt, as nothing else can write while the transaction runs.
+    pub fn run(
+        &mut self,
+        cmd: Command,
+        raw_frame: &[u8],
+        db: usize,
+        protocol: Protocol,
+    ) -> RespData {
+        if self.from_leader {
+            self.keyspace.set_lazy_expiry(LazyExpiry::Ignore);
+        }
+        let mut ctx = Context::new(&mut self.keyspace.dbs, db, protocol);
+        let reply = execute(cmd, &mut ctx);
+        self.effects.extend(ctx.finish(raw_frame));
+        if self.from_leader {
+            self.keyspace.set_lazy_expiry(LazyExpiry::Hide);
+        }
+        reply
+    }
+
+    /// Replicates the writes of the transaction, wrapped in MULTI and EXEC
+    /// when there are several so replicas apply them at once too, then
+    /// serves the clients blocked on the keys they made ready.
+    pub async fn commit(self) {
+        let Transaction {
+            state,
+            mut keyspace,
+            mut effects,
+            ..
+        } = self;
+        if let (Some(&(first, _)), Some(&(last, _))) =
+            (effects.frames.first(), effects.frames.last())
+        {
+            if effects.frames.len() > 1 {
+                effects
+                    .frames
+                    .insert(0, (first, RespData::command(&["MULTI"]).to_vec()));
+                effects
+                    .frames
+                    .push((last, RespData::command(&["EXEC"]).to_vec()));
+            }
+        }
+        complete(&mut keyspace, effects, state).await;
+    }
+}
+
+/// Runs a command that may block. It is tried right away like any other;
+/// when that finds nothing the client waits on the command's keys until a
+/// write serves it, the timeout passes or `hang_up` resolves. Timing out
+/// gets the reply of the attempt that found nothing.
+pub async fn run_blocking(
+    mut cmd: Command,
+    raw_frame: &[u8],
+    state: &SharedState,
+    db: usize,
+    protocol: Protocol,
+    hang_up: impl Future<Output = ()>,
+) -> RespData {
+    let (id, mut receiver, timeout, empty_reply) = {
+        let mut keyspace = state.kv_store.lock().await;
+        let mut ctx = Context::new(&mut keyspace.dbs, db, protocol);
+        if let Command::Xread(o) = &mut cmd {
+            if let Err(e) = stream::pin_last_ids(o, &mut ctx) {
+                return RespData::ErrorStr(e.to_string());
+            }
+        }
+        let reply = execute(cmd.clone(), &mut ctx);
+        let effects = ctx.finish(raw_frame);
+        // Even an attempt that finds nothing may have expired keys on the way.
+        complete(&mut keyspace, effects, state).await;
+        let Some((keys, timeout)) = blocking_on(&cmd).filter(|_| is_empty(&reply)) else {
+            return reply;
+        };
+        let (id, receiver) = state
+            .blocked_clients
+            .lock()
+            .await
+            .block(db, keys, cmd, protocol);
+        (id, receiver, timeout, reply)
+    };
+
+    let served = tokio::select! {
+        reply = &mut receiver => reply.ok(),
+        _ = sleep(timeout) => None,
+        _ = hang_up => None,
+    };
+    if let Some(reply) = served {
+        return reply;
+    }
+    // A write may have served us while the timeout fired. Writes serve under
+    // the keyspace lock, so once we hold it the waiter is either still
+    // registered or its reply is waiting in the channel.
+    let _keyspace = state.kv_store.lock().await;
+    match state.blocked_clients.lock().await.unblock(id) {
+        Some(_) => empty_reply,
+        None => receiver.try_recv().unwrap_or(empty_reply),
+    }
+}
+
+/// The keys a blocking command waits on and for how long, `None` meaning
+/// forever. `None` altogether for commands that never block.
+fn blocking_on(cmd: &Command) -> Option<(Vec<Bytes>, Option<Duration>)> {
+    match cmd {
+        Command::Bpop(o) => Some((o.keys.clone(), o.timeout)),
+        Command::Blmove(o) => Some((vec![o.lmove.source.clone()], o.timeout)),
+        Command::Blmpop(o) => Some((o.lmpop.keys.clone(), o.timeout)),
+        Command::Bzpop(o) => Some((o.keys.clone(), o.timeout)),
+        Command::Bzmpop(o) => Some((o.zmpop.keys.clone(), o.timeout)),
+        Command::Xread(o) => o.block.map(|block| {
+            let timeout = (block > 0).then(|| Duration::from_millis(block));
+            (o.keys.clone(), timeout)
+        }),
+        _ => None,
+    }
+}
+
+/// `cmd` narrowed to `key`, for retrying it once `key` is ready. Its other
+/// keys are left alone, so one of them since taken over by another type
+/// does not stop the client from being served.
+fn retry_on(cmd: &Command, key: &Bytes) -> Command {
+    let mut cmd = cmd.clone();
+    match &mut cmd {
+        Command::Bpop(o) => o.keys = vec![key.clone()],
+        Command::Blmpop(o) => o.lmpop.keys = vec![key.clone()],
+        Command::Bzpop(o) => o.keys = vec![key.clone()],
+        Command::Bzmpop(o) => o.zmpop.keys = vec![key.clone()],
+        Command::Xread(o) => {
+            if let Some(i) = o.keys.iter().position(|other| other == key) {
+                o.entry_ids = vec![o.entry_ids[i].clone()];
+                o.keys = vec![key.clone()];
+            }
+        }
+        // BLMOVE only ever waits on its source.
+        _ => {}
+    }
+    cmd
+}
+
+/// Whether a blocking command found nothing to return.
+fn is_empty(reply: &RespData) -> bool {
+    matches!(reply, RespData::Null | RespData::NullArray)
+}
+
+fn is_wrong_type(reply: &RespData) -> bool {
+    matches!(reply, RespData::ErrorStr(e) if *e == DbError::WrongType.to_string())
+}
+
+async fn sleep(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => time::sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Replicates what commands changed and serves the clients blocked on the
+/// keys they made ready. The keyspace lock is still held, so replicas apply
+/// writes in the order they happened here.
+async fn complete(keyspace: &mut Databases, effects: Effects, state: &SharedState) {
+    let mut frames = effects.frames;
+    if !effects.ready_keys.is_empty() || !effects.ready_dbs.is_empty() {
+        let mut blocked = state.blocked_clients.lock().await;
+        let mut ready_keys = effects.ready_keys;
+        for db in effects.ready_dbs {
+            ready_keys.extend(blocked.keys_in(db).into_iter().map(|key| (db, key)));
+        }
+        frames.extend(serve_blocked_clients(
+            &mut keyspace.dbs,
+            &mut blocked,
+            ready_keys,
+        ));
+    }
+    for (db, frame) in frames {
+        // Replicas switch databases only when told to.
+        if db != keyspace.replicated_db {
+            let select = RespData::command(&["SELECT", &db.to_string()]).to_vec();
+            state.broadcast_peers(select).await;
+            keyspace.replicated_db = db;
+        }
+        state.broadcast_peers(frame).await;
+    }
+}
+
+/// Tells replicas about the keys and fields active expiry deleted.
+pub async fn replicate_expired(keyspace: &mut Databases, state: &SharedState) {
+    let effects = Context::new(&mut keyspace.dbs, 0, Protocol::Resp2).finish(&[]);
+    complete(keyspace, effects, state).await;
+}
+
+/// Retries the commands of the clients blocked on `ready_keys`, against the
+/// ready key only, longest waiting first, and hands each one that gets
+/// something its reply. Serving
+/// one can make more keys ready (BLMOVE pushes to its destination), so this
+/// goes on until none are left. Returns what to send to replicas, with the
+/// database each frame applies to.
+fn serve_blocked_clients(
+    dbs: &mut [KeyValueStore],
+    blocked: &mut BlockedClients,
+    ready_keys: Vec<(usize, Bytes)>,
+) -> Vec<(usize, Vec<u8>)> {
+    let mut ready_keys = VecDeque::from(ready_keys);
+    let mut frames = Vec::new();
+    while let Some((db, key)) = ready_keys.pop_front() {
+        for id in blocked.waiting_on(db, &key) {
+            // Already served through another of its keys.
+            let Some(waiter) = blocked.get(id) else {
+                continue;
+            };
+            // Gone without unregistering; whatever it would take is left for
+            // the next client.
+            if waiter.reply.is_closed() {
+                blocked.unblock(id);
+                continue;
+            }
+            let mut ctx = Context::new(dbs, db, waiter.protocol);
+            let reply = execute(retry_on(&waiter.command, &key), &mut ctx);
+            // A key of another type than the command pops from (a list for
+            // BZPOPMIN, say) leaves the client blocked, as nothing was taken.
+            if is_empty(&reply) || is_wrong_type(&reply) {
+                continue;
+            }
+            let effects = ctx.finish(&[]);
+            frames.extend(effects.frames);
+            ready_keys.extend(effects.ready_keys);
+            if let Some(waiter) = blocked.unblock(id) {
+                let _ = waiter.reply.send(reply);
+            }
+        }
+    }
+    frames
+}
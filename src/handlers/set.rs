@@ -0,0 +1,213 @@
+use bytes::Bytes;
+use rand::{seq::IteratorRandom, seq::SliceRandom, Rng};
+
+use super::{keyspace, Context};
+use crate::{
+    cmds::{
+        Sadd, Scard, SetOp, Setop, Sintercard, Sismember, Smembers, Smismember, Spop, Srandmember,
+        Srem, Sscan,
+    },
+    database::{DbError, RedisValue, Set},
+    resp::RespData,
+};
+
+/// Members as a RESP3 set, which RESP2 clients receive as an array.
+fn set_reply<'a>(members: impl IntoIterator<Item = &'a Bytes>) -> RespData {
+    RespData::Set(members.into_iter().map(RespData::bulk).collect())
+}
+
+pub fn sadd(o: Sadd, ctx: &mut Context) -> Result<RespData, DbError> {
+    let set = ctx.db.entry_as::<Set>(o.key)?;
+    let added = o
+        .members
+        .into_iter()
+        .filter(|member| set.insert(member.clone()))
+        .count();
+    if added > 0 {
+        ctx.mark_dirty();
+    }
+    Ok(RespData::Integer(added as i64))
+}
+
+pub fn srem(o: Srem, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(set) = ctx.db.get_mut_as::<Set>(&o.key)? else {
+        return Ok(RespData::Integer(0));
+    };
+    let removed = o.members.iter().filter(|member| set.remove(member)).count();
+    ctx.db.remove_if_empty(&o.key);
+    if removed > 0 {
+        ctx.mark_dirty();
+    }
+    Ok(RespData::Integer(removed as i64))
+}
+
+pub fn smembers(o: Smembers, ctx: &mut Context) -> Result<RespData, DbError> {
+    Ok(set_reply(
+        ctx.db.get_as::<Set>(&o.key)?.into_iter().flatten(),
+    ))
+}
+
+pub fn sismember(o: Sismember, ctx: &mut Context) -> Result<RespData, DbError> {
+    let is_member = ctx
+        .db
+        .get_as::<Set>(&o.key)?
+        .is_some_and(|set| set.contains(&o.member));
+    Ok(RespData::Integer(is_member as i64))
+}
+
+pub fn smismember(o: Smismember, ctx: &mut Context) -> Result<RespData, DbError> {
+    let set = ctx.db.get_as::<Set>(&o.key)?;
+    Ok(RespData::Array(
+        o.members
+            .iter()
+            .map(|member| RespData::Integer(set.is_some_and(|set| set.contains(member)) as i64))
+            .collect(),
+    ))
+}
+
+pub fn scard(o: Scard, ctx: &mut Context) -> Result<RespData, DbError> {
+    let len = ctx.db.get_as::<Set>(&o.key)?.map_or(0, Set::len);
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn spop(o: Spop, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(set) = ctx.db.get_mut_as::<Set>(&o.key)? else {
+        return Ok(match o.count {
+            Some(_) => RespData::Set(Vec::new()),
+            None => RespData::Null,
+        });
+    };
+    let mut rng = rand::thread_rng();
+    let popped: Vec<Bytes> = set
+        .iter()
+        .cloned()
+        .choose_multiple(&mut rng, o.count.unwrap_or(1));
+    for member in &popped {
+        set.remove(member);
+    }
+    ctx.db.remove_if_empty(&o.key);
+
+    // Replicas must remove the same members, not pick their own.
+    if !popped.is_empty() {
+        let mut args: Vec<&[u8]> = vec![b"SREM", &o.key];
+        args.extend(popped.iter().map(|member| member.as_ref()));
+        ctx.propagate(&args);
+    }
+    Ok(match o.count {
+        Some(_) => set_reply(&popped),
+        None => popped.first().map_or(RespData::Null, RespData::bulk),
+    })
+}
+
+pub fn srandmember(o: Srandmember, ctx: &mut Context) -> Result<RespData, DbError> {
+    // The bound HRANDFIELD has too.
+    if o.count.is_some_and(|count| count < -(i64::MAX / 2)) {
+        return Err(DbError::ValueOutOfRange);
+    }
+    let set = ctx.db.get_as::<Set>(&o.key)?;
+    let mut rng = rand::thread_rng();
+    let Some(count) = o.count else {
+        let member = set.and_then(|set| set.iter().choose(&mut rng));
+        return Ok(member.map_or(RespData::Null, RespData::bulk));
+    };
+    let Some(set) = set else {
+        return Ok(RespData::Array(Vec::new()));
+    };
+
+    let picked: Vec<&Bytes> = if count >= 0 {
+        // Distinct members, as many as there are up to `count`.
+        let mut picked = set
+            .iter()
+            .choose_multiple(&mut rng, (count as usize).min(set.len()));
+        picked.shuffle(&mut rng);
+        picked
+    } else {
+        // Grown one pick at a time rather than sized up front from a count
+        // the client chose.
+        let members: Vec<&Bytes> = set.iter().collect();
+        let mut picked = Vec::new();
+        for _ in 0..count.unsigned_abs() {
+            picked.push(members[rng.gen_range(0..members.len())]);
+        }
+        picked
+    };
+    // The same member may come up twice, so this is no set.
+    Ok(RespData::Array(
+        picked.into_iter().map(RespData::bulk).collect(),
+    ))
+}
+
+/// Combines the sets stored at `keys`; missing keys count as empty sets.
+fn combine(op: SetOp, keys: &[Bytes], ctx: &mut Context) -> Result<Set, DbError> {
+    let sets = ctx.db.get_many_as::<Set>(keys)?;
+    Ok(match op {
+        SetOp::Union => sets.into_iter().flatten().flatten().cloned().collect(),
+        SetOp::Diff => match sets.split_first() {
+            Some((Some(first), others)) => first
+                .iter()
+                .filter(|member| !others.iter().flatten().any(|set| set.contains(member)))
+                .cloned()
+                .collect(),
+            _ => Set::new(),
+        },
+        SetOp::Inter => {
+            let Some(mut sets) = sets.into_iter().collect::<Option<Vec<_>>>() else {
+                return Ok(Set::new());
+            };
+            // Walk the smallest set, checking the others.
+            sets.sort_by_key(|set| set.len());
+            match sets.split_first() {
+                Some((smallest, others)) => smallest
+                    .iter()
+                    .filter(|member| others.iter().all(|set| set.contains(member)))
+                    .cloned()
+                    .collect(),
+                None => Set::new(),
+            }
+        }
+    })
+}
+
+pub fn setop(o: Setop, ctx: &mut Context) -> Result<RespData, DbError> {
+    let result = combine(o.op, &o.keys, ctx)?;
+    let Some(destination) = o.destination else {
+        return Ok(set_reply(&result));
+    };
+
+    // The destination is overwritten whatever it held, and deleted when the
+    // result is empty.
+    let len = result.len();
+    ctx.db.remove(&destination);
+    if len > 0 {
+        ctx.db.insert(destination, RedisValue::Set(result), None);
+    }
+    ctx.mark_dirty();
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn sintercard(o: Sintercard, ctx: &mut Context) -> Result<RespData, DbError> {
+    let sets = ctx.db.get_many_as::<Set>(&o.keys)?;
+    let Some(mut sets) = sets.into_iter().collect::<Option<Vec<_>>>() else {
+        return Ok(RespData::Integer(0));
+    };
+    sets.sort_by_key(|set| set.len());
+    let Some((smallest, others)) = sets.split_first() else {
+        return Ok(RespData::Integer(0));
+    };
+    let limit = if o.limit == 0 { usize::MAX } else { o.limit };
+    let count = smallest
+        .iter()
+        .filter(|member| others.iter().all(|set| set.contains(member)))
+        .take(limit)
+        .count();
+    Ok(RespData::Integer(count as i64))
+}
+
+pub fn sscan(o: Sscan, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(set) = ctx.db.get_as::<Set>(&o.key)? else {
+        return Ok(keyspace::scan_reply(0, Vec::new()));
+    };
+    let (cursor, page) = keyspace::scan_page(set.scan_index(), &o.options);
+    let members = page.into_iter().map(RespData::BulkStr).collect();
+    Ok(keyspace::scan_reply(cursor, members))
+}
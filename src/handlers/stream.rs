@@ -0,0 +1,133 @@
+use bytes::Bytes;
+
+use super::Context;
+use crate::{
+    cmds::{Xadd, Xrange, Xread},
+    database::{DbError, EntryID, RedisValue, Stream, StreamEntry},
+    resp::{Protocol, RespData},
+};
+
+pub fn xadd(o: Xadd, ctx: &mut Context) -> Result<RespData, DbError> {
+    let entry_id = match ctx.db.get_mut_as::<Stream>(&o.key)? {
+        Some(stream) => stream.insert(&o.entry_id, o.args.clone())?,
+        None => {
+            let mut stream = Stream::default();
+            let entry_id = stream.insert(&o.entry_id, o.args.clone())?;
+            ctx.db
+                .insert(o.key.clone(), RedisValue::Stream(stream), None);
+            entry_id
+        }
+    };
+    let entry_id = Bytes::from(entry_id.print());
+
+    // Replicas must store the entry under the same ID, even when it was
+    // generated here.
+    let mut args: Vec<&[u8]> = vec![b"XADD", &o.key, &entry_id];
+    for (field, value) in &o.args {
+        args.push(field);
+        args.push(value);
+    }
+    ctx.propagate(&args);
+    ctx.signal_key_as_ready(&o.key);
+    Ok(RespData::BulkStr(entry_id))
+}
+
+pub fn xrange(o: Xrange, ctx: &mut Context) -> Result<RespData, DbError> {
+    let start = match o.start.as_str() {
+        "-" => EntryID::MIN,
+        start => EntryID::parse(start, 0)?,
+    };
+    let end = match o.end.as_str() {
+        "+" => EntryID::MAX,
+        end => EntryID::parse(end, u64::MAX)?,
+    };
+    let entries = match ctx.db.get_as::<Stream>(&o.key)? {
+        Some(stream) => stream.range(start, end),
+        None => Vec::new(),
+    };
+    Ok(format_entries(&entries))
+}
+
+/// XREAD, without waiting: `Null` when none of the streams has anything new.
+pub fn xread(o: Xread, ctx: &mut Context) -> Result<RespData, DbError> {
+    let entry_ids = resolve_entry_ids(&o.keys, &o.entry_ids, ctx)?;
+    Ok(read_streams(&o.keys, &entry_ids, ctx)?.unwrap_or(RespData::Null))
+}
+
+/// Replaces `$` with the stream's current last ID. A blocked XREAD waits for
+/// entries added after it was issued, not after each retry.
+pub fn pin_last_ids(o: &mut Xread, ctx: &mut Context) -> Result<(), DbError> {
+    let entry_ids = resolve_entry_ids(&o.keys, &o.entry_ids, ctx)?;
+    o.entry_ids = entry_ids.iter().map(EntryID::print).collect();
+    Ok(())
+}
+
+fn resolve_entry_ids(
+    keys: &[Bytes],
+    entry_ids: &[String],
+    ctx: &mut Context,
+) -> Result<Vec<EntryID>, DbError> {
+    keys.iter()
+        .zip(entry_ids)
+        .map(|(key, entry_id)| match entry_id.as_str() {
+            "$" => Ok(ctx
+                .db
+                .get_as::<Stream>(key)?
+                .map_or(EntryID::MIN, Stream::last_entry_id)),
+            entry_id => Ok(EntryID::parse(entry_id, 0)?),
+        })
+        .collect()
+}
+
+/// Entries after the given IDs, for the streams that have any.
+fn read_streams(
+    keys: &[Bytes],
+    entry_ids: &[EntryID],
+    ctx: &mut Context,
+) -> Result<Option<RespData>, DbError> {
+    let mut streams: Vec<(RespData, RespData)> = Vec::new();
+    for (key, entry_id) in keys.iter().zip(entry_ids) {
+        let entries = match (ctx.db.get_as::<Stream>(key)?, entry_id.successor()) {
+            (Some(stream), Some(start)) => stream.range(start, EntryID::MAX),
+            _ => continue,
+        };
+        if !entries.is_empty() {
+            streams.push((RespData::bulk(key), format_entries(&entries)));
+        }
+    }
+
+    if streams.is_empty() {
+        return Ok(None);
+    }
+    // RESP3 clients get a map keyed by stream, RESP2 clients the classic
+    // array of `[key, entries]` pairs.
+    Ok(Some(if ctx.protocol == Protocol::Resp3 {
+        RespData::Map(streams)
+    } else {
+        RespData::Array(
+            streams
+                .into_iter()
+                .map(|(key, entries)| RespData::Array(vec![key, entries]))
+                .collect(),
+        )
+    }))
+}
+
+fn format_entries(entries: &[StreamEntry]) -> RespData {
+    RespData::Array(
+        entries
+            .iter()
+            .map(|entry| {
+                let fields = entry
+                    .data
+                    .iter()
+                    .flat_map(|(k, v)| [RespData::bulk(k), RespData::bulk(v)])
+                    .collect();
+                RespData::Array(vec![
+                    RespData::bulk(entry.entry_id.print()),
+                    RespData::Array(fields),
+                ])
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,342 @@
+use bytes::Bytes;
+
+use super::{format_float_sum, parse_integer, Context};
+use crate::{
+    cmds::{
+        Append, Get, Getdel, Getex, Getrange, Getset, Incr, Incrbyfloat, Lcs, Mget, Mset, Set,
+        Setrange, Strlen,
+    },
+    database::{now_ms, DbError, RedisValue, MAX_STRING_LEN},
+    resp::RespData,
+};
+
+pub fn get(o: Get, ctx: &mut Context) -> Result<RespData, DbError> {
+    Ok(match ctx.db.get_as::<Bytes>(&o.key)? {
+        Some(value) => RespData::BulkStr(value.clone()),
+        None => RespData::Null,
+    })
+}
+
+pub fn set(o: Set, ctx: &mut Context) -> Result<RespData, DbError> {
+    let (exists, old) = match ctx.db.get(&o.key) {
+        Some(RedisValue::String(value)) => (true, Some(value.clone())),
+        Some(_) if o.get => return Err(DbError::WrongType),
+        Some(_) => (true, None),
+        None => (false, None),
+    };
+    // A failed condition leaves the key alone and gets a null reply, unless
+    // GET asked for the value, which is then returned all the same.
+    let reply = if o.get {
+        old.map_or(RespData::Null, RespData::BulkStr)
+    } else {
+        RespData::ok()
+    };
+    if (o.nx && exists) || (o.xx && !exists) {
+        return Ok(if o.get { reply } else { RespData::Null });
+    }
+
+    let now = now_ms();
+    let expires_at = match o.expiry {
+        Some(expiry) => Some(
+            expiry
+                .resolve(now)
+                .ok_or(DbError::InvalidExpireTime("set"))?,
+        ),
+        None if o.keep_ttl => ctx.db.expires_at(&o.key).map(|t| t as i64),
+        None => None,
+    };
+    match expires_at {
+        // Only an absolute time can be in the past; the key is as good as
+        // expired right away. Replicas leave expiry to the leader, so they
+        // get the deletion instead of the SET.
+        Some(expires_at) if expires_at <= now as i64 => {
+            ctx.db.remove(&o.key);
+            ctx.propagate(&[b"DEL", &o.key]);
+        }
+        Some(expires_at) => {
+            ctx.db.insert(
+                o.key.clone(),
+                RedisValue::String(o.value.clone()),
+                Some(expires_at as u64),
+            );
+            // Replicas get the absolute time, so the key expires at the same
+            // moment everywhere.
+            ctx.propagate(&[
+                b"SET",
+                &o.key,
+                &o.value,
+                b"PXAT",
+                expires_at.to_string().as_bytes(),
+            ]);
+        }
+        None => {
+            ctx.db.insert(o.key, RedisValue::String(o.value), None);
+            ctx.mark_dirty();
+        }
+    }
+    Ok(reply)
+}
+
+pub fn incr(o: Incr, ctx: &mut Context) -> Result<RespData, DbError> {
+    let value = match ctx.db.get_as::<Bytes>(&o.key)? {
+        Some(value) => parse_integer(value).ok_or(DbError::NotAnInteger)?,
+        None => 0,
+    };
+    let new_value = value.checked_add(o.by).ok_or(DbError::Overflow)?;
+    let encoded = Bytes::from(new_value.to_string());
+
+    // Updating in place keeps the key's expiry.
+    match ctx.db.get_mut_as::<Bytes>(&o.key)? {
+        Some(value) => *value = encoded,
+        None => ctx.db.insert(o.key, RedisValue::String(encoded), None),
+    }
+    ctx.mark_dirty();
+    Ok(RespData::Integer(new_value))
+}
+
+pub fn incrbyfloat(o: Incrbyfloat, ctx: &mut Context) -> Result<RespData, DbError> {
+    let value = match ctx.db.get_as::<Bytes>(&o.key)? {
+        Some(value) => std::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|value| !value.is_nan())
+            .ok_or(DbError::NotAFloat)?,
+        None => 0.0,
+    };
+    let new_value = value + o.increment;
+    if !new_value.is_finite() {
+        return Err(DbError::NanOrInfinity);
+    }
+    let encoded = Bytes::from(format_float_sum(value, o.increment));
+
+    match ctx.db.get_mut_as::<Bytes>(&o.key)? {
+        Some(value) => *value = encoded.clone(),
+        None => ctx
+            .db
+            .insert(o.key.clone(), RedisValue::String(encoded.clone()), None),
+    }
+    // Replicas get the result rather than redo the float arithmetic.
+    ctx.propagate(&[b"SET", &o.key, &encoded, b"KEEPTTL"]);
+    Ok(RespData::BulkStr(encoded))
+}
+
+pub fn append(o: Append, ctx: &mut Context) -> Result<RespData, DbError> {
+    let value = ctx.db.entry_as::<Bytes>(o.key)?;
+    if value.len() + o.value.len() > MAX_STRING_LEN {
+        return Err(DbError::StringTooLong);
+    }
+    let mut appended = Vec::with_capacity(value.len() + o.value.len());
+    appended.extend_from_slice(value);
+    appended.extend_from_slice(&o.value);
+    *value = Bytes::from(appended);
+    let len = value.len();
+    ctx.mark_dirty();
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn strlen(o: Strlen, ctx: &mut Context) -> Result<RespData, DbError> {
+    let len = ctx.db.get_as::<Bytes>(&o.key)?.map_or(0, Bytes::len);
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn getrange(o: Getrange, ctx: &mut Context) -> Result<RespData, DbError> {
+    let value = ctx.db.get_as::<Bytes>(&o.key)?.cloned().unwrap_or_default();
+    let len = value.len() as i64;
+    // Unlike list ranges, an end before the start of the string still
+    // selects its first byte, unless both offsets are negative.
+    if o.start < 0 && o.end < 0 && o.start > o.end {
+        return Ok(RespData::bulk(""));
+    }
+    let start = if o.start < 0 { o.start + len } else { o.start }.max(0);
+    let end = if o.end < 0 { o.end + len } else { o.end }
+        .max(0)
+        .min(len - 1);
+    if len == 0 || start > end {
+        return Ok(RespData::bulk(""));
+    }
+    Ok(RespData::BulkStr(
+        value.slice(start as usize..=end as usize),
+    ))
+}
+
+pub fn setrange(o: Setrange, ctx: &mut Context) -> Result<RespData, DbError> {
+    let current_len = ctx.db.get_as::<Bytes>(&o.key)?.map(Bytes::len);
+    // Writing nothing changes nothing, and creates no key either.
+    if o.value.is_empty() {
+        return Ok(RespData::Integer(current_len.unwrap_or(0) as i64));
+    }
+    let end = o.offset + o.value.len();
+    if end > MAX_STRING_LEN {
+        return Err(DbError::StringTooLong);
+    }
+    let value = ctx.db.entry_as::<Bytes>(o.key)?;
+    let mut updated = value.to_vec();
+    if updated.len() < end {
+        updated.resize(end, 0);
+    }
+    updated[o.offset..end].copy_from_slice(&o.value);
+    *value = Bytes::from(updated);
+    let len = value.len();
+    ctx.mark_dirty();
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn mget(o: Mget, ctx: &mut Context) -> Result<RespData, DbError> {
+    Ok(RespData::Array(
+        o.keys
+            .iter()
+            .map(|key| match ctx.db.get(key) {
+                Some(RedisValue::String(value)) => RespData::BulkStr(value.clone()),
+                // Keys of other types read as missing rather than failing
+                // the whole batch.
+                _ => RespData::Null,
+            })
+            .collect(),
+    ))
+}
+
+pub fn mset(o: Mset, ctx: &mut Context) -> Result<RespData, DbError> {
+    if o.only_if_none_exist && o.pairs.iter().any(|(key, _)| ctx.db.get(key).is_some()) {
+        return Ok(RespData::Integer(0));
+    }
+    for (key, value) in o.pairs {
+        ctx.db.insert(key, RedisValue::String(value), None);
+    }
+    ctx.mark_dirty();
+    Ok(if o.only_if_none_exist {
+        RespData::Integer(1)
+    } else {
+        RespData::ok()
+    })
+}
+
+pub fn getdel(o: Getdel, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(value) = ctx.db.get_as::<Bytes>(&o.key)?.cloned() else {
+        return Ok(RespData::Null);
+    };
+    ctx.db.remove(&o.key);
+    ctx.mark_dirty();
+    Ok(RespData::BulkStr(value))
+}
+
+pub fn getex(o: Getex, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(value) = ctx.db.get_as::<Bytes>(&o.key)?.cloned() else {
+        return Ok(RespData::Null);
+    };
+    if o.persist {
+        if ctx.db.expires_at(&o.key).is_some() {
+            ctx.db.set_expires_at(&o.key, None);
+            ctx.mark_dirty();
+        }
+    } else if let Some(expiry) = o.expiry {
+        let now = now_ms();
+        let expires_at = expiry
+            .resolve(now)
+            .ok_or(DbError::InvalidExpireTime("getex"))?;
+        // Replicas get the absolute time, so the key expires at the same
+        // moment everywhere.
+        if expires_at <= now as i64 {
+            ctx.db.remove(&o.key);
+            ctx.propagate(&[b"GETDEL", &o.key]);
+        } else {
+            ctx.db.set_expires_at(&o.key, Some(expires_at as u64));
+            ctx.propagate(&[b"GETEX", &o.key, b"PXAT", expires_at.to_string().as_bytes()]);
+        }
+    }
+    Ok(RespData::BulkStr(value))
+}
+
+pub fn getset(o: Getset, ctx: &mut Context) -> Result<RespData, DbError> {
+    let old = ctx.db.get_as::<Bytes>(&o.key)?.cloned();
+    ctx.db.insert(o.key, RedisValue::String(o.value), None);
+    ctx.mark_dirty();
+    Ok(old.map_or(RespData::Null, RespData::BulkStr))
+}
+
+pub fn lcs(o: Lcs, ctx: &mut Context) -> Result<RespData, DbError> {
+    let a = ctx
+        .db
+        .get_as::<Bytes>(&o.key1)?
+        .cloned()
+        .unwrap_or_default();
+    let b = ctx
+        .db
+        .get_as::<Bytes>(&o.key2)?
+        .cloned()
+        .unwrap_or_default();
+
+    // dp[i][j] is the length of the LCS of the first i bytes of `a` and the
+    // first j bytes of `b`, kept in one row-major vector.
+    let width = b.len() + 1;
+    let cells = (a.len() + 1)
+        .checked_mul(width)
+        .filter(|cells| *cells <= MAX_STRING_LEN / std::mem::size_of::<u32>())
+        .ok_or(DbError::LcsTooLong)?;
+    let mut dp = vec![0u32; cells];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            dp[i * width + j] = if a[i - 1] == b[j - 1] {
+                dp[(i - 1) * width + j - 1] + 1
+            } else {
+                dp[(i - 1) * width + j].max(dp[i * width + j - 1])
+            };
+        }
+    }
+    let lcs_len = dp[a.len() * width + b.len()] as usize;
+    if o.len {
+        return Ok(RespData::Integer(lcs_len as i64));
+    }
+
+    // Walk back from the end, collecting the common bytes and the ranges of
+    // consecutive ones, last match first.
+    let mut lcs = vec![0; lcs_len];
+    let mut matches = Vec::new();
+    // (start in a, end in a, start in b, end in b) of the current match.
+    let mut range: Option<(usize, usize, usize, usize)> = None;
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 && j > 0 {
+        let emit = if a[i - 1] == b[j - 1] {
+            lcs[dp[i * width + j] as usize - 1] = a[i - 1];
+            let (a_start, _, b_start, _) = range.get_or_insert((i, i - 1, j, j - 1));
+            *a_start -= 1;
+            *b_start -= 1;
+            i -= 1;
+            j -= 1;
+            i == 0 || j == 0
+        } else {
+            if dp[(i - 1) * width + j] > dp[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+            range.is_some()
+        };
+        if !emit {
+            continue;
+        }
+        if let Some((a_start, a_end, b_start, b_end)) = range.take() {
+            let match_len = a_end - a_start + 1;
+            if match_len >= o.min_match_len {
+                let position = |start: usize, end: usize| {
+                    RespData::Array(vec![
+                        RespData::Integer(start as i64),
+                        RespData::Integer(end as i64),
+                    ])
+                };
+                let mut entry = vec![position(a_start, a_end), position(b_start, b_end)];
+                if o.with_match_len {
+                    entry.push(RespData::Integer(match_len as i64));
+                }
+                matches.push(RespData::Array(entry));
+            }
+        }
+    }
+
+    if !o.idx {
+        return Ok(RespData::BulkStr(Bytes::from(lcs)));
+    }
+    Ok(RespData::Map(vec![
+        (RespData::bulk("matches"), RespData::Array(matches)),
+        (RespData::bulk("len"), RespData::Integer(lcs_len as i64)),
+    ]))
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use super::{index_range, keyspace, Context};
+use crate::{
+    cmds::{
+        Aggregate, Bzmpop, Bzpop, SetOp, Zadd, Zcard, Zcount, Zincrby, Zmpop, Zpop, Zrange,
+        ZrangeBy, Zrank, Zrem, Zscan, Zscore, Zsetop,
+    },
+    database::{DbError, RedisValue, ZSet},
+    resp::{format_double, Protocol, RespData},
+};
+
+/// Members with their scores, in the order they are replied with.
+type Members = Vec<(Bytes, f64)>;
+
+/// Members, with their scores when asked for. RESP3 clients get one array
+/// per member and score, RESP2 clients a flat array.
+fn members_reply(members: Members, with_scores: bool, protocol: Protocol) -> RespData {
+    let members = members.into_iter();
+    RespData::Array(match (with_scores, protocol) {
+        (false, _) => members
+            .map(|(member, _)| RespData::BulkStr(member))
+            .collect(),
+        (true, Protocol::Resp3) => return pairs_reply(members.collect()),
+        (true, Protocol::Resp2) => members
+            .flat_map(|(member, score)| [RespData::BulkStr(member), RespData::Double(score)])
+            .collect(),
+    })
+}
+
+/// One array per member and score, whatever the protocol.
+fn pairs_reply(members: Members) -> RespData {
+    RespData::Array(
+        members
+            .into_iter()
+            .map(|(member, score)| {
+                RespData::Array(vec![RespData::BulkStr(member), RespData::Double(score)])
+            })
+            .collect(),
+    )
+}
+
+fn pop_command(max: bool) -> &'static [u8] {
+    if max {
+        b"ZPOPMAX"
+    } else {
+        b"ZPOPMIN"
+    }
+}
+
+/// Replaces `destination` with a sorted set of `members`, deleting it when
+/// there are none. Replies with how many were stored.
+pub(super) fn store(
+    destination: Bytes,
+    members: impl IntoIterator<Item = (Bytes, f64)>,
+    ctx: &mut Context,
+) -> Result<RespData, DbError> {
+    let mut zset = ZSet::default();
+    for (member, score) in members {
+        zset.insert(member, score);
+    }
+    let len = zset.len();
+    ctx.db.remove(&destination);
+    if len > 0 {
+        ctx.db
+            .insert(destination.clone(), RedisValue::ZSet(zset), None);
+        ctx.signal_key_as_ready(&destination);
+    }
+    ctx.mark_dirty();
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn zadd(o: Zadd, ctx: &mut Context) -> Result<RespData, DbError> {
+    let zset = ctx.db.entry_as::<ZSet>(o.key.clone())?;
+    let mut added = 0;
+    let mut changed = 0;
+    let mut updated = None;
+    let mut result = Ok(());
+    for (score, member) in o.pairs {
+        let current = zset.score(&member);
+        let score = match current {
+            Some(current) if o.incr => current + score,
+            _ => score,
+        };
+        if score.is_nan() {
+            result = Err(DbError::ScoreIsNan);
+            break;
+        }
+        let allowed = match current {
+            None => !o.xx,
+            Some(_) if o.nx => false,
+            Some(current) if o.gt => score > current,
+            Some(current) if o.lt => score < current,
+            Some(_) => true,
+        };
+        if !allowed {
+            continue;
+        }
+        match current {
+            None => added += 1,
+            Some(current) if current != score => changed += 1,
+            Some(_) => {}
+        }
+        zset.insert(member, score);
+        updated = Some(score);
+    }
+    // XX or NX may have left behind the set created for them.
+    ctx.db.remove_if_empty(&o.key);
+    result?;
+
+    if added + changed > 0 {
+        ctx.mark_dirty();
+        ctx.signal_key_as_ready(&o.key);
+    }
+    Ok(if o.incr {
+        updated.map_or(RespData::Null, RespData::Double)
+    } else if o.ch {
+        RespData::Integer(added + changed)
+    } else {
+        RespData::Integer(added)
+    })
+}
+
+pub fn zincrby(o: Zincrby, ctx: &mut Context) -> Result<RespData, DbError> {
+    let zset = ctx.db.entry_as::<ZSet>(o.key.clone())?;
+    let score = zset.score(&o.member).unwrap_or(0.0) + o.increment;
+    if score.is_nan() {
+        ctx.db.remove_if_empty(&o.key);
+        return Err(DbError::ScoreIsNan);
+    }
+    zset.insert(o.member, score);
+    ctx.mark_dirty();
+    ctx.signal_key_as_ready(&o.key);
+    Ok(RespData::Double(score))
+}
+
+/// The members ZRANGE selects, in the order it returns them.
+fn select(zset: &ZSet, by: ZrangeBy, rev: bool, limit: Option<(i64, i64)>) -> Members {
+    let (offset, count) = match limit {
+        Some((offset, _)) if offset < 0 => return Vec::new(),
+        Some((offset, count)) => (offset as usize, usize::try_from(count).ok()),
+        None => (0, None),
+    };
+    match by {
+        ZrangeBy::Rank { start, stop } => match index_range(start, stop, zset.len()) {
+            Some(range) => zset.range_by_rank(*range.start(), *range.end(), rev),
+            None => Vec::new(),
+        },
+        ZrangeBy::Score { min, max } => zset.range_by_score(min, max, rev, offset, count),
+        ZrangeBy::Lex { min, max } => zset.range_by_lex(&min, &max, rev, offset, count),
+    }
+}
+
+pub fn zrange(o: Zrange, ctx: &mut Context) -> Result<RespData, DbError> {
+    let members = match ctx.db.get_as::<ZSet>(&o.key)? {
+        Some(zset) => select(zset, o.by, o.rev, o.limit),
+        None => Vec::new(),
+    };
+    match o.destination {
+        Some(destination) => store(destination, members, ctx),
+        None => Ok(members_reply(members, o.with_scores, ctx.protocol)),
+    }
+}
+
+pub fn zrank(o: Zrank, ctx: &mut Context) -> Result<RespData, DbError> {
+    let found = ctx.db.get_as::<ZSet>(&o.key)?.and_then(|zset| {
+        let rank = zset.rank(&o.member, o.rev)?;
+        Some((rank, zset.score(&o.member)?))
+    });
+    Ok(match (found, o.with_score) {
+        (Some((rank, _)), false) => RespData::Integer(rank as i64),
+        (Some((rank, score)), true) => RespData::Array(vec![
+            RespData::Integer(rank as i64),
+            RespData::Double(score),
+        ]),
+        (None, false) => RespData::Null,
+        (None, true) => RespData::NullArray,
+    })
+}
+
+pub fn zscore(o: Zscore, ctx: &mut Context) -> Result<RespData, DbError> {
+    let score = ctx
+        .db
+        .get_as::<ZSet>(&o.key)?
+        .and_then(|zset| zset.score(&o.member));
+    Ok(score.map_or(RespData::Null, RespData::Double))
+}
+
+pub fn zcard(o: Zcard, ctx: &mut Context) -> Result<RespData, DbError> {
+    let len = ctx.db.get_as::<ZSet>(&o.key)?.map_or(0, ZSet::len);
+    Ok(RespData::Integer(len as i64))
+}
+
+pub fn zrem(o: Zrem, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(zset) = ctx.db.get_mut_as::<ZSet>(&o.key)? else {
+        return Ok(RespData::Integer(0));
+    };
+    let removed = o
+        .members
+        .iter()
+        .filter(|member| zset.remove(member).is_some())
+        .count();
+    ctx.db.remove_if_empty(&o.key);
+    if removed > 0 {
+        ctx.mark_dirty();
+    }
+    Ok(RespData::Integer(removed as i64))
+}
+
+pub fn zcount(o: Zcount, ctx: &mut Context) -> Result<RespData, DbError> {
+    let count = ctx
+        .db
+        .get_as::<ZSet>(&o.key)?
+        .map_or(0, |zset| zset.count_in_score_range(o.min, o.max));
+    Ok(RespData::Integer(count as i64))
+}
+
+pub fn zpop(o: Zpop, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(zset) = ctx.db.get_mut_as::<ZSet>(&o.key)? else {
+        return Ok(RespData::Array(Vec::new()));
+    };
+    let popped = zset.pop(o.count.unwrap_or(1), o.max);
+    ctx.db.remove_if_empty(&o.key);
+    if !popped.is_empty() {
+        ctx.mark_dirty();
+    }
+    // Without a count RESP3 clients get the one member and its score as a
+    // plain pair, like RESP2 clients do.
+    let protocol = match o.count {
+        Some(_) => ctx.protocol,
+        None => Protocol::Resp2,
+    };
+    Ok(members_reply(popped, true, protocol))
+}
+
+/// Pops up to `count` members from the first of `keys` holding a sorted set.
+fn pop_first(
+    keys: Vec<Bytes>,
+    max: bool,
+    count: usize,
+    ctx: &mut Context,
+) -> Result<Option<(Bytes, Members)>, DbError> {
+    for key in keys {
+        let Some(zset) = ctx.db.get_mut_as::<ZSet>(&key)? else {
+            continue;
+        };
+        let popped = zset.pop(count, max);
+        ctx.db.remove_if_empty(&key);
+        ctx.mark_dirty();
+        return Ok(Some((key, popped)));
+    }
+    Ok(None)
+}
+
+pub fn zmpop(o: Zmpop, ctx: &mut Context) -> Result<RespData, DbError> {
+    Ok(match pop_first(o.keys, o.max, o.count, ctx)? {
+        Some((key, popped)) => RespData::Array(vec![RespData::BulkStr(key), pairs_reply(popped)]),
+        None => RespData::NullArray,
+    })
+}
+
+// Like the blocking list pops, these make a single attempt and replicate as
+// the equivalent non-blocking command.
+
+pub fn bzpop(o: Bzpop, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some((key, mut popped)) = pop_first(o.keys, o.max, 1, ctx)? else {
+        return Ok(RespData::NullArray);
+    };
+    ctx.propagate(&[pop_command(o.max), &key]);
+    let Some((member, score)) = popped.pop() else {
+        return Ok(RespData::NullArray);
+    };
+    Ok(RespData::Array(vec![
+        RespData::BulkStr(key),
+        RespData::BulkStr(member),
+        RespData::Double(score),
+    ]))
+}
+
+pub fn bzmpop(o: Bzmpop, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Zmpop { keys, max, count } = o.zmpop;
+    let Some((key, popped)) = pop_first(keys, max, count, ctx)? else {
+        return Ok(RespData::NullArray);
+    };
+    ctx.propagate(&[pop_command(max), &key, popped.len().to_string().as_bytes()]);
+    Ok(RespData::Array(vec![
+        RespData::BulkStr(key),
+        pairs_reply(popped),
+    ]))
+}
+
+/// A score times its weight, where the infinite score with a zero weight
+/// counts as 0 rather than NaN.
+fn weighted(score: f64, weight: f64) -> f64 {
+    let weighted = score * weight;
+    if weighted.is_nan() {
+        0.0
+    } else {
+        weighted
+    }
+}
+
+fn aggregate(a: f64, b: f64, aggregate: Aggregate) -> f64 {
+    match aggregate {
+        // Opposite infinities add up to 0.
+        Aggregate::Sum if (a + b).is_nan() => 0.0,
+        Aggregate::Sum => a + b,
+        Aggregate::Min => a.min(b),
+        Aggregate::Max => a.max(b),
+    }
+}
+
+/// The weighted members of each of `keys`, `None` for missing keys. Plain
+/// sets count as sorted sets with all scores 1.
+fn weighted_inputs(
+    keys: &[Bytes],
+    weights: &[f64],
+    ctx: &mut Context,
+) -> Result<Vec<Option<HashMap<Bytes, f64>>>, DbError> {
+    keys.iter()
+        .zip(weights)
+        .map(|(key, weight)| match ctx.db.get(key) {
+            None => Ok(None),
+            Some(RedisValue::ZSet(zset)) => Ok(Some(
+                zset.iter()
+                    .map(|(member, score)| (member.clone(), weighted(score, *weight)))
+                    .collect(),
+            )),
+            Some(RedisValue::Set(set)) => Ok(Some(
+                set.iter()
+                    .map(|member| (member.clone(), weighted(1.0, *weight)))
+                    .collect(),
+            )),
+            Some(_) => Err(DbError::WrongType),
+        })
+        .collect()
+}
+
+pub fn zsetop(o: Zsetop, ctx: &mut Context) -> Result<RespData, DbError> {
+    let mut inputs = weighted_inputs(&o.keys, &o.weights, ctx)?.into_iter();
+    let first = inputs.next().flatten().unwrap_or_default();
+    let result = match o.op {
+        SetOp::Union => inputs.flatten().fold(first, |mut result, input| {
+            for (member, score) in input {
+                result
+                    .entry(member)
+                    .and_modify(|current| *current = aggregate(*current, score, o.aggregate))
+                    .or_insert(score);
+            }
+            result
+        }),
+        SetOp::Inter => inputs.fold(first, |mut result, input| {
+            let input = input.unwrap_or_default();
+            result.retain(|member, current| match input.get(member) {
+                Some(score) => {
+                    *current = aggregate(*current, *score, o.aggregate);
+                    true
+                }
+                None => false,
+            });
+            result
+        }),
+        SetOp::Diff => inputs.flatten().fold(first, |mut result, input| {
+            result.retain(|member, _| !input.contains_key(member));
+            result
+        }),
+    };
+
+    if let Some(destination) = o.destination {
+        return store(destination, result, ctx);
+    }
+    let mut zset = ZSet::default();
+    for (member, score) in result {
+        zset.insert(member, score);
+    }
+    let members = zset
+        .iter()
+        .map(|(member, score)| (member.clone(), score))
+        .collect();
+    Ok(members_reply(members, o.with_scores, ctx.protocol))
+}
+
+pub fn zscan(o: Zscan, ctx: &mut Context) -> Result<RespData, DbError> {
+    let Some(zset) = ctx.db.get_as::<ZSet>(&o.key)? else {
+        return Ok(keyspace::scan_reply(0, Vec::new()));
+    };
+    let (cursor, page) = keyspace::scan_page(zset.scan_index(), &o.options);
+    // Scores come as strings whatever the protocol.
+    let members = page
+        .into_iter()
+        .flat_map(|member| {
+            let score = zset.score(&member).expect("Indexed members exist");
+            [
+                RespData::BulkStr(member),
+                RespData::bulk(format_double(score)),
+            ]
+        })
+        .collect();
+    Ok(keyspace::scan_reply(cursor, members))
+}
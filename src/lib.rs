@@ -2,7 +2,9 @@ mod cli;
 mod cmds;
 mod connection;
 mod database;
+mod glob;
 mod global;
+mod handlers;
 mod parse;
 mod resp;
 
@@ -22,13 +24,13 @@ use bytes::BytesMut;
 pub use cli::Cli;
 use cmds::Command;
 use connection::Connection;
-pub use database::{load_from_rdb, KeyValueStore};
-use database::{Client, RadixTreeStore, SharedState};
+use database::{active_expire_cycle, Client, LazyExpiry, SharedState, DEFAULT_DATABASES};
+pub use database::{load_from_rdb, KeyValueStore, RedisValue};
 pub use global::STATE;
 
 use parse::parse_command;
 use rand::{distributions::Alphanumeric, Rng};
-use resp::RespData;
+use resp::{Protocol, RespData};
 
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
@@ -38,9 +40,8 @@ use tokio::{
 };
 
 const CHUNK_SIZE: usize = 16 * 1024;
-const CRLF: &str = "\r\n";
 trait RedisInstance: Any + Send + Sync {
-    fn run(&self) -> Pin<Box<dyn Future<Output = ()> + '_>>;
+    fn run(&self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + '_>>;
 }
 
 pub struct Follower {
@@ -109,9 +110,16 @@ pub enum Response {
 }
 
 impl RedisInstance for Follower {
-    fn run(&self) -> Pin<Box<dyn Future<Output = ()> + '_>> {
+    fn run(&self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + '_>> {
         Box::pin(async {
             let conn_states = Arc::new(SharedState::new());
+            // Expired keys stay until the leader deletes them; until then
+            // clients read them as gone.
+            conn_states
+                .kv_store
+                .lock()
+                .await
+                .set_lazy_expiry(LazyExpiry::Hide);
             // Handle the follower thread
             let leader_addr = self.leader_addr.clone();
             let bytes_received = self.bytes_received.clone();
@@ -140,12 +148,19 @@ impl RedisInstance for Follower {
                     socket_addr.ip().to_string()
                 );
 
+                conn_states
+                    .insert_client(socket_addr, Client::default())
+                    .await;
+
                 // Handle clients
                 let shared_state = Arc::clone(&conn_states);
 
                 tokio::spawn(async move {
-                    let mut conn = Connection::new(shared_state, tcp_stream, socket_addr);
+                    let mut conn =
+                        Connection::new(Arc::clone(&shared_state), tcp_stream, socket_addr);
                     let _ = conn.handle().await;
+                    // Along with any transaction it left open.
+                    shared_state.remove_client(socket_addr).await;
                 });
             }
         })
@@ -177,7 +192,7 @@ impl Leader {
 }
 
 impl RedisInstance for Leader {
-    fn run(&self) -> Pin<Box<dyn Future<Output = ()> + '_>> {
+    fn run(&self) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + '_>> {
         Box::pin(async {
             // manages all states of all connections (peers and clients) to the leader
             let conn_states = Arc::new(SharedState::new());
@@ -188,10 +203,14 @@ impl RedisInstance for Leader {
                     self.dir_name.clone().unwrap(),
                     self.dbfilename.clone().unwrap()
                 );
-                load_from_rdb(conn_states.kv_store.clone())
-                    .await
-                    .expect("RDB file read failed");
+                // A file that cannot be loaded stops the server rather than
+                // have it start without the data and save over it later.
+                if let Err(e) = load_from_rdb(&mut conn_states.kv_store.lock().await.dbs) {
+                    log::error!("RDB file read failed: {:?}", e);
+                    return Err(e);
+                }
             }
+            tokio::spawn(active_expire_cycle(Arc::clone(&conn_states)));
 
             // Create TCP Listener
             let bind_address = STATE.get_val(&"bind_address".to_string()).unwrap();
@@ -228,8 +247,11 @@ impl RedisInstance for Leader {
                 // Handle Clients
                 let shared_state = Arc::clone(&conn_states);
                 tokio::spawn(async move {
-                    let mut conn = Connection::new(shared_state, tcp_stream, socket_addr);
+                    let mut conn =
+                        Connection::new(Arc::clone(&shared_state), tcp_stream, socket_addr);
                     let _ = conn.handle().await;
+                    // Along with any transaction it left open.
+                    shared_state.remove_client(socket_addr).await;
                 });
             }
         })
@@ -242,7 +264,8 @@ pub async fn start_server(
     dir_name: Option<String>,
     dbfilename: Option<String>,
     replicaof: Option<String>,
-) {
+    databases: Option<usize>,
+) -> anyhow::Result<()> {
     // Start logging.
     femme::start();
     if bind_address.is_some() {
@@ -261,6 +284,9 @@ pub async fn start_server(
         STATE.push(("dbfilename".to_string(), dbfilename.clone().unwrap()));
     }
 
+    let databases = databases.unwrap_or(DEFAULT_DATABASES);
+    STATE.push(("databases".to_string(), databases.to_string()));
+
     let leader_addr = if replicaof.is_some() {
         let leader_addr = if let Some(val) = replicaof {
             let ip_and_port: Vec<&str> = val.split_whitespace().collect();
@@ -285,7 +311,7 @@ pub async fn start_server(
             dir_name.clone(),
             dbfilename.clone(),
         ));
-        leader.run().await;
+        leader.run().await
     } else {
         // it's a follower instance
         let follower: Box<dyn RedisInstance> = Box::new(Follower::new(
@@ -293,7 +319,7 @@ pub async fn start_server(
             listening_port,
             leader_addr.clone(),
         ));
-        follower.run().await;
+        follower.run().await
     }
 }
 
@@ -310,8 +336,12 @@ async fn follower_thread(
         }
     };
 
-    let mut buffer = BytesMut::with_capacity(16 * 1024);
+    let mut buffer = BytesMut::with_capacity(CHUNK_SIZE);
     let mut stream = stream.lock().await;
+    // The database the leader's writes apply to, switched by SELECT.
+    let mut db = 0;
+    // The writes of a MULTI block, applied together once EXEC arrives.
+    let mut transaction: Option<Vec<(Command, BytesMut)>> = None;
     loop {
         if let Ok(n) = stream.read_buf(&mut buffer).await {
             if n == 0 {
@@ -321,81 +351,81 @@ async fn follower_thread(
                     return Err(anyhow::format_err!("Follower thread failed!".to_string()));
                 }
             }
-            // check if the buffer contains `getack` command. We will need to omit length of one `getack`
-            // from the total_bytes as each getack calculates length of commands processed so far excluding the
-            // current get ack
-            let cmd_from_leader = buffer[..n].to_vec();
-
-            let s = String::from_utf8_lossy(&cmd_from_leader).to_string();
-
-            if let Ok(resp_parsed) = RespData::parse(&s) {
-                let total_bytes = calculate_bytes(bytes_received.clone(), &resp_parsed);
-                // let resp_parsed_clone = resp_parsed.clone();
-                let mut resp_parsed_iter = resp_parsed.iter();
-                while let Some(parsed) = resp_parsed_iter.next() {
-                    match parsed {
-                        RespData::Array(v) => match parse_command(v.to_vec()) {
-                            Ok(res) => match res {
-                                Command::Set(o) => {
-                                    let key = o.key;
-                                    let value = o.value;
-                                    let expiry = o.expiry;
-                                    state
-                                        .kv_store_insert(key.clone(), value.clone(), expiry)
-                                        .await;
-                                }
-                                Command::Replconf(o) => {
-                                    let args = o.args;
-                                    let mut args_iter = args.iter();
-                                    let first = args_iter.next().expect("First cannot be empty");
-                                    match first.to_ascii_lowercase().as_str() {
-                                        "getack" => {
-                                            let opt = args_iter
-                                                .next()
-                                                .expect("Expect a valid port number");
-                                            match opt.to_ascii_lowercase().as_str() {
-                                                "*" => {
-                                                    let response = format!(
-                                                        "*3{}$8{}REPLCONF{}$3{}ACK{}${}{}{}{}",
-                                                        CRLF,
-                                                        CRLF,
-                                                        CRLF,
-                                                        CRLF,
-                                                        CRLF,
-                                                        total_bytes.to_string().len(),
-                                                        CRLF,
-                                                        total_bytes,
-                                                        CRLF
-                                                    )
-                                                    .as_bytes()
-                                                    .to_vec();
-                                                    let _ = stream.write_all(&response).await;
-                                                }
-                                                _ => {}
-                                            }
-                                        }
-                                        _ => {}
+
+            loop {
+                let (frame, frame_len) = match RespData::decode_request(&buffer) {
+                    Ok(Some(decoded)) => decoded,
+                    Ok(None) => break,
+                    Err(e) => {
+                        return Err(anyhow::format_err!("Replication stream invalid: {:?}", e))
+                    }
+                };
+                let raw_frame = buffer.split_to(frame_len);
+                // The offset acknowledged by `REPLCONF GETACK` covers every command
+                // processed before the GETACK itself, so count the frame only after
+                // reading the offset.
+                let total_bytes = bytes_received.fetch_add(frame_len, Ordering::Relaxed);
+
+                let RespData::Array(v) = frame else {
+                    continue;
+                };
+                match parse_command(v) {
+                    Ok(res) => match res {
+                        Command::Replconf(o) => {
+                            let args = o.args;
+                            let mut args_iter = args.iter();
+                            let first = args_iter.next().expect("First cannot be empty");
+                            if first.eq_ignore_ascii_case("getack")
+                                && args_iter.next().is_some_and(|opt| opt == "*")
+                            {
+                                let response = RespData::command(&[
+                                    "REPLCONF",
+                                    "ACK",
+                                    &total_bytes.to_string(),
+                                ])
+                                .to_vec();
+                                let _ = stream.write_all(&response).await;
+                            }
+                        }
+                        Command::Multi(_) => transaction = Some(Vec::new()),
+                        Command::Exec(_) => {
+                            let Some(queue) = transaction.take() else {
+                                continue;
+                            };
+                            let mut transaction = handlers::Transaction::from_leader(&state).await;
+                            for (cmd, raw_frame) in queue {
+                                match cmd {
+                                    Command::Select(o) => {
+                                        let count = transaction.databases().len();
+                                        handlers::select(o, &mut db, count);
+                                    }
+                                    cmd => {
+                                        transaction.run(cmd, &raw_frame, db, Protocol::Resp2);
                                     }
                                 }
-                                _ => {}
-                            },
-                            Err(e) => log::error!("{:?}", e),
-                        },
-                        RespData::String(_) => todo!(),
-                        RespData::ErrorStr(_) => todo!(),
-                        RespData::Integer(_) => todo!(),
-                        RespData::BulkStr(_) => todo!(),
-                        RespData::Null => todo!(),
-                        RespData::Boolean(_) => todo!(),
-                        RespData::Double(_) => todo!(),
-                        RespData::BulkError(_) => todo!(),
-                        RespData::VerbatimStr(_) => todo!(),
-                        RespData::Map(_) => todo!(),
-                        RespData::Set(_) => todo!(),
-                    }
+                            }
+                            transaction.commit().await;
+                        }
+                        cmd if transaction.is_some() => {
+                            if let Some(queue) = transaction.as_mut() {
+                                queue.push((cmd, raw_frame));
+                            }
+                        }
+                        Command::Select(o) => {
+                            let count = state.kv_store.lock().await.dbs.len();
+                            handlers::select(o, &mut db, count);
+                        }
+                        // Everything else the leader sends is a write to apply
+                        // locally; replies are not sent back.
+                        cmd => {
+                            let mut transaction = handlers::Transaction::from_leader(&state).await;
+                            transaction.run(cmd, &raw_frame, db, Protocol::Resp2);
+                            transaction.commit().await;
+                        }
+                    },
+                    Err(e) => log::error!("{:?}", e),
                 }
             }
-            buffer.clear();
         }
     }
 }
@@ -496,14 +526,13 @@ async fn follower_handshake(stream: Arc<Mutex<TcpStream>>) -> anyhow::Result<(),
         }
     }
 
-    let rdb_len = if let Ok(parsed) = RespData::parse(&String::from_utf8_lossy(&buffer).to_string())
+    let rdb_len = match std::str::from_utf8(&buffer)
+        .ok()
+        .and_then(|line| line.trim_end().strip_prefix('$'))
+        .and_then(|len| len.parse::<usize>().ok())
     {
-        match parsed[0] {
-            RespData::Integer(num) => num as usize,
-            _ => return Err("Handshake failed!".to_string()),
-        }
-    } else {
-        return Err("Handshake failed!".to_string());
+        Some(rdb_len) => rdb_len,
+        None => return Err("Handshake failed!".to_string()),
     };
 
     // Read `rdb_len` bytes
@@ -521,30 +550,3 @@ async fn follower_handshake(stream: Arc<Mutex<TcpStream>>) -> anyhow::Result<(),
 
     Ok(())
 }
-
-fn calculate_bytes(bytes_received: Arc<AtomicUsize>, parsed: &Vec<RespData>) -> usize {
-    let mut total_bytes: usize = 0;
-    for data in parsed {
-        match data {
-            RespData::Array(vec) => {
-                let mut cmd = String::from(&format!("*{}\r\n", vec.len()));
-                for item in vec {
-                    match item {
-                        RespData::String(s) => {
-                            cmd.push_str(&format!("${}\r\n{}\r\n", s.len(), s));
-                        }
-                        RespData::ErrorStr(_) => todo!(),
-                        RespData::Integer(num) => {
-                            cmd.push_str(&format!("${}\r\n{}\r\n", num.to_string().len(), num));
-                        }
-                        _ => todo!(),
-                    }
-                }
-                total_bytes = bytes_received.fetch_add(cmd.len(), Ordering::Relaxed);
-            }
-            _ => todo!(),
-        }
-    }
-
-    total_bytes
-}
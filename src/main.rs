@@ -2,14 +2,6 @@ use anyhow::Error;
 use redis_starter_rust::start_server;
 use redis_starter_rust::Cli;
 
-mod cli;
-mod cmds;
-mod connection;
-mod database;
-mod global;
-mod parse;
-mod resp;
-
 #[tokio::main]
 pub async fn main() -> anyhow::Result<(), Error> {
     let config_params = Cli::new(std::env::args());
@@ -18,14 +10,15 @@ pub async fn main() -> anyhow::Result<(), Error> {
     let dir_name = config_params.dir_name.clone();
     let dbfilename = config_params.db_filename.clone();
     let replicaof = config_params.replicaof.clone();
+    let databases = config_params.databases;
 
-    let _ = start_server(
+    start_server(
         bind_address,
         listening_port,
         dir_name,
         dbfilename,
         replicaof,
+        databases,
     )
-    .await;
-    Ok(())
+    .await
 }
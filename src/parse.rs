@@ -1,422 +1,2072 @@
-use std::time::Duration;
+use std::{str::FromStr, time::Duration};
+
+use bytes::Bytes;
 
 use crate::{
     cmds::{
-        Command, CommandError, Config, Discard, Echo, Exec, Get, Incr, Info, InfoSubCommand, Keys,
-        Multi, Ping, Psync, Replconf, Save, Set, SubCommand, Type, Wait, Xadd, Xrange, Xread,
+        Aggregate, Append, BitOperation, BitUnit, Bitcount, Bitfield, BitfieldOp, BitfieldOverflow,
+        BitfieldType, Bitop, Bitpos, Blmove, Blmpop, Bpop, Bzmpop, Bzpop, Command, CommandError,
+        Config, CopyKey, Dbsize, Del, Discard, Echo, End, Exec, Exists, Expire, ExpireCondition,
+        Expiry, Flush, GeoCenter, GeoSort, GeoUnit, Geoadd, Geodist, Geopos, Geosearch, Get,
+        Getbit, Getdel, Getex, Getrange, Getset, Hdel, Hello, Hexists, Hexpire, Hget, Hgetall,
+        Hincrby, Hincrbyfloat, Hkeys, Hlen, Hmget, Hpersist, Hrandfield, Hscan, Hset, Httl, Hvals,
+        Incr, Incrbyfloat, Info, InfoSubCommand, Keys, Lcs, Lindex, Linsert, Llen, Lmove, Lmpop,
+        Lpos, Lrange, Lrem, Lset, Ltrim, Mget, Move, Mset, Multi, Persist, Pfadd, Pfcount, Pfmerge,
+        Ping, Pop, Psync, Push, Randomkey, Rename, Replconf, Sadd, Save, Scan, ScanOptions, Scard,
+        Select, Set, SetOp, Setbit, Setop, Setrange, Sintercard, Sismember, Smembers, Smismember,
+        Spop, Srandmember, Srem, Sscan, Strlen, SubCommand, Swapdb, Ttl, Type, Wait, Xadd, Xrange,
+        Xread, Zadd, Zcard, Zcount, Zincrby, Zmpop, Zpop, Zrange, ZrangeBy, Zrank, Zrem, Zscan,
+        Zscore, Zsetop,
     },
+    database::{GeoPoint, GeoShape, LexBound, ScoreBound, MAX_FIELD_EXPIRY, MAX_STRING_LEN},
     resp::RespData,
 };
 
-pub fn parse_command(v: Vec<RespData>) -> anyhow::Result<Command, CommandError> {
-    let mut v_iter = v.iter();
-    let cmd_str = if let Some(cmd_str) = v_iter.next() {
-        match cmd_str {
-            RespData::String(cmd) => Some(cmd.to_owned()),
+/// Cursor over the arguments of a command frame. Clients send every argument
+/// as a bulk string, so the helpers below do the conversions the individual
+/// commands need and report errors against the command name.
+struct Args {
+    name: String,
+    iter: std::vec::IntoIter<RespData>,
+}
+
+impl Args {
+    fn next_string(&mut self) -> Option<String> {
+        match self.iter.next()? {
+            RespData::BulkStr(b) => Some(String::from_utf8_lossy(&b).into_owned()),
+            RespData::String(s) => Some(s),
+            RespData::Integer(n) => Some(n.to_string()),
             _ => None,
         }
-    } else {
-        None
-    };
+    }
 
-    if let Some(cmd_name) = cmd_str {
-        match cmd_name.to_ascii_lowercase().as_str() {
-            "set" => {
-                let key = match v_iter.next() {
-                    Some(key) => match key {
-                        RespData::String(s) => s.to_owned(),
-                        RespData::Integer(n) => n.to_string(),
-                        _ => return Err(CommandError::NotValidType("set".into())),
-                    },
-                    None => return Err(CommandError::WrongNumberOfArguments("set".into())),
-                };
+    /// Next argument as raw bytes, for keys and values.
+    fn next_bytes(&mut self) -> Option<Bytes> {
+        match self.iter.next()? {
+            RespData::BulkStr(b) => Some(b),
+            RespData::String(s) => Some(Bytes::from(s)),
+            RespData::Integer(n) => Some(Bytes::from(n.to_string())),
+            _ => None,
+        }
+    }
 
-                let value = match v_iter.next() {
-                    Some(value) => match value {
-                        RespData::String(s) => s.to_owned(),
-                        RespData::Integer(n) => n.to_string(),
-                        _ => return Err(CommandError::NotValidType("set".into())),
-                    },
-                    None => return Err(CommandError::WrongNumberOfArguments("set".into())),
-                };
+    fn bytes(&mut self) -> Result<Bytes, CommandError> {
+        self.next_bytes()
+            .ok_or_else(|| CommandError::WrongNumberOfArguments(self.name.clone()))
+    }
 
-                let mut expiry: Option<Duration> = None;
-                match v_iter.next() {
-                    Some(RespData::String(nt)) => match nt.to_ascii_lowercase().as_str() {
-                        "ex" | "px" => {
-                            expiry = match v_iter.next() {
-                                Some(RespData::Integer(expiry)) => {
-                                    let t = if nt == "ex" {
-                                        Duration::from_secs(expiry.clone() as u64)
-                                    } else {
-                                        Duration::from_millis(expiry.clone() as u64)
-                                    };
-                                    Some(Duration::new(t.as_secs(), t.subsec_nanos()))
-                                }
-                                Some(_) => {
-                                    return Err(CommandError::NotValidType("set".into()));
-                                }
-                                None => return Err(CommandError::SyntaxError("set".into())),
-                            };
-                        }
-                        "nx" => todo!(),
-                        "xx" => todo!(),
-                        "keepttl" => todo!(),
-                        _ => return Err(CommandError::SyntaxError("set".into())),
-                    },
-                    Some(_) => {
-                        return Err(CommandError::NotValidType("set".into()));
-                    }
-                    None => {}
-                }
+    /// Next argument, which the command cannot do without.
+    fn string(&mut self) -> Result<String, CommandError> {
+        self.next_string()
+            .ok_or_else(|| CommandError::WrongNumberOfArguments(self.name.clone()))
+    }
 
-                let s = Set { key, value, expiry };
+    fn integer<T: FromStr>(&mut self) -> Result<T, CommandError> {
+        self.string()?
+            .parse::<T>()
+            .map_err(|_| CommandError::NotAnInteger)
+    }
 
-                if v_iter.next().is_some() {
-                    return Err(CommandError::SyntaxError("set".into()));
-                }
+    fn float(&mut self) -> Result<f64, CommandError> {
+        parse_float(&self.string()?)
+    }
 
-                return Ok(Command::Set(s));
-            }
-            "get" => {
-                let key = if let Some(RespData::String(key)) = v_iter.next() {
-                    key.to_owned()
-                } else {
-                    return Err(CommandError::WrongNumberOfArguments("get".into()));
-                };
+    /// A count that must not be negative.
+    fn count(&mut self) -> Result<usize, CommandError> {
+        let count = self.integer::<i64>()?;
+        usize::try_from(count).map_err(|_| CommandError::NotPositive)
+    }
 
-                if v_iter.next().is_some() {
-                    return Err(CommandError::WrongNumberOfArguments("get".into()));
-                }
+    /// `LEFT` or `RIGHT`.
+    fn end(&mut self) -> Result<End, CommandError> {
+        match self.string()?.to_ascii_lowercase().as_str() {
+            "left" => Ok(End::Left),
+            "right" => Ok(End::Right),
+            _ => Err(CommandError::SyntaxError(self.name.clone())),
+        }
+    }
 
-                let g = Get { key };
-                return Ok(Command::Get(g));
-            }
-            "incr" => {
-                let key = if let Some(RespData::String(key)) = v_iter.next() {
-                    key.to_owned()
-                } else {
-                    return Err(CommandError::WrongNumberOfArguments("INCR".into()));
-                };
+    /// Seconds to block for, fractions allowed. Zero means forever.
+    fn timeout(&mut self) -> Result<Option<Duration>, CommandError> {
+        parse_timeout(&self.string()?)
+    }
 
-                if v_iter.next().is_some() {
-                    return Err(CommandError::WrongNumberOfArguments("INCR".into()));
-                }
+    fn is_empty(&self) -> bool {
+        self.iter.len() == 0
+    }
 
-                let g = Incr { key };
-                return Ok(Command::Incr(g));
-            }
-            "ping" => {
-                if let Some(RespData::String(value)) = v_iter.next() {
-                    let p = Ping {
-                        value: Some(value.to_owned()),
-                    };
+    /// Fails when arguments are left over after the command was fully parsed.
+    fn finish(&self) -> Result<(), CommandError> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(CommandError::WrongNumberOfArguments(self.name.clone()))
+        }
+    }
+}
 
-                    if let Some(_) = v_iter.next() {
-                        return Err(CommandError::WrongNumberOfArguments("ping".into()));
+pub fn parse_command(v: Vec<RespData>) -> anyhow::Result<Command, CommandError> {
+    let mut args = Args {
+        name: String::new(),
+        iter: v.into_iter(),
+    };
+    let cmd_name = match args.next_string() {
+        Some(cmd_name) => cmd_name.to_ascii_lowercase(),
+        None => return Err(CommandError::NotSupported),
+    };
+    args.name = cmd_name.clone();
+
+    match cmd_name.as_str() {
+        "set" => {
+            let key = args.bytes()?;
+            let value = args.bytes()?;
+            let (mut nx, mut xx, mut get, mut keep_ttl) = (false, false, false, false);
+            let mut expiry = None;
+            while let Some(option) = args.next_string() {
+                let option = option.to_ascii_lowercase();
+                match option.as_str() {
+                    "nx" if !xx => nx = true,
+                    "xx" if !nx => xx = true,
+                    "get" => get = true,
+                    "keepttl" if expiry.is_none() => keep_ttl = true,
+                    "ex" | "px" | "exat" | "pxat" if expiry.is_none() && !keep_ttl => {
+                        let time = args
+                            .next_string()
+                            .ok_or_else(|| CommandError::SyntaxError(cmd_name.clone()))?
+                            .parse::<i64>()
+                            .map_err(|_| CommandError::NotAnInteger)?;
+                        let unit = if option.starts_with('e') { 1000 } else { 1 };
+                        let time =
+                            time.checked_mul(unit)
+                                .filter(|time| *time > 0)
+                                .ok_or_else(|| {
+                                    CommandError::InvalidArgument(
+                                        "ERR invalid expire time in 'set' command".into(),
+                                    )
+                                })?;
+                        expiry = Some(if option.ends_with("at") {
+                            Expiry::At(time)
+                        } else {
+                            Expiry::In(time)
+                        });
                     }
-                    return Ok(Command::Ping(p));
-                } else {
-                    let p = Ping { value: None };
-                    return Ok(Command::Ping(p));
-                };
+                    _ => return Err(CommandError::SyntaxError(cmd_name)),
+                }
+            }
+            Ok(Command::Set(Set {
+                key,
+                value,
+                nx,
+                xx,
+                get,
+                expiry,
+                keep_ttl,
+            }))
+        }
+        "get" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Get(Get { key }))
+        }
+        "incr" | "decr" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            let by = if cmd_name == "incr" { 1 } else { -1 };
+            Ok(Command::Incr(Incr { key, by }))
+        }
+        "incrby" | "decrby" => {
+            let key = args.bytes()?;
+            let by = args.integer::<i64>()?;
+            args.finish()?;
+            let by = if cmd_name == "incrby" {
+                by
+            } else {
+                by.checked_neg().ok_or_else(|| {
+                    CommandError::InvalidArgument("ERR decrement would overflow".into())
+                })?
+            };
+            Ok(Command::Incr(Incr { key, by }))
+        }
+        "incrbyfloat" => {
+            let key = args.bytes()?;
+            let increment = args.float()?;
+            args.finish()?;
+            Ok(Command::Incrbyfloat(Incrbyfloat { key, increment }))
+        }
+        "append" => {
+            let key = args.bytes()?;
+            let value = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Append(Append { key, value }))
+        }
+        "strlen" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Strlen(Strlen { key }))
+        }
+        "getrange" | "substr" => {
+            let key = args.bytes()?;
+            let start = args.integer::<i64>()?;
+            let end = args.integer::<i64>()?;
+            args.finish()?;
+            Ok(Command::Getrange(Getrange { key, start, end }))
+        }
+        "setrange" => {
+            let key = args.bytes()?;
+            let offset = usize::try_from(args.integer::<i64>()?)
+                .map_err(|_| CommandError::InvalidArgument("ERR offset is out of range".into()))?;
+            let value = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Setrange(Setrange { key, offset, value }))
+        }
+        "mget" => {
+            let mut keys = vec![args.bytes()?];
+            while let Some(key) = args.next_bytes() {
+                keys.push(key);
+            }
+            Ok(Command::Mget(Mget { keys }))
+        }
+        "mset" | "msetnx" | "setnx" => {
+            let mut pairs = vec![(args.bytes()?, args.bytes()?)];
+            while let Some(key) = args.next_bytes() {
+                pairs.push((key, args.bytes()?));
             }
-            "echo" => {
-                if let Some(RespData::String(value)) = v_iter.next() {
-                    let e = Echo {
-                        value: Some(value.to_owned()),
+            if cmd_name == "setnx" && pairs.len() > 1 {
+                return Err(CommandError::WrongNumberOfArguments(cmd_name));
+            }
+            Ok(Command::Mset(Mset {
+                pairs,
+                only_if_none_exist: cmd_name != "mset",
+            }))
+        }
+        "setex" | "psetex" => {
+            let key = args.bytes()?;
+            let time = args.integer::<i64>()?;
+            let value = args.bytes()?;
+            args.finish()?;
+            let unit = if cmd_name == "setex" { 1000 } else { 1 };
+            let time = time
+                .checked_mul(unit)
+                .filter(|time| *time > 0)
+                .ok_or_else(|| {
+                    CommandError::InvalidArgument(format!(
+                        "ERR invalid expire time in '{}' command",
+                        cmd_name
+                    ))
+                })?;
+            Ok(Command::Set(Set {
+                key,
+                value,
+                nx: false,
+                xx: false,
+                get: false,
+                expiry: Some(Expiry::In(time)),
+                keep_ttl: false,
+            }))
+        }
+        "getdel" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Getdel(Getdel { key }))
+        }
+        "getex" => {
+            let key = args.bytes()?;
+            let mut expiry = None;
+            let mut persist = false;
+            if let Some(option) = args.next_string() {
+                let option = option.to_ascii_lowercase();
+                if option == "persist" {
+                    persist = true;
+                } else {
+                    let unit = match option.as_str() {
+                        "ex" | "exat" => 1000,
+                        "px" | "pxat" => 1,
+                        _ => return Err(CommandError::SyntaxError(cmd_name)),
                     };
-
-                    if v_iter.next().is_some() {
-                        return Err(CommandError::WrongNumberOfArguments("echo".into()));
+                    let time = args
+                        .next_string()
+                        .ok_or_else(|| CommandError::SyntaxError(cmd_name.clone()))?
+                        .parse::<i64>()
+                        .map_err(|_| CommandError::NotAnInteger)?;
+                    let time =
+                        time.checked_mul(unit)
+                            .filter(|time| *time > 0)
+                            .ok_or_else(|| {
+                                CommandError::InvalidArgument(
+                                    "ERR invalid expire time in 'getex' command".into(),
+                                )
+                            })?;
+                    expiry = Some(if option.ends_with("at") {
+                        Expiry::At(time)
+                    } else {
+                        Expiry::In(time)
+                    });
+                }
+            }
+            if !args.is_empty() {
+                return Err(CommandError::SyntaxError(cmd_name));
+            }
+            Ok(Command::Getex(Getex {
+                key,
+                expiry,
+                persist,
+            }))
+        }
+        "getset" => {
+            let key = args.bytes()?;
+            let value = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Getset(Getset { key, value }))
+        }
+        "lcs" => {
+            let key1 = args.bytes()?;
+            let key2 = args.bytes()?;
+            let (mut len, mut idx, mut with_match_len) = (false, false, false);
+            let mut min_match_len = 0;
+            while let Some(option) = args.next_string() {
+                match option.to_ascii_lowercase().as_str() {
+                    "len" => len = true,
+                    "idx" => idx = true,
+                    "withmatchlen" => with_match_len = true,
+                    "minmatchlen" => {
+                        // A negative minimum is no minimum.
+                        min_match_len = args.integer::<i64>()?.max(0) as usize;
                     }
-                    return Ok(Command::Echo(e));
-                } else {
-                    let e = Echo { value: None };
-                    return Ok(Command::Echo(e));
-                };
+                    _ => return Err(CommandError::SyntaxError(cmd_name)),
+                }
             }
-            "exec" => {
-                if v_iter.next().is_some() {
-                    return Err(CommandError::WrongNumberOfArguments("exec".into()));
+            if len && idx {
+                return Err(CommandError::InvalidArgument(
+                    "ERR If you want both the length and indexes, please just use IDX.".into(),
+                ));
+            }
+            Ok(Command::Lcs(Lcs {
+                key1,
+                key2,
+                len,
+                idx,
+                min_match_len,
+                with_match_len,
+            }))
+        }
+        "setbit" => {
+            let key = args.bytes()?;
+            let offset = bit_offset(&args.string()?, 1, false)?;
+            let value = match args.string()?.as_str() {
+                "0" => false,
+                "1" => true,
+                _ => {
+                    return Err(CommandError::InvalidArgument(
+                        "ERR bit is not an integer or out of range".into(),
+                    ))
+                }
+            };
+            args.finish()?;
+            Ok(Command::Setbit(Setbit { key, offset, value }))
+        }
+        "getbit" => {
+            let key = args.bytes()?;
+            let offset = bit_offset(&args.string()?, 1, false)?;
+            args.finish()?;
+            Ok(Command::Getbit(Getbit { key, offset }))
+        }
+        "bitcount" => {
+            let key = args.bytes()?;
+            let range = match args.next_string() {
+                Some(start) => {
+                    let start = start
+                        .parse::<i64>()
+                        .map_err(|_| CommandError::NotAnInteger)?;
+                    let end = args
+                        .next_string()
+                        .ok_or_else(|| CommandError::SyntaxError(cmd_name.clone()))?
+                        .parse::<i64>()
+                        .map_err(|_| CommandError::NotAnInteger)?;
+                    Some((start, end, bit_unit(&mut args)?))
                 }
-                return Ok(Command::Exec(Exec));
+                None => None,
+            };
+            Ok(Command::Bitcount(Bitcount { key, range }))
+        }
+        "bitpos" => {
+            let key = args.bytes()?;
+            let bit = match args.integer::<i64>()? {
+                0 => false,
+                1 => true,
+                _ => {
+                    return Err(CommandError::InvalidArgument(
+                        "ERR The bit argument must be 1 or 0.".into(),
+                    ))
+                }
+            };
+            let mut start = 0;
+            let mut end = None;
+            if let Some(value) = args.next_string() {
+                start = value.parse().map_err(|_| CommandError::NotAnInteger)?;
+                if let Some(value) = args.next_string() {
+                    end = Some(value.parse().map_err(|_| CommandError::NotAnInteger)?);
+                }
+            }
+            let unit = bit_unit(&mut args)?;
+            Ok(Command::Bitpos(Bitpos {
+                key,
+                bit,
+                start,
+                end,
+                unit,
+            }))
+        }
+        "bitop" => {
+            let op = match args.string()?.to_ascii_lowercase().as_str() {
+                "and" => BitOperation::And,
+                "or" => BitOperation::Or,
+                "xor" => BitOperation::Xor,
+                "not" => BitOperation::Not,
+                _ => return Err(CommandError::SyntaxError(cmd_name)),
+            };
+            let destination = args.bytes()?;
+            let mut keys = vec![args.bytes()?];
+            while let Some(key) = args.next_bytes() {
+                keys.push(key);
+            }
+            if op == BitOperation::Not && keys.len() != 1 {
+                return Err(CommandError::InvalidArgument(
+                    "ERR BITOP NOT must be called with a single source key.".into(),
+                ));
             }
-            "multi" => {
-                if v_iter.next().is_some() {
-                    return Err(CommandError::WrongNumberOfArguments("multi".into()));
+            Ok(Command::Bitop(Bitop {
+                op,
+                destination,
+                keys,
+            }))
+        }
+        "bitfield" | "bitfield_ro" => {
+            let key = args.bytes()?;
+            let mut ops = Vec::new();
+            while let Some(op) = args.next_string() {
+                let op = op.to_ascii_lowercase();
+                if cmd_name == "bitfield_ro" && op != "get" {
+                    return Err(CommandError::InvalidArgument(
+                        "ERR BITFIELD_RO only supports the GET subcommand".into(),
+                    ));
                 }
-                return Ok(Command::Multi(Multi));
+                ops.push(match op.as_str() {
+                    "get" => {
+                        let ty = bitfield_type(&args.string()?)?;
+                        let offset = bit_offset(&args.string()?, ty.bits, true)?;
+                        BitfieldOp::Get { ty, offset }
+                    }
+                    "set" => {
+                        let ty = bitfield_type(&args.string()?)?;
+                        let offset = bit_offset(&args.string()?, ty.bits, true)?;
+                        let value = args.integer()?;
+                        BitfieldOp::Set { ty, offset, value }
+                    }
+                    "incrby" => {
+                        let ty = bitfield_type(&args.string()?)?;
+                        let offset = bit_offset(&args.string()?, ty.bits, true)?;
+                        let increment = args.integer()?;
+                        BitfieldOp::Incrby {
+                            ty,
+                            offset,
+                            increment,
+                        }
+                    }
+                    "overflow" => {
+                        BitfieldOp::Overflow(match args.string()?.to_ascii_lowercase().as_str() {
+                            "wrap" => BitfieldOverflow::Wrap,
+                            "sat" => BitfieldOverflow::Sat,
+                            "fail" => BitfieldOverflow::Fail,
+                            _ => {
+                                return Err(CommandError::InvalidArgument(
+                                    "ERR Invalid OVERFLOW type specified".into(),
+                                ))
+                            }
+                        })
+                    }
+                    _ => return Err(CommandError::SyntaxError(cmd_name)),
+                });
+            }
+            Ok(Command::Bitfield(Bitfield { key, ops }))
+        }
+        "pfadd" => {
+            let key = args.bytes()?;
+            let mut elements = Vec::new();
+            while let Some(element) = args.next_bytes() {
+                elements.push(element);
+            }
+            Ok(Command::Pfadd(Pfadd { key, elements }))
+        }
+        "pfcount" => {
+            let mut keys = vec![args.bytes()?];
+            while let Some(key) = args.next_bytes() {
+                keys.push(key);
+            }
+            Ok(Command::Pfcount(Pfcount { keys }))
+        }
+        "pfmerge" => {
+            let destination = args.bytes()?;
+            let mut sources = Vec::new();
+            while let Some(source) = args.next_bytes() {
+                sources.push(source);
+            }
+            Ok(Command::Pfmerge(Pfmerge {
+                destination,
+                sources,
+            }))
+        }
+        "geoadd" => {
+            let key = args.bytes()?;
+            let mut rest: Vec<Bytes> = std::iter::from_fn(|| args.next_bytes()).collect();
+            if rest.len() < 3 {
+                return Err(CommandError::WrongNumberOfArguments(cmd_name));
             }
-            "discard" => {
-                if v_iter.next().is_some() {
-                    return Err(CommandError::WrongNumberOfArguments("discard".into()));
+            let (mut nx, mut xx, mut ch) = (false, false, false);
+            let options = rest
+                .iter()
+                .take_while(|arg| {
+                    matches!(arg.to_ascii_lowercase().as_slice(), b"nx" | b"xx" | b"ch")
+                })
+                .count();
+            for option in rest.drain(..options) {
+                match option.to_ascii_lowercase().as_slice() {
+                    b"nx" => nx = true,
+                    b"xx" => xx = true,
+                    _ => ch = true,
                 }
-                return Ok(Command::Discard(Discard));
             }
-            "config" => {
-                let subcommand = if let Some(RespData::String(name)) = v_iter.next() {
-                    match name.to_ascii_lowercase().as_str() {
-                        "get" => {
-                            let pattern = if let Some(RespData::String(pattern)) = v_iter.next() {
-                                pattern.to_owned()
-                            } else {
-                                return Err(CommandError::WrongNumberOfArguments("config".into()));
-                            };
-                            SubCommand::Get(pattern)
+            if nx && xx {
+                return Err(CommandError::InvalidArgument(
+                    "ERR XX and NX options at the same time are not compatible".into(),
+                ));
+            }
+            let chunks = rest.chunks_exact(3);
+            if rest.is_empty() || !chunks.remainder().is_empty() {
+                return Err(CommandError::InvalidArgument(
+                    "ERR syntax error. Try GEOADD key [x1] [y1] [name1] [x2] [y2] [name2] ... "
+                        .into(),
+                ));
+            }
+            let members = chunks
+                .map(|chunk| {
+                    let lon = parse_float(&String::from_utf8_lossy(&chunk[0]))?;
+                    let lat = parse_float(&String::from_utf8_lossy(&chunk[1]))?;
+                    Ok((geo_point(lon, lat)?, chunk[2].clone()))
+                })
+                .collect::<Result<_, CommandError>>()?;
+            Ok(Command::Geoadd(Geoadd {
+                key,
+                nx,
+                xx,
+                ch,
+                members,
+            }))
+        }
+        "geodist" => {
+            let key = args.bytes()?;
+            let member1 = args.bytes()?;
+            let member2 = args.bytes()?;
+            let unit = match args.next_string() {
+                Some(unit) => geo_unit(&unit)?,
+                None => GeoUnit::M,
+            };
+            if !args.is_empty() {
+                return Err(CommandError::SyntaxError(cmd_name));
+            }
+            Ok(Command::Geodist(Geodist {
+                key,
+                member1,
+                member2,
+                unit,
+            }))
+        }
+        "geopos" | "geohash" => {
+            let key = args.bytes()?;
+            let members = std::iter::from_fn(|| args.next_bytes()).collect();
+            Ok(Command::Geopos(Geopos {
+                key,
+                members,
+                geohash: cmd_name == "geohash",
+            }))
+        }
+        "geosearch" | "geosearchstore" => {
+            let destination = if cmd_name == "geosearchstore" {
+                Some(args.bytes()?)
+            } else {
+                None
+            };
+            let key = args.bytes()?;
+            let storing = destination.is_some();
+            let (mut center, mut shape, mut unit) = (None, None, GeoUnit::M);
+            let (mut sort, mut count, mut any) = (None, None, false);
+            let (mut with_coord, mut with_dist, mut with_hash, mut store_dist) =
+                (false, false, false, false);
+            while let Some(option) = args.next_string() {
+                match option.to_ascii_lowercase().as_str() {
+                    "frommember" if center.is_none() => {
+                        center = Some(GeoCenter::Member(args.bytes()?));
+                    }
+                    "fromlonlat" if center.is_none() => {
+                        let (lon, lat) = (args.float()?, args.float()?);
+                        center = Some(GeoCenter::Point(geo_point(lon, lat)?));
+                    }
+                    "frommember" | "fromlonlat" => {
+                        return Err(CommandError::InvalidArgument(format!(
+                            "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for {}",
+                            cmd_name
+                        )))
+                    }
+                    "byradius" if shape.is_none() => {
+                        let radius = args.float()?;
+                        if radius < 0.0 {
+                            return Err(CommandError::InvalidArgument(
+                                "ERR radius cannot be negative".into(),
+                            ));
                         }
-                        _ => return Err(CommandError::UnknownSubCommand("get".into())),
+                        unit = geo_unit(&args.string()?)?;
+                        shape = Some(GeoShape::Radius(radius * unit.meters()));
                     }
-                } else {
-                    return Err(CommandError::WrongNumberOfArguments("config".into()));
-                };
-
-                if let Some(_) = v_iter.next() {
-                    return Err(CommandError::SyntaxError("config".into()));
+                    "bybox" if shape.is_none() => {
+                        let (width, height) = (args.float()?, args.float()?);
+                        if width < 0.0 || height < 0.0 {
+                            return Err(CommandError::InvalidArgument(
+                                "ERR height or width cannot be negative".into(),
+                            ));
+                        }
+                        unit = geo_unit(&args.string()?)?;
+                        shape = Some(GeoShape::Box {
+                            width: width * unit.meters(),
+                            height: height * unit.meters(),
+                        });
+                    }
+                    "byradius" | "bybox" => {
+                        return Err(CommandError::InvalidArgument(format!(
+                            "ERR exactly one of BYRADIUS and BYBOX can be specified for {}",
+                            cmd_name
+                        )))
+                    }
+                    "asc" => sort = Some(GeoSort::Asc),
+                    "desc" => sort = Some(GeoSort::Desc),
+                    "count" => {
+                        let n = args.integer::<i64>()?;
+                        if n <= 0 {
+                            return Err(CommandError::InvalidArgument(
+                                "ERR COUNT must be > 0".into(),
+                            ));
+                        }
+                        count = Some(n as usize);
+                    }
+                    "any" => any = true,
+                    "withcoord" if !storing => with_coord = true,
+                    "withdist" if !storing => with_dist = true,
+                    "withhash" if !storing => with_hash = true,
+                    "storedist" if storing => store_dist = true,
+                    _ => return Err(CommandError::SyntaxError(cmd_name)),
                 }
-
-                let s = Config {
-                    sub_command: subcommand,
-                };
-
-                return Ok(Command::Config(s));
             }
-            "keys" => {
-                if let Some(RespData::String(arg)) = v_iter.next() {
-                    let p = Keys {
-                        arg: arg.to_owned(),
-                    };
-                    return Ok(Command::Keys(p));
-                } else {
-                    return Err(CommandError::SyntaxError("KEYS".into()));
+            let Some(center) = center else {
+                return Err(CommandError::InvalidArgument(format!(
+                    "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for {}",
+                    cmd_name
+                )));
+            };
+            let Some(shape) = shape else {
+                return Err(CommandError::InvalidArgument(format!(
+                    "ERR exactly one of BYRADIUS and BYBOX can be specified for {}",
+                    cmd_name
+                )));
+            };
+            if any && count.is_none() {
+                return Err(CommandError::InvalidArgument(
+                    "ERR the ANY argument requires COUNT argument".into(),
+                ));
+            }
+            Ok(Command::Geosearch(Geosearch {
+                key,
+                destination,
+                center,
+                shape,
+                unit,
+                sort,
+                count,
+                any,
+                with_coord,
+                with_dist,
+                with_hash,
+                store_dist,
+            }))
+        }
+        "del" | "unlink" => {
+            let mut keys = vec![args.bytes()?];
+            while let Some(key) = args.next_bytes() {
+                keys.push(key);
+            }
+            Ok(Command::Del(Del { keys }))
+        }
+        "exists" | "touch" => {
+            let mut keys = vec![args.bytes()?];
+            while let Some(key) = args.next_bytes() {
+                keys.push(key);
+            }
+            Ok(Command::Exists(Exists { keys }))
+        }
+        "rename" | "renamenx" => {
+            let key = args.bytes()?;
+            let new_key = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Rename(Rename {
+                key,
+                new_key,
+                only_if_new: cmd_name == "renamenx",
+            }))
+        }
+        "copy" => {
+            let source = args.bytes()?;
+            let destination = args.bytes()?;
+            let mut db = None;
+            let mut replace = false;
+            while let Some(option) = args.next_string() {
+                match option.to_ascii_lowercase().as_str() {
+                    "replace" => replace = true,
+                    "db" => db = Some(args.integer::<i64>()?),
+                    _ => return Err(CommandError::SyntaxError(cmd_name)),
                 }
             }
-            "info" => {
-                let sub_command = if let Some(RespData::String(sub_command)) = v_iter.next() {
-                    Some(sub_command)
-                } else {
-                    None
-                };
+            Ok(Command::CopyKey(CopyKey {
+                source,
+                destination,
+                db,
+                replace,
+            }))
+        }
+        "move" => {
+            let key = args.bytes()?;
+            let db = args.integer::<i64>()?;
+            args.finish()?;
+            Ok(Command::Move(Move { key, db }))
+        }
+        "swapdb" => {
+            let db1 = args
+                .string()?
+                .parse::<i64>()
+                .map_err(|_| CommandError::InvalidArgument("ERR invalid first DB index".into()))?;
+            let db2 = args
+                .string()?
+                .parse::<i64>()
+                .map_err(|_| CommandError::InvalidArgument("ERR invalid second DB index".into()))?;
+            args.finish()?;
+            Ok(Command::Swapdb(Swapdb { db1, db2 }))
+        }
+        "randomkey" => {
+            args.finish()?;
+            Ok(Command::Randomkey(Randomkey))
+        }
+        "dbsize" => {
+            args.finish()?;
+            Ok(Command::Dbsize(Dbsize))
+        }
+        "flushdb" | "flushall" => {
+            if let Some(mode) = args.next_string() {
+                if !matches!(mode.to_ascii_lowercase().as_str(), "async" | "sync") {
+                    return Err(CommandError::SyntaxError(cmd_name));
+                }
+            }
+            if !args.is_empty() {
+                return Err(CommandError::SyntaxError(cmd_name));
+            }
+            Ok(Command::Flush(Flush {
+                all: cmd_name == "flushall",
+            }))
+        }
+        "expire" | "pexpire" | "expireat" | "pexpireat" => {
+            let command = match cmd_name.as_str() {
+                "pexpire" => "pexpire",
+                "expireat" => "expireat",
+                "pexpireat" => "pexpireat",
+                _ => "expire",
+            };
+            let key = args.bytes()?;
+            let time = args.integer::<i64>()?;
+            let unit = if cmd_name.starts_with('p') { 1 } else { 1000 };
+            let time = time.checked_mul(unit).ok_or_else(|| {
+                CommandError::InvalidArgument(format!(
+                    "ERR invalid expire time in '{}' command",
+                    cmd_name
+                ))
+            })?;
+            let expiry = if cmd_name.ends_with("at") {
+                Expiry::At(time)
+            } else {
+                Expiry::In(time)
+            };
+            let mut conditions = Vec::new();
+            while let Some(option) = args.next_string() {
+                let condition = expire_condition(&option).ok_or_else(|| {
+                    CommandError::InvalidArgument(format!("ERR Unsupported option {}", option))
+                })?;
+                conditions.push(condition);
+            }
+            let has = |condition| conditions.contains(&condition);
+            if has(ExpireCondition::Nx)
+                && (has(ExpireCondition::Xx)
+                    || has(ExpireCondition::Gt)
+                    || has(ExpireCondition::Lt))
+            {
+                return Err(CommandError::InvalidArgument(
+                    "ERR NX and XX, GT or LT options at the same time are not compatible".into(),
+                ));
+            }
+            if has(ExpireCondition::Gt) && has(ExpireCondition::Lt) {
+                return Err(CommandError::InvalidArgument(
+                    "ERR GT and LT options at the same time are not compatible".into(),
+                ));
+            }
+            Ok(Command::Expire(Expire {
+                command,
+                key,
+                expiry,
+                conditions,
+            }))
+        }
+        "ttl" | "pttl" | "expiretime" | "pexpiretime" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Ttl(Ttl {
+                key,
+                absolute: cmd_name.ends_with("time"),
+                millis: cmd_name.starts_with('p'),
+            }))
+        }
+        "persist" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Persist(Persist { key }))
+        }
+        "scan" => {
+            let options = scan_options(&mut args, &cmd_name)?;
+            Ok(Command::Scan(Scan { options }))
+        }
+        "ping" => {
+            let value = args.next_bytes();
+            args.finish()?;
+            Ok(Command::Ping(Ping { value }))
+        }
+        "echo" => {
+            let value = args.next_bytes();
+            args.finish()?;
+            Ok(Command::Echo(Echo { value }))
+        }
+        "select" => {
+            let index = args.integer::<i64>()?;
+            args.finish()?;
+            Ok(Command::Select(Select { index }))
+        }
+        "hello" => {
+            let protover = match args.next_string() {
+                Some(protover) => Some(
+                    protover
+                        .parse::<i64>()
+                        .map_err(|_| CommandError::InvalidProtocolVersion)?,
+                ),
+                None => None,
+            };
 
-                let s = if sub_command.is_some() {
-                    Info {
-                        sub_command: Some(InfoSubCommand::Replication),
+            let mut auth = None;
+            let mut setname = None;
+            while let Some(option) = args.next_string() {
+                match option.to_ascii_lowercase().as_str() {
+                    "auth" => auth = Some((args.string()?, args.string()?)),
+                    "setname" => setname = Some(args.string()?),
+                    _ => return Err(CommandError::SyntaxError("hello".into())),
+                }
+            }
+
+            Ok(Command::Hello(Hello {
+                protover,
+                auth,
+                setname,
+            }))
+        }
+        "exec" => {
+            args.finish()?;
+            Ok(Command::Exec(Exec))
+        }
+        "multi" => {
+            args.finish()?;
+            Ok(Command::Multi(Multi))
+        }
+        "discard" => {
+            args.finish()?;
+            Ok(Command::Discard(Discard))
+        }
+        "config" => {
+            let sub_command = match args.string()?.to_ascii_lowercase().as_str() {
+                "get" => {
+                    let mut patterns = vec![args.string()?];
+                    while let Some(pattern) = args.next_string() {
+                        patterns.push(pattern);
                     }
-                } else {
-                    Info { sub_command: None }
-                };
+                    SubCommand::Get(patterns)
+                }
+                _ => return Err(CommandError::UnknownSubCommand("get".into())),
+            };
 
-                return Ok(Command::Info(s));
+            Ok(Command::Config(Config { sub_command }))
+        }
+        "keys" => {
+            let pattern = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Keys(Keys { pattern }))
+        }
+        "info" => {
+            let sub_command = args.next_string().map(|_| InfoSubCommand::Replication);
+            Ok(Command::Info(Info { sub_command }))
+        }
+        "save" => Ok(Command::Save(Save)),
+        "replconf" => {
+            let option = args.string()?.to_ascii_lowercase();
+            match option.as_str() {
+                "listening-port" => {
+                    let port = args.integer::<u16>()?;
+                    args.finish()?;
+                    Ok(Command::Replconf(Replconf {
+                        args: vec![option, port.to_string()],
+                    }))
+                }
+                "capa" | "getack" => {
+                    let mut replconf_args = vec![option];
+                    replconf_args.extend(args.next_string());
+                    Ok(Command::Replconf(Replconf {
+                        args: replconf_args,
+                    }))
+                }
+                "ack" => {
+                    let offset = args.integer::<usize>()?;
+                    Ok(Command::Replconf(Replconf {
+                        args: vec![option, offset.to_string()],
+                    }))
+                }
+                _ => Err(CommandError::SyntaxError("replconf".into())),
             }
-            "save" => {
-                let o = Save;
-                return Ok(Command::Save(o));
+        }
+        "psync" => {
+            let replid = args.string()?;
+            let offset = args.integer::<i64>()?;
+            args.finish()?;
+            Ok(Command::Psync(Psync {
+                args: vec![replid, offset.to_string()],
+            }))
+        }
+        "type" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Type(Type { key }))
+        }
+        "wait" => {
+            let numreplicas = args.integer::<usize>()?;
+            let timeout = args.integer::<u64>()?;
+            args.finish()?;
+            Ok(Command::Wait(Wait {
+                args: vec![numreplicas.to_string(), timeout.to_string()],
+            }))
+        }
+        "xadd" => {
+            let key = args.bytes()?;
+            let entry_id = args.string()?;
+
+            let mut fields: Vec<(Bytes, Bytes)> = Vec::new();
+            while let Some(field) = args.next_bytes() {
+                fields.push((field, args.bytes()?));
+            }
+            if fields.is_empty() {
+                return Err(CommandError::WrongNumberOfArguments("xadd".into()));
             }
-            "replconf" => match v_iter.next() {
-                Some(RespData::String(s)) => match s.to_ascii_lowercase().as_str() {
-                    "listening-port" => {
-                        let port = if let Some(RespData::Integer(port)) = v_iter.next() {
-                            port
-                        } else {
-                            return Err(CommandError::NotValidType("replconf".into()));
-                        };
 
-                        if v_iter.next().is_some() {
-                            return Err(CommandError::WrongNumberOfArguments("replconf".into()));
+            Ok(Command::Xadd(Xadd {
+                key,
+                entry_id,
+                args: fields,
+            }))
+        }
+        "xrange" => {
+            let key = args.bytes()?;
+            let start = args.string()?;
+            let end = args.string()?;
+            args.finish()?;
+            Ok(Command::Xrange(Xrange { key, start, end }))
+        }
+        "xread" => {
+            let mut block: Option<u64> = None;
+            let mut cmd_options: Vec<Bytes> = Vec::new();
+            loop {
+                match args.string()?.to_ascii_lowercase().as_str() {
+                    "block" => block = Some(args.integer::<u64>()?),
+                    "streams" => {
+                        while let Some(s) = args.next_bytes() {
+                            cmd_options.push(s);
                         }
-                        return Ok(Command::Replconf(Replconf {
-                            args: vec!["listening-port".into(), port.to_string()],
-                        }));
+                        break;
                     }
-                    "capa" => {
-                        let mut args: Vec<String> = vec!["capa".into()];
-                        if let Some(RespData::String(s)) = v_iter.next() {
-                            args.push(s.to_string());
+                    _ => return Err(CommandError::SyntaxError("xread".into())),
+                }
+            }
+            if cmd_options.is_empty() || cmd_options.len() % 2 == 1 {
+                return Err(CommandError::WrongNumberOfArguments("xread".into()));
+            }
+
+            let entry_ids = cmd_options
+                .split_off(cmd_options.len() / 2)
+                .iter()
+                .map(|id| String::from_utf8_lossy(id).into_owned())
+                .collect();
+            Ok(Command::Xread(Xread {
+                block,
+                keys: cmd_options,
+                entry_ids,
+            }))
+        }
+        "lpush" | "rpush" | "lpushx" | "rpushx" => {
+            let key = args.bytes()?;
+            let mut elements = vec![args.bytes()?];
+            while let Some(element) = args.next_bytes() {
+                elements.push(element);
+            }
+            let end = if cmd_name.starts_with('l') {
+                End::Left
+            } else {
+                End::Right
+            };
+            Ok(Command::Push(Push {
+                key,
+                end,
+                elements,
+                only_if_exists: cmd_name.ends_with('x'),
+            }))
+        }
+        "lpop" | "rpop" => {
+            let key = args.bytes()?;
+            let count = if args.is_empty() {
+                None
+            } else {
+                Some(args.count()?)
+            };
+            args.finish()?;
+            let end = if cmd_name == "lpop" {
+                End::Left
+            } else {
+                End::Right
+            };
+            Ok(Command::Pop(Pop { key, end, count }))
+        }
+        "llen" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Llen(Llen { key }))
+        }
+        "lrange" | "ltrim" => {
+            let key = args.bytes()?;
+            let start = args.integer::<i64>()?;
+            let stop = args.integer::<i64>()?;
+            args.finish()?;
+            if cmd_name == "lrange" {
+                Ok(Command::Lrange(Lrange { key, start, stop }))
+            } else {
+                Ok(Command::Ltrim(Ltrim { key, start, stop }))
+            }
+        }
+        "lindex" => {
+            let key = args.bytes()?;
+            let index = args.integer::<i64>()?;
+            args.finish()?;
+            Ok(Command::Lindex(Lindex { key, index }))
+        }
+        "lset" => {
+            let key = args.bytes()?;
+            let index = args.integer::<i64>()?;
+            let element = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Lset(Lset {
+                key,
+                index,
+                element,
+            }))
+        }
+        "lrem" => {
+            let key = args.bytes()?;
+            let count = args.integer::<i64>()?;
+            let element = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Lrem(Lrem {
+                key,
+                count,
+                element,
+            }))
+        }
+        "linsert" => {
+            let key = args.bytes()?;
+            let before = match args.string()?.to_ascii_lowercase().as_str() {
+                "before" => true,
+                "after" => false,
+                _ => return Err(CommandError::SyntaxError("linsert".into())),
+            };
+            let pivot = args.bytes()?;
+            let element = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Linsert(Linsert {
+                key,
+                before,
+                pivot,
+                element,
+            }))
+        }
+        "lpos" => {
+            let key = args.bytes()?;
+            let element = args.bytes()?;
+            let mut rank = 1;
+            let mut count = None;
+            let mut maxlen = 0;
+            while let Some(option) = args.next_string() {
+                match option.to_ascii_lowercase().as_str() {
+                    "rank" => {
+                        rank = args.integer::<i64>()?;
+                        if rank == 0 || rank == i64::MIN {
+                            return Err(CommandError::InvalidArgument(
+                                "ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list".into(),
+                            ));
                         }
-                        return Ok(Command::Replconf(Replconf { args }));
                     }
-                    "getack" => {
-                        let mut args: Vec<String> = vec!["getack".into()];
-                        if let Some(RespData::String(s)) = v_iter.next() {
-                            args.push(s.to_string());
-                        }
-                        return Ok(Command::Replconf(Replconf { args }));
+                    "count" => {
+                        count = Some(args.count().map_err(|_| {
+                            CommandError::InvalidArgument("ERR COUNT can't be negative".into())
+                        })?);
                     }
-                    "ack" => {
-                        let mut args: Vec<String> = vec!["ack".into()];
-                        if let Some(RespData::Integer(s)) = v_iter.next() {
-                            args.push(s.to_string());
-                        }
-                        return Ok(Command::Replconf(Replconf { args }));
+                    "maxlen" => {
+                        maxlen = args.count().map_err(|_| {
+                            CommandError::InvalidArgument("ERR MAXLEN can't be negative".into())
+                        })?;
                     }
-                    _ => {}
-                },
-                Some(_) => {}
-                None => todo!(),
-            },
-            "psync" => {
-                let mut args: Vec<String> = Vec::new();
-                if let Some(RespData::String(s)) = v_iter.next() {
-                    args.push(s.to_string());
+                    _ => return Err(CommandError::SyntaxError("lpos".into())),
                 }
-
-                if let Some(RespData::Integer(s)) = v_iter.next() {
-                    args.push(s.to_string());
+            }
+            Ok(Command::Lpos(Lpos {
+                key,
+                element,
+                rank,
+                count,
+                maxlen,
+            }))
+        }
+        "lmove" => {
+            let lmove = lmove(&mut args)?;
+            args.finish()?;
+            Ok(Command::Lmove(lmove))
+        }
+        "rpoplpush" => {
+            let source = args.bytes()?;
+            let destination = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Lmove(Lmove {
+                source,
+                destination,
+                from: End::Right,
+                to: End::Left,
+            }))
+        }
+        "lmpop" => Ok(Command::Lmpop(lmpop(&mut args)?)),
+        "blpop" | "brpop" => {
+            let mut keys = Vec::new();
+            while let Some(key) = args.next_bytes() {
+                keys.push(key);
+            }
+            let timeout = match keys.pop() {
+                Some(timeout) if !keys.is_empty() => {
+                    parse_timeout(&String::from_utf8_lossy(&timeout))?
                 }
-                if let Some(_) = v_iter.next() {
-                    return Err(CommandError::WrongNumberOfArguments("psync".into()));
+                _ => return Err(CommandError::WrongNumberOfArguments(cmd_name)),
+            };
+            let end = if cmd_name == "blpop" {
+                End::Left
+            } else {
+                End::Right
+            };
+            Ok(Command::Bpop(Bpop { keys, end, timeout }))
+        }
+        "blmove" => {
+            let lmove = lmove(&mut args)?;
+            let timeout = args.timeout()?;
+            args.finish()?;
+            Ok(Command::Blmove(Blmove { lmove, timeout }))
+        }
+        "brpoplpush" => {
+            let source = args.bytes()?;
+            let destination = args.bytes()?;
+            let timeout = args.timeout()?;
+            args.finish()?;
+            let lmove = Lmove {
+                source,
+                destination,
+                from: End::Right,
+                to: End::Left,
+            };
+            Ok(Command::Blmove(Blmove { lmove, timeout }))
+        }
+        "blmpop" => {
+            let timeout = args.timeout()?;
+            let lmpop = lmpop(&mut args)?;
+            Ok(Command::Blmpop(Blmpop { lmpop, timeout }))
+        }
+        "hset" => {
+            let key = args.bytes()?;
+            let mut pairs = vec![(args.bytes()?, args.bytes()?)];
+            while let Some(field) = args.next_bytes() {
+                pairs.push((field, args.bytes()?));
+            }
+            Ok(Command::Hset(Hset { key, pairs }))
+        }
+        "hget" => {
+            let key = args.bytes()?;
+            let field = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Hget(Hget { key, field }))
+        }
+        "hmget" | "hdel" => {
+            let key = args.bytes()?;
+            let mut fields = vec![args.bytes()?];
+            while let Some(field) = args.next_bytes() {
+                fields.push(field);
+            }
+            Ok(if cmd_name == "hmget" {
+                Command::Hmget(Hmget { key, fields })
+            } else {
+                Command::Hdel(Hdel { key, fields })
+            })
+        }
+        "hexists" => {
+            let key = args.bytes()?;
+            let field = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Hexists(Hexists { key, field }))
+        }
+        "hincrby" => {
+            let key = args.bytes()?;
+            let field = args.bytes()?;
+            let increment = args.integer::<i64>()?;
+            args.finish()?;
+            Ok(Command::Hincrby(Hincrby {
+                key,
+                field,
+                increment,
+            }))
+        }
+        "hincrbyfloat" => {
+            let key = args.bytes()?;
+            let field = args.bytes()?;
+            let increment = args.float()?;
+            args.finish()?;
+            Ok(Command::Hincrbyfloat(Hincrbyfloat {
+                key,
+                field,
+                increment,
+            }))
+        }
+        "hgetall" | "hkeys" | "hvals" | "hlen" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            Ok(match cmd_name.as_str() {
+                "hgetall" => Command::Hgetall(Hgetall { key }),
+                "hkeys" => Command::Hkeys(Hkeys { key }),
+                "hvals" => Command::Hvals(Hvals { key }),
+                _ => Command::Hlen(Hlen { key }),
+            })
+        }
+        "hscan" => {
+            let key = args.bytes()?;
+            let options = scan_options(&mut args, &cmd_name)?;
+            Ok(Command::Hscan(Hscan { key, options }))
+        }
+        "hrandfield" => {
+            let key = args.bytes()?;
+            let count = match args.next_string() {
+                Some(count) => Some(
+                    count
+                        .parse::<i64>()
+                        .map_err(|_| CommandError::NotAnInteger)?,
+                ),
+                None => None,
+            };
+            let with_values = match args.next_string() {
+                Some(option) if option.eq_ignore_ascii_case("withvalues") => true,
+                Some(_) => return Err(CommandError::SyntaxError(cmd_name)),
+                None => false,
+            };
+            args.finish()?;
+            Ok(Command::Hrandfield(Hrandfield {
+                key,
+                count,
+                with_values,
+            }))
+        }
+        "hexpire" | "hpexpire" | "hexpireat" | "hpexpireat" => {
+            let key = args.bytes()?;
+            let time = args.integer::<i64>()?;
+            let unit = if cmd_name.starts_with("hp") { 1 } else { 1000 };
+            let time = time
+                .checked_mul(unit)
+                .filter(|time| (0..=MAX_FIELD_EXPIRY).contains(time))
+                .ok_or_else(|| {
+                    CommandError::InvalidArgument(format!(
+                        "ERR invalid expire time, must be >= 0 and <= {}",
+                        MAX_FIELD_EXPIRY
+                    ))
+                })?;
+            let expiry = if cmd_name.ends_with("at") {
+                Expiry::At(time)
+            } else {
+                Expiry::In(time)
+            };
+            let mut option = args.string()?;
+            let condition = match option.to_ascii_lowercase().as_str() {
+                "fields" => ExpireCondition::Always,
+                _ => {
+                    let condition = expire_condition(&option)
+                        .ok_or_else(|| CommandError::SyntaxError(cmd_name.clone()))?;
+                    option = args.string()?;
+                    condition
                 }
-                return Ok(Command::Psync(Psync { args }));
+            };
+            let fields = hash_fields(&mut args, &option)?;
+            Ok(Command::Hexpire(Hexpire {
+                key,
+                expiry,
+                condition,
+                fields,
+            }))
+        }
+        "httl" | "hpttl" | "hexpiretime" | "hpexpiretime" => {
+            let key = args.bytes()?;
+            let option = args.string()?;
+            let fields = hash_fields(&mut args, &option)?;
+            Ok(Command::Httl(Httl {
+                key,
+                fields,
+                absolute: cmd_name.ends_with("time"),
+                millis: cmd_name.starts_with("hp"),
+            }))
+        }
+        "hpersist" => {
+            let key = args.bytes()?;
+            let option = args.string()?;
+            let fields = hash_fields(&mut args, &option)?;
+            Ok(Command::Hpersist(Hpersist { key, fields }))
+        }
+        "sadd" | "srem" | "smismember" => {
+            let key = args.bytes()?;
+            let mut members = vec![args.bytes()?];
+            while let Some(member) = args.next_bytes() {
+                members.push(member);
             }
-            "type" => {
-                let key = if let Some(RespData::String(key)) = v_iter.next() {
-                    key.to_owned()
-                } else {
-                    return Err(CommandError::WrongNumberOfArguments("type".into()));
-                };
-
-                if let Some(_) = v_iter.next() {
-                    return Err(CommandError::WrongNumberOfArguments("type".into()));
+            Ok(match cmd_name.as_str() {
+                "sadd" => Command::Sadd(Sadd { key, members }),
+                "srem" => Command::Srem(Srem { key, members }),
+                _ => Command::Smismember(Smismember { key, members }),
+            })
+        }
+        "smembers" | "scard" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            Ok(if cmd_name == "smembers" {
+                Command::Smembers(Smembers { key })
+            } else {
+                Command::Scard(Scard { key })
+            })
+        }
+        "sismember" => {
+            let key = args.bytes()?;
+            let member = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Sismember(Sismember { key, member }))
+        }
+        "spop" => {
+            let key = args.bytes()?;
+            let count = if args.is_empty() {
+                None
+            } else {
+                Some(args.count()?)
+            };
+            if !args.is_empty() {
+                return Err(CommandError::SyntaxError(cmd_name));
+            }
+            Ok(Command::Spop(Spop { key, count }))
+        }
+        "srandmember" => {
+            let key = args.bytes()?;
+            let count = if args.is_empty() {
+                None
+            } else {
+                Some(args.integer::<i64>()?)
+            };
+            if !args.is_empty() {
+                return Err(CommandError::SyntaxError(cmd_name));
+            }
+            Ok(Command::Srandmember(Srandmember { key, count }))
+        }
+        "sinter" | "sunion" | "sdiff" | "sinterstore" | "sunionstore" | "sdiffstore" => {
+            let destination = if cmd_name.ends_with("store") {
+                Some(args.bytes()?)
+            } else {
+                None
+            };
+            let mut keys = vec![args.bytes()?];
+            while let Some(key) = args.next_bytes() {
+                keys.push(key);
+            }
+            let op = match &cmd_name[1..4] {
+                "int" => SetOp::Inter,
+                "uni" => SetOp::Union,
+                _ => SetOp::Diff,
+            };
+            Ok(Command::Setop(Setop {
+                op,
+                keys,
+                destination,
+            }))
+        }
+        "sscan" => {
+            let key = args.bytes()?;
+            let options = scan_options(&mut args, &cmd_name)?;
+            Ok(Command::Sscan(Sscan { key, options }))
+        }
+        "sintercard" => {
+            let numkeys = args.integer::<i64>()?;
+            if numkeys <= 0 {
+                return Err(CommandError::InvalidArgument(
+                    "ERR numkeys should be greater than 0".into(),
+                ));
+            }
+            let mut keys = Vec::new();
+            for _ in 0..numkeys {
+                keys.push(args.next_bytes().ok_or_else(|| {
+                    CommandError::InvalidArgument(
+                        "ERR Number of keys can't be greater than number of args".into(),
+                    )
+                })?);
+            }
+            let mut limit = 0;
+            while let Some(option) = args.next_string() {
+                if !option.eq_ignore_ascii_case("limit") {
+                    return Err(CommandError::SyntaxError(cmd_name));
                 }
-
-                let g = Type { key };
-                return Ok(Command::Type(g));
+                limit = args.count().map_err(|_| {
+                    CommandError::InvalidArgument("ERR LIMIT can't be negative".into())
+                })?;
             }
-            "wait" => {
-                let mut args: Vec<String> = Vec::new();
-                if let Some(RespData::Integer(s)) = v_iter.next() {
-                    args.push(s.to_string());
+            Ok(Command::Sintercard(Sintercard { keys, limit }))
+        }
+        "zadd" => {
+            let key = args.bytes()?;
+            let (mut nx, mut xx, mut gt, mut lt, mut ch, mut incr) =
+                (false, false, false, false, false, false);
+            let mut score = args.string()?;
+            loop {
+                match score.to_ascii_lowercase().as_str() {
+                    "nx" => nx = true,
+                    "xx" => xx = true,
+                    "gt" => gt = true,
+                    "lt" => lt = true,
+                    "ch" => ch = true,
+                    "incr" => incr = true,
+                    _ => break,
                 }
-
-                if let Some(RespData::Integer(s)) = v_iter.next() {
-                    args.push(s.to_string());
+                score = args.string()?;
+            }
+            if nx && xx {
+                return Err(CommandError::InvalidArgument(
+                    "ERR XX and NX options at the same time are not compatible".into(),
+                ));
+            }
+            if (gt && lt) || (nx && (gt || lt)) {
+                return Err(CommandError::InvalidArgument(
+                    "ERR GT, LT, and/or NX options at the same time are not compatible".into(),
+                ));
+            }
+            let mut pairs = Vec::new();
+            loop {
+                let member = args
+                    .next_bytes()
+                    .ok_or_else(|| CommandError::SyntaxError(cmd_name.clone()))?;
+                pairs.push((score, member));
+                match args.next_string() {
+                    Some(next) => score = next,
+                    None => break,
                 }
-                if let Some(_) = v_iter.next() {
-                    return Err(CommandError::WrongNumberOfArguments("psync".into()));
+            }
+            if incr && pairs.len() > 1 {
+                return Err(CommandError::InvalidArgument(
+                    "ERR INCR option supports a single increment-element pair".into(),
+                ));
+            }
+            let pairs = pairs
+                .into_iter()
+                .map(|(score, member)| Ok((parse_float(&score)?, member)))
+                .collect::<Result<_, CommandError>>()?;
+            Ok(Command::Zadd(Zadd {
+                key,
+                nx,
+                xx,
+                gt,
+                lt,
+                ch,
+                incr,
+                pairs,
+            }))
+        }
+        "zincrby" => {
+            let key = args.bytes()?;
+            let increment = args.float()?;
+            let member = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Zincrby(Zincrby {
+                key,
+                increment,
+                member,
+            }))
+        }
+        "zrange" | "zrangestore" => {
+            let destination = if cmd_name == "zrangestore" {
+                Some(args.bytes()?)
+            } else {
+                None
+            };
+            let key = args.bytes()?;
+            let start = args.bytes()?;
+            let stop = args.bytes()?;
+            let (mut by_score, mut by_lex, mut rev, mut with_scores) = (false, false, false, false);
+            let mut limit = None;
+            while let Some(option) = args.next_string() {
+                match option.to_ascii_lowercase().as_str() {
+                    "byscore" => by_score = true,
+                    "bylex" => by_lex = true,
+                    "rev" => rev = true,
+                    "withscores" if destination.is_none() => with_scores = true,
+                    "limit" => limit = Some((args.integer::<i64>()?, args.integer::<i64>()?)),
+                    _ => return Err(CommandError::SyntaxError(cmd_name)),
                 }
-                return Ok(Command::Wait(Wait { args }));
             }
-            "xadd" => {
-                let mut args: Vec<(String, String)> = Vec::new();
-                let key = if let Some(RespData::String(s)) = v_iter.next() {
-                    s.to_string()
-                } else {
-                    return Err(CommandError::NotValidType("XADD".into()));
-                };
-
-                let entry_id = if let Some(RespData::String(s)) = v_iter.next() {
-                    s.to_string()
-                } else {
-                    return Err(CommandError::NotValidType("XADD".into()));
+            if by_score && by_lex {
+                return Err(CommandError::SyntaxError(cmd_name));
+            }
+            if limit.is_some() && !by_score && !by_lex {
+                return Err(CommandError::InvalidArgument(
+                    "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX".into(),
+                ));
+            }
+            if with_scores && by_lex {
+                return Err(CommandError::InvalidArgument(
+                    "ERR syntax error, WITHSCORES not supported in combination with BYLEX".into(),
+                ));
+            }
+            // Score and lex ranges are given from max to min with REV.
+            let (min, max) = if rev {
+                (&stop, &start)
+            } else {
+                (&start, &stop)
+            };
+            let by = if by_score {
+                ZrangeBy::Score {
+                    min: score_bound(min)?,
+                    max: score_bound(max)?,
+                }
+            } else if by_lex {
+                ZrangeBy::Lex {
+                    min: lex_bound(min)?,
+                    max: lex_bound(max)?,
+                }
+            } else {
+                let index = |index: &Bytes| {
+                    std::str::from_utf8(index)
+                        .ok()
+                        .and_then(|index| index.parse::<i64>().ok())
+                        .ok_or(CommandError::NotAnInteger)
                 };
-
-                let kv_pairs = v_iter.collect::<Vec<&RespData>>();
-                if kv_pairs.len() % 2 != 0 {
-                    return Err(CommandError::WrongNumberOfArguments("xread".into()));
+                ZrangeBy::Rank {
+                    start: index(&start)?,
+                    stop: index(&stop)?,
                 }
-
-                let kv_pairs = kv_pairs
-                    .chunks(2)
-                    .map(|chunk| {
-                        let chunk = chunk.to_vec();
-                        (chunk[0].to_owned(), chunk[1].to_owned())
-                    })
-                    .collect::<Vec<(RespData, RespData)>>();
-
-                for (k, v) in kv_pairs {
-                    match (k, v) {
-                        (RespData::String(k), RespData::String(v)) => args.push((k, v)),
-                        (RespData::String(k), RespData::Integer(v)) => {
-                            args.push((k, v.to_string()))
+            };
+            Ok(Command::Zrange(Zrange {
+                key,
+                destination,
+                by,
+                rev,
+                limit,
+                with_scores,
+            }))
+        }
+        "zrank" | "zrevrank" => {
+            let key = args.bytes()?;
+            let member = args.bytes()?;
+            let with_score = match args.next_string() {
+                Some(option) if option.eq_ignore_ascii_case("withscore") => true,
+                Some(_) => return Err(CommandError::SyntaxError(cmd_name)),
+                None => false,
+            };
+            args.finish()?;
+            Ok(Command::Zrank(Zrank {
+                key,
+                member,
+                rev: cmd_name == "zrevrank",
+                with_score,
+            }))
+        }
+        "zscore" => {
+            let key = args.bytes()?;
+            let member = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Zscore(Zscore { key, member }))
+        }
+        "zcard" => {
+            let key = args.bytes()?;
+            args.finish()?;
+            Ok(Command::Zcard(Zcard { key }))
+        }
+        "zrem" => {
+            let key = args.bytes()?;
+            let mut members = vec![args.bytes()?];
+            while let Some(member) = args.next_bytes() {
+                members.push(member);
+            }
+            Ok(Command::Zrem(Zrem { key, members }))
+        }
+        "zcount" => {
+            let key = args.bytes()?;
+            let min = score_bound(&args.bytes()?)?;
+            let max = score_bound(&args.bytes()?)?;
+            args.finish()?;
+            Ok(Command::Zcount(Zcount { key, min, max }))
+        }
+        "zpopmin" | "zpopmax" => {
+            let key = args.bytes()?;
+            let count = if args.is_empty() {
+                None
+            } else {
+                Some(args.count()?)
+            };
+            if !args.is_empty() {
+                return Err(CommandError::SyntaxError(cmd_name));
+            }
+            Ok(Command::Zpop(Zpop {
+                key,
+                max: cmd_name == "zpopmax",
+                count,
+            }))
+        }
+        "zmpop" => {
+            let zmpop = zmpop(&mut args)?;
+            Ok(Command::Zmpop(zmpop))
+        }
+        "bzpopmin" | "bzpopmax" => {
+            let mut keys = Vec::new();
+            while let Some(key) = args.next_bytes() {
+                keys.push(key);
+            }
+            let timeout = match keys.pop() {
+                Some(timeout) if !keys.is_empty() => {
+                    parse_timeout(&String::from_utf8_lossy(&timeout))?
+                }
+                _ => return Err(CommandError::WrongNumberOfArguments(cmd_name)),
+            };
+            Ok(Command::Bzpop(Bzpop {
+                keys,
+                max: cmd_name == "bzpopmax",
+                timeout,
+            }))
+        }
+        "bzmpop" => {
+            let timeout = args.timeout()?;
+            let zmpop = zmpop(&mut args)?;
+            Ok(Command::Bzmpop(Bzmpop { zmpop, timeout }))
+        }
+        "zscan" => {
+            let key = args.bytes()?;
+            let options = scan_options(&mut args, &cmd_name)?;
+            Ok(Command::Zscan(Zscan { key, options }))
+        }
+        "zunion" | "zinter" | "zdiff" | "zunionstore" | "zinterstore" | "zdiffstore" => {
+            let destination = if cmd_name.ends_with("store") {
+                Some(args.bytes()?)
+            } else {
+                None
+            };
+            let numkeys = args.integer::<i64>()?;
+            if numkeys <= 0 {
+                return Err(CommandError::InvalidArgument(format!(
+                    "ERR at least 1 input key is needed for '{}' command",
+                    cmd_name
+                )));
+            }
+            let mut keys = Vec::new();
+            for _ in 0..numkeys {
+                keys.push(
+                    args.next_bytes()
+                        .ok_or_else(|| CommandError::SyntaxError(cmd_name.clone()))?,
+                );
+            }
+            let op = match &cmd_name[1..4] {
+                "int" => SetOp::Inter,
+                "uni" => SetOp::Union,
+                _ => SetOp::Diff,
+            };
+            let mut weights = vec![1.0; keys.len()];
+            let mut aggregate = Aggregate::Sum;
+            let mut with_scores = false;
+            while let Some(option) = args.next_string() {
+                match option.to_ascii_lowercase().as_str() {
+                    "weights" if op != SetOp::Diff => {
+                        for weight in weights.iter_mut() {
+                            *weight = args
+                                .next_string()
+                                .ok_or_else(|| CommandError::SyntaxError(cmd_name.clone()))
+                                .and_then(|weight| {
+                                    parse_float(&weight).map_err(|_| {
+                                        CommandError::InvalidArgument(
+                                            "ERR weight value is not a float".into(),
+                                        )
+                                    })
+                                })?;
                         }
-                        (_, _) => return Err(CommandError::NotValidType("XADD".into())),
                     }
+                    "aggregate" if op != SetOp::Diff => {
+                        aggregate = match args.string()?.to_ascii_lowercase().as_str() {
+                            "sum" => Aggregate::Sum,
+                            "min" => Aggregate::Min,
+                            "max" => Aggregate::Max,
+                            _ => return Err(CommandError::SyntaxError(cmd_name)),
+                        };
+                    }
+                    "withscores" if destination.is_none() => with_scores = true,
+                    _ => return Err(CommandError::SyntaxError(cmd_name)),
                 }
-
-                return Ok(Command::Xadd(Xadd {
-                    key,
-                    entry_id,
-                    args,
-                }));
             }
-            "xrange" => {
-                let key = if let Some(RespData::String(s)) = v_iter.next() {
-                    s.to_string()
-                } else {
-                    return Err(CommandError::NotValidType("XRANGE".into()));
-                };
+            Ok(Command::Zsetop(Zsetop {
+                op,
+                keys,
+                weights,
+                aggregate,
+                destination,
+                with_scores,
+            }))
+        }
+        _ => Err(CommandError::NotSupported),
+    }
+}
 
-                let start = if let Some(RespData::String(s)) = v_iter.next() {
-                    s.to_string()
-                } else {
-                    return Err(CommandError::NotValidType("XRANGE".into()));
-                };
+/// The arguments ZMPOP and BZMPOP share: `numkeys key [key ...] MIN|MAX
+/// [COUNT count]`.
+fn zmpop(args: &mut Args) -> Result<Zmpop, CommandError> {
+    let numkeys = args.integer::<i64>()?;
+    if numkeys <= 0 {
+        return Err(CommandError::InvalidArgument(
+            "ERR numkeys should be greater than 0".into(),
+        ));
+    }
+    let mut keys = Vec::new();
+    for _ in 0..numkeys {
+        keys.push(args.bytes()?);
+    }
+    let max = match args.string()?.to_ascii_lowercase().as_str() {
+        "min" => false,
+        "max" => true,
+        _ => return Err(CommandError::SyntaxError(args.name.clone())),
+    };
+    let mut count = 1;
+    if let Some(option) = args.next_string() {
+        if !option.eq_ignore_ascii_case("count") {
+            return Err(CommandError::SyntaxError(args.name.clone()));
+        }
+        count = args.integer::<i64>()?;
+        if count <= 0 {
+            return Err(CommandError::InvalidArgument(
+                "ERR count should be greater than 0".into(),
+            ));
+        }
+    }
+    if !args.is_empty() {
+        return Err(CommandError::SyntaxError(args.name.clone()));
+    }
+    Ok(Zmpop {
+        keys,
+        max,
+        count: count as usize,
+    })
+}
 
-                let end = if let Some(RespData::String(s)) = v_iter.next() {
-                    s.to_string()
-                } else {
-                    return Err(CommandError::NotValidType("XRANGE".into()));
-                };
+/// The optional `BYTE` or `BIT` that ends BITCOUNT and BITPOS, after which
+/// nothing may follow.
+fn bit_unit(args: &mut Args) -> Result<BitUnit, CommandError> {
+    let unit = match args.next_string() {
+        None => return Ok(BitUnit::Byte),
+        Some(unit) => match unit.to_ascii_lowercase().as_str() {
+            "byte" => BitUnit::Byte,
+            "bit" => BitUnit::Bit,
+            _ => return Err(CommandError::SyntaxError(args.name.clone())),
+        },
+    };
+    if !args.is_empty() {
+        return Err(CommandError::SyntaxError(args.name.clone()));
+    }
+    Ok(unit)
+}
 
-                if let Some(_) = v_iter.next() {
-                    return Err(CommandError::WrongNumberOfArguments("xrange".into()));
-                }
+/// An offset to `bits` bits that have to fit in the longest string there
+/// can be. BITFIELD also takes `#N`, the Nth field of that width.
+fn bit_offset(offset: &str, bits: u32, fields: bool) -> Result<usize, CommandError> {
+    let error =
+        || CommandError::InvalidArgument("ERR bit offset is not an integer or out of range".into());
+    let (offset, width) = match offset.strip_prefix('#') {
+        Some(offset) if fields => (offset, bits as u64),
+        _ => (offset, 1),
+    };
+    let offset = offset
+        .parse::<u64>()
+        .ok()
+        .and_then(|offset| offset.checked_mul(width))
+        .filter(|offset| offset + bits as u64 <= MAX_STRING_LEN as u64 * 8)
+        .ok_or_else(error)?;
+    Ok(offset as usize)
+}
+
+/// `i1` to `i64`, or `u1` to `u63`.
+fn bitfield_type(ty: &str) -> Result<BitfieldType, CommandError> {
+    let (signed, bits) = match ty.as_bytes().first() {
+        Some(b'i' | b'I') => (true, &ty[1..]),
+        Some(b'u' | b'U') => (false, &ty[1..]),
+        _ => (false, ""),
+    };
+    let bits = bits.parse::<u32>().unwrap_or(0);
+    let max = if signed { 64 } else { 63 };
+    if bits == 0 || bits > max {
+        return Err(CommandError::InvalidArgument(
+            "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.".into(),
+        ));
+    }
+    Ok(BitfieldType { signed, bits })
+}
 
-                return Ok(Command::Xrange(Xrange { key, start, end }));
+/// Coordinates, which have to be within what geohashes can encode.
+fn geo_point(lon: f64, lat: f64) -> Result<GeoPoint, CommandError> {
+    GeoPoint::new(lon, lat).ok_or_else(|| {
+        CommandError::InvalidArgument(format!(
+            "ERR invalid longitude,latitude pair {:.6},{:.6}",
+            lon, lat
+        ))
+    })
+}
+
+fn geo_unit(unit: &str) -> Result<GeoUnit, CommandError> {
+    match unit.to_ascii_lowercase().as_str() {
+        "m" => Ok(GeoUnit::M),
+        "km" => Ok(GeoUnit::Km),
+        "ft" => Ok(GeoUnit::Ft),
+        "mi" => Ok(GeoUnit::Mi),
+        _ => Err(CommandError::InvalidArgument(
+            "ERR unsupported unit provided. please use M, KM, FT, MI".into(),
+        )),
+    }
+}
+
+fn parse_float(value: &str) -> Result<f64, CommandError> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|f| !f.is_nan())
+        .ok_or(CommandError::NotAFloat)
+}
+
+/// A score range end: a float, `-inf`/`+inf`, or either prefixed by `(` to
+/// leave it out of the range.
+fn score_bound(bound: &[u8]) -> Result<ScoreBound, CommandError> {
+    let invalid = || CommandError::InvalidArgument("ERR min or max is not a float".into());
+    let bound = std::str::from_utf8(bound).map_err(|_| invalid())?;
+    let (exclusive, score) = match bound.strip_prefix('(') {
+        Some(score) => (true, score),
+        None => (false, bound),
+    };
+    let score = parse_float(score).map_err(|_| invalid())?;
+    Ok(if exclusive {
+        ScoreBound::Exclusive(score)
+    } else {
+        ScoreBound::Inclusive(score)
+    })
+}
+
+/// A lex range end: `-`, `+`, or a member prefixed by `[` to include it or
+/// `(` to leave it out.
+fn lex_bound(bound: &[u8]) -> Result<LexBound, CommandError> {
+    match bound.first() {
+        Some(b'-') if bound.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if bound.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(Bytes::copy_from_slice(&bound[1..]))),
+        Some(b'(') => Ok(LexBound::Exclusive(Bytes::copy_from_slice(&bound[1..]))),
+        _ => Err(CommandError::InvalidArgument(
+            "ERR min or max not valid string range item".into(),
+        )),
+    }
+}
+
+/// The cursor and options of the SCAN family. Only SCAN takes TYPE and only
+/// HSCAN takes NOVALUES.
+fn scan_options(args: &mut Args, cmd_name: &str) -> Result<ScanOptions, CommandError> {
+    let cursor = args
+        .string()?
+        .parse::<u64>()
+        .map_err(|_| CommandError::InvalidArgument("ERR invalid cursor".into()))?;
+    let mut options = ScanOptions {
+        cursor,
+        pattern: None,
+        count: 10,
+        type_name: None,
+        no_values: false,
+    };
+    let syntax_error = || CommandError::SyntaxError(cmd_name.to_string());
+    while let Some(option) = args.next_string() {
+        match option.to_ascii_lowercase().as_str() {
+            "match" => {
+                let pattern = args.next_bytes().ok_or_else(syntax_error)?;
+                options.pattern = (pattern != "*").then_some(pattern);
             }
-            "xread" => {
-                let mut block: Option<u64> = None;
-                let mut cmd_options: Vec<String> = Vec::new();
-                loop {
-                    let cmd = match v_iter.next() {
-                        Some(RespData::String(s)) => s.to_string(),
-                        Some(_) => return Err(CommandError::NotValidType("XREAD".into())),
-                        None => return Err(CommandError::NotValidType("XREAD".into())),
-                    };
-                    match cmd.to_ascii_lowercase().as_str() {
-                        "block" => {
-                            if let Some(RespData::Integer(n)) = v_iter.next() {
-                                block = Some(n.clone() as u64);
-                            }
-                        }
-                        "streams" => {
-                            while let Some(d) = v_iter.next() {
-                                match d {
-                                    RespData::String(s) => {
-                                        cmd_options.push(s.to_string());
-                                    }
-                                    RespData::Integer(s) => {
-                                        cmd_options.push(s.to_string());
-                                    }
-                                    _ => {
-                                        return Err(CommandError::WrongNumberOfArguments(
-                                            "XREAD".into(),
-                                        ))
-                                    }
-                                }
-                            }
-                            if cmd_options.len() % 2 != 0 {
-                                return Err(CommandError::WrongNumberOfArguments("XREAD".into()));
-                            } else {
-                                break;
-                            }
-                        }
-                        _ => break,
-                    }
+            "count" => {
+                let count = args
+                    .next_string()
+                    .ok_or_else(syntax_error)?
+                    .parse::<i64>()
+                    .map_err(|_| CommandError::NotAnInteger)?;
+                if count < 1 {
+                    return Err(syntax_error());
                 }
-
-                let mid = cmd_options.len() / 2;
-                let keys: Vec<_> = cmd_options[..mid].to_vec();
-                let entry_ids: Vec<_> = cmd_options[mid..].to_vec();
-                let cmd = Command::Xread(Xread {
-                    block,
-                    keys,
-                    entry_ids,
-                });
-                return Ok(cmd);
+                options.count = count as usize;
+            }
+            "type" if cmd_name == "scan" => {
+                let name = args.next_string().ok_or_else(syntax_error)?;
+                let name = name.to_ascii_lowercase();
+                if !matches!(
+                    name.as_str(),
+                    "string" | "list" | "set" | "zset" | "hash" | "stream"
+                ) {
+                    // The name is left out, so client bytes are not echoed.
+                    return Err(CommandError::InvalidArgument(
+                        "ERR unknown type name".into(),
+                    ));
+                }
+                options.type_name = Some(name);
             }
-            _ => {}
+            "novalues" if cmd_name == "hscan" => options.no_values = true,
+            _ => return Err(syntax_error()),
         }
     }
-    return Err(CommandError::NotSupported);
+    Ok(options)
+}
+
+/// NX, XX, GT or LT.
+fn expire_condition(option: &str) -> Option<ExpireCondition> {
+    match option.to_ascii_lowercase().as_str() {
+        "nx" => Some(ExpireCondition::Nx),
+        "xx" => Some(ExpireCondition::Xx),
+        "gt" => Some(ExpireCondition::Gt),
+        "lt" => Some(ExpireCondition::Lt),
+        _ => None,
+    }
+}
+
+/// `FIELDS numfields field [field ...]`, which ends every hash field expiry
+/// command. `keyword` is the argument expected to be FIELDS.
+fn hash_fields(args: &mut Args, keyword: &str) -> Result<Vec<Bytes>, CommandError> {
+    if !keyword.eq_ignore_ascii_case("fields") {
+        return Err(CommandError::InvalidArgument(
+            "ERR Mandatory argument FIELDS is missing or not at the right position".into(),
+        ));
+    }
+    let numfields = args.integer::<i64>()?;
+    if numfields <= 0 {
+        return Err(CommandError::InvalidArgument(
+            "ERR Parameter `numFields` should be greater than 0".into(),
+        ));
+    }
+    let mut fields = Vec::new();
+    while let Some(field) = args.next_bytes() {
+        fields.push(field);
+    }
+    if fields.len() as i64 != numfields {
+        return Err(CommandError::InvalidArgument(
+            "ERR The `numfields` parameter must match the number of arguments".into(),
+        ));
+    }
+    Ok(fields)
+}
+
+/// The arguments LMPOP and BLMPOP share: `numkeys key [key ...] LEFT|RIGHT
+/// [COUNT count]`.
+fn lmpop(args: &mut Args) -> Result<Lmpop, CommandError> {
+    let numkeys = args.integer::<i64>()?;
+    if numkeys <= 0 {
+        return Err(CommandError::InvalidArgument(
+            "ERR numkeys should be greater than 0".into(),
+        ));
+    }
+    let mut keys = Vec::new();
+    for _ in 0..numkeys {
+        keys.push(args.bytes()?);
+    }
+    let end = args.end()?;
+    let mut count = 1;
+    if let Some(option) = args.next_string() {
+        if !option.eq_ignore_ascii_case("count") {
+            return Err(CommandError::SyntaxError(args.name.clone()));
+        }
+        count = args.integer::<i64>()?;
+        if count <= 0 {
+            return Err(CommandError::InvalidArgument(
+                "ERR count should be greater than 0".into(),
+            ));
+        }
+    }
+    if !args.is_empty() {
+        return Err(CommandError::SyntaxError(args.name.clone()));
+    }
+    Ok(Lmpop {
+        keys,
+        end,
+        count: count as usize,
+    })
+}
+
+/// The arguments LMOVE and BLMOVE share: `source destination LEFT|RIGHT
+/// LEFT|RIGHT`.
+fn lmove(args: &mut Args) -> Result<Lmove, CommandError> {
+    Ok(Lmove {
+        source: args.bytes()?,
+        destination: args.bytes()?,
+        from: args.end()?,
+        to: args.end()?,
+    })
+}
+
+fn parse_timeout(timeout: &str) -> Result<Option<Duration>, CommandError> {
+    let seconds = timeout
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite())
+        .ok_or_else(|| {
+            CommandError::InvalidArgument("ERR timeout is not a float or out of range".into())
+        })?;
+    if seconds < 0.0 {
+        return Err(CommandError::InvalidArgument(
+            "ERR timeout is negative".into(),
+        ));
+    }
+    if seconds == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(seconds)
+        .map(Some)
+        .map_err(|_| CommandError::InvalidArgument("ERR timeout is out of range".into()))
 }
@@ -1,109 +1,45 @@
-use std::{
-    collections::{HashMap, HashSet},
-    str::{self, Chars, Utf8Error},
-};
+use std::str::{self, Utf8Error};
 
-use bytes::Bytes;
-use std::iter::Peekable;
+use bytes::{BufMut, Bytes, BytesMut};
 
-type IT<'b> = Peekable<Chars<'b>>;
+// Same limits Redis applies to untrusted clients (`proto-max-bulk-len` and the
+// multibulk cap), so a bogus header cannot make us wait for gigabytes of data.
+const MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+const MAX_ARRAY_LEN: i64 = 1024 * 1024;
+// Longest inline command line we are willing to buffer.
+const MAX_INLINE_LEN: usize = 64 * 1024;
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum Token {
-    Asterisk,
-    CRLF,
-    Dollar,
-    Plus,
-    Minus,
-    Colon,
-    Underscore,
-    Comma,
-    PercentSign,
-    BracketOpen,
-    Exclamation,
-    EqualSign,
-    Tilde,
-    GreaterThan,
-    Question,
-    Num(i64),
-    Word(String),
-    NewLine,
-    CarriageReturn,
-}
-
-#[derive(Debug, Clone)]
-pub struct Tokenizer<'b> {
-    it: IT<'b>,
-}
-
-impl<'b> Tokenizer<'b> {
-    pub fn new(s: &'b String) -> Result<Self, Utf8Error> {
-        Ok(Tokenizer {
-            it: s.chars().peekable(),
-        })
-    }
+#[derive(Debug)]
+pub enum RespError {
+    Invalid,
+    UnbalancedQuotes,
+    InlineTooBig,
+    LineTooBig,
+    ExpectedBulk(u8),
 }
 
-impl<'b> Iterator for Tokenizer<'b> {
-    type Item = Result<Token, String>;
-    // *2\r\n\$3\r\nGET\r\n\$3\r\nfoo\r\n
-    fn next(&mut self) -> Option<Self::Item> {
-        while let Some(c) = self.it.next() {
-            match c {
-                '\r' | '\n' => return Some(Ok(Token::CRLF)),
-                ':' => return Some(Ok(Token::Colon)),
-                '_' => return Some(Ok(Token::Underscore)),
-                '-' => {
-                    let t = utf8_token(&mut self.it, c);
-                    if let Ok(num) = t.parse::<i64>() {
-                        return Some(Ok(Token::Num(num)));
-                    } else {
-                        return Some(Ok(Token::Word(t)));
-                    }
-                }
-                '+' => return Some(Ok(Token::Plus)),
-                '*' => return Some(Ok(Token::Asterisk)),
-                '$' => return Some(Ok(Token::Dollar)),
-                ',' => return Some(Ok(Token::Comma)),
-                '%' => return Some(Ok(Token::PercentSign)),
-                '(' => return Some(Ok(Token::BracketOpen)),
-                '!' => return Some(Ok(Token::Exclamation)),
-                '=' => return Some(Ok(Token::EqualSign)),
-                '~' => return Some(Ok(Token::Tilde)),
-                '>' => return Some(Ok(Token::GreaterThan)),
-                '?' => return Some(Ok(Token::Question)),
-                v => {
-                    let t = utf8_token(&mut self.it, v);
-                    if let Ok(num) = t.parse::<i64>() {
-                        return Some(Ok(Token::Num(num)));
-                    } else {
-                        return Some(Ok(Token::Word(t)));
-                    }
-                }
+impl RespError {
+    pub fn message(&self) -> String {
+        match self {
+            Self::Invalid => "ERR Protocol error".to_string(),
+            Self::UnbalancedQuotes => {
+                "ERR Protocol error: unbalanced quotes in request".to_string()
+            }
+            Self::InlineTooBig => "ERR Protocol error: too big inline request".to_string(),
+            Self::LineTooBig => "ERR Protocol error: too big count string".to_string(),
+            Self::ExpectedBulk(got) => {
+                format!("ERR Protocol error: expected '$', got '{}'", *got as char)
             }
         }
-        None
-    }
-}
-
-fn utf8_token(it: &mut IT, c: char) -> String {
-    let mut word = c.to_owned().to_string();
-
-    while let Some(c) = it.next() {
-        if c == '\r' {
-            it.next();
-            //it.next();
-            break;
-        }
-        word.push(c);
     }
-
-    word
 }
 
-#[derive(Debug)]
-pub enum RespError {
-    Invalid,
+/// Protocol version negotiated by a connection through `HELLO`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
 }
 
 impl From<Utf8Error> for RespError {
@@ -120,159 +56,355 @@ pub enum RespData {
     BulkStr(Bytes),
     Array(Vec<RespData>),
     Null,
+    NullArray,
+    // No command replies with a boolean or a bulk error yet, but both are
+    // part of RESP3 and the encoder knows how to write them.
+    #[allow(dead_code)]
     Boolean(bool),
     Double(f64),
     // BigNum(BigInt),
+    #[allow(dead_code)]
     BulkError(Bytes),
     VerbatimStr(Bytes),
-    Map(HashMap<RespData, RespData>),
-    Set(HashSet<RespData>),
+    // Kept as ordered pairs rather than a HashMap: replies must be deterministic
+    // and `RespData` holds an f64, so it cannot be hashed anyway.
+    Map(Vec<(RespData, RespData)>),
+    Set(Vec<RespData>),
 }
 
-// todo:
-// impl PartialEq for RespData {
-//     fn eq(&self, other: &Self) -> bool {}
+impl RespData {
+    /// Decodes the next command sent by a client or a leader.
+    ///
+    /// Commands arrive as flat arrays of bulk strings, and like Redis nothing
+    /// else is accepted after a `*`, so a client cannot nest aggregates.
+    /// Anything that does not start with `*` is an inline command typed by
+    /// hand, e.g. through netcat. Inline commands are split into the same
+    /// array of bulk strings, so callers cannot tell the two apart. Blank
+    /// lines and empty arrays are skipped; their bytes are still included in
+    /// the reported length.
+    pub fn decode_request(buf: &[u8]) -> Result<Option<(RespData, usize)>, RespError> {
+        let mut pos = 0;
+        while pos < buf.len() {
+            if buf[pos] == b'*' {
+                let mut decoder = Decoder { buf, pos: pos + 1 };
+                match decoder.request()? {
+                    Some(RespData::Array(args)) if args.is_empty() => {
+                        pos = decoder.pos;
+                        continue;
+                    }
+                    frame => return Ok(frame.map(|frame| (frame, decoder.pos))),
+                }
+            }
+
+            let rest = &buf[pos..];
+            let Some(end) = rest.iter().position(|&b| b == b'\n') else {
+                if rest.len() > MAX_INLINE_LEN {
+                    return Err(RespError::InlineTooBig);
+                }
+                return Ok(None);
+            };
+            let line = rest[..end].strip_suffix(b"\r").unwrap_or(&rest[..end]);
+            pos += end + 1;
 
-//     fn ne(&self, other: &Self) -> bool {
-//         !self.eq(other)
-//     }
-// }
+            let args = split_inline_args(line)?;
+            if !args.is_empty() {
+                let args = args.into_iter().map(RespData::BulkStr).collect();
+                return Ok(Some((RespData::Array(args), pos)));
+            }
+        }
+        Ok(None)
+    }
 
-impl RespData {
-    pub fn parse(resp_str: &String) -> anyhow::Result<Vec<RespData>, RespError> {
-        let mut result: Vec<RespData> = Vec::new();
-        if let Ok(mut tk) = Tokenizer::new(resp_str) {
-            while let Some(Ok(t)) = tk.next() {
-                match t {
-                    Token::Asterisk => {
-                        // RESP Array
-                        let mut res: Vec<RespData> = Vec::new();
-                        let array_length = if let Some(Ok(Token::Num(array_length))) = tk.next() {
-                            array_length
-                        } else {
-                            break;
-                        };
-                        while let Some(Ok(token)) = tk.next() {
-                            match token {
-                                Token::Dollar => {
-                                    let word_len = if let Some(Ok(Token::Num(word_len))) = tk.next()
-                                    {
-                                        word_len
-                                    } else {
-                                        break;
-                                    };
-                                    let word = match tk.next() {
-                                        Some(Ok(Token::Word(word))) => word,
-                                        Some(Ok(Token::Num(num))) => num.to_string(),
-                                        Some(Ok(Token::Question)) => "?".to_string(),
-                                        Some(Ok(Token::Asterisk)) => "*".to_string(),
-                                        Some(Ok(Token::Dollar)) => "$".to_string(),
-                                        Some(Ok(Token::Minus)) => "-".to_string(),
-                                        Some(Ok(Token::Plus)) => "+".to_string(),
-                                        Some(Ok(_)) => break,
-                                        Some(Err(_)) => break,
-                                        None => break,
-                                    };
+    pub fn bulk(data: impl AsRef<[u8]>) -> RespData {
+        RespData::BulkStr(Bytes::copy_from_slice(data.as_ref()))
+    }
 
-                                    if word.len() == word_len as usize {
-                                        if let Ok(n) = word.parse::<i64>() {
-                                            res.push(RespData::Integer(n));
-                                        } else {
-                                            res.push(RespData::String(word));
-                                        }
-                                    } else {
-                                        return Err(RespError::Invalid);
-                                    }
+    pub fn ok() -> RespData {
+        RespData::String("OK".to_string())
+    }
 
-                                    if res.len() == array_length as usize {
-                                        result.push(RespData::Array(res));
-                                        break;
-                                    }
-                                }
-                                Token::CRLF => {}
-                                _ => todo!(),
-                            }
-                        }
-                    }
-                    Token::CRLF => {}
-                    Token::Dollar => {}
-                    Token::Plus => {
-                        let mut res: Vec<RespData> = Vec::new();
-                        while let Some(Ok(token)) = tk.next() {
-                            match token {
-                                Token::Word(word) => {
-                                    res.push(RespData::String(word));
-                                }
-                                Token::CRLF => break,
-                                _ => {}
-                            }
-                        }
-                        result.push(RespData::Array(res));
-                    }
-                    Token::Minus => todo!(),
-                    Token::Colon => todo!(),
-                    Token::Underscore => todo!(),
-                    Token::Comma => todo!(),
-                    Token::PercentSign => todo!(),
-                    Token::BracketOpen => todo!(),
-                    Token::Exclamation => todo!(),
-                    Token::EqualSign => todo!(),
-                    Token::Tilde => todo!(),
-                    Token::GreaterThan => todo!(),
-                    Token::Question => todo!(),
-                    Token::Num(num) => {
-                        result.push(RespData::Integer(num));
-                    }
-                    Token::Word(_) => {}
-                    Token::NewLine => todo!(),
-                    Token::CarriageReturn => todo!(),
+    /// Builds a command frame, as sent to replicas, out of its arguments.
+    pub fn command(args: &[&str]) -> RespData {
+        RespData::Array(args.iter().map(RespData::bulk).collect())
+    }
+
+    /// Appends the wire representation of the value to `dst`. RESP2 has no
+    /// maps, sets, doubles, booleans or verbatim strings, so for RESP2 clients
+    /// those are sent as the RESP2 types Redis itself falls back to.
+    pub fn encode(&self, dst: &mut BytesMut, protocol: Protocol) {
+        let resp3 = protocol == Protocol::Resp3;
+        match self {
+            RespData::String(s) => encode_simple(dst, b'+', s.as_bytes()),
+            RespData::ErrorStr(s) => encode_simple(dst, b'-', s.as_bytes()),
+            RespData::Integer(n) => encode_line(dst, b':', n.to_string().as_bytes()),
+            RespData::BulkStr(b) => encode_blob(dst, b'$', b),
+            RespData::Array(items) => {
+                encode_line(dst, b'*', items.len().to_string().as_bytes());
+                for item in items {
+                    item.encode(dst, protocol);
+                }
+            }
+            RespData::Null if resp3 => dst.put_slice(b"_\r\n"),
+            RespData::Null => dst.put_slice(b"$-1\r\n"),
+            RespData::NullArray if resp3 => dst.put_slice(b"_\r\n"),
+            RespData::NullArray => dst.put_slice(b"*-1\r\n"),
+            RespData::Boolean(b) if resp3 => encode_line(dst, b'#', if *b { b"t" } else { b"f" }),
+            RespData::Boolean(b) => encode_line(dst, b':', if *b { b"1" } else { b"0" }),
+            RespData::Double(d) if resp3 => encode_line(dst, b',', format_double(*d).as_bytes()),
+            RespData::Double(d) => encode_blob(dst, b'$', format_double(*d).as_bytes()),
+            RespData::BulkError(b) if resp3 => encode_blob(dst, b'!', b),
+            RespData::BulkError(b) => encode_simple(dst, b'-', b),
+            RespData::VerbatimStr(b) if resp3 => {
+                // Verbatim strings carry a three letter format prefix.
+                let mut payload = BytesMut::with_capacity(b.len() + 4);
+                payload.put_slice(b"txt:");
+                payload.put_slice(b);
+                encode_blob(dst, b'=', &payload);
+            }
+            RespData::VerbatimStr(b) => encode_blob(dst, b'$', b),
+            RespData::Map(pairs) => {
+                // RESP2 clients get the pairs flattened into one array.
+                let len = if resp3 { pairs.len() } else { pairs.len() * 2 };
+                encode_line(
+                    dst,
+                    if resp3 { b'%' } else { b'*' },
+                    len.to_string().as_bytes(),
+                );
+                for (k, v) in pairs {
+                    k.encode(dst, protocol);
+                    v.encode(dst, protocol);
+                }
+            }
+            RespData::Set(items) => {
+                encode_line(
+                    dst,
+                    if resp3 { b'~' } else { b'*' },
+                    items.len().to_string().as_bytes(),
+                );
+                for item in items {
+                    item.encode(dst, protocol);
                 }
             }
         }
-        Ok(result)
+    }
+
+    /// Wire bytes for frames that look the same under every protocol version,
+    /// such as the commands sent to replicas.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut dst = BytesMut::new();
+        self.encode(&mut dst, Protocol::Resp2);
+        dst.to_vec()
+    }
+}
+
+fn encode_line(dst: &mut BytesMut, prefix: u8, line: &[u8]) {
+    dst.reserve(line.len() + 3);
+    dst.put_u8(prefix);
+    dst.put_slice(line);
+    dst.put_slice(b"\r\n");
+}
+
+/// Like `encode_line` for text that may hold line breaks, which would end
+/// the line early. Redis turns them into spaces in errors, and so does this
+/// in simple strings too.
+fn encode_simple(dst: &mut BytesMut, prefix: u8, line: &[u8]) {
+    let line: Vec<u8> = line
+        .iter()
+        .map(|&b| if b == b'\r' || b == b'\n' { b' ' } else { b })
+        .collect();
+    encode_line(dst, prefix, &line);
+}
+
+fn encode_blob(dst: &mut BytesMut, prefix: u8, blob: &[u8]) {
+    encode_line(dst, prefix, blob.len().to_string().as_bytes());
+    dst.reserve(blob.len() + 2);
+    dst.put_slice(blob);
+    dst.put_slice(b"\r\n");
+}
+
+/// Formats a double the way Redis prints scores and RESP3 doubles: the
+/// shortest representation that round-trips, `inf`/`-inf`/`nan` for the
+/// special values and an exponent only for very large or small magnitudes.
+pub fn format_double(d: f64) -> String {
+    if d.is_nan() {
+        return "nan".to_string();
+    }
+    if d.is_infinite() {
+        return if d > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+
+    let magnitude = d.abs();
+    if magnitude != 0.0 && !(1e-5..1e17).contains(&magnitude) {
+        let formatted = format!("{:e}", d);
+        match formatted.split_once('e') {
+            Some((mantissa, exp)) if !exp.starts_with('-') => format!("{}e+{}", mantissa, exp),
+            _ => formatted,
+        }
+    } else {
+        format!("{}", d)
+    }
+}
+
+/// Length-driven RESP reader over a borrowed buffer. `pos` only ever moves
+/// forward and is the number of bytes consumed once a frame is complete.
+struct Decoder<'b> {
+    buf: &'b [u8],
+    pos: usize,
+}
+
+impl<'b> Decoder<'b> {
+    /// Returns the bytes up to the next CRLF and moves past it. A line that
+    /// grows past the inline limit without a CRLF is rejected rather than
+    /// buffered forever.
+    fn line(&mut self) -> Result<Option<&'b [u8]>, RespError> {
+        let rest = &self.buf[self.pos..];
+        let Some(end) = rest.windows(2).position(|w| w == b"\r\n") else {
+            if rest.len() > MAX_INLINE_LEN {
+                return Err(RespError::LineTooBig);
+            }
+            return Ok(None);
+        };
+        self.pos += end + 2;
+        Ok(Some(&rest[..end]))
+    }
+
+    /// Reads the body of a multibulk request, after its `*`: a count followed
+    /// by exactly that many bulk strings. A count of zero or less, which Redis
+    /// ignores, gives an empty array.
+    fn request(&mut self) -> Result<Option<RespData>, RespError> {
+        let Some(line) = self.line()? else {
+            return Ok(None);
+        };
+        let len = parse_int(line)?;
+        if len > MAX_ARRAY_LEN {
+            return Err(RespError::Invalid);
+        }
+
+        let mut args = Vec::new();
+        for _ in 0..len {
+            match self.buf.get(self.pos) {
+                Some(b'$') => self.pos += 1,
+                Some(&other) => return Err(RespError::ExpectedBulk(other)),
+                None => return Ok(None),
+            }
+            match self.blob()? {
+                Some(b) => args.push(RespData::BulkStr(Bytes::copy_from_slice(b))),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(RespData::Array(args)))
+    }
+
+    /// Reads the `<len>\r\n<payload>\r\n` body of a bulk string.
+    fn blob(&mut self) -> Result<Option<&'b [u8]>, RespError> {
+        let Some(line) = self.line()? else {
+            return Ok(None);
+        };
+        let len = parse_int(line)?;
+        if !(0..=MAX_BULK_LEN).contains(&len) {
+            return Err(RespError::Invalid);
+        }
+
+        let len = len as usize;
+        if self.buf.len() - self.pos < len + 2 {
+            return Ok(None);
+        }
+        let data = &self.buf[self.pos..self.pos + len];
+        if &self.buf[self.pos + len..self.pos + len + 2] != b"\r\n" {
+            return Err(RespError::Invalid);
+        }
+        self.pos += len + 2;
+        Ok(Some(data))
     }
 }
 
-pub fn parse_handshake_response(resp_str: &String) -> Vec<Vec<String>> {
-    let mut result: Vec<Vec<String>> = Vec::new();
-    if let Ok(mut tk) = Tokenizer::new(resp_str) {
-        while let Some(Ok(t)) = tk.next() {
-            match t {
-                Token::Asterisk => {}
-                Token::CRLF => {}
-                Token::Dollar => todo!(),
-                Token::Plus => {
-                    let mut res: Vec<String> = Vec::new();
-                    while let Some(Ok(token)) = tk.next() {
-                        match token {
-                            Token::Word(word) => {
-                                res.push(word);
-                            }
-                            Token::CRLF => break,
-                            _ => {}
+/// Splits an inline command line into arguments following the quoting rules
+/// of `redis-cli` and Redis' own `sdssplitargs`: double quotes understand
+/// `\n`, `\r`, `\t`, `\b`, `\a` and `\xHH` escapes, single quotes only `\'`,
+/// and a closing quote must be followed by whitespace or the end of line.
+fn split_inline_args(line: &[u8]) -> Result<Vec<Bytes>, RespError> {
+    let mut args = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(args);
+        }
+
+        let mut arg: Vec<u8> = Vec::new();
+        let mut in_double = false;
+        let mut in_single = false;
+        loop {
+            let Some(&c) = line.get(i) else {
+                if in_double || in_single {
+                    return Err(RespError::UnbalancedQuotes);
+                }
+                break;
+            };
+
+            if in_double {
+                match c {
+                    b'\\'
+                        if i + 3 < line.len()
+                            && line[i + 1] == b'x'
+                            && line[i + 2].is_ascii_hexdigit()
+                            && line[i + 3].is_ascii_hexdigit() =>
+                    {
+                        let hex = str::from_utf8(&line[i + 2..i + 4])?;
+                        arg.push(u8::from_str_radix(hex, 16).map_err(|_| RespError::Invalid)?);
+                        i += 3;
+                    }
+                    b'\\' if i + 1 < line.len() => {
+                        i += 1;
+                        arg.push(match line[i] {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            other => other,
+                        });
+                    }
+                    b'"' => {
+                        if line.get(i + 1).is_some_and(|n| !n.is_ascii_whitespace()) {
+                            return Err(RespError::UnbalancedQuotes);
+                        }
+                        in_double = false;
+                    }
+                    _ => arg.push(c),
+                }
+            } else if in_single {
+                match c {
+                    b'\\' if line.get(i + 1) == Some(&b'\'') => {
+                        i += 1;
+                        arg.push(b'\'');
+                    }
+                    b'\'' => {
+                        if line.get(i + 1).is_some_and(|n| !n.is_ascii_whitespace()) {
+                            return Err(RespError::UnbalancedQuotes);
                         }
+                        in_single = false;
                     }
-                    result.push(res);
+                    _ => arg.push(c),
+                }
+            } else {
+                match c {
+                    c if c.is_ascii_whitespace() => break,
+                    b'"' => in_double = true,
+                    b'\'' => in_single = true,
+                    _ => arg.push(c),
                 }
-                Token::Minus => todo!(),
-                Token::Colon => todo!(),
-                Token::Underscore => todo!(),
-                Token::Comma => todo!(),
-                Token::PercentSign => todo!(),
-                Token::BracketOpen => todo!(),
-                Token::Exclamation => todo!(),
-                Token::EqualSign => todo!(),
-                Token::Tilde => todo!(),
-                Token::GreaterThan => todo!(),
-                Token::Question => todo!(),
-                Token::Num(_) => todo!(),
-                Token::Word(_) => todo!(),
-                Token::NewLine => todo!(),
-                Token::CarriageReturn => todo!(),
             }
+            i += 1;
         }
-    } else {
-        println!("Creating tokenizer failed");
+        args.push(Bytes::from(arg));
     }
-    result
+}
+
+fn parse_int(line: &[u8]) -> Result<i64, RespError> {
+    str::from_utf8(line)?
+        .parse::<i64>()
+        .map_err(|_| RespError::Invalid)
 }
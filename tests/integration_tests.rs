@@ -1,10 +1,57 @@
 use std::io::{BufRead, BufReader, Read, Write};
 use std::net::TcpStream;
+use std::sync::Once;
+use std::time::Duration;
+
+use redis_starter_rust::start_server;
+
+const SERVER_ADDR: &str = "127.0.0.1:6379";
+
+static SERVER: Once = Once::new();
+
+// Boot a leader in the background unless one is already listening, so the
+// suite also runs without a separately started server.
+fn ensure_server() {
+    SERVER.call_once(|| {
+        if TcpStream::connect(SERVER_ADDR).is_ok() {
+            return;
+        }
+        std::thread::spawn(|| {
+            tokio::runtime::Runtime::new()
+                .expect("Failed to build runtime")
+                .block_on(start_server(
+                    Some("127.0.0.1".to_string()),
+                    Some(6379),
+                    None,
+                    None,
+                    None,
+                    None,
+                ))
+                .expect("Server failed");
+        });
+        for _ in 0..50 {
+            if TcpStream::connect(SERVER_ADDR).is_ok() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        panic!("Server did not start");
+    });
+}
+
+fn connect() -> TcpStream {
+    ensure_server();
+    let stream = TcpStream::connect(SERVER_ADDR).expect("Failed to connect");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
 
 // Import functions from lib.rs
 fn start_client(message: &[u8]) -> std::io::Result<String> {
-    let mut stream = TcpStream::connect("127.0.0.1:6379")?;
-    stream.write(message)?;
+    let mut stream = connect();
+    stream.write_all(message)?;
 
     let mut buffer = [0; 512];
     let n = stream.read(&mut buffer)?;
@@ -12,6 +59,64 @@ fn start_client(message: &[u8]) -> std::io::Result<String> {
     Ok(String::from_utf8_lossy(&buffer[..n]).to_string())
 }
 
+// Reads until exactly `expected.len()` bytes arrived and compares them.
+fn assert_reply(stream: &mut TcpStream, expected: &[u8]) {
+    let mut buffer = vec![0; expected.len()];
+    stream
+        .read_exact(&mut buffer)
+        .expect("Failed to read reply");
+    assert_eq!(
+        String::from_utf8_lossy(&buffer),
+        String::from_utf8_lossy(expected)
+    );
+}
+
+// Reads one complete reply frame, whatever its type, and returns it verbatim.
+fn read_frame(reader: &mut impl BufRead) -> String {
+    let mut frame = String::new();
+    reader.read_line(&mut frame).expect("Failed to read reply");
+    let len = frame[1..].trim_end().parse::<i64>().unwrap_or(0);
+    let nested = match frame.as_bytes()[0] {
+        b'$' | b'=' | b'!' if len >= 0 => {
+            let mut payload = vec![0; len as usize + 2];
+            reader
+                .read_exact(&mut payload)
+                .expect("Failed to read reply");
+            frame.push_str(&String::from_utf8_lossy(&payload));
+            0
+        }
+        b'*' | b'~' | b'>' => len,
+        b'%' => len * 2,
+        _ => 0,
+    };
+    for _ in 0..nested {
+        frame.push_str(&read_frame(reader));
+    }
+    frame
+}
+
+// Sends a command made of `args` without waiting for the reply.
+fn send(stream: &mut BufReader<TcpStream>, args: &[&str]) {
+    let mut message = format!("*{}\r\n", args.len());
+    for arg in args {
+        message.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    stream
+        .get_mut()
+        .write_all(message.as_bytes())
+        .expect("Failed to send command");
+}
+
+// Sends a command made of `args` and returns the complete reply.
+fn command(stream: &mut BufReader<TcpStream>, args: &[&str]) -> String {
+    send(stream, args);
+    read_frame(stream)
+}
+
+fn client() -> BufReader<TcpStream> {
+    BufReader::new(connect())
+}
+
 #[test]
 fn test_redis_ping() {
     let message = b"*1\r\n$4\r\nPING\r\n";
@@ -23,7 +128,7 @@ fn test_redis_ping() {
 #[test]
 fn test_redis_echo() {
     let message = b"*2\r\n$4\r\nECHO\r\n$5\r\nHELLO\r\n";
-    let result = b"+HELLO\r\n";
+    let result = b"$5\r\nHELLO\r\n";
     let response = start_client(message).expect("Failed to send/receive message");
     assert_eq!(response, String::from_utf8_lossy(result).to_string());
 }
@@ -32,8 +137,8 @@ fn test_redis_echo() {
 fn test_redis_echo_error() {
     // Not working
     let message = b"*2\r\n$4\r\nECHO\r\n";
-    let result = b"+HELLO\r\n";
-    let stream = TcpStream::connect("127.0.0.1:6379").unwrap();
+    let _result = b"+HELLO\r\n";
+    let stream = connect();
     let mut reader = BufReader::new(stream.try_clone().unwrap());
 
     let mut stream = stream;
@@ -55,3 +160,1738 @@ fn test_redis_echo_error() {
 
     // assert_eq!(response, String::from_utf8_lossy(result).to_string());
 }
+
+#[test]
+fn test_echo_and_ping_reply_with_bulk_strings() {
+    let mut client = client();
+    assert_eq!(
+        command(&mut client, &["ECHO", "a\r\nb"]),
+        "$4\r\na\r\nb\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["PING", "a\r\nb"]),
+        "$4\r\na\r\nb\r\n"
+    );
+    assert_eq!(command(&mut client, &["PING"]), "+PONG\r\n");
+}
+
+#[test]
+fn test_pipelined_commands() {
+    let mut stream = connect();
+    stream
+        .write_all(b"*1\r\n$4\r\nPING\r\n*2\r\n$4\r\nECHO\r\n$3\r\nhey\r\n*1\r\n$4\r\nPING\r\n")
+        .unwrap();
+    assert_reply(&mut stream, b"+PONG\r\n$3\r\nhey\r\n+PONG\r\n");
+}
+
+#[test]
+fn test_frame_split_across_writes() {
+    let mut stream = connect();
+    stream.write_all(b"*3\r\n$3\r\nSET\r\n$5\r\nsp").unwrap();
+    stream.flush().unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    stream.write_all(b"lit\r\n$2\r\nok\r\n").unwrap();
+    assert_reply(&mut stream, b"+OK\r\n");
+
+    stream
+        .write_all(b"*2\r\n$3\r\nGET\r\n$5\r\nsplit\r\n")
+        .unwrap();
+    assert_reply(&mut stream, b"$2\r\nok\r\n");
+}
+
+#[test]
+fn test_value_with_embedded_crlf() {
+    let mut stream = connect();
+    stream
+        .write_all(b"*3\r\n$3\r\nSET\r\n$4\r\ncrlf\r\n$8\r\na\r\nb\r\n$c\r\n")
+        .unwrap();
+    assert_reply(&mut stream, b"+OK\r\n");
+
+    stream
+        .write_all(b"*2\r\n$3\r\nGET\r\n$4\r\ncrlf\r\n")
+        .unwrap();
+    assert_reply(&mut stream, b"$8\r\na\r\nb\r\n$c\r\n");
+}
+
+#[test]
+fn test_value_larger_than_read_chunk() {
+    let mut stream = connect();
+    let value = "x".repeat(40 * 1024);
+    let message = format!(
+        "*3\r\n$3\r\nSET\r\n$5\r\nlarge\r\n${}\r\n{}\r\n",
+        value.len(),
+        value
+    );
+    stream.write_all(message.as_bytes()).unwrap();
+    assert_reply(&mut stream, b"+OK\r\n");
+
+    stream
+        .write_all(b"*2\r\n$3\r\nGET\r\n$5\r\nlarge\r\n")
+        .unwrap();
+    assert_reply(
+        &mut stream,
+        format!("${}\r\n{}\r\n", value.len(), value).as_bytes(),
+    );
+}
+
+#[test]
+fn test_typed_replies() {
+    let mut stream = connect();
+    stream
+        .write_all(b"*3\r\n$6\r\nCONFIG\r\n$3\r\nGET\r\n$7\r\nunknown\r\n")
+        .unwrap();
+    assert_reply(&mut stream, b"*0\r\n");
+
+    stream
+        .write_all(b"*5\r\n$4\r\nXADD\r\n$7\r\nreplies\r\n$3\r\n1-1\r\n$1\r\nf\r\n$1\r\nv\r\n")
+        .unwrap();
+    assert_reply(&mut stream, b"$3\r\n1-1\r\n");
+
+    stream
+        .write_all(b"*4\r\n$6\r\nXRANGE\r\n$7\r\nreplies\r\n$1\r\n-\r\n$1\r\n+\r\n")
+        .unwrap();
+    assert_reply(
+        &mut stream,
+        b"*1\r\n*2\r\n$3\r\n1-1\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n",
+    );
+}
+
+#[test]
+fn test_hello_negotiates_resp3() {
+    let mut client = client();
+    let reply = command(&mut client, &["HELLO", "3", "SETNAME", "worker"]);
+    assert!(reply.starts_with("%7\r\n$6\r\nserver\r\n$5\r\nredis\r\n"));
+    assert!(reply.contains("$5\r\nproto\r\n:3\r\n"));
+
+    assert_eq!(
+        command(&mut client, &["CONFIG", "GET", "unknown"]),
+        "%0\r\n"
+    );
+    assert_eq!(command(&mut client, &["GET", "hello-missing"]), "_\r\n");
+    assert!(command(&mut client, &["INFO", "replication"])
+        .starts_with("%1\r\n$11\r\nreplication\r\n%3\r\n$4\r\nrole\r\n$6\r\nmaster\r\n"));
+
+    let reply = command(&mut client, &["HELLO", "2"]);
+    assert!(reply.starts_with("*14\r\n"));
+    assert_eq!(command(&mut client, &["GET", "hello-missing"]), "$-1\r\n");
+    assert!(command(&mut client, &["INFO"]).contains("\r\nrole:master\r\n"));
+}
+
+#[test]
+fn test_hello_rejects_unknown_protocol() {
+    let mut client = client();
+    assert_eq!(
+        command(&mut client, &["HELLO", "4"]),
+        "-NOPROTO unsupported protocol version\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HELLO", "3", "AUTH", "someone", "secret"]),
+        "-WRONGPASS invalid username-password pair or user is disabled.\r\n"
+    ); // A rejected name leaves the protocol unchanged too.
+    assert_eq!(
+        command(&mut client, &["HELLO", "3", "SETNAME", "bad name"]),
+        "-ERR Client names cannot contain spaces, newlines or special characters.\r\n"
+    );
+    assert_eq!(command(&mut client, &["GET", "hello-missing"]), "$-1\r\n");
+}
+
+#[test]
+fn test_inline_commands() {
+    let mut stream = connect();
+    stream.write_all(b"PING\r\n\r\nECHO hi\n").unwrap();
+    assert_reply(&mut stream, b"+PONG\r\n$2\r\nhi\r\n");
+
+    stream
+        .write_all(b"SET inline \"a b\\r\\n\\x41\" \r\nGET inline\r\nGET 'inline'\r\n")
+        .unwrap();
+    assert_reply(&mut stream, b"+OK\r\n$6\r\na b\r\nA\r\n$6\r\na b\r\nA\r\n");
+}
+
+#[test]
+fn test_inline_unbalanced_quotes() {
+    let mut stream = connect();
+    stream.write_all(b"SET inline \"oops\r\n").unwrap();
+    assert_reply(
+        &mut stream,
+        b"-ERR Protocol error: unbalanced quotes in request\r\n",
+    );
+}
+
+#[test]
+fn test_empty_arrays_are_ignored() {
+    let mut stream = connect();
+    stream.write_all(b"*-1\r\n*0\r\nPING\r\n").unwrap();
+    assert_reply(&mut stream, b"+PONG\r\n");
+    stream.write_all(b"*1\r\n$4\r\nPING\r\n").unwrap();
+    assert_reply(&mut stream, b"+PONG\r\n");
+}
+
+#[test]
+fn test_requests_must_be_flat_arrays_of_bulk_strings() {
+    let mut stream = connect();
+    stream.write_all(&b"*1\r\n".repeat(200_000)).unwrap();
+    assert_reply(
+        &mut stream,
+        b"-ERR Protocol error: expected '$', got '*'\r\n",
+    );
+
+    let mut stream = connect();
+    stream.write_all(b"*2\r\n$4\r\nECHO\r\n:1\r\n").unwrap();
+    assert_reply(
+        &mut stream,
+        b"-ERR Protocol error: expected '$', got ':'\r\n",
+    );
+
+    // A header that never ends is refused instead of buffered.
+    let mut stream = connect();
+    let mut header = b"*1\r\n$".to_vec();
+    header.extend(std::iter::repeat_n(b'1', 70 * 1024));
+    stream.write_all(&header).unwrap();
+    assert_reply(
+        &mut stream,
+        b"-ERR Protocol error: too big count string\r\n",
+    );
+
+    // The server is still up for everyone else.
+    assert_eq!(command(&mut client(), &["PING"]), "+PONG\r\n");
+}
+
+#[test]
+fn test_wrong_type_errors() {
+    let mut client = client();
+    let wrongtype = "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+    assert_eq!(
+        command(&mut client, &["XADD", "typed-stream", "1-1", "f", "v"]),
+        "$3\r\n1-1\r\n"
+    );
+    assert_eq!(command(&mut client, &["GET", "typed-stream"]), wrongtype);
+    assert_eq!(command(&mut client, &["INCR", "typed-stream"]), wrongtype);
+    assert_eq!(
+        command(&mut client, &["TYPE", "typed-stream"]),
+        "+stream\r\n"
+    );
+
+    assert_eq!(
+        command(&mut client, &["SET", "typed-string", "1"]),
+        "+OK\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["XADD", "typed-string", "*", "f", "v"]),
+        wrongtype
+    );
+    assert_eq!(
+        command(&mut client, &["TYPE", "typed-string"]),
+        "+string\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["TYPE", "typed-missing"]),
+        "+none\r\n"
+    );
+
+    // SET replaces a value of any type.
+    assert_eq!(
+        command(&mut client, &["SET", "typed-stream", "x"]),
+        "+OK\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["GET", "typed-stream"]),
+        "$1\r\nx\r\n"
+    );
+}
+
+#[test]
+fn test_binary_safe_keys() {
+    let mut stream = connect();
+    stream
+        .write_all(b"*3\r\n$3\r\nSET\r\n$4\r\nk\x00\xff\n\r\n$1\r\nv\r\n")
+        .unwrap();
+    assert_reply(&mut stream, b"+OK\r\n");
+    stream
+        .write_all(b"*2\r\n$3\r\nGET\r\n$4\r\nk\x00\xff\n\r\n")
+        .unwrap();
+    assert_reply(&mut stream, b"$1\r\nv\r\n");
+    stream
+        .write_all(b"*2\r\n$3\r\nGET\r\n$4\r\nk\x00\xfe\n\r\n")
+        .unwrap();
+    assert_reply(&mut stream, b"$-1\r\n");
+}
+
+#[test]
+fn test_stream_ids_are_ordered_numerically() {
+    let mut client = client();
+    for id in ["9-1", "10-1", "10-2"] {
+        assert_eq!(
+            command(&mut client, &["XADD", "ordered-stream", id, "f", "v"]),
+            format!("${}\r\n{}\r\n", id.len(), id)
+        );
+    }
+    assert_eq!(
+        command(&mut client, &["XADD", "ordered-stream", "10-2", "f", "v"]),
+        "-ERR The ID specified in XADD is equal or smaller than the target stream top item\r\n"
+    );
+
+    let reply = command(&mut client, &["XRANGE", "ordered-stream", "10", "+"]);
+    assert!(reply.starts_with("*2\r\n*2\r\n$4\r\n10-1\r\n"));
+    assert_eq!(
+        command(&mut client, &["XRANGE", "ordered-stream", "11", "+"]),
+        "*0\r\n"
+    );
+}
+
+#[test]
+fn test_list_push_pop_and_range() {
+    let mut client = client();
+    assert_eq!(
+        command(&mut client, &["RPUSH", "jobs", "a", "b", "c"]),
+        ":3\r\n"
+    );
+    assert_eq!(command(&mut client, &["LPUSH", "jobs", "z"]), ":4\r\n");
+    assert_eq!(command(&mut client, &["LLEN", "jobs"]), ":4\r\n");
+    assert_eq!(
+        command(&mut client, &["LRANGE", "jobs", "0", "-1"]),
+        "*4\r\n$1\r\nz\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LRANGE", "jobs", "-2", "100"]),
+        "*2\r\n$1\r\nb\r\n$1\r\nc\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LRANGE", "jobs", "5", "10"]),
+        "*0\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LINDEX", "jobs", "-1"]),
+        "$1\r\nc\r\n"
+    );
+    assert_eq!(command(&mut client, &["LINDEX", "jobs", "9"]), "$-1\r\n");
+
+    assert_eq!(command(&mut client, &["LPOP", "jobs"]), "$1\r\nz\r\n");
+    assert_eq!(
+        command(&mut client, &["RPOP", "jobs", "2"]),
+        "*2\r\n$1\r\nc\r\n$1\r\nb\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LPOP", "jobs", "-1"]),
+        "-ERR value is out of range, must be positive\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LPOP", "jobs", "5"]),
+        "*1\r\n$1\r\na\r\n"
+    );
+
+    // The emptied list is gone.
+    assert_eq!(command(&mut client, &["TYPE", "jobs"]), "+none\r\n");
+    assert_eq!(command(&mut client, &["LPOP", "jobs"]), "$-1\r\n");
+    assert_eq!(command(&mut client, &["LPOP", "jobs", "1"]), "*-1\r\n");
+    assert_eq!(command(&mut client, &["LPUSHX", "jobs", "a"]), ":0\r\n");
+    assert_eq!(
+        command(&mut client, &["LRANGE", "jobs", "0", "-1"]),
+        "*0\r\n"
+    );
+}
+
+#[test]
+fn test_list_editing_commands() {
+    let mut client = client();
+    command(&mut client, &["RPUSH", "edit", "a", "b", "a", "c", "a"]);
+    assert_eq!(command(&mut client, &["LREM", "edit", "-2", "a"]), ":2\r\n");
+    assert_eq!(
+        command(&mut client, &["LRANGE", "edit", "0", "-1"]),
+        "*3\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LINSERT", "edit", "BEFORE", "c", "x"]),
+        ":4\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LINSERT", "edit", "AFTER", "missing", "x"]),
+        ":-1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LSET", "edit", "-1", "d"]),
+        "+OK\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LSET", "edit", "10", "d"]),
+        "-ERR index out of range\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LSET", "edit-missing", "0", "d"]),
+        "-ERR no such key\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LTRIM", "edit", "1", "-2"]),
+        "+OK\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LRANGE", "edit", "0", "-1"]),
+        "*2\r\n$1\r\nb\r\n$1\r\nx\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LTRIM", "edit", "5", "10"]),
+        "+OK\r\n"
+    );
+    assert_eq!(command(&mut client, &["TYPE", "edit"]), "+none\r\n");
+    assert_eq!(command(&mut client, &["LLEN", "edit"]), ":0\r\n");
+}
+
+#[test]
+fn test_list_lpos() {
+    let mut client = client();
+    command(
+        &mut client,
+        &["RPUSH", "pos", "a", "b", "c", "1", "2", "3", "c", "c"],
+    );
+    assert_eq!(command(&mut client, &["LPOS", "pos", "c"]), ":2\r\n");
+    assert_eq!(
+        command(&mut client, &["LPOS", "pos", "c", "RANK", "2"]),
+        ":6\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LPOS", "pos", "c", "RANK", "-1"]),
+        ":7\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LPOS", "pos", "c", "COUNT", "0"]),
+        "*3\r\n:2\r\n:6\r\n:7\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["LPOS", "pos", "c", "RANK", "-1", "COUNT", "2"]
+        ),
+        "*2\r\n:7\r\n:6\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["LPOS", "pos", "c", "COUNT", "0", "MAXLEN", "3"]
+        ),
+        "*1\r\n:2\r\n"
+    );
+    assert_eq!(command(&mut client, &["LPOS", "pos", "z"]), "$-1\r\n");
+    assert!(command(&mut client, &["LPOS", "pos", "c", "RANK", "0"]).starts_with("-ERR RANK"));
+}
+
+#[test]
+fn test_list_move_and_mpop() {
+    let mut client = client();
+    command(&mut client, &["RPUSH", "src", "1", "2", "3"]);
+    assert_eq!(
+        command(&mut client, &["LMOVE", "src", "dst", "LEFT", "RIGHT"]),
+        "$1\r\n1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["RPOPLPUSH", "src", "dst"]),
+        "$1\r\n3\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LRANGE", "dst", "0", "-1"]),
+        "*2\r\n$1\r\n3\r\n$1\r\n1\r\n"
+    );
+    // Rotating a list onto itself.
+    assert_eq!(
+        command(&mut client, &["LMOVE", "dst", "dst", "LEFT", "RIGHT"]),
+        "$1\r\n3\r\n"
+    );
+    // A one-element list moved onto itself keeps its expiry.
+    command(&mut client, &["RPUSH", "move-single", "a"]);
+    command(&mut client, &["EXPIRE", "move-single", "100"]);
+    assert_eq!(
+        command(
+            &mut client,
+            &["LMOVE", "move-single", "move-single", "LEFT", "RIGHT"]
+        ),
+        "$1\r\na\r\n"
+    );
+    assert_eq!(command(&mut client, &["TTL", "move-single"]), ":100\r\n");
+    assert_eq!(
+        command(
+            &mut client,
+            &["LMOVE", "move-missing", "dst", "LEFT", "RIGHT"]
+        ),
+        "$-1\r\n"
+    );
+    command(&mut client, &["SET", "move-string", "x"]);
+    assert_eq!(
+        command(
+            &mut client,
+            &["LMOVE", "src", "move-string", "LEFT", "RIGHT"]
+        ),
+        "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+    );
+    assert_eq!(command(&mut client, &["LLEN", "src"]), ":1\r\n");
+
+    assert_eq!(
+        command(
+            &mut client,
+            &["LMPOP", "2", "mpop-missing", "dst", "RIGHT", "COUNT", "5"]
+        ),
+        "*2\r\n$3\r\ndst\r\n*2\r\n$1\r\n3\r\n$1\r\n1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LMPOP", "1", "mpop-missing", "LEFT"]),
+        "*-1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["LMPOP", "0", "LEFT"]),
+        "-ERR numkeys should be greater than 0\r\n"
+    );
+}
+
+#[test]
+fn test_blocking_pops_serve_clients_in_order() {
+    let mut first = client();
+    let mut second = client();
+    let mut pusher = client();
+    send(&mut first, &["BLPOP", "bqueue", "0"]);
+    std::thread::sleep(Duration::from_millis(100));
+    send(&mut second, &["BLPOP", "bqueue", "other", "0"]);
+    std::thread::sleep(Duration::from_millis(100));
+
+    assert_eq!(
+        command(&mut pusher, &["RPUSH", "bqueue", "a", "b", "c"]),
+        ":3\r\n"
+    );
+    assert_eq!(read_frame(&mut first), "*2\r\n$6\r\nbqueue\r\n$1\r\na\r\n");
+    assert_eq!(read_frame(&mut second), "*2\r\n$6\r\nbqueue\r\n$1\r\nb\r\n");
+    assert_eq!(
+        command(&mut pusher, &["LRANGE", "bqueue", "0", "-1"]),
+        "*1\r\n$1\r\nc\r\n"
+    );
+
+    // A served BLMOVE pushes to its destination, which serves the next one.
+    send(
+        &mut first,
+        &["BLMOVE", "bchain", "bchain-out", "LEFT", "LEFT", "0"],
+    );
+    std::thread::sleep(Duration::from_millis(100));
+    send(
+        &mut second,
+        &["BLMPOP", "0", "1", "bchain-out", "LEFT", "COUNT", "2"],
+    );
+    std::thread::sleep(Duration::from_millis(100));
+    command(&mut pusher, &["LPUSH", "bchain", "x"]);
+    assert_eq!(read_frame(&mut first), "$1\r\nx\r\n");
+    assert_eq!(
+        read_frame(&mut second),
+        "*2\r\n$10\r\nbchain-out\r\n*1\r\n$1\r\nx\r\n"
+    );
+}
+
+#[test]
+fn test_blocked_client_served_by_the_key_that_became_ready() {
+    let mut waiter = client();
+    let mut writer = client();
+    send(&mut waiter, &["BLPOP", "bwrong", "bright", "0"]);
+    std::thread::sleep(Duration::from_millis(100));
+
+    // The first key now holds a string, which must not keep the client
+    // waiting once the second gets an element.
+    assert_eq!(command(&mut writer, &["SET", "bwrong", "s"]), "+OK\r\n");
+    assert_eq!(command(&mut writer, &["RPUSH", "bright", "x"]), ":1\r\n");
+    assert_eq!(read_frame(&mut waiter), "*2\r\n$6\r\nbright\r\n$1\r\nx\r\n");
+}
+
+#[test]
+fn test_blocking_pop_timeout_and_multi() {
+    let mut client = client();
+    assert_eq!(
+        command(&mut client, &["BRPOP", "btimeout", "0.1"]),
+        "*-1\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["BLMOVE", "btimeout", "x", "LEFT", "LEFT", "0.1"]
+        ),
+        "$-1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["BLPOP", "btimeout", "-1"]),
+        "-ERR timeout is negative\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["BLPOP", "btimeout", "soon"]),
+        "-ERR timeout is not a float or out of range\r\n"
+    );
+
+    // Inside a transaction nothing could push, so it does not wait.
+    command(&mut client, &["MULTI"]);
+    assert_eq!(
+        command(&mut client, &["BLPOP", "btimeout", "0"]),
+        "+QUEUED\r\n"
+    );
+    assert_eq!(command(&mut client, &["EXEC"]), "*1\r\n*-1\r\n");
+}
+
+#[test]
+fn test_transactions() {
+    let mut client = client();
+    assert_eq!(command(&mut client, &["MULTI"]), "+OK\r\n");
+    assert_eq!(
+        command(&mut client, &["MULTI"]),
+        "-ERR MULTI calls can not be nested\r\n"
+    );
+    for args in [
+        &["SET", "tx:a", "1"][..],
+        &["INCR", "tx:a"],
+        &["LPUSH", "tx:a", "x"],
+        &["PING"],
+    ] {
+        assert_eq!(command(&mut client, args), "+QUEUED\r\n");
+    }
+    // A command failing as it runs leaves the others be.
+    assert_eq!(
+        command(&mut client, &["EXEC"]),
+        "*4\r\n+OK\r\n:2\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n+PONG\r\n"
+    );
+
+    command(&mut client, &["MULTI"]);
+    command(&mut client, &["SET", "tx:b", "1"]);
+    assert_eq!(
+        command(&mut client, &["GET"]),
+        "-ERR wrong number of arguments for 'get' command\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["EXEC"]),
+        "-EXECABORT Transaction discarded because of previous errors.\r\n"
+    );
+    assert_eq!(command(&mut client, &["GET", "tx:b"]), "$-1\r\n");
+    assert_eq!(
+        command(&mut client, &["DISCARD"]),
+        "-ERR DISCARD without MULTI\r\n"
+    );
+
+    // SELECT inside the transaction sticks to the connection.
+    command(&mut client, &["MULTI"]);
+    command(&mut client, &["SELECT", "13"]);
+    command(&mut client, &["SET", "tx:c", "1"]);
+    assert_eq!(command(&mut client, &["EXEC"]), "*2\r\n+OK\r\n+OK\r\n");
+    assert_eq!(command(&mut client, &["GET", "tx:c"]), "$1\r\n1\r\n");
+    command(&mut client, &["DEL", "tx:c"]);
+}
+
+#[test]
+fn test_hash_commands() {
+    let mut client = client();
+    assert_eq!(
+        command(&mut client, &["HSET", "user", "name", "ann", "age", "41"]),
+        ":2\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HSET", "user", "name", "bob", "city", "x"]),
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HGET", "user", "name"]),
+        "$3\r\nbob\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HMGET", "user", "age", "missing"]),
+        "*2\r\n$2\r\n41\r\n$-1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HINCRBY", "user", "age", "1"]),
+        ":42\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HINCRBY", "user", "name", "1"]),
+        "-ERR hash value is not an integer\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HINCRBYFLOAT", "user", "score", "10.5"]),
+        "$4\r\n10.5\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HINCRBYFLOAT", "user", "score", "0.1"]),
+        "$4\r\n10.6\r\n"
+    );
+    command(&mut client, &["HSET", "h-float", "f", "3.2", "n", "007"]);
+    assert_eq!(
+        command(&mut client, &["HINCRBYFLOAT", "h-float", "f", "0.1"]),
+        "$3\r\n3.3\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HGET", "h-float", "f"]),
+        "$3\r\n3.3\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HINCRBY", "h-float", "n", "1"]),
+        "-ERR hash value is not an integer\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HDEL", "user", "city", "missing"]),
+        ":1\r\n"
+    );
+    assert_eq!(command(&mut client, &["HEXISTS", "user", "city"]), ":0\r\n");
+    assert_eq!(command(&mut client, &["HLEN", "user"]), ":3\r\n");
+    assert_eq!(command(&mut client, &["TYPE", "user"]), "+hash\r\n");
+    assert_eq!(
+        command(&mut client, &["HSET", "user", "dangling"]),
+        "-ERR wrong number of arguments for 'hset' command\r\n"
+    );
+
+    assert!(command(&mut client, &["HGETALL", "user"]).starts_with("*6\r\n"));
+    assert!(command(&mut client, &["HSCAN", "user", "0"]).starts_with("*2\r\n$1\r\n0\r\n*6\r\n"));
+    assert!(command(&mut client, &["HRANDFIELD", "user", "-5"]).starts_with("*5\r\n"));
+    assert!(command(&mut client, &["HRANDFIELD", "user", "5", "WITHVALUES"]).starts_with("*6\r\n"));
+    assert_eq!(command(&mut client, &["HRANDFIELD", "nohash"]), "$-1\r\n");
+    assert_eq!(
+        command(&mut client, &["HRANDFIELD", "user", "-9223372036854775808"]),
+        "-ERR value is out of range\r\n"
+    );
+    assert!(
+        command(&mut client, &["HRANDFIELD", "user", "9223372036854775807"]).starts_with("*3\r\n")
+    );
+
+    // Deleting the last field deletes the key.
+    command(&mut client, &["HDEL", "user", "name", "age", "score"]);
+    assert_eq!(command(&mut client, &["TYPE", "user"]), "+none\r\n");
+}
+
+#[test]
+fn test_hash_field_expiry() {
+    let mut client = client();
+    command(
+        &mut client,
+        &["HSET", "session", "token", "t", "user", "u", "tmp", "x"],
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["HEXPIRE", "session", "100", "FIELDS", "2", "token", "nope"]
+        ),
+        "*2\r\n:1\r\n:-2\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["HEXPIRE", "session", "200", "NX", "FIELDS", "1", "token"]
+        ),
+        "*1\r\n:0\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["HTTL", "session", "FIELDS", "2", "token", "user"]
+        ),
+        "*2\r\n:100\r\n:-1\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["HPERSIST", "session", "FIELDS", "2", "token", "user"]
+        ),
+        "*2\r\n:1\r\n:-1\r\n"
+    );
+    // A time in the past deletes the field right away.
+    assert_eq!(
+        command(
+            &mut client,
+            &["HEXPIRE", "session", "0", "FIELDS", "1", "tmp"]
+        ),
+        "*1\r\n:2\r\n"
+    );
+
+    // Expired fields disappear on their own, and the key with its last one.
+    assert_eq!(
+        command(
+            &mut client,
+            &["HPEXPIRE", "session", "50", "FIELDS", "1", "user"]
+        ),
+        "*1\r\n:1\r\n"
+    );
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(command(&mut client, &["HLEN", "session"]), ":1\r\n");
+    assert_eq!(
+        command(&mut client, &["HGET", "session", "user"]),
+        "$-1\r\n"
+    );
+    command(
+        &mut client,
+        &["HPEXPIRE", "session", "50", "FIELDS", "1", "token"],
+    );
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(command(&mut client, &["TYPE", "session"]), "+none\r\n");
+
+    assert_eq!(
+        command(
+            &mut client,
+            &["HEXPIRE", "session", "10", "FIELDS", "2", "a"]
+        ),
+        "-ERR The `numfields` parameter must match the number of arguments\r\n"
+    );
+}
+
+#[test]
+fn test_set_commands() {
+    let mut client = client();
+    assert_eq!(
+        command(&mut client, &["SADD", "tags", "a", "b", "c", "a"]),
+        ":3\r\n"
+    );
+    command(&mut client, &["SADD", "other-tags", "b", "c", "d"]);
+    assert_eq!(command(&mut client, &["SCARD", "tags"]), ":3\r\n");
+    assert_eq!(command(&mut client, &["SISMEMBER", "tags", "b"]), ":1\r\n");
+    assert_eq!(
+        command(&mut client, &["SMISMEMBER", "tags", "a", "z"]),
+        "*2\r\n:1\r\n:0\r\n"
+    );
+    assert_eq!(command(&mut client, &["SREM", "tags", "a", "z"]), ":1\r\n");
+    assert_eq!(
+        command(&mut client, &["SDIFF", "other-tags", "tags"]),
+        "*1\r\n$1\r\nd\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["SINTERSTORE", "common-tags", "tags", "other-tags"]
+        ),
+        ":2\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["SUNIONSTORE", "all-tags", "tags", "other-tags"]
+        ),
+        ":3\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["SINTERCARD", "2", "tags", "other-tags", "LIMIT", "1"]
+        ),
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SINTER", "tags", "no-tags"]),
+        "*0\r\n"
+    );
+    assert!(command(&mut client, &["SRANDMEMBER", "all-tags", "-5"]).starts_with("*5\r\n"));
+    assert_eq!(
+        command(
+            &mut client,
+            &["SRANDMEMBER", "all-tags", "-9223372036854775808"]
+        ),
+        "-ERR value is out of range\r\n"
+    );
+    assert!(command(
+        &mut client,
+        &["SRANDMEMBER", "all-tags", "9223372036854775807"]
+    )
+    .starts_with("*3\r\n"));
+    assert!(command(&mut client, &["SPOP", "all-tags", "2"]).starts_with("*2\r\n"));
+    assert_eq!(command(&mut client, &["SCARD", "all-tags"]), ":1\r\n");
+
+    // RESP3 clients get set replies.
+    command(&mut client, &["HELLO", "3"]);
+    assert_eq!(command(&mut client, &["SMEMBERS", "no-tags"]), "~0\r\n");
+}
+
+#[test]
+fn test_sorted_set_commands() {
+    let mut client = client();
+    assert_eq!(
+        command(
+            &mut client,
+            &["ZADD", "ranking", "1", "a", "2", "b", "3", "c"]
+        ),
+        ":3\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["ZADD", "ranking", "XX", "CH", "5", "a", "9", "z"]
+        ),
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["ZADD", "ranking", "GT", "1", "a"]),
+        ":0\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["ZADD", "ranking", "INCR", "2", "a"]),
+        "$1\r\n7\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["ZADD", "ranking", "NX", "GT", "1", "a"]),
+        "-ERR GT, LT, and/or NX options at the same time are not compatible\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["ZINCRBY", "ranking", "0.5", "b"]),
+        "$3\r\n2.5\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["ZRANGE", "ranking", "0", "-1", "WITHSCORES"]),
+        "*6\r\n$1\r\nb\r\n$3\r\n2.5\r\n$1\r\nc\r\n$1\r\n3\r\n$1\r\na\r\n$1\r\n7\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["ZRANGE", "ranking", "+inf", "(2.5", "BYSCORE", "REV", "LIMIT", "0", "1"]
+        ),
+        "*1\r\n$1\r\na\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["ZCOUNT", "ranking", "(2.5", "+inf"]),
+        ":2\r\n"
+    );
+    assert_eq!(command(&mut client, &["ZRANK", "ranking", "a"]), ":2\r\n");
+    assert_eq!(
+        command(&mut client, &["ZREVRANK", "ranking", "a"]),
+        ":0\r\n"
+    );
+    assert_eq!(command(&mut client, &["ZSCORE", "ranking", "z"]), "$-1\r\n");
+    assert_eq!(
+        command(&mut client, &["ZREM", "ranking", "c", "z"]),
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["ZPOPMIN", "ranking"]),
+        "*2\r\n$1\r\nb\r\n$3\r\n2.5\r\n"
+    );
+
+    command(
+        &mut client,
+        &["ZADD", "names", "0", "ann", "0", "bob", "0", "cid"],
+    );
+    assert_eq!(
+        command(&mut client, &["ZRANGE", "names", "[bob", "+", "BYLEX"]),
+        "*2\r\n$3\r\nbob\r\n$3\r\ncid\r\n"
+    );
+
+    // RESP3 clients get each member paired with a double.
+    command(&mut client, &["HELLO", "3"]);
+    assert_eq!(
+        command(&mut client, &["ZPOPMAX", "ranking", "5"]),
+        "*1\r\n*2\r\n$1\r\na\r\n,7\r\n"
+    );
+    assert_eq!(command(&mut client, &["ZCARD", "ranking"]), ":0\r\n");
+}
+
+#[test]
+fn test_sorted_set_aggregates_and_blocking_pops() {
+    let mut writer = client();
+    command(&mut writer, &["ZADD", "agg-a", "1", "x", "2", "y"]);
+    command(&mut writer, &["ZADD", "agg-b", "3", "y", "4", "z"]);
+    command(&mut writer, &["SADD", "agg-c", "y"]);
+    assert_eq!(
+        command(
+            &mut writer,
+            &[
+                "ZUNION",
+                "3",
+                "agg-a",
+                "agg-b",
+                "agg-c",
+                "WEIGHTS",
+                "1",
+                "2",
+                "10",
+                "AGGREGATE",
+                "MAX",
+                "WITHSCORES"
+            ]
+        ),
+        "*6\r\n$1\r\nx\r\n$1\r\n1\r\n$1\r\nz\r\n$1\r\n8\r\n$1\r\ny\r\n$2\r\n10\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut writer,
+            &["ZINTERSTORE", "agg-out", "2", "agg-a", "agg-b"]
+        ),
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut writer, &["ZSCORE", "agg-out", "y"]),
+        "$1\r\n5\r\n"
+    );
+    assert_eq!(
+        command(&mut writer, &["ZDIFF", "2", "agg-a", "agg-b"]),
+        "*1\r\n$1\r\nx\r\n"
+    );
+    assert_eq!(
+        command(&mut writer, &["ZRANGESTORE", "agg-out", "agg-b", "0", "0"]),
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut writer, &["ZRANGE", "agg-out", "0", "-1"]),
+        "*1\r\n$1\r\ny\r\n"
+    );
+
+    let mut first = client();
+    let mut second = client();
+    send(&mut first, &["BZPOPMIN", "bjobs", "0"]);
+    std::thread::sleep(Duration::from_millis(100));
+    send(
+        &mut second,
+        &["BZMPOP", "0", "1", "bjobs", "MAX", "COUNT", "2"],
+    );
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(
+        command(
+            &mut writer,
+            &["ZADD", "bjobs", "1", "a", "2", "b", "3", "c"]
+        ),
+        ":3\r\n"
+    );
+    assert_eq!(
+        read_frame(&mut first),
+        "*3\r\n$5\r\nbjobs\r\n$1\r\na\r\n$1\r\n1\r\n"
+    );
+    assert_eq!(
+        read_frame(&mut second),
+        "*2\r\n$5\r\nbjobs\r\n*2\r\n*2\r\n$1\r\nc\r\n$1\r\n3\r\n*2\r\n$1\r\nb\r\n$1\r\n2\r\n"
+    );
+    assert_eq!(
+        command(&mut writer, &["BZPOPMAX", "bjobs", "0.1"]),
+        "*-1\r\n"
+    );
+}
+
+#[test]
+fn test_string_commands() {
+    let mut client = client();
+    assert_eq!(
+        command(
+            &mut client,
+            &["MSET", "str-a", "ohmytext", "str-b", "mynewtext"]
+        ),
+        "+OK\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["MGET", "str-a", "str-none", "str-b"]),
+        "*3\r\n$8\r\nohmytext\r\n$-1\r\n$9\r\nmynewtext\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["MSETNX", "str-a", "x", "str-c", "y"]),
+        ":0\r\n"
+    );
+    assert_eq!(command(&mut client, &["SETNX", "str-c", "y"]), ":1\r\n");
+    assert_eq!(
+        command(&mut client, &["LCS", "str-a", "str-b"]),
+        "$6\r\nmytext\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["LCS", "str-a", "str-b", "IDX", "MINMATCHLEN", "4", "WITHMATCHLEN"]
+        ),
+        "*4\r\n$7\r\nmatches\r\n*1\r\n*3\r\n*2\r\n:4\r\n:7\r\n*2\r\n:5\r\n:8\r\n:4\r\n$3\r\nlen\r\n:6\r\n"
+    );
+
+    assert_eq!(
+        command(&mut client, &["APPEND", "str-d", "Hello"]),
+        ":5\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SETRANGE", "str-d", "7", "World"]),
+        ":12\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["GET", "str-d"]),
+        "$12\r\nHello\0\0World\r\n"
+    );
+    assert_eq!(command(&mut client, &["STRLEN", "str-d"]), ":12\r\n");
+    assert_eq!(
+        command(&mut client, &["GETRANGE", "str-d", "-5", "-1"]),
+        "$5\r\nWorld\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["GETSET", "str-d", "new"]),
+        "$12\r\nHello\0\0World\r\n"
+    );
+
+    assert_eq!(
+        command(&mut client, &["PSETEX", "str-e", "0", "v"]),
+        "-ERR invalid expire time in 'psetex' command\r\n"
+    );
+    command(&mut client, &["SET", "str-e", "v"]);
+    assert_eq!(
+        command(&mut client, &["GETEX", "str-e", "PX", "100"]),
+        "$1\r\nv\r\n"
+    );
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(command(&mut client, &["GET", "str-e"]), "$-1\r\n");
+    assert_eq!(command(&mut client, &["GETDEL", "str-c"]), "$1\r\ny\r\n");
+    assert_eq!(command(&mut client, &["GET", "str-c"]), "$-1\r\n");
+}
+
+#[test]
+fn test_set_options() {
+    let mut client = client();
+    assert_eq!(
+        command(&mut client, &["SET", "opt-lock", "a", "NX", "PX", "30000"]),
+        "+OK\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SET", "opt-lock", "b", "NX", "PX", "30000"]),
+        "$-1\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["SET", "opt-lock", "c", "XX", "KEEPTTL", "GET"]
+        ),
+        "$1\r\na\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SET", "opt-none", "v", "XX", "GET"]),
+        "$-1\r\n"
+    );
+    assert_eq!(command(&mut client, &["GET", "opt-none"]), "$-1\r\n");
+    assert_eq!(
+        command(&mut client, &["SET", "opt-lock", "v", "NX", "XX"]),
+        "-ERR syntax error\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SET", "opt-lock", "v", "KEEPTTL", "EX", "1"]),
+        "-ERR syntax error\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SET", "opt-lock", "v", "EX", "0"]),
+        "-ERR invalid expire time in 'set' command\r\n"
+    );
+
+    // KEEPTTL carries the expiry over; a plain SET clears it.
+    command(&mut client, &["SET", "opt-kept", "v", "PX", "100"]);
+    command(&mut client, &["SET", "opt-kept", "w", "KEEPTTL"]);
+    command(&mut client, &["SET", "opt-cleared", "v", "PX", "100"]);
+    command(&mut client, &["SET", "opt-cleared", "w"]);
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(
+        command(&mut client, &["MGET", "opt-kept", "opt-cleared"]),
+        "*2\r\n$-1\r\n$1\r\nw\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SET", "opt-past", "v", "PXAT", "1"]),
+        "+OK\r\n"
+    );
+    assert_eq!(command(&mut client, &["GET", "opt-past"]), "$-1\r\n");
+}
+
+#[test]
+fn test_counters() {
+    let mut client = client();
+    assert_eq!(command(&mut client, &["INCRBY", "ctr", "10"]), ":10\r\n");
+    assert_eq!(command(&mut client, &["DECR", "ctr"]), ":9\r\n");
+    assert_eq!(command(&mut client, &["DECRBY", "ctr", "-3"]), ":12\r\n");
+    assert_eq!(
+        command(&mut client, &["DECRBY", "ctr", "-9223372036854775808"]),
+        "-ERR decrement would overflow\r\n"
+    );
+    command(&mut client, &["SET", "ctr-max", "9223372036854775807"]);
+    assert_eq!(
+        command(&mut client, &["INCR", "ctr-max"]),
+        "-ERR increment or decrement would overflow\r\n"
+    );
+    for value in ["+5", "007", " 5", "-0"] {
+        command(&mut client, &["SET", "ctr-strict", value]);
+        assert_eq!(
+            command(&mut client, &["INCR", "ctr-strict"]),
+            "-ERR value is not an integer or out of range\r\n"
+        );
+    }
+
+    command(&mut client, &["SET", "ctr-float", "10.50"]);
+    assert_eq!(
+        command(&mut client, &["INCRBYFLOAT", "ctr-float", "0.1"]),
+        "$4\r\n10.6\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["INCRBYFLOAT", "ctr-float", "-5"]),
+        "$3\r\n5.6\r\n"
+    );
+    command(&mut client, &["SET", "ctr-float", "5.0e3"]);
+    assert_eq!(
+        command(&mut client, &["INCRBYFLOAT", "ctr-float", "2.0e2"]),
+        "$4\r\n5200\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["INCRBYFLOAT", "ctr-float", "inf"]),
+        "-ERR increment would produce NaN or Infinity\r\n"
+    );
+    command(&mut client, &["SET", "ctr-float", "0.1"]);
+    assert_eq!(
+        command(&mut client, &["INCRBYFLOAT", "ctr-float", "0.2"]),
+        "$3\r\n0.3\r\n"
+    );
+    assert_eq!(command(&mut client, &["GET", "ctr-float"]), "$3\r\n0.3\r\n");
+    assert_eq!(
+        command(
+            &mut client,
+            &["INCRBYFLOAT", "ctr-float", "-0.30000000000000001"]
+        ),
+        "$1\r\n0\r\n"
+    );
+    command(&mut client, &["SET", "ctr-float", "1e20"]);
+    assert_eq!(
+        command(&mut client, &["INCRBYFLOAT", "ctr-float", "0.000001"]),
+        "$21\r\n100000000000000000000\r\n"
+    );
+    command(&mut client, &["SET", "ctr-float", "0.0000001"]);
+    assert_eq!(
+        command(&mut client, &["INCRBYFLOAT", "ctr-float", "0.0000002"]),
+        "$9\r\n0.0000003\r\n"
+    );
+    command(&mut client, &["SET", "ctr-text", "abc"]);
+    assert_eq!(
+        command(&mut client, &["INCRBYFLOAT", "ctr-text", "1"]),
+        "-ERR value is not a valid float\r\n"
+    );
+}
+
+#[test]
+fn test_bitmap_commands() {
+    let mut client = client();
+    assert_eq!(
+        command(&mut client, &["SETBIT", "bits", "7", "1"]),
+        ":0\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SETBIT", "bits", "7", "0"]),
+        ":1\r\n"
+    );
+    assert_eq!(command(&mut client, &["GETBIT", "bits", "100"]), ":0\r\n");
+
+    command(&mut client, &["SET", "bits-text", "foobar"]);
+    assert_eq!(command(&mut client, &["BITCOUNT", "bits-text"]), ":26\r\n");
+    assert_eq!(
+        command(&mut client, &["BITCOUNT", "bits-text", "1", "1"]),
+        ":6\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["BITCOUNT", "bits-text", "5", "30", "BIT"]),
+        ":17\r\n"
+    );
+
+    // Byte 1 is the first with a set bit; a closed range finds no clear bit
+    // past the end of the string, an open one does.
+    command(&mut client, &["SETBIT", "bits-pos", "8", "1"]);
+    assert_eq!(command(&mut client, &["BITPOS", "bits-pos", "1"]), ":8\r\n");
+    assert_eq!(
+        command(&mut client, &["BITPOS", "bits-pos", "1", "9", "-1", "BIT"]),
+        ":-1\r\n"
+    );
+    command(&mut client, &["SET", "bits-ones", "\u{0}"]);
+    command(&mut client, &["BITOP", "NOT", "bits-ones", "bits-ones"]);
+    assert_eq!(
+        command(&mut client, &["BITPOS", "bits-ones", "0"]),
+        ":8\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["BITPOS", "bits-ones", "0", "0", "0"]),
+        ":-1\r\n"
+    );
+
+    command(&mut client, &["SET", "bits-a", "abc"]);
+    command(&mut client, &["SET", "bits-b", "ab"]);
+    assert_eq!(
+        command(&mut client, &["BITOP", "XOR", "bits-x", "bits-a", "bits-b"]),
+        ":3\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["GET", "bits-x"]),
+        "$3\r\n\u{0}\u{0}c\r\n"
+    );
+
+    assert_eq!(
+        command(
+            &mut client,
+            &[
+                "BITFIELD",
+                "bits-field",
+                "INCRBY",
+                "i5",
+                "100",
+                "1",
+                "GET",
+                "u4",
+                "0"
+            ]
+        ),
+        "*2\r\n:1\r\n:0\r\n"
+    );
+    command(
+        &mut client,
+        &["BITFIELD", "bits-field", "SET", "u2", "#51", "3"],
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &[
+                "BITFIELD",
+                "bits-field",
+                "OVERFLOW",
+                "SAT",
+                "INCRBY",
+                "u2",
+                "102",
+                "1",
+                "OVERFLOW",
+                "FAIL",
+                "INCRBY",
+                "u2",
+                "102",
+                "1",
+                "OVERFLOW",
+                "WRAP",
+                "INCRBY",
+                "u2",
+                "102",
+                "1"
+            ]
+        ),
+        "*3\r\n:3\r\n$-1\r\n:0\r\n"
+    );
+    // Out of range values saturate toward their own sign.
+    assert_eq!(
+        command(
+            &mut client,
+            &[
+                "BITFIELD", "bits-sat", "OVERFLOW", "SAT", "SET", "u8", "0", "300", "SET", "u8",
+                "0", "-5", "OVERFLOW", "WRAP", "SET", "u8", "0", "-1", "GET", "u8", "0"
+            ]
+        ),
+        "*4\r\n:0\r\n:255\r\n:0\r\n:255\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["BITFIELD_RO", "bits-field", "SET", "u2", "0", "1"]
+        ),
+        "-ERR BITFIELD_RO only supports the GET subcommand\r\n"
+    );
+}
+
+#[test]
+fn test_hyperloglog_commands() {
+    let mut client = client();
+    assert_eq!(
+        command(&mut client, &["PFADD", "hll", "foo", "bar", "zap"]),
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["PFADD", "hll", "zap", "zap", "zap"]),
+        ":0\r\n"
+    );
+    assert_eq!(command(&mut client, &["PFCOUNT", "hll"]), ":3\r\n");
+    command(&mut client, &["PFADD", "hll-other", "1", "2", "3"]);
+    assert_eq!(
+        command(&mut client, &["PFCOUNT", "hll", "hll-other"]),
+        ":6\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["PFMERGE", "hll-merged", "hll", "hll-other"]),
+        "+OK\r\n"
+    );
+    assert_eq!(command(&mut client, &["PFCOUNT", "hll-merged"]), ":6\r\n");
+
+    // The value is a plain string in Redis's layout, starting with a sparse
+    // header.
+    assert_eq!(
+        command(&mut client, &["GETRANGE", "hll-merged", "0", "4"]),
+        "$5\r\nHYLL\u{1}\r\n"
+    );
+
+    command(&mut client, &["SET", "hll-string", "hello"]);
+    assert_eq!(
+        command(&mut client, &["PFADD", "hll-string", "x"]),
+        "-WRONGTYPE Key is not a valid HyperLogLog string value.\r\n"
+    );
+}
+
+#[test]
+fn test_geo_commands() {
+    let mut client = client();
+    assert_eq!(
+        command(
+            &mut client,
+            &[
+                "GEOADD",
+                "sicily",
+                "13.361389",
+                "38.115556",
+                "Palermo",
+                "15.087269",
+                "37.502669",
+                "Catania"
+            ]
+        ),
+        ":2\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &["GEODIST", "sicily", "Palermo", "Catania", "km"]
+        ),
+        "$8\r\n166.2742\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["GEOHASH", "sicily", "Palermo", "Nowhere"]),
+        "*2\r\n$11\r\nsqc8b49rny0\r\n$-1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["GEOADD", "sicily", "200", "100", "Nowhere"]),
+        "-ERR invalid longitude,latitude pair 200.000000,100.000000\r\n"
+    );
+
+    command(
+        &mut client,
+        &[
+            "GEOADD",
+            "sicily",
+            "12.758489",
+            "38.788135",
+            "edge1",
+            "17.241510",
+            "38.788135",
+            "edge2",
+        ],
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &[
+                "GEOSEARCH",
+                "sicily",
+                "FROMLONLAT",
+                "15",
+                "37",
+                "BYRADIUS",
+                "200",
+                "km",
+                "ASC"
+            ]
+        ),
+        "*2\r\n$7\r\nCatania\r\n$7\r\nPalermo\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &[
+                "GEOSEARCH",
+                "sicily",
+                "FROMMEMBER",
+                "Catania",
+                "BYBOX",
+                "400",
+                "400",
+                "km",
+                "DESC",
+                "COUNT",
+                "1",
+                "WITHDIST",
+                "WITHHASH"
+            ]
+        ),
+        "*1\r\n*3\r\n$5\r\nedge2\r\n$8\r\n236.5292\r\n:3481342659049484\r\n"
+    );
+    assert_eq!(
+        command(
+            &mut client,
+            &[
+                "GEOSEARCHSTORE",
+                "sicily-near",
+                "sicily",
+                "FROMLONLAT",
+                "15",
+                "37",
+                "BYRADIUS",
+                "100",
+                "km",
+                "STOREDIST"
+            ]
+        ),
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["ZRANGE", "sicily-near", "0", "-1"]),
+        "*1\r\n$7\r\nCatania\r\n"
+    );
+}
+
+#[test]
+fn test_keyspace_commands() {
+    let mut client = client();
+    command(&mut client, &["SET", "ks:a", "1"]);
+    command(&mut client, &["RPUSH", "ks:list", "x"]);
+    command(&mut client, &["XADD", "ks:stream", "1-1", "f", "v"]);
+    assert_eq!(
+        command(
+            &mut client,
+            &["EXISTS", "ks:a", "ks:a", "ks:list", "ks:none"]
+        ),
+        ":3\r\n"
+    );
+    assert_eq!(command(&mut client, &["COPY", "ks:a", "ks:b"]), ":1\r\n");
+    assert_eq!(command(&mut client, &["COPY", "ks:list", "ks:b"]), ":0\r\n");
+    assert_eq!(
+        command(&mut client, &["COPY", "ks:a", "ks:a"]),
+        "-ERR source and destination objects are the same\r\n"
+    );
+
+    assert_eq!(
+        command(&mut client, &["RENAMENX", "ks:a", "ks:b"]),
+        ":0\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["RENAME", "ks:list", "ks:b"]),
+        "+OK\r\n"
+    );
+    assert_eq!(command(&mut client, &["TYPE", "ks:b"]), "+list\r\n");
+    assert_eq!(
+        command(&mut client, &["RENAME", "ks:none", "ks:c"]),
+        "-ERR no such key\r\n"
+    );
+
+    assert_eq!(
+        command(
+            &mut client,
+            &["DEL", "ks:a", "ks:b", "ks:stream", "ks:none"]
+        ),
+        ":3\r\n"
+    );
+    assert_eq!(command(&mut client, &["UNLINK", "ks:a"]), ":0\r\n");
+    assert_eq!(command(&mut client, &["TYPE", "ks:stream"]), "+none\r\n");
+}
+
+#[test]
+fn test_logical_databases() {
+    let mut waiter = client();
+    let mut other = client();
+    let mut client = client();
+    for db in ["15", "14"] {
+        command(&mut client, &["SELECT", db]);
+        command(&mut client, &["FLUSHDB"]);
+    }
+    assert_eq!(
+        command(&mut client, &["SELECT", "16"]),
+        "-ERR DB index is out of range\r\n"
+    );
+    command(&mut client, &["SET", "db:a", "1", "EX", "100"]);
+    assert_eq!(command(&mut client, &["MOVE", "db:a", "15"]), ":1\r\n");
+    assert_eq!(command(&mut client, &["EXISTS", "db:a"]), ":0\r\n");
+    assert_eq!(
+        command(&mut client, &["MOVE", "db:a", "14"]),
+        "-ERR source and destination objects are the same\r\n"
+    );
+
+    command(&mut client, &["SELECT", "15"]);
+    assert_eq!(command(&mut client, &["TTL", "db:a"]), ":100\r\n");
+    assert_eq!(
+        command(&mut client, &["COPY", "db:a", "db:b", "DB", "14"]),
+        ":1\r\n"
+    );
+
+    // Swapping brings the pushed list to the client blocked in database 14.
+    command(&mut waiter, &["SELECT", "14"]);
+    send(&mut waiter, &["BLPOP", "db:list", "5"]);
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(command(&mut client, &["RPUSH", "db:list", "x"]), ":1\r\n");
+    assert_eq!(command(&mut client, &["SWAPDB", "14", "15"]), "+OK\r\n");
+    assert_eq!(
+        read_frame(&mut waiter),
+        "*2\r\n$7\r\ndb:list\r\n$1\r\nx\r\n"
+    );
+    assert_eq!(command(&mut client, &["GET", "db:b"]), "$1\r\n1\r\n");
+    assert_eq!(
+        command(&mut client, &["EXISTS", "db:a", "db:list"]),
+        ":0\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SWAPDB", "x", "15"]),
+        "-ERR invalid first DB index\r\n"
+    );
+    assert_eq!(command(&mut other, &["GET", "db:b"]), "$-1\r\n");
+}
+
+#[test]
+fn test_expire_commands() {
+    let mut client = client();
+    command(&mut client, &["SET", "ttl:a", "v"]);
+    assert_eq!(command(&mut client, &["TTL", "ttl:a"]), ":-1\r\n");
+    assert_eq!(command(&mut client, &["TTL", "ttl:none"]), ":-2\r\n");
+    assert_eq!(
+        command(&mut client, &["EXPIRE", "ttl:a", "100", "XX"]),
+        ":0\r\n"
+    );
+    assert_eq!(command(&mut client, &["EXPIRE", "ttl:a", "100"]), ":1\r\n");
+    assert_eq!(command(&mut client, &["TTL", "ttl:a"]), ":100\r\n");
+    assert_eq!(
+        command(&mut client, &["EXPIRE", "ttl:a", "50", "GT"]),
+        ":0\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["EXPIRE", "ttl:a", "50", "XX", "LT"]),
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["EXPIRE", "ttl:a", "50", "NX", "GT"]),
+        "-ERR NX and XX, GT or LT options at the same time are not compatible\r\n"
+    );
+    // Line breaks in an echoed option cannot add a reply of their own.
+    assert_eq!(
+        command(&mut client, &["EXPIRE", "ttl:a", "10", "x\r\n:1"]),
+        "-ERR Unsupported option x  :1\r\n"
+    );
+    assert_eq!(command(&mut client, &["PING"]), "+PONG\r\n");
+
+    assert_eq!(
+        command(&mut client, &["PEXPIREAT", "ttl:a", "99999999999999"]),
+        ":1\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["PEXPIRETIME", "ttl:a"]),
+        ":99999999999999\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["EXPIRETIME", "ttl:a"]),
+        ":100000000000\r\n"
+    );
+    assert_eq!(command(&mut client, &["PERSIST", "ttl:a"]), ":1\r\n");
+    assert_eq!(command(&mut client, &["PERSIST", "ttl:a"]), ":0\r\n");
+    assert_eq!(command(&mut client, &["PTTL", "ttl:a"]), ":-1\r\n");
+    assert_eq!(
+        command(&mut client, &["PEXPIRE", "ttl:a", "9223372036854775807"]),
+        "-ERR invalid expire time in 'pexpire' command\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["EXPIREAT", "ttl:a", "9223372036854775807"]),
+        "-ERR invalid expire time in 'expireat' command\r\n"
+    );
+
+    assert_eq!(command(&mut client, &["EXPIRE", "ttl:a", "-1"]), ":1\r\n");
+    assert_eq!(command(&mut client, &["EXISTS", "ttl:a"]), ":0\r\n");
+}
+
+#[test]
+fn test_scan_commands() {
+    let mut client = client();
+    for i in 0..50 {
+        command(&mut client, &["SADD", "scan:set", &format!("m{}", i)]);
+    }
+    // Every member comes back exactly once over the pages.
+    let mut cursor = "0".to_string();
+    let mut members = Vec::new();
+    loop {
+        let reply = command(&mut client, &["SSCAN", "scan:set", &cursor, "COUNT", "7"]);
+        let lines: Vec<&str> = reply.split("\r\n").collect();
+        cursor = lines[2].to_string();
+        members.extend(
+            lines
+                .iter()
+                .filter(|line| line.starts_with('m'))
+                .map(|line| line.to_string()),
+        );
+        if cursor == "0" {
+            break;
+        }
+    }
+    members.sort();
+    let count = members.len();
+    members.dedup();
+    assert_eq!((count, members.len()), (50, 50));
+
+    assert_eq!(
+        command(
+            &mut client,
+            &["SSCAN", "scan:set", "0", "MATCH", "m4?", "COUNT", "100"]
+        ),
+        command(
+            &mut client,
+            &["SSCAN", "scan:set", "0", "MATCH", "m4[0-9]", "COUNT", "100"]
+        )
+    );
+    command(&mut client, &["HSET", "scan:hash", "f", "v"]);
+    command(&mut client, &["ZADD", "scan:zset", "1.5", "z"]);
+    assert_eq!(
+        command(
+            &mut client,
+            &["SCAN", "0", "MATCH", "scan:*", "TYPE", "zset", "COUNT", "1000000"]
+        ),
+        "*2\r\n$1\r\n0\r\n*1\r\n$9\r\nscan:zset\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["HSCAN", "scan:hash", "0", "NOVALUES"]),
+        "*2\r\n$1\r\n0\r\n*1\r\n$1\r\nf\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["ZSCAN", "scan:zset", "0"]),
+        "*2\r\n$1\r\n0\r\n*2\r\n$1\r\nz\r\n$3\r\n1.5\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SCAN", "abc"]),
+        "-ERR invalid cursor\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["SCAN", "0", "TYPE", "x\r\n:1"]),
+        "-ERR unknown type name\r\n"
+    );
+}
+
+#[test]
+fn test_scan_pages_through_a_changing_keyspace() {
+    let mut client = client();
+    assert_eq!(command(&mut client, &["SELECT", "7"]), "+OK\r\n");
+    command(&mut client, &["FLUSHDB"]);
+    for i in 0..200 {
+        command(&mut client, &["SET", &format!("stable:{}", i), "v"]);
+    }
+    for i in 0..50 {
+        command(&mut client, &["SET", &format!("doomed:{}", i), "v"]);
+    }
+
+    // Keys present from the first page to the last come back exactly once,
+    // whatever is added or deleted in between.
+    let mut cursor = "0".to_string();
+    let mut stable = Vec::new();
+    for page in 0.. {
+        assert!(page < 1000, "SCAN did not finish");
+        let reply = command(&mut client, &["SCAN", &cursor, "COUNT", "10"]);
+        let lines: Vec<&str> = reply.split("\r\n").collect();
+        cursor = lines[2].to_string();
+        stable.extend(
+            lines
+                .iter()
+                .filter(|line| line.starts_with("stable:"))
+                .map(|line| line.to_string()),
+        );
+        if cursor == "0" {
+            break;
+        }
+        command(&mut client, &["SET", &format!("new:{}", page), "v"]);
+        command(&mut client, &["DEL", &format!("doomed:{}", page)]);
+    }
+    stable.sort();
+    let count = stable.len();
+    stable.dedup();
+    assert_eq!((count, stable.len()), (200, 200));
+    command(&mut client, &["FLUSHDB"]);
+}
+
+#[test]
+fn test_glob_patterns() {
+    let mut client = client();
+    for key in ["glob:hello", "glob:hallo", "glob:h*llo", "glob:heeello"] {
+        command(&mut client, &["SET", key, "v"]);
+    }
+    assert_eq!(
+        command(&mut client, &["KEYS", "glob:h\\*llo"]),
+        "*1\r\n$10\r\nglob:h*llo\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["KEYS", "glob:h[^e*]llo"]),
+        "*1\r\n$10\r\nglob:hallo\r\n"
+    );
+    assert_eq!(
+        command(&mut client, &["KEYS", "glob:h?e*o"]),
+        "*1\r\n$12\r\nglob:heeello\r\n"
+    );
+    assert_eq!(command(&mut client, &["KEYS", "glob:x*"]), "*0\r\n");
+    assert_eq!(
+        command(&mut client, &["CONFIG", "GET", "P?RT"]),
+        "*2\r\n$4\r\nport\r\n$4\r\n6379\r\n"
+    );
+}